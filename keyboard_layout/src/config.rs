@@ -1,39 +1,297 @@
+//! This module provides the layout config, i.e. a keyboard and its base layout.
+//!
+//! A layout config may extend another one (`extends: <filename>`, relative to the extending file).
+//! Its settings are merged into those of the extended config: Maps are merged recursively, all other
+//! values (including lists) replace the extended ones. Single keys can be changed with `overrides`
+//! addressed by matrix position instead of repeating whole lists:
+//!
+//! ```yaml
+//! extends: svalboard.yml
+//! keyboard:
+//!   overrides:
+//!     - matrix_position: [5, 1]
+//!       key_cost: 2.5
+//! base_layout:
+//!   overrides:
+//!     - matrix_position: [5, 1]
+//!       symbols: ["b", "7", "", "B"]
+//!       fixed: true
+//! ```
+
 use crate::keyboard::KeyboardYAML;
 use crate::neo_layout_generator::BaseLayoutYAML;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
 use std::error::Error;
-use std::{fs::File, str::FromStr};
+use std::path::{Path, PathBuf};
+use std::{fs, fs::File, str::FromStr};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LayoutConfigError {
+    #[error("Invalid layout config: `extends` needs to be a filename")]
+    InvalidExtends,
+    #[error("Invalid layout config: Cyclic `extends` of {0}")]
+    CyclicExtends(String),
+    #[error("Invalid layout config: Override {1} of `{0}` is not a map with a `matrix_position`")]
+    InvalidOverride(String, usize),
+    #[error("Invalid layout config: Override {1} of `{0}` has unknown matrix position {2}")]
+    UnknownMatrixPosition(String, usize, String),
+    #[error("Invalid layout config: Override {1} of `{0}` has unknown setting `{2}`")]
+    UnknownOverrideSetting(String, usize, String),
+    #[error("Invalid layout config: Override {1} of `{0}` refers to a missing key in `{2}`")]
+    MissingOverrideTarget(String, usize, String),
+}
+
+/// The settings of a key that can be overridden in the `keyboard` section, with the lists they are
+/// taken from
+const KEYBOARD_OVERRIDE_SETTINGS: [(&str, &str); 7] = [
+    ("position", "positions"),
+    ("hand", "hands"),
+    ("finger", "fingers"),
+    ("key_cost", "key_costs"),
+    ("symmetry", "symmetries"),
+    ("unbalancing_position", "unbalancing_positions"),
+    ("cluster", "clusters"),
+];
+
+/// The settings of a key that can be overridden in the `base_layout` section, with the lists they are
+/// taken from
+const BASE_LAYOUT_OVERRIDE_SETTINGS: [(&str, &str); 2] =
+    [("symbols", "keys"), ("fixed", "fixed_keys")];
 
 #[derive(Deserialize, Debug)]
 pub struct LayoutConfig {
     pub keyboard: KeyboardYAML,
     pub base_layout: BaseLayoutYAML,
+    /// The files the config was resolved from (the config's file first, followed by the ones it
+    /// extends)
+    #[serde(skip)]
+    pub resolved_from: Vec<String>,
 }
 
 impl LayoutConfig {
     pub fn from_yaml(filename: &str) -> Result<Self> {
-        let f = File::open(filename)?;
-        let cfg: LayoutConfig = serde_yaml::from_reader(f)?;
+        let mut resolved_from = Vec::new();
+        let value = resolve_file(Path::new(filename), &mut resolved_from)?;
+        let mut cfg: LayoutConfig = serde_yaml::from_value(value)?;
+        cfg.resolved_from = resolved_from
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
         cfg.validate()?;
 
         Ok(cfg)
     }
 
+    /// The YAML content of a layout config file with its `extends` and `overrides` resolved
+    pub fn resolved_yaml(filename: &str) -> Result<String> {
+        let value = resolve_file(Path::new(filename), &mut Vec::new())?;
+        Ok(serde_yaml::to_string(&value)?)
+    }
+
     pub fn validate(&self) -> Result<()> {
-        self.keyboard.validate()?;
-        self.base_layout.validate()?;
-        Ok(())
+        let res = self
+            .keyboard
+            .validate()
+            .and_then(|_| self.base_layout.validate());
+        match self.resolved_from.as_slice() {
+            [] | [_] => res,
+            files => res.map_err(|e| {
+                anyhow!(
+                    "{} (in the layout config resolved from {})",
+                    e,
+                    files.join(" extending ")
+                )
+            }),
+        }
     }
 }
 
 impl FromStr for LayoutConfig {
     type Err = Box<dyn Error>;
     fn from_str(layout_config_str: &str) -> Result<Self, Self::Err> {
-        let cfg: LayoutConfig = serde_yaml::from_str(layout_config_str)?;
+        let value: Value = serde_yaml::from_str(layout_config_str)?;
+        let value = resolve_value(value, Path::new(""), &mut Vec::new())?;
+        let cfg: LayoutConfig = serde_yaml::from_value(value)?;
         cfg.validate()?;
 
         Ok(cfg)
     }
 }
+
+/// Read a layout config file and resolve its `extends` and `overrides`. `resolved_from` collects the
+/// files read so far (for detecting cycles).
+fn resolve_file(filename: &Path, resolved_from: &mut Vec<PathBuf>) -> Result<Value> {
+    let canonical = fs::canonicalize(filename)
+        .with_context(|| format!("Could not read layout config {}", filename.display()))?;
+    let is_cyclic = resolved_from
+        .iter()
+        .any(|f| fs::canonicalize(f).ok().as_ref() == Some(&canonical));
+    if is_cyclic {
+        return Err(LayoutConfigError::CyclicExtends(filename.display().to_string()).into());
+    }
+    resolved_from.push(filename.to_path_buf());
+
+    let f = File::open(filename)?;
+    let value: Value = serde_yaml::from_reader(f)
+        .with_context(|| format!("Could not parse layout config {}", filename.display()))?;
+
+    resolve_value(
+        value,
+        filename.parent().unwrap_or_else(|| Path::new("")),
+        resolved_from,
+    )
+}
+
+/// Merge a layout config into the one it extends (filename relative to `dir`) and apply its
+/// `overrides`.
+fn resolve_value(mut value: Value, dir: &Path, resolved_from: &mut Vec<PathBuf>) -> Result<Value> {
+    let extends = value.as_mapping_mut().and_then(|m| m.remove("extends"));
+    if let Some(extends) = extends {
+        let base_filename = extends.as_str().ok_or(LayoutConfigError::InvalidExtends)?;
+        let base = resolve_file(&dir.join(base_filename), resolved_from)?;
+        value = merge(base, value);
+    }
+
+    apply_overrides(&mut value)?;
+
+    Ok(value)
+}
+
+/// Merge the settings of `value` into `base`. Maps are merged recursively, all other values replace
+/// those of `base`.
+fn merge(base: Value, value: Value) -> Value {
+    match (base, value) {
+        (Value::Mapping(mut base), Value::Mapping(value)) => {
+            for (k, v) in value {
+                match base.get_mut(&k) {
+                    Some(base_v) => *base_v = merge(std::mem::take(base_v), v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+            Value::Mapping(base)
+        }
+        (_, value) => value,
+    }
+}
+
+/// Apply (and remove) the `overrides` of the `keyboard` and `base_layout` sections.
+fn apply_overrides(value: &mut Value) -> Result<()> {
+    let matrix_positions: Vec<Value> = value
+        .get("keyboard")
+        .and_then(|k| k.get("matrix_positions"))
+        .and_then(|m| m.as_sequence())
+        .map(|rows| {
+            rows.iter()
+                .filter_map(|row| row.as_sequence())
+                .flatten()
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for (section_name, settings) in [
+        ("keyboard", KEYBOARD_OVERRIDE_SETTINGS.as_slice()),
+        ("base_layout", BASE_LAYOUT_OVERRIDE_SETTINGS.as_slice()),
+    ] {
+        let section = match value.get_mut(section_name).and_then(|s| s.as_mapping_mut()) {
+            Some(section) => section,
+            None => continue,
+        };
+        let overrides = match section.remove("overrides") {
+            Some(Value::Sequence(overrides)) => overrides,
+            Some(_) => {
+                return Err(LayoutConfigError::InvalidOverride(section_name.into(), 0).into())
+            }
+            None => continue,
+        };
+
+        for (i, key_override) in overrides.into_iter().enumerate() {
+            let mut key_override = match key_override {
+                Value::Mapping(key_override) => key_override,
+                _ => return Err(LayoutConfigError::InvalidOverride(section_name.into(), i).into()),
+            };
+            let matrix_position = key_override
+                .remove("matrix_position")
+                .ok_or_else(|| LayoutConfigError::InvalidOverride(section_name.into(), i))?;
+            let key_idx = matrix_positions
+                .iter()
+                .position(|mp| mp == &matrix_position)
+                .ok_or_else(|| {
+                    LayoutConfigError::UnknownMatrixPosition(
+                        section_name.into(),
+                        i,
+                        format_flow(&matrix_position),
+                    )
+                })?;
+
+            apply_override(section, section_name, i, key_idx, key_override, settings)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the settings of the key with index `key_idx` in the (nested) lists of a section.
+fn apply_override(
+    section: &mut Mapping,
+    section_name: &str,
+    override_idx: usize,
+    key_idx: usize,
+    key_override: Mapping,
+    settings: &[(&str, &str)],
+) -> Result<()> {
+    for (setting, v) in key_override {
+        let setting = setting.as_str().unwrap_or_default().to_string();
+        let list_name = settings
+            .iter()
+            .find(|(s, _)| *s == setting)
+            .map(|(_, l)| *l)
+            .ok_or_else(|| {
+                LayoutConfigError::UnknownOverrideSetting(
+                    section_name.into(),
+                    override_idx,
+                    setting.clone(),
+                )
+            })?;
+
+        let target = section
+            .get_mut(list_name)
+            .and_then(|l| l.as_sequence_mut())
+            .and_then(|rows| {
+                rows.iter_mut()
+                    .filter_map(|row| row.as_sequence_mut())
+                    .flatten()
+                    .nth(key_idx)
+            })
+            .ok_or_else(|| {
+                LayoutConfigError::MissingOverrideTarget(
+                    section_name.into(),
+                    override_idx,
+                    list_name.into(),
+                )
+            })?;
+        *target = v;
+    }
+
+    Ok(())
+}
+
+/// Format a (nested) list of values on a single line, e.g. `[5, 1]`
+fn format_flow(value: &Value) -> String {
+    match value {
+        Value::Sequence(values) => {
+            let values: Vec<String> = values.iter().map(format_flow).collect();
+            format!("[{}]", values.join(", "))
+        }
+        value => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
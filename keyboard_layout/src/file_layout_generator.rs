@@ -0,0 +1,156 @@
+//! This module provides layout definition files, which list the symbols of all layers of each key,
+//! and a layout generator based on them.
+//!
+//! A layout file replaces the `keys` (and optionally the `modifiers`) of a layout config's
+//! `base_layout`. All other settings (e.g. `fixed_keys`, `permuted_layers`, combos) are taken from the
+//! layout config.
+
+use crate::key::Hand;
+use crate::keyboard::Keyboard;
+use crate::layout::{LayerModifierLocations, Layout};
+use crate::layout_generator::LayoutGenerator;
+use crate::layout_symmetry::LayoutSymmetry;
+use crate::neo_layout_generator::BaseLayoutYAML;
+use crate::slot_layout_generator::SlotLayoutGenerator;
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::Result;
+use serde::Deserialize;
+use std::{fs::File, sync::Arc};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LayoutFileError {
+    #[error("Invalid layout file: It has {0} keys, but the layout config has {1}")]
+    WrongKeyNumber(usize, usize),
+    #[error("Invalid layout file: Non-fixed key {0} has no symbol in its first layer")]
+    MissingBaseSymbol(usize),
+    #[error("Invalid layout file: Duplicate symbols in the first layer of non-fixed keys: '{0}'")]
+    DuplicateBaseSymbols(String),
+}
+
+/// A layout with the symbols of all layers of each key.
+///
+/// Corresponds to a YAML layout file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LayoutFileYAML {
+    /// Symbols of the layers of each key (in the order of the `keys` of the `base_layout`)
+    pub keys: Vec<Vec<Vec<String>>>,
+    /// Modifiers of the layout's layers (default: those of the `base_layout`)
+    #[serde(default)]
+    pub modifiers: Option<Vec<AHashMap<Hand, LayerModifierLocations>>>,
+}
+
+impl LayoutFileYAML {
+    /// Read a [`LayoutFileYAML`] from a YAML file
+    pub fn from_yaml_file(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        Ok(serde_yaml::from_reader(f)?)
+    }
+
+    /// Replace the `keys` (and `modifiers`, if given) of a `base_layout` with those of the layout file.
+    pub fn apply(self, mut base: BaseLayoutYAML) -> Result<BaseLayoutYAML> {
+        let flat_keys: Vec<&Vec<String>> = self.keys.iter().flatten().collect();
+        let flat_fixed_keys: Vec<bool> = base.fixed_keys.concat();
+        if flat_keys.len() != flat_fixed_keys.len() {
+            return Err(
+                LayoutFileError::WrongKeyNumber(flat_keys.len(), flat_fixed_keys.len()).into(),
+            );
+        }
+
+        // the base layer symbols of non-fixed keys make up the layout string
+        let mut seen = AHashSet::default();
+        let mut duplicates = String::new();
+        for (i, (key_layers, fixed)) in flat_keys.iter().zip(flat_fixed_keys.iter()).enumerate() {
+            if *fixed {
+                continue;
+            }
+            let c = key_layers
+                .first()
+                .and_then(|s| s.chars().next())
+                .ok_or(LayoutFileError::MissingBaseSymbol(i))?;
+            if !seen.insert(c) {
+                duplicates.push(c);
+            }
+        }
+        if !duplicates.is_empty() {
+            return Err(LayoutFileError::DuplicateBaseSymbols(duplicates).into());
+        }
+
+        base.keys = self.keys;
+        if let Some(modifiers) = self.modifiers {
+            base.modifiers = modifiers;
+        }
+
+        Ok(base)
+    }
+}
+
+/// Provides the layout of a layout file (see [`LayoutFileYAML`]).
+///
+/// As generator, it permutes the layout file's symbols like the [`SlotLayoutGenerator`] permutes those
+/// of the `base_layout`. Its [`LayoutGenerator::base_layout_string`] corresponds to the layout of the
+/// layout file, e.g. for starting optimizations from it.
+#[derive(Clone, Debug)]
+pub struct FileLayoutGenerator {
+    generator: SlotLayoutGenerator,
+    layout_str: String,
+}
+
+impl FileLayoutGenerator {
+    /// Generate a [`FileLayoutGenerator`] from a [`LayoutFileYAML`] object and the `base_layout` it
+    /// belongs to
+    pub fn from_object(
+        layout: LayoutFileYAML,
+        base: BaseLayoutYAML,
+        keyboard: Arc<Keyboard>,
+    ) -> Result<Self> {
+        let base = layout.apply(base)?;
+        base.validate()?;
+        let generator = SlotLayoutGenerator::from_object(base, keyboard);
+        let layout_str = generator.base_layout_string();
+
+        Ok(FileLayoutGenerator {
+            generator,
+            layout_str,
+        })
+    }
+
+    /// Generate a [`FileLayoutGenerator`] from a layout file and the `base_layout` it belongs to
+    pub fn from_yaml_file(
+        filename: &str,
+        base: BaseLayoutYAML,
+        keyboard: Arc<Keyboard>,
+    ) -> Result<Self> {
+        let layout = LayoutFileYAML::from_yaml_file(filename)?;
+        FileLayoutGenerator::from_object(layout, base, keyboard)
+    }
+
+    /// The layout string of the layout file's layout
+    pub fn layout_string(&self) -> &str {
+        &self.layout_str
+    }
+
+    /// Generate the layout file's [`Layout`]
+    pub fn layout(&self) -> Result<Layout> {
+        self.generator.generate(&self.layout_str)
+    }
+}
+
+impl LayoutGenerator for FileLayoutGenerator {
+    fn generate(&self, layout_keys: &str) -> Result<Layout> {
+        self.generator.generate(layout_keys)
+    }
+
+    fn base_layout_string(&self) -> String {
+        self.layout_str.clone()
+    }
+
+    fn permutation_segments(&self) -> Vec<usize> {
+        self.generator.permutation_segments()
+    }
+
+    fn symmetry(&self) -> LayoutSymmetry {
+        self.generator.symmetry()
+    }
+}
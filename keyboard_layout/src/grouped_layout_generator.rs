@@ -1,7 +1,8 @@
-use crate::key::Hand;
+use crate::key::{Hand, MatrixPosition};
 use crate::keyboard::Keyboard;
-use crate::layout::{LayerModifierLocations, Layout};
+use crate::layout::{ContextKey, LayerModifierLocations, Layout};
 use crate::layout_generator::LayoutGenerator;
+use crate::layout_symmetry::LayoutSymmetry;
 use crate::neo_layout_generator::BaseLayoutYAML;
 
 use ahash::{AHashMap, AHashSet};
@@ -26,6 +27,9 @@ pub struct GroupedLayoutGenerator {
     permutable_key_map: AHashMap<char, (u8, u8)>,
     grouped_layers: u8,
     modifiers: Vec<AHashMap<Hand, LayerModifierLocations>>,
+    combos: Vec<Vec<MatrixPosition>>,
+    context_keys: Vec<ContextKey>,
+    compose: AHashMap<char, String>,
     keyboard: Arc<Keyboard>,
 }
 
@@ -33,12 +37,11 @@ impl GroupedLayoutGenerator {
     /// Generate a [`GroupedLayoutGenerator`] from a [`BaseLayoutYAML`] object
     pub fn from_object(base: BaseLayoutYAML, keyboard: Arc<Keyboard>) -> Self {
         let base_layout_symbols: Vec<Vec<char>> = base
-            .keys
-            .iter()
-            .flatten()
+            .key_symbols()
             .map(|layers| layers.iter().filter_map(|l| l.chars().next()).collect())
             .collect();
-        let fixed_keys: Vec<bool> = base.fixed_keys.iter().flatten().cloned().collect();
+        let fixed_keys: Vec<bool> = base.flat_fixed_keys();
+        let combos = base.combo_positions();
 
         let mut permutable_key_map: AHashMap<char, (u8, u8)> = AHashMap::default();
         base_layout_symbols
@@ -66,6 +69,9 @@ impl GroupedLayoutGenerator {
             permutable_key_map,
             grouped_layers: base.grouped_layers,
             modifiers: base.modifiers,
+            combos,
+            context_keys: base.context_keys,
+            compose: base.compose,
             keyboard,
         }
     }
@@ -150,6 +156,9 @@ impl GroupedLayoutGenerator {
             self.fixed_keys.clone(),
             self.keyboard.clone(),
             self.modifiers.clone(),
+            self.combos.clone(),
+            self.context_keys.clone(),
+            self.compose.clone(),
         )
     }
 }
@@ -183,4 +192,33 @@ impl LayoutGenerator for GroupedLayoutGenerator {
 
         self.generate_unchecked(layout_keys)
     }
+
+    fn base_layout_string(&self) -> String {
+        self.base_layout_symbols
+            .iter()
+            .zip(self.fixed_keys.iter())
+            .filter(|(_key_layers, fixed)| !**fixed)
+            .filter_map(|(key_layers, _fixed)| key_layers.first())
+            .collect()
+    }
+
+    /// Each group of layers is mirrored separately
+    fn symmetry(&self) -> LayoutSymmetry {
+        let non_fixed: Vec<usize> = self
+            .fixed_keys
+            .iter()
+            .enumerate()
+            .filter(|(_i, fixed)| !**fixed)
+            .map(|(i, _fixed)| i)
+            .collect();
+        let n_groups = self
+            .permutable_key_map
+            .len()
+            .div_ceil(non_fixed.len().max(1));
+        let slots: Vec<(usize, u8)> = (0..n_groups)
+            .flat_map(|group| non_fixed.iter().map(move |i| (*i, group as u8)))
+            .collect();
+
+        LayoutSymmetry::new(&self.keyboard, &slots)
+    }
 }
@@ -1,12 +1,12 @@
 //! This module provides structs for representing physical properties of keys in a keyboard
 
 use ahash::AHashMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::slice;
 
 /// Row and columnar location on the keyboard
-#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Hash, Debug)]
 pub struct MatrixPosition(
     /// Index of column
     pub u8,
@@ -39,7 +39,7 @@ impl Default for Position {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Debug)]
 #[repr(u8)]
 pub enum Finger {
     Thumb,  // 0
@@ -63,7 +63,7 @@ impl Finger {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Debug)]
 #[repr(u8)]
 pub enum Hand {
     Left,  // 0
@@ -86,6 +86,36 @@ impl Hand {
     }
 }
 
+/// Direction in which a key of a cluster (keys around a finger's resting position, e.g. on a
+/// Svalboard) is pressed. Thumb clusters use the roles of the thumb instead of compass directions.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Debug)]
+pub enum KeyDirection {
+    North,
+    South,
+    East,
+    West,
+    Center,
+    /// Thumb: pressed with the pad of the thumb
+    Pad,
+    /// Thumb: pressed with the nail of the thumb
+    Nail,
+    /// Thumb: pressed with the knuckle of the thumb
+    Knuckle,
+    /// Thumb: pressed downwards
+    Down,
+    /// Thumb: pressed upwards
+    Up,
+}
+
+/// Membership of a key in a cluster of keys
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash, Debug)]
+pub struct ClusterPosition(
+    /// Index of the cluster
+    pub u8,
+    /// Direction of the key within the cluster
+    pub KeyDirection,
+);
+
 /// A map that associates each hand with a value
 #[derive(Clone, Debug)]
 pub struct HandMap<T: Copy>([T; 2]);
@@ -315,4 +345,7 @@ pub struct Key {
 
     /// How strongly does the hand need to move away from the home row (start position) horizontally and vertically
     pub unbalancing: Position,
+
+    /// Cluster of keys (e.g. of a Svalboard) the key belongs to, if the keyboard declares any
+    pub cluster: Option<ClusterPosition>,
 }
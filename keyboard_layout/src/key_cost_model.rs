@@ -0,0 +1,167 @@
+//! This module provides a model deriving the key costs and unbalancing positions of a keyboard from
+//! its geometry instead of hand-entered tables.
+//!
+//! The cost of a key grows with the distance of the key from the resting position of the finger
+//! pressing it (`finger_resting_positions`). Each direction of movement (up, down, inwards, outwards)
+//! has its own cost per key unit in the finger's profile. All costs of a finger are divided by its
+//! strength. The unbalancing position of a key is its offset from the resting position (thumb keys
+//! are never unbalancing).
+//!
+//! The model is applied at load time if a keyboard config contains a `key_cost_model`:
+//!
+//! ```yaml
+//! keyboard:
+//!   key_cost_model:
+//!     unit: 50
+//!     fingers:
+//!       Pinky: {home_cost: 5, up: 12, down: 12, inwards: 6, outwards: 6}
+//!     strength:
+//!       Right:
+//!         Ring: 0.7  # weakened right ring finger
+//! ```
+
+use crate::key::{Finger, Hand, HandFingerMap, Position};
+use crate::keyboard::Keyboard;
+
+use ahash::AHashMap;
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs::File;
+
+/// Costs of a finger for its home key and for moving away from it
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct FingerProfile {
+    /// Cost of the finger's home key
+    pub home_cost: f64,
+    /// Additional cost per key unit of movement upwards
+    pub up: f64,
+    /// Additional cost per key unit of movement downwards
+    pub down: f64,
+    /// Additional cost per key unit of movement towards the other hand
+    pub inwards: f64,
+    /// Additional cost per key unit of movement away from the other hand
+    pub outwards: f64,
+}
+
+impl FingerProfile {
+    /// The default profile of a finger
+    pub fn default_for(finger: Finger) -> Self {
+        let (home_cost, horizontal, vertical) = match finger {
+            Finger::Thumb => (3.0, 3.0, 3.0),
+            Finger::Index => (2.0, 6.0, 6.0),
+            Finger::Middle => (2.0, 8.0, 4.0),
+            Finger::Ring => (3.0, 8.0, 7.0),
+            Finger::Pinky => (5.0, 6.0, 12.0),
+        };
+        Self {
+            home_cost,
+            up: vertical,
+            down: vertical,
+            inwards: horizontal,
+            outwards: horizontal,
+        }
+    }
+}
+
+/// A model for deriving key costs and unbalancing positions from the keys' positions.
+///
+/// Corresponds to (parts of) a YAML configuration file.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct KeyCostModel {
+    /// Distance between neighboring keys in the keyboard's `positions` (default: the smallest distance
+    /// between two keys)
+    pub unit: Option<f64>,
+    /// Profiles of the fingers of both hands (fingers that are not listed have their default profile)
+    pub fingers: AHashMap<Finger, FingerProfile>,
+    /// Strength of single fingers (default: 1.0). All costs of a finger are divided by its strength
+    pub strength: AHashMap<Hand, AHashMap<Finger, f64>>,
+}
+
+/// Round to one decimal place (avoiding negative zeros)
+fn round_decimal(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0 + 0.0
+}
+
+/// Round to multiples of 0.5 with ties towards zero (avoiding negative zeros)
+fn round_half(v: f64) -> f64 {
+    ((2.0 * v.abs() - 0.5).ceil().max(0.0) / 2.0).copysign(v) + 0.0
+}
+
+impl KeyCostModel {
+    /// Read a [`KeyCostModel`] from a YAML file
+    pub fn from_yaml_file(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        Ok(serde_yaml::from_reader(f)?)
+    }
+
+    /// The profile of a finger
+    pub fn profile(&self, finger: Finger) -> FingerProfile {
+        self.fingers
+            .get(&finger)
+            .copied()
+            .unwrap_or_else(|| FingerProfile::default_for(finger))
+    }
+
+    /// The strength of a finger
+    pub fn strength(&self, hand: Hand, finger: Finger) -> f64 {
+        self.strength
+            .get(&hand)
+            .and_then(|fingers| fingers.get(&finger))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// The cost of a key with the given offset (in key units, `y` pointing downwards) from the resting
+    /// position of the finger pressing it
+    pub fn key_cost(&self, hand: Hand, finger: Finger, offset: (f64, f64)) -> f64 {
+        let profile = self.profile(finger);
+        let (dx, dy) = offset;
+        let inwards = match hand {
+            Hand::Left => dx,
+            Hand::Right => -dx,
+        };
+        let horizontal = if inwards > 0.0 {
+            profile.inwards * inwards
+        } else {
+            -profile.outwards * inwards
+        };
+        let vertical = if dy > 0.0 {
+            profile.down * dy
+        } else {
+            -profile.up * dy
+        };
+
+        round_decimal((profile.home_cost + horizontal + vertical) / self.strength(hand, finger))
+    }
+
+    /// The unbalancing position of a key with the given offset (in key units, `y` pointing downwards)
+    /// from the resting position of the finger pressing it
+    pub fn unbalancing(&self, finger: Finger, offset: (f64, f64)) -> (f64, f64) {
+        match finger {
+            Finger::Thumb => (0.0, 0.0),
+            _ => (round_half(offset.0), round_half(-offset.1)),
+        }
+    }
+
+    /// The distance between neighboring keys in the `positions` of a keyboard
+    fn unit(&self, keyboard: &Keyboard) -> f64 {
+        self.unit.unwrap_or_else(|| keyboard.key_spacing())
+    }
+
+    /// Replace the costs and unbalancing positions of all keys of a keyboard with those of the model
+    pub fn apply(&self, keyboard: &mut Keyboard) {
+        let unit = self.unit(keyboard);
+        let resting_positions: HandFingerMap<Position> = keyboard.finger_resting_positions;
+        for key in keyboard.keys.iter_mut() {
+            let rest = resting_positions.get(&key.hand, &key.finger);
+            let offset = (
+                (key.position.0 - rest.0) / unit,
+                (key.position.1 - rest.1) / unit,
+            );
+            key.cost = self.key_cost(key.hand, key.finger, offset);
+            let (x, y) = self.unbalancing(key.finger, offset);
+            key.unbalancing = Position(x, y);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! This module provides a struct representing a keyboard.
 
-use crate::key::{Finger, Hand, HandFingerMap, Key, MatrixPosition, Position};
+use crate::key::{ClusterPosition, Finger, Hand, HandFingerMap, Key, MatrixPosition, Position};
+use crate::key_cost_model::KeyCostModel;
 
 use ahash::{AHashMap, AHashSet};
 use anyhow::Result;
@@ -16,6 +17,30 @@ pub enum KeyboardError {
     DuplicateMatrixPositions,
     #[error("Invalid keyboard: Duplicate `positions`.")]
     DuplicatePositions,
+    #[error("Invalid keyboard: Duplicate directions within a cluster in `clusters`.")]
+    DuplicateClusterPositions,
+    #[error("Invalid keyboard: The `key_cost_model` requires `finger_resting_positions` of the {0:?} {1:?}.")]
+    MissingRestingPosition(Hand, Finger),
+}
+
+/// The smallest distance between two keys (1.0 if there are no two keys at distinct positions)
+fn smallest_key_distance(keys: &[Key]) -> f64 {
+    let distance = keys
+        .iter()
+        .enumerate()
+        .flat_map(|(i, k1)| {
+            keys[i + 1..]
+                .iter()
+                .map(|k2| k1.position.distance(&k2.position))
+        })
+        .filter(|d| *d > 0.0)
+        .fold(f64::INFINITY, f64::min);
+
+    if distance.is_finite() {
+        distance
+    } else {
+        1.0
+    }
 }
 
 /// The index of a [`Key`] in the `keys` vec of a [`Keyboard`]
@@ -27,6 +52,7 @@ pub struct Keyboard {
     /// The keys of the keyboard
     pub keys: Vec<Key>,
     pub finger_resting_positions: HandFingerMap<Position>,
+    key_spacing: f64,
     plot_template: String,
     plot_template_short: String,
 }
@@ -40,10 +66,21 @@ pub struct KeyboardYAML {
     positions: Vec<Vec<Position>>,
     hands: Vec<Vec<Hand>>,
     fingers: Vec<Vec<Finger>>,
+    /// Cost of each key (may be omitted if there is a `key_cost_model`)
+    #[serde(default)]
     key_costs: Vec<Vec<f64>>,
     symmetries: Vec<Vec<u8>>,
+    /// Unbalancing position of each key (may be omitted if there is a `key_cost_model`)
+    #[serde(default)]
     unbalancing_positions: Vec<Vec<Position>>,
     finger_resting_positions: AHashMap<Hand, AHashMap<Finger, Position>>,
+    /// Model replacing the `key_costs` and `unbalancing_positions` with values derived from the
+    /// positions of the keys
+    #[serde(default)]
+    key_cost_model: Option<KeyCostModel>,
+    /// Cluster and direction of each key (`~` for keys outside of any cluster)
+    #[serde(default)]
+    clusters: Option<Vec<Vec<Option<ClusterPosition>>>>,
     plot_template: String,
     plot_template_short: String,
 }
@@ -74,9 +111,14 @@ impl KeyboardYAML {
         lengths.insert(flat_positions.len());
         lengths.insert(self.hands.concat().len());
         lengths.insert(self.fingers.concat().len());
-        lengths.insert(self.key_costs.concat().len());
         lengths.insert(self.symmetries.concat().len());
-        lengths.insert(self.unbalancing_positions.concat().len());
+        if self.key_cost_model.is_none() {
+            lengths.insert(self.key_costs.concat().len());
+            lengths.insert(self.unbalancing_positions.concat().len());
+        }
+        if let Some(clusters) = &self.clusters {
+            lengths.insert(clusters.concat().len());
+        }
         if lengths.len() > 1 {
             return Err(KeyboardError::WrongKeyNumber.into());
         }
@@ -91,14 +133,61 @@ impl KeyboardYAML {
             return Err(KeyboardError::DuplicatePositions.into());
         }
 
+        // Make sure the fingers pressing keys have resting positions if the key costs are derived from them.
+        if self.key_cost_model.is_some() {
+            for (hand, finger) in self.hands.concat().iter().zip(self.fingers.concat().iter()) {
+                let has_resting_position = self
+                    .finger_resting_positions
+                    .get(hand)
+                    .is_some_and(|fingers| fingers.contains_key(finger));
+                if !has_resting_position {
+                    return Err(KeyboardError::MissingRestingPosition(*hand, *finger).into());
+                }
+            }
+        }
+
+        // Make sure there are no duplicate directions within a cluster.
+        if let Some(clusters) = &self.clusters {
+            let flat_clusters: Vec<ClusterPosition> =
+                clusters.concat().into_iter().flatten().collect();
+            if contains_duplicates(&flat_clusters) {
+                return Err(KeyboardError::DuplicateClusterPositions.into());
+            }
+        }
+
         Ok(())
     }
+
+    /// The model for deriving the key costs and unbalancing positions, if any
+    pub fn key_cost_model(&self) -> Option<&KeyCostModel> {
+        self.key_cost_model.as_ref()
+    }
+
+    /// The number of keys in each row of the keyboard's lists
+    pub fn row_lengths(&self) -> Vec<usize> {
+        self.matrix_positions.iter().map(|row| row.len()).collect()
+    }
 }
 
 impl Keyboard {
     /// Generate a [`Keyboard`] from a [`KeyboardYAML`] object
     pub fn from_yaml_object(k: KeyboardYAML) -> Self {
-        let keys = k
+        let n_keys = k.hands.iter().map(|row| row.len()).sum();
+        let clusters = k
+            .clusters
+            .map(|clusters| clusters.into_iter().flatten().collect())
+            .unwrap_or_else(|| vec![None; n_keys]);
+        // the key costs and unbalancing positions of a `key_cost_model` are filled in below
+        let key_costs: Vec<f64> = match k.key_costs.is_empty() {
+            true => vec![0.0; n_keys],
+            false => k.key_costs.into_iter().flatten().collect(),
+        };
+        let unbalancing_positions: Vec<Position> = match k.unbalancing_positions.is_empty() {
+            true => vec![Position::default(); n_keys],
+            false => k.unbalancing_positions.into_iter().flatten().collect(),
+        };
+
+        let keys: Vec<Key> = k
             .hands
             .into_iter()
             .flatten()
@@ -106,12 +195,16 @@ impl Keyboard {
             .zip(k.matrix_positions.into_iter().flatten())
             .zip(k.positions.into_iter().flatten())
             .zip(k.symmetries.into_iter().flatten())
-            .zip(k.key_costs.into_iter().flatten())
-            .zip(k.unbalancing_positions.into_iter().flatten())
+            .zip(key_costs)
+            .zip(unbalancing_positions)
+            .zip(clusters)
             .map(
                 |(
-                    (((((hand, finger), matrix_position), position), symmetry_index), cost),
-                    unbalancing,
+                    (
+                        (((((hand, finger), matrix_position), position), symmetry_index), cost),
+                        unbalancing,
+                    ),
+                    cluster,
                 )| Key {
                     hand,
                     finger,
@@ -120,11 +213,13 @@ impl Keyboard {
                     symmetry_index,
                     cost,
                     unbalancing,
+                    cluster,
                 },
             )
             .collect();
 
-        Keyboard {
+        let mut keyboard = Keyboard {
+            key_spacing: smallest_key_distance(&keys),
             keys,
             finger_resting_positions: HandFingerMap::with_hashmap(
                 &k.finger_resting_positions,
@@ -132,7 +227,12 @@ impl Keyboard {
             ),
             plot_template: k.plot_template,
             plot_template_short: k.plot_template_short,
+        };
+        if let Some(model) = &k.key_cost_model {
+            model.apply(&mut keyboard);
         }
+
+        keyboard
     }
 
     /// Generate a [`Keyboard`] from a YAML file
@@ -178,6 +278,12 @@ impl Keyboard {
             .unwrap()
     }
 
+    /// The distance between neighboring keys in the `positions` of the keyboard (the smallest
+    /// distance between two keys)
+    pub fn key_spacing(&self) -> f64 {
+        self.key_spacing
+    }
+
     pub fn estimated_finger_loads(&self, exclude_thumbs: bool) -> HandFingerMap<f64> {
         let mut intended_loads: HandFingerMap<f64> = HandFingerMap::with_default(0.0);
 
@@ -0,0 +1,640 @@
+//! This module provides a generator for keyboard configs (the `keyboard` section of a layout config).
+//! Their input is either a compact description of a (column-staggered) keyboard or a KLE
+//! ([keyboard-layout-editor](http://www.keyboard-layout-editor.com)) JSON file.
+//!
+//! The generator places the keys and assigns hands and fingers. Each key is pressed by the finger
+//! whose home key is closest horizontally. Key costs and unbalancing positions (with the default
+//! [`KeyCostModel`]), symmetries and both plot templates are derived from the geometry. The result is
+//! `validate()`-clean and serves as a starting point for fine-tuning by hand.
+
+use crate::key::{Finger, Hand};
+use crate::key_cost_model::KeyCostModel;
+use crate::keyboard::KeyboardYAML;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::fmt::Write;
+use std::fs::File;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum KeyboardGeneratorError {
+    #[error("Invalid keyboard description: {0}")]
+    InvalidDescription(String),
+    #[error("Invalid KLE file: {0}")]
+    InvalidKle(String),
+    #[error("Invalid keyboard: The {0:?} hand has {1} keys in the home row, but at least 4 are required")]
+    TooFewHomeKeys(Hand, usize),
+}
+
+/// Distance between neighboring keys in the generated `positions` (as in most shipped keyboard configs)
+const POSITION_UNIT: f64 = 50.0;
+
+/// Minimal vertical distance (in key units) below the home row of keys that are pressed by a thumb
+/// (only for KLE files)
+const THUMB_ROW_DISTANCE: f64 = 1.75;
+
+fn default_split_gap() -> f64 {
+    3.0
+}
+
+/// A compact description of a column-staggered (or ortholinear) keyboard.
+///
+/// Corresponds to a YAML keyboard description file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct KeyboardDescription {
+    /// Number of rows (without the thumb keys)
+    pub rows: usize,
+    /// Number of columns of each hand
+    pub columns: usize,
+    /// Row of the home keys (default: `rows / 2`)
+    #[serde(default)]
+    pub home_row: Option<usize>,
+    /// Vertical offset (in key units, positive values downwards) of each column of the left hand from
+    /// the outer to the inner one. Mirrored for the right hand (default: no stagger)
+    #[serde(default)]
+    pub column_stagger: Vec<f64>,
+    /// Number of thumb keys of each hand, placed in a row below the inner columns
+    #[serde(default)]
+    pub thumb_keys: usize,
+    /// Whether the keyboard is split into two halves
+    #[serde(default)]
+    pub split: bool,
+    /// Horizontal gap (in key units) between the halves of a split keyboard
+    #[serde(default = "default_split_gap")]
+    pub split_gap: f64,
+}
+
+/// Settings for generating a keyboard config from a KLE file
+#[derive(Clone, Debug, Default)]
+pub struct KleOptions {
+    /// Row (of the file) of the home keys (default: the middle row)
+    pub home_row: Option<usize>,
+    /// Horizontal coordinate (in key units) separating the keys of the left and right hand (default:
+    /// the center of the keyboard)
+    pub split_x: Option<f64>,
+}
+
+/// A key placed by the generator
+#[derive(Clone, Debug)]
+struct GeneratedKey {
+    matrix_position: (u8, u8),
+    /// Center of the key in key units (`y` pointing downwards)
+    position: (f64, f64),
+    hand: Hand,
+    finger: Option<Finger>,
+    /// Row of the key in the generated lists and in the short plot template
+    row: usize,
+    /// Whether the key lies in the home row
+    home: bool,
+}
+
+impl KeyboardDescription {
+    /// Read a [`KeyboardDescription`] from a YAML file
+    pub fn from_yaml_file(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        Ok(serde_yaml::from_reader(f)?)
+    }
+
+    /// Generate the YAML of a keyboard config (a `keyboard` section) for the described keyboard
+    pub fn generate(&self) -> Result<String> {
+        let home_row = self.home_row.unwrap_or(self.rows / 2);
+        if self.rows == 0 || home_row >= self.rows {
+            return Err(KeyboardGeneratorError::InvalidDescription(format!(
+                "The home row {} does not exist in {} rows",
+                home_row, self.rows
+            ))
+            .into());
+        }
+        if !self.column_stagger.is_empty() && self.column_stagger.len() != self.columns {
+            return Err(KeyboardGeneratorError::InvalidDescription(format!(
+                "`column_stagger` has {} entries, but there are {} columns",
+                self.column_stagger.len(),
+                self.columns
+            ))
+            .into());
+        }
+        if self.thumb_keys > self.columns {
+            return Err(KeyboardGeneratorError::InvalidDescription(
+                "There are more thumb keys than columns".to_string(),
+            )
+            .into());
+        }
+
+        let n = self.columns;
+        let gap = if self.split { self.split_gap } else { 0.0 };
+        let stagger = |c: usize| self.column_stagger.get(c).copied().unwrap_or(0.0);
+        // mirror a horizontal coordinate of the left hand to the right hand
+        let mirror = |x: f64| 2.0 * n as f64 + gap - 1.0 - x;
+
+        // the matrix columns follow the convention of the shipped configs (e.g. 1-6 and 13-18)
+        let mut keys = Vec::new();
+        for row in 0..self.rows {
+            for c in 0..n {
+                keys.push(GeneratedKey {
+                    matrix_position: ((c + 1) as u8, (row + 1) as u8),
+                    position: (c as f64, row as f64 + stagger(c)),
+                    hand: Hand::Left,
+                    finger: None,
+                    row,
+                    home: row == home_row,
+                });
+            }
+            for c in (0..n).rev() {
+                keys.push(GeneratedKey {
+                    matrix_position: ((2 * n + 6 - c) as u8, (row + 1) as u8),
+                    position: (mirror(c as f64), row as f64 + stagger(c)),
+                    hand: Hand::Right,
+                    finger: None,
+                    row,
+                    home: row == home_row,
+                });
+            }
+        }
+
+        // the thumb keys lie below the lowest key, shifted inwards between the columns on split keyboards
+        let thumb_y =
+            self.rows as f64 + self.column_stagger.iter().fold(0.0, |m: f64, s| m.max(*s));
+        let thumb_shift = if gap >= 1.0 { 0.5 } else { 0.0 };
+        let thumb_xs: Vec<f64> = (0..self.thumb_keys)
+            .map(|i| (n - self.thumb_keys + i) as f64 + thumb_shift)
+            .collect();
+        for (i, x) in thumb_xs.iter().enumerate() {
+            keys.push(GeneratedKey {
+                matrix_position: ((n - self.thumb_keys + 2 + i) as u8, (self.rows + 1) as u8),
+                position: (*x, thumb_y),
+                hand: Hand::Left,
+                finger: Some(Finger::Thumb),
+                row: self.rows,
+                home: false,
+            });
+        }
+        for (i, x) in thumb_xs.iter().rev().enumerate() {
+            keys.push(GeneratedKey {
+                matrix_position: ((n + 6 + i) as u8, (self.rows + 1) as u8),
+                position: (mirror(*x), thumb_y),
+                hand: Hand::Right,
+                finger: Some(Finger::Thumb),
+                row: self.rows,
+                home: false,
+            });
+        }
+
+        keyboard_yaml(keys, (2.0 * n as f64 + gap - 1.0) / 2.0)
+    }
+}
+
+/// Generate the YAML of a keyboard config (a `keyboard` section) from the content of a KLE JSON file
+/// ("Download JSON" in the keyboard-layout-editor). Keys in the rows of the file are ordered as in the
+/// file. Decals are skipped.
+pub fn generate_from_kle(data: &str, options: &KleOptions) -> Result<String> {
+    let rows = parse_kle(data)?;
+    if rows.len() > u8::MAX as usize || rows.iter().any(|row| row.len() > u8::MAX as usize) {
+        return Err(KeyboardGeneratorError::InvalidKle("Too many keys".to_string()).into());
+    }
+    let home_row = options.home_row.unwrap_or(rows.len().saturating_sub(1) / 2);
+    if home_row >= rows.len() {
+        return Err(KeyboardGeneratorError::InvalidKle(format!(
+            "The home row {} does not exist in {} rows",
+            home_row,
+            rows.len()
+        ))
+        .into());
+    }
+
+    let (min_x, max_x) = rows
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+            (min.min(p.0), max.max(p.0))
+        });
+    let split_x = options.split_x.unwrap_or((min_x + max_x) / 2.0);
+
+    let mut keys: Vec<GeneratedKey> = rows
+        .iter()
+        .enumerate()
+        .flat_map(|(row, positions)| {
+            positions
+                .iter()
+                .enumerate()
+                .map(move |(i, position)| GeneratedKey {
+                    matrix_position: ((i + 1) as u8, (row + 1) as u8),
+                    position: *position,
+                    hand: if position.0 < split_x {
+                        Hand::Left
+                    } else {
+                        Hand::Right
+                    },
+                    finger: None,
+                    row,
+                    home: row == home_row,
+                })
+        })
+        .collect();
+
+    // keys well below the home row and inwards of the pinky are pressed by the thumbs
+    for hand in [Hand::Left, Hand::Right] {
+        let homes = finger_homes(&keys, hand)?;
+        let pinky_distance = (homes[3].1 .0 - split_x).abs();
+        let home_y = homes.iter().map(|(_, p)| p.1).sum::<f64>() / homes.len() as f64;
+        for key in keys.iter_mut().filter(|k| k.hand == hand && !k.home) {
+            if key.position.1 - home_y >= THUMB_ROW_DISTANCE
+                && (key.position.0 - split_x).abs() < pinky_distance
+            {
+                key.finger = Some(Finger::Thumb);
+            }
+        }
+    }
+
+    keyboard_yaml(keys, split_x)
+}
+
+/// Read the centers (in key units, rotations applied) of the keys in each row of a KLE JSON file
+fn parse_kle(data: &str) -> Result<Vec<Vec<(f64, f64)>>> {
+    let rows: Vec<JsonValue> = serde_json::from_str(data)?;
+
+    // the state is carried over between rows, see https://github.com/ijprest/kle-serial
+    let (mut x, mut y) = (0.0, 0.0);
+    let (mut r, mut rx, mut ry) = (0.0, 0.0, 0.0);
+    let mut res = Vec::new();
+    for row in rows {
+        let items = match row {
+            JsonValue::Array(items) => items,
+            // metadata of the keyboard
+            JsonValue::Object(_) => continue,
+            _ => {
+                return Err(
+                    KeyboardGeneratorError::InvalidKle("Rows need to be lists".to_string()).into(),
+                )
+            }
+        };
+
+        let mut positions = Vec::new();
+        let (mut w, mut h, mut decal) = (1.0, 1.0, false);
+        for item in items {
+            match item {
+                JsonValue::Object(props) => {
+                    let get = |name: &str| props.get(name).and_then(|v| v.as_f64());
+                    if let Some(v) = get("r") {
+                        r = v;
+                    }
+                    if let Some(v) = get("rx") {
+                        rx = v;
+                        (x, y) = (rx, ry);
+                    }
+                    if let Some(v) = get("ry") {
+                        ry = v;
+                        (x, y) = (rx, ry);
+                    }
+                    x += get("x").unwrap_or(0.0);
+                    y += get("y").unwrap_or(0.0);
+                    w = get("w").unwrap_or(w);
+                    h = get("h").unwrap_or(h);
+                    decal = props.get("d").and_then(|v| v.as_bool()).unwrap_or(decal);
+                }
+                JsonValue::String(_) => {
+                    if !decal {
+                        let (sin, cos) = r.to_radians().sin_cos();
+                        let (dx, dy) = (x + w / 2.0 - rx, y + h / 2.0 - ry);
+                        positions.push((rx + dx * cos - dy * sin, ry + dx * sin + dy * cos));
+                    }
+                    x += w;
+                    (w, h, decal) = (1.0, 1.0, false);
+                }
+                _ => {
+                    return Err(KeyboardGeneratorError::InvalidKle(
+                        "Keys need to be strings or objects".to_string(),
+                    )
+                    .into())
+                }
+            }
+        }
+        y += 1.0;
+        x = rx;
+
+        if !positions.is_empty() {
+            res.push(positions);
+        }
+    }
+
+    Ok(res)
+}
+
+/// The home positions of the index, middle, ring and pinky finger of a hand. The index finger rests
+/// on the second innermost key of the home row if there are at least five of them (the innermost one
+/// is a stretch key).
+fn finger_homes(keys: &[GeneratedKey], hand: Hand) -> Result<Vec<(Finger, (f64, f64))>> {
+    let mut home_keys: Vec<(f64, f64)> = keys
+        .iter()
+        .filter(|k| k.hand == hand && k.home)
+        .map(|k| k.position)
+        .collect();
+    // inner keys first
+    home_keys.sort_by(|a, b| match hand {
+        Hand::Left => b.0.total_cmp(&a.0),
+        Hand::Right => a.0.total_cmp(&b.0),
+    });
+    if home_keys.len() < 4 {
+        return Err(KeyboardGeneratorError::TooFewHomeKeys(hand, home_keys.len()).into());
+    }
+
+    let offset = if home_keys.len() >= 5 { 1 } else { 0 };
+    Ok([Finger::Index, Finger::Middle, Finger::Ring, Finger::Pinky]
+        .into_iter()
+        .zip(home_keys[offset..].iter().copied())
+        .collect())
+}
+
+/// Round to one decimal place (avoiding negative zeros)
+fn round_decimal(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0 + 0.0
+}
+
+/// Assign fingers, key costs, unbalancing positions and symmetries (mirrored at `mirror_x`) to the keys
+/// and format the resulting keyboard config as YAML. The config is validated before it is returned.
+fn keyboard_yaml(mut keys: Vec<GeneratedKey>, mirror_x: f64) -> Result<String> {
+    let mut resting_positions = Vec::new();
+    for hand in [Hand::Left, Hand::Right] {
+        let mut homes = finger_homes(&keys, hand)?;
+
+        // the thumb rests on the middle one of its keys
+        let mut thumb_keys: Vec<(f64, f64)> = keys
+            .iter()
+            .filter(|k| k.hand == hand && k.finger == Some(Finger::Thumb))
+            .map(|k| k.position)
+            .collect();
+        thumb_keys.sort_by(|a, b| (a.0 - mirror_x).abs().total_cmp(&(b.0 - mirror_x).abs()));
+        if !thumb_keys.is_empty() {
+            homes.push((Finger::Thumb, thumb_keys[(thumb_keys.len() - 1) / 2]));
+        }
+
+        for key in keys.iter_mut().filter(|k| k.hand == hand) {
+            if key.finger.is_none() {
+                key.finger = homes
+                    .iter()
+                    .filter(|(f, _)| *f != Finger::Thumb)
+                    .min_by(|(_, a), (_, b)| {
+                        (a.0 - key.position.0)
+                            .abs()
+                            .total_cmp(&(b.0 - key.position.0).abs())
+                    })
+                    .map(|(f, _)| *f);
+            }
+        }
+        resting_positions.push((hand, homes));
+    }
+
+    let rest = |key: &GeneratedKey| -> (f64, f64) {
+        resting_positions
+            .iter()
+            .filter(|(hand, _)| *hand == key.hand)
+            .flat_map(|(_, homes)| homes.iter())
+            .find(|(f, _)| Some(*f) == key.finger)
+            .map(|(_, p)| *p)
+            .unwrap_or(key.position)
+    };
+    let offset = |key: &GeneratedKey| -> (f64, f64) {
+        let (rx, ry) = rest(key);
+        (key.position.0 - rx, key.position.1 - ry)
+    };
+    let model = KeyCostModel::default();
+    let costs: Vec<f64> = keys
+        .iter()
+        .map(|k| model.key_cost(k.hand, k.finger.unwrap_or(Finger::Index), offset(k)))
+        .collect();
+    let unbalancing: Vec<(f64, f64)> = keys
+        .iter()
+        .map(|k| model.unbalancing(k.finger.unwrap_or(Finger::Index), offset(k)))
+        .collect();
+
+    // mirrored keys share a symmetry index
+    let mut symmetries: Vec<Option<u8>> = vec![None; keys.len()];
+    let mut next_index: u8 = 1;
+    for i in 0..keys.len() {
+        if symmetries[i].is_some() {
+            continue;
+        }
+        symmetries[i] = Some(next_index);
+        let (x, y) = keys[i].position;
+        let mirrored = (i + 1..keys.len()).find(|j| {
+            symmetries[*j].is_none()
+                && keys[*j].hand != keys[i].hand
+                && (keys[*j].position.0 - (2.0 * mirror_x - x)).abs() < 0.5
+                && (keys[*j].position.1 - y).abs() < 0.5
+        });
+        if let Some(j) = mirrored {
+            symmetries[j] = Some(next_index);
+        }
+        next_index = next_index.saturating_add(1);
+    }
+
+    let row_lengths: Vec<usize> = rows(&keys).iter().map(|row| row.len()).collect();
+    let mut yaml = String::from("keyboard:\n");
+    let mut push_list = |name: &str, values: Vec<String>| {
+        yaml.push_str(&format_list(name, &values, &row_lengths));
+        yaml.push('\n');
+    };
+    let fmt_position = |(x, y): (f64, f64)| {
+        format!(
+            "[{}, {}]",
+            round_decimal(x * POSITION_UNIT),
+            round_decimal(y * POSITION_UNIT)
+        )
+    };
+
+    push_list(
+        "matrix_positions",
+        keys.iter()
+            .map(|k| format!("[{}, {}]", k.matrix_position.0, k.matrix_position.1))
+            .collect(),
+    );
+    push_list(
+        "positions",
+        keys.iter().map(|k| fmt_position(k.position)).collect(),
+    );
+    push_list(
+        "hands",
+        keys.iter().map(|k| format!("{:?}", k.hand)).collect(),
+    );
+    push_list(
+        "fingers",
+        keys.iter()
+            .map(|k| format!("{:?}", k.finger.unwrap_or(Finger::Index)))
+            .collect(),
+    );
+    push_list("key_costs", costs.iter().map(|c| c.to_string()).collect());
+    push_list(
+        "unbalancing_positions",
+        unbalancing
+            .iter()
+            .map(|(x, y)| format!("[{}, {}]", x, y))
+            .collect(),
+    );
+    push_list(
+        "symmetries",
+        symmetries
+            .iter()
+            .map(|s| s.unwrap_or_default().to_string())
+            .collect(),
+    );
+
+    yaml.push_str("  finger_resting_positions:\n");
+    for (hand, homes) in resting_positions.iter() {
+        writeln!(yaml, "    {:?}:", hand).unwrap();
+        // from the outer to the inner fingers, followed by the thumb
+        let (fingers, thumb) = homes.split_at(4.min(homes.len()));
+        for (finger, position) in fingers.iter().rev().chain(thumb.iter()) {
+            writeln!(yaml, "      {:?}: {}", finger, fmt_position(*position)).unwrap();
+        }
+    }
+    yaml.push('\n');
+
+    for (name, template) in [
+        ("plot_template", plot_template(&keys)),
+        ("plot_template_short", plot_template_short(&keys)),
+    ] {
+        writeln!(yaml, "  {}: |2", name).unwrap();
+        for line in template.lines() {
+            writeln!(yaml, "    {}", line).unwrap();
+        }
+        yaml.push('\n');
+    }
+
+    #[derive(Deserialize)]
+    struct KeyboardSection {
+        keyboard: KeyboardYAML,
+    }
+    let generated: KeyboardSection = serde_yaml::from_str(&yaml)?;
+    generated.keyboard.validate()?;
+
+    Ok(yaml)
+}
+
+/// Format a list of a keyboard config (indented as in a `keyboard` section) with one line per row of
+/// keys and right-aligned values
+pub fn format_list(name: &str, values: &[String], row_lengths: &[usize]) -> String {
+    let width = values.iter().map(|v| v.chars().count()).max().unwrap_or(0);
+    let mut list = format!("  {}:\n", name);
+    let mut values = values.iter();
+    for row_length in row_lengths {
+        let row: Vec<String> = values
+            .by_ref()
+            .take(*row_length)
+            .map(|v| format!("{:>width$}", v, width = width))
+            .collect();
+        writeln!(list, "    - [{}]", row.join(", ")).unwrap();
+    }
+    list
+}
+
+/// The keys split into the rows of the generated lists
+fn rows(keys: &[GeneratedKey]) -> Vec<&[GeneratedKey]> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+    for i in 1..=keys.len() {
+        if i == keys.len() || keys[i].row != keys[start].row {
+            rows.push(&keys[start..i]);
+            start = i;
+        }
+    }
+    rows
+}
+
+/// A plot template drawing a box (four characters wide, two lines high) for each key. The keys are
+/// snapped to a grid of half a key unit (keys that would overlap are moved to the right).
+fn plot_template(keys: &[GeneratedKey]) -> String {
+    let min_x = keys
+        .iter()
+        .map(|k| k.position.0)
+        .fold(f64::INFINITY, f64::min);
+    let min_y = keys
+        .iter()
+        .map(|k| k.position.1)
+        .fold(f64::INFINITY, f64::min);
+
+    // character and line of the upper left corner of each key
+    let mut cells: Vec<(usize, usize)> = Vec::with_capacity(keys.len());
+    for key in keys {
+        let mut x = 2 * (2.0 * (key.position.0 - min_x)).round() as usize;
+        let line = (2.0 * (key.position.1 - min_y)).round() as usize;
+        while cells
+            .iter()
+            .any(|(c, l)| c.abs_diff(x) < 4 && l.abs_diff(line) < 2)
+        {
+            x += 2;
+        }
+        cells.push((x, line));
+    }
+
+    let width = cells.iter().map(|(c, _)| c).max().unwrap_or(&0) + 5;
+    let height = cells.iter().map(|(_, l)| l).max().unwrap_or(&0) + 3;
+
+    // borders as segments between neighboring characters
+    let mut horizontal = vec![vec![false; width]; height];
+    let mut vertical = vec![vec![false; width]; height];
+    for (x, l) in cells.iter() {
+        let x = *x;
+        for dx in 0..4 {
+            horizontal[*l][x + dx] = true;
+            horizontal[l + 2][x + dx] = true;
+        }
+        for dy in 0..2 {
+            vertical[l + dy][x] = true;
+            vertical[l + dy][x + 4] = true;
+        }
+    }
+
+    let mut grid: Vec<Vec<String>> = (0..height)
+        .map(|l| {
+            (0..width)
+                .map(|x| {
+                    let left = x > 0 && horizontal[l][x - 1];
+                    let right = horizontal[l][x];
+                    let up = l > 0 && vertical[l - 1][x];
+                    let down = vertical[l][x];
+                    let c = match (up, down, left, right) {
+                        (false, false, false, false) => ' ',
+                        (false, false, _, _) => '─',
+                        (_, _, false, false) => '│',
+                        (false, true, false, true) => '┌',
+                        (false, true, true, false) => '┐',
+                        (true, false, false, true) => '└',
+                        (true, false, true, false) => '┘',
+                        (true, true, false, true) => '├',
+                        (true, true, true, false) => '┤',
+                        (false, true, true, true) => '┬',
+                        (true, false, true, true) => '┴',
+                        (true, true, true, true) => '┼',
+                    };
+                    c.to_string()
+                })
+                .collect()
+        })
+        .collect();
+    for (i, (c, l)) in cells.iter().enumerate() {
+        grid[l + 1][c + 2] = format!("{{{{{}}}}}", i);
+    }
+
+    grid.iter()
+        .map(|line| line.concat().trim_end().to_string() + "\n")
+        .collect()
+}
+
+/// A plot template with one line per row and the hands separated by a space. Note that the short plot
+/// only shows the keys that are not fixed, whose indices need to be adjusted after fixing keys.
+fn plot_template_short(keys: &[GeneratedKey]) -> String {
+    let mut template = String::new();
+    let mut i = 0;
+    for row in rows(keys) {
+        for (j, key) in row.iter().enumerate() {
+            if j > 0 && key.hand != row[j - 1].hand {
+                template.push(' ');
+            }
+            write!(template, "{{{{{}}}}}", i).unwrap();
+            i += 1;
+        }
+        template.push('\n');
+    }
+    template
+}
@@ -0,0 +1,663 @@
+//! The `keymap` module converts layouts to and from firmware keymaps, i.e. QMK `keymap.c` files,
+//! ZMK `.keymap` devicetrees and Vial JSON (`.vil`) files.
+//!
+//! A [`Keymap`] holds a [`KeyAction`] for each layer and firmware key. The firmware keys are related
+//! to the keys of a [`Keyboard`] by a [`FirmwareConfig`] that lists the [`MatrixPosition`] of each
+//! firmware key index (the order of the arguments of QMK's `LAYOUT` macro and ZMK's `bindings`).
+//!
+//! Symbols are translated to keycodes assuming that the host uses a US ANSI layout. Layers that are
+//! reached with a single `Hold` or `OneShot` modifier become momentary and one-shot layer keys.
+//! If the modifier key generates a symbol itself, it becomes a layer-tap key.
+
+pub mod qmk;
+pub mod vial;
+pub mod zmk;
+
+use crate::key::MatrixPosition;
+use crate::keyboard::Keyboard;
+use crate::layout::{LayerModifierType, LayerModifiers, Layout};
+use crate::slot_layout_generator::EMPTY_SLOT;
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::Result;
+use serde::Deserialize;
+use std::{fs::File, str::FromStr};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum KeymapError {
+    #[error("Invalid keymap: {0}")]
+    Parse(String),
+    #[error("Invalid keymap: Layer {0} has {1} keys, but the firmware config lists {2} keys")]
+    WrongKeyNumber(usize, usize, usize),
+    #[error("Invalid keymap: No symbol on layer {1} for the key at matrix position {0:?}")]
+    MissingSymbol(MatrixPosition, u8),
+    #[error("Invalid keymap: The key at matrix position {0:?} has no firmware key index")]
+    UnmappedPosition(MatrixPosition),
+    #[error("Invalid layout slot: The keyboard has no key with index {0} (combos are not supported by keymaps)")]
+    InvalidKeyIndex(usize),
+    #[error("Invalid firmware config: `vial_matrix` is required for Vial keymaps")]
+    MissingVialMatrix,
+    #[error("Unknown keymap format '{0}' (supported: qmk, zmk, vial)")]
+    UnknownFormat(String),
+}
+
+/// The supported firmware keymap formats
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeymapFormat {
+    Qmk,
+    Zmk,
+    Vial,
+}
+
+impl FromStr for KeymapFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "qmk" => Ok(Self::Qmk),
+            "zmk" => Ok(Self::Zmk),
+            "vial" => Ok(Self::Vial),
+            _ => Err(KeymapError::UnknownFormat(s.to_string()).into()),
+        }
+    }
+}
+
+/// The firmware configuration as given in a YAML configuration file.
+///
+/// Keys are grouped in rows (as in the keyboard configuration), which determine the line breaks of
+/// exported keymaps.
+#[derive(Deserialize, Debug)]
+pub struct FirmwareConfigYAML {
+    qmk_layout: String,
+    positions: Vec<Vec<Option<MatrixPosition>>>,
+    #[serde(default)]
+    vial_matrix: Vec<Vec<(usize, usize)>>,
+}
+
+/// Relates the keys of a firmware keymap to the keys of a keyboard (configuration).
+#[derive(Clone, Debug)]
+pub struct FirmwareConfig {
+    /// Name of the `LAYOUT` macro in QMK keymaps
+    pub qmk_layout: String,
+    /// Matrix position (as in the keyboard configuration) of each firmware key index.
+    /// Firmware keys without a counterpart in the keyboard configuration are `None`.
+    pub positions: Vec<Option<MatrixPosition>>,
+    /// Number of keys in each row of exported keymaps
+    pub row_lengths: Vec<usize>,
+    /// Row and column of each firmware key index in the firmware's matrix (only required for Vial)
+    pub vial_matrix: Vec<(usize, usize)>,
+}
+
+impl FirmwareConfig {
+    pub fn from_yaml_object(cfg: FirmwareConfigYAML) -> Self {
+        Self {
+            qmk_layout: cfg.qmk_layout,
+            row_lengths: cfg.positions.iter().map(|row| row.len()).collect(),
+            positions: cfg.positions.concat(),
+            vial_matrix: cfg.vial_matrix.concat(),
+        }
+    }
+
+    pub fn from_yaml(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        let cfg: FirmwareConfigYAML = serde_yaml::from_reader(f)?;
+
+        Ok(Self::from_yaml_object(cfg))
+    }
+
+    /// Firmware key index of each matrix position
+    fn key_indices(&self) -> AHashMap<MatrixPosition, usize> {
+        self.positions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pos)| pos.map(|pos| (pos, i)))
+            .collect()
+    }
+}
+
+/// The behavior of a firmware key on a layer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyAction {
+    /// Generates a symbol
+    Symbol(char),
+    /// Activates a layer (if held or tapped once, depending on the modifier type)
+    Layer(u8, LayerModifierType),
+    /// Activates a layer if held, generates a symbol if tapped
+    LayerTap(u8, char),
+    /// Falls through to the next lower active layer
+    Transparent,
+    /// Does nothing
+    None,
+}
+
+/// A firmware keymap with a [`KeyAction`] for each layer and firmware key index.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    pub layers: Vec<Vec<KeyAction>>,
+}
+
+impl Keymap {
+    /// Assemble the keymap of a [`Layout`].
+    ///
+    /// Layers that require several modifiers at once can not be reached with a single layer key.
+    /// Their symbols are included nevertheless.
+    pub fn from_layout(layout: &Layout, config: &FirmwareConfig) -> Self {
+        let symbol_layerkeys = layout
+            .layerkeys
+            .iter()
+            .filter(|lk| lk.is_modifier.is_none() && lk.symbol != EMPTY_SLOT)
+            // combos and compose sequences are not part of the keymap's layers
+            .filter(|lk| lk.combo_keys.is_empty() && !lk.is_combo_part)
+            .filter(|lk| lk.compose_sequence.is_empty());
+
+        let mut symbols: AHashMap<(MatrixPosition, u8), char> = AHashMap::default();
+        let mut layer_modifiers: AHashMap<MatrixPosition, (u8, LayerModifierType)> =
+            AHashMap::default();
+        for lk in symbol_layerkeys {
+            symbols
+                .entry((lk.key.matrix_position, lk.layer))
+                .or_insert(lk.symbol);
+
+            if lk.layer == 0 {
+                continue;
+            }
+            let (mods, modifier_type) = match &lk.modifiers {
+                LayerModifiers::Hold(mods) => (mods, LayerModifierType::Hold),
+                LayerModifiers::OneShot(mods) => (mods, LayerModifierType::OneShot),
+                LayerModifiers::LongPress => continue,
+            };
+            if let [m] = mods.as_slice() {
+                let pos = layout.get_layerkey(m).key.matrix_position;
+                let entry = layer_modifiers
+                    .entry(pos)
+                    .or_insert((lk.layer, modifier_type));
+                if lk.layer < entry.0 {
+                    *entry = (lk.layer, modifier_type);
+                }
+            }
+        }
+
+        let n_layers = symbols
+            .keys()
+            .map(|(_pos, layer)| *layer as usize + 1)
+            .max()
+            .unwrap_or(1);
+
+        let layers = (0..n_layers as u8)
+            .map(|layer| {
+                config
+                    .positions
+                    .iter()
+                    .map(|pos| {
+                        let pos = match pos {
+                            Some(pos) => pos,
+                            None => return KeyAction::None,
+                        };
+                        let symbol = symbols.get(&(*pos, layer)).cloned();
+                        match (layer_modifiers.get(pos), symbol) {
+                            (Some((mod_layer, modifier_type)), Some(c)) if layer == 0 => {
+                                if modifier_type.is_hold() && is_translatable(c) {
+                                    KeyAction::LayerTap(*mod_layer, c)
+                                } else {
+                                    KeyAction::Layer(*mod_layer, *modifier_type)
+                                }
+                            }
+                            (Some((mod_layer, modifier_type)), None) if layer == 0 => {
+                                KeyAction::Layer(*mod_layer, *modifier_type)
+                            }
+                            // keep modifiers active on their layer
+                            (Some(_), _) => KeyAction::Transparent,
+                            (None, Some(c)) => KeyAction::Symbol(c),
+                            (None, None) => KeyAction::None,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { layers }
+    }
+
+    /// Symbols of the keymap that can not be expressed by keycodes (they are exported as "no key")
+    pub fn untranslatable_symbols(&self) -> Vec<char> {
+        let mut symbols: Vec<char> = self
+            .layers
+            .iter()
+            .flatten()
+            .filter_map(|action| match action {
+                KeyAction::Symbol(c) | KeyAction::LayerTap(_, c) => Some(*c),
+                _ => None,
+            })
+            .filter(|c| !is_translatable(*c))
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        symbols
+    }
+
+    /// Export the keymap in given format. The `title` is added as comment (not for Vial).
+    pub fn export(
+        &self,
+        format: KeymapFormat,
+        config: &FirmwareConfig,
+        title: &str,
+    ) -> Result<String> {
+        self.validate(config)?;
+        match format {
+            KeymapFormat::Qmk => Ok(qmk::to_keymap_c(self, config, title)),
+            KeymapFormat::Zmk => Ok(zmk::to_devicetree(self, config, title)),
+            KeymapFormat::Vial => vial::to_json(self, config),
+        }
+    }
+
+    /// Parse a keymap file of given format.
+    pub fn import(format: KeymapFormat, data: &str, config: &FirmwareConfig) -> Result<Self> {
+        match format {
+            KeymapFormat::Qmk => qmk::parse(data, config),
+            KeymapFormat::Zmk => zmk::parse(data, config),
+            KeymapFormat::Vial => vial::parse(data, config),
+        }
+    }
+
+    /// Checks that each layer has one action per firmware key.
+    fn validate(&self, config: &FirmwareConfig) -> Result<()> {
+        for (layer, actions) in self.layers.iter().enumerate() {
+            if actions.len() != config.positions.len() {
+                return Err(KeymapError::WrongKeyNumber(
+                    layer,
+                    actions.len(),
+                    config.positions.len(),
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate the layout string that corresponds to the keymap. It contains the symbols of the
+    /// given `(key index, layer)` slots, e.g. the base layer of all non-fixed keys.
+    ///
+    /// Where several symbols correspond to the same keycode (e.g. " " and "␣"), the one contained
+    /// in `known_symbols` (usually the symbols of the layout configuration) is used.
+    ///
+    /// Slots without a symbol in the keymap (e.g. keys with `KC_NO` or unsupported keycodes) get the
+    /// symbol of `fallback_symbols` (one per slot, usually those of the layout configuration). Their
+    /// matrix positions and layers are returned along with the layout string.
+    pub fn layout_string(
+        &self,
+        config: &FirmwareConfig,
+        keyboard: &Keyboard,
+        slots: &[(usize, u8)],
+        known_symbols: &AHashSet<char>,
+        fallback_symbols: &[Option<char>],
+    ) -> Result<(String, Vec<(MatrixPosition, u8)>)> {
+        let key_indices = config.key_indices();
+
+        let mut missing = Vec::new();
+        let layout_str = slots
+            .iter()
+            .enumerate()
+            .map(|(slot, (key_idx, layer))| {
+                let pos = keyboard
+                    .keys
+                    .get(*key_idx)
+                    .ok_or(KeymapError::InvalidKeyIndex(*key_idx))?
+                    .matrix_position;
+                let idx = key_indices
+                    .get(&pos)
+                    .ok_or(KeymapError::UnmappedPosition(pos))?;
+                let action = self
+                    .layers
+                    .get(*layer as usize)
+                    .and_then(|actions| actions.get(*idx));
+                match (action, layer) {
+                    (Some(KeyAction::Symbol(c)), _) | (Some(KeyAction::LayerTap(_, c)), 0) => {
+                        Ok(known_symbol(*c, known_symbols))
+                    }
+                    _ => match fallback_symbols.get(slot).cloned().flatten() {
+                        Some(c) => {
+                            missing.push((pos, *layer));
+                            Ok(c)
+                        }
+                        None => Err(KeymapError::MissingSymbol(pos, *layer).into()),
+                    },
+                }
+            })
+            .collect::<Result<String>>()?;
+
+        Ok((layout_str, missing))
+    }
+}
+
+/// Symbols that are shown differently in layout configurations, but generate the same keycode
+/// (symbol, symbol of the keycode)
+const SYMBOL_ALIASES: &[(char, char)] = &[('␣', ' '), ('↵', '\n'), ('⏎', '\n'), ('⇥', '\t')];
+
+/// Unshifted keys of a US ANSI layout (symbol, [QMK keycode, ZMK keycode])
+const KEYCODES: &[(char, [&str; 2])] = &[
+    ('a', ["KC_A", "A"]),
+    ('b', ["KC_B", "B"]),
+    ('c', ["KC_C", "C"]),
+    ('d', ["KC_D", "D"]),
+    ('e', ["KC_E", "E"]),
+    ('f', ["KC_F", "F"]),
+    ('g', ["KC_G", "G"]),
+    ('h', ["KC_H", "H"]),
+    ('i', ["KC_I", "I"]),
+    ('j', ["KC_J", "J"]),
+    ('k', ["KC_K", "K"]),
+    ('l', ["KC_L", "L"]),
+    ('m', ["KC_M", "M"]),
+    ('n', ["KC_N", "N"]),
+    ('o', ["KC_O", "O"]),
+    ('p', ["KC_P", "P"]),
+    ('q', ["KC_Q", "Q"]),
+    ('r', ["KC_R", "R"]),
+    ('s', ["KC_S", "S"]),
+    ('t', ["KC_T", "T"]),
+    ('u', ["KC_U", "U"]),
+    ('v', ["KC_V", "V"]),
+    ('w', ["KC_W", "W"]),
+    ('x', ["KC_X", "X"]),
+    ('y', ["KC_Y", "Y"]),
+    ('z', ["KC_Z", "Z"]),
+    ('1', ["KC_1", "N1"]),
+    ('2', ["KC_2", "N2"]),
+    ('3', ["KC_3", "N3"]),
+    ('4', ["KC_4", "N4"]),
+    ('5', ["KC_5", "N5"]),
+    ('6', ["KC_6", "N6"]),
+    ('7', ["KC_7", "N7"]),
+    ('8', ["KC_8", "N8"]),
+    ('9', ["KC_9", "N9"]),
+    ('0', ["KC_0", "N0"]),
+    (' ', ["KC_SPC", "SPACE"]),
+    ('\n', ["KC_ENT", "RET"]),
+    ('\t', ["KC_TAB", "TAB"]),
+    ('-', ["KC_MINS", "MINUS"]),
+    ('=', ["KC_EQL", "EQUAL"]),
+    ('[', ["KC_LBRC", "LBKT"]),
+    (']', ["KC_RBRC", "RBKT"]),
+    ('\\', ["KC_BSLS", "BSLH"]),
+    (';', ["KC_SCLN", "SEMI"]),
+    ('\'', ["KC_QUOT", "SQT"]),
+    ('`', ["KC_GRV", "GRAVE"]),
+    (',', ["KC_COMM", "COMMA"]),
+    ('.', ["KC_DOT", "DOT"]),
+    ('/', ["KC_SLSH", "SLASH"]),
+    ('⌫', ["KC_BSPC", "BSPC"]),
+    ('⌦', ["KC_DEL", "DEL"]),
+    ('⎋', ["KC_ESC", "ESC"]),
+    ('⇪', ["KC_CAPS", "CAPS"]),
+    ('⇠', ["KC_LEFT", "LEFT"]),
+    ('⇢', ["KC_RGHT", "RIGHT"]),
+    ('⇡', ["KC_UP", "UP"]),
+    ('⇣', ["KC_DOWN", "DOWN"]),
+    ('⇱', ["KC_HOME", "HOME"]),
+    ('⇲', ["KC_END", "END"]),
+    ('⇞', ["KC_PGUP", "PG_UP"]),
+    ('⇟', ["KC_PGDN", "PG_DN"]),
+];
+
+/// Shifted symbols of a US ANSI layout (symbol, unshifted symbol, [QMK keycode, ZMK keycode])
+const SHIFTED_KEYCODES: &[(char, char, [&str; 2])] = &[
+    ('!', '1', ["KC_EXLM", "EXCL"]),
+    ('@', '2', ["KC_AT", "AT"]),
+    ('#', '3', ["KC_HASH", "HASH"]),
+    ('$', '4', ["KC_DLR", "DOLLAR"]),
+    ('%', '5', ["KC_PERC", "PRCNT"]),
+    ('^', '6', ["KC_CIRC", "CARET"]),
+    ('&', '7', ["KC_AMPR", "AMPS"]),
+    ('*', '8', ["KC_ASTR", "STAR"]),
+    ('(', '9', ["KC_LPRN", "LPAR"]),
+    (')', '0', ["KC_RPRN", "RPAR"]),
+    ('_', '-', ["KC_UNDS", "UNDER"]),
+    ('+', '=', ["KC_PLUS", "PLUS"]),
+    ('{', '[', ["KC_LCBR", "LBRC"]),
+    ('}', ']', ["KC_RCBR", "RBRC"]),
+    ('|', '\\', ["KC_PIPE", "PIPE"]),
+    (':', ';', ["KC_COLN", "COLON"]),
+    ('"', '\'', ["KC_DQUO", "DQT"]),
+    ('~', '`', ["KC_TILD", "TILDE"]),
+    ('<', ',', ["KC_LT", "LT"]),
+    ('>', '.', ["KC_GT", "GT"]),
+    ('?', '/', ["KC_QUES", "QMARK"]),
+];
+
+/// The symbol that generates the same keycode as given symbol, but is shown in keycode tables
+fn canonical_symbol(c: char) -> char {
+    SYMBOL_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == c)
+        .map(|(_, canonical)| *canonical)
+        .unwrap_or(c)
+}
+
+/// The symbol of `known_symbols` that generates the same keycode as given symbol (if any)
+fn known_symbol(c: char, known_symbols: &AHashSet<char>) -> char {
+    if known_symbols.contains(&c) {
+        return c;
+    }
+    SYMBOL_ALIASES
+        .iter()
+        .find(|(alias, canonical)| *canonical == c && known_symbols.contains(alias))
+        .map(|(alias, _)| *alias)
+        .unwrap_or(c)
+}
+
+/// Whether a symbol can be expressed by a keycode
+fn is_translatable(c: char) -> bool {
+    Keycodes::Qmk.keycode(c, false).is_some()
+}
+
+/// The keycode names of a firmware
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Keycodes {
+    Qmk = 0,
+    Zmk = 1,
+}
+
+impl Keycodes {
+    /// The keycode for a symbol and whether it needs to be shifted. With `use_aliases`, shifted
+    /// symbols are expressed by their aliases (e.g. `KC_EXLM` instead of shifted `KC_1`) if available.
+    pub(crate) fn keycode(self, c: char, use_aliases: bool) -> Option<(&'static str, bool)> {
+        let c = canonical_symbol(c);
+        if let Some((_, codes)) = KEYCODES.iter().find(|(s, _)| *s == c) {
+            return Some((codes[self as usize], false));
+        }
+        if let Some((_, unshifted, codes)) = SHIFTED_KEYCODES.iter().find(|(s, _, _)| *s == c) {
+            if use_aliases {
+                return Some((codes[self as usize], false));
+            }
+            return self.keycode(*unshifted, false).map(|(kc, _)| (kc, true));
+        }
+        if c.is_ascii_uppercase() {
+            return self
+                .keycode(c.to_ascii_lowercase(), false)
+                .map(|(kc, _)| (kc, true));
+        }
+
+        None
+    }
+
+    /// The symbol generated by a (possibly shifted) keycode or alias
+    pub(crate) fn symbol(self, keycode: &str, shifted: bool) -> Option<char> {
+        if let Some((s, _, _)) = SHIFTED_KEYCODES
+            .iter()
+            .find(|(_, _, codes)| codes[self as usize] == keycode)
+        {
+            return Some(*s);
+        }
+        let c = KEYCODES
+            .iter()
+            .find(|(_, codes)| codes[self as usize] == keycode)?
+            .0;
+        if !shifted {
+            return Some(c);
+        }
+        match SHIFTED_KEYCODES
+            .iter()
+            .find(|(_, unshifted, _)| *unshifted == c)
+        {
+            Some((s, _, _)) => Some(*s),
+            None if c.is_ascii_lowercase() => Some(c.to_ascii_uppercase()),
+            None => None,
+        }
+    }
+}
+
+/// Split a comma separated list at commas that are not enclosed in parentheses
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = s[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+
+    parts
+}
+
+/// Remove C-style comments (as in QMK and ZMK keymaps)
+fn strip_comments(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut rest = s;
+    while !rest.is_empty() {
+        if let Some(stripped) = rest.strip_prefix("//") {
+            rest = stripped.find('\n').map(|i| &stripped[i..]).unwrap_or("");
+        } else if let Some(stripped) = rest.strip_prefix("/*") {
+            rest = stripped
+                .find("*/")
+                .map(|i| &stripped[i + 2..])
+                .unwrap_or("");
+        } else {
+            let c = rest.chars().next().unwrap();
+            res.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LayoutConfig;
+
+    const LAYOUT_CONFIG: &str = "../config/keyboard/crkbd.yml";
+    const FIRMWARE_CONFIG: &str = "../config/firmware/crkbd.yml";
+
+    /// A keymap with each kind of key action on the base layer and a mostly transparent second layer
+    fn keymap(config: &FirmwareConfig) -> Keymap {
+        let n = config.positions.len();
+        let mut base: Vec<KeyAction> = (0..n)
+            .map(|i| KeyAction::Symbol((b'a' + (i % 26) as u8) as char))
+            .collect();
+        base[1] = KeyAction::Symbol('Q');
+        base[2] = KeyAction::Symbol('!');
+        base[3] = KeyAction::Symbol(',');
+        base[4] = KeyAction::Transparent;
+        base[5] = KeyAction::None;
+        base[n - 3] = KeyAction::Layer(1, LayerModifierType::Hold);
+        base[n - 2] = KeyAction::Layer(1, LayerModifierType::OneShot);
+        base[n - 1] = KeyAction::LayerTap(1, ' ');
+
+        let mut upper = vec![KeyAction::Transparent; n];
+        upper[0] = KeyAction::Symbol('1');
+        upper[1] = KeyAction::Symbol('?');
+
+        Keymap {
+            layers: vec![base, upper],
+        }
+    }
+
+    fn assert_round_trip(format: KeymapFormat) {
+        let config = FirmwareConfig::from_yaml(FIRMWARE_CONFIG).unwrap();
+        let keymap = keymap(&config);
+
+        let exported = keymap.export(format, &config, "Test layout").unwrap();
+        let imported = Keymap::import(format, &exported, &config).unwrap();
+
+        assert_eq!(imported.layers, keymap.layers);
+    }
+
+    #[test]
+    fn qmk_round_trip() {
+        assert_round_trip(KeymapFormat::Qmk);
+    }
+
+    #[test]
+    fn zmk_round_trip() {
+        assert_round_trip(KeymapFormat::Zmk);
+    }
+
+    #[test]
+    fn vial_round_trip() {
+        assert_round_trip(KeymapFormat::Vial);
+    }
+
+    #[test]
+    fn layout_string_keeps_fallback_symbols() {
+        let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG).unwrap();
+        let keyboard = Keyboard::from_yaml_object(layout_config.keyboard);
+        let config = FirmwareConfig::from_yaml(FIRMWARE_CONFIG).unwrap();
+        let keymap = keymap(&config);
+
+        let key_indices = config.key_indices();
+        let slot = |firmware_idx: usize| {
+            let key_idx = keyboard
+                .keys
+                .iter()
+                .position(|k| key_indices.get(&k.matrix_position) == Some(&firmware_idx))
+                .unwrap();
+            (key_idx, 0)
+        };
+        let slots = [slot(0), slot(1), slot(5)];
+
+        let (layout_str, missing) = keymap
+            .layout_string(
+                &config,
+                &keyboard,
+                &slots,
+                &AHashSet::default(),
+                &[None, None, Some('ß')],
+            )
+            .unwrap();
+        assert_eq!(layout_str, "aQß");
+        assert_eq!(missing, vec![(config.positions[5].unwrap(), 0)]);
+
+        let res = keymap.layout_string(&config, &keyboard, &slots, &AHashSet::default(), &[]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn split_top_level_ignores_nested_commas() {
+        assert_eq!(
+            split_top_level("KC_A, LT(1, KC_SPC),MT(MOD_LSFT, KC_B) , KC_C,"),
+            vec!["KC_A", "LT(1, KC_SPC)", "MT(MOD_LSFT, KC_B)", "KC_C"]
+        );
+        assert!(split_top_level("  ").is_empty());
+    }
+
+    #[test]
+    fn strip_comments_removes_line_and_block_comments() {
+        let s = "KC_A, // first key\nKC_B, /* second\nkey */ KC_C /* unterminated";
+        assert_eq!(strip_comments(s), "KC_A, \nKC_B,  KC_C ");
+    }
+}
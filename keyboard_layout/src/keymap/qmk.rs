@@ -0,0 +1,211 @@
+//! Export and import of QMK keymaps (`keymap.c` with one `LAYOUT` macro per layer).
+
+use super::{
+    split_top_level, strip_comments, FirmwareConfig, KeyAction, Keycodes, Keymap, KeymapError,
+};
+use crate::layout::LayerModifierType;
+
+use anyhow::Result;
+
+/// The QMK keycode of a symbol (`KC_NO` if there is none)
+fn symbol_keycode(c: char) -> String {
+    match Keycodes::Qmk.keycode(c, true) {
+        Some((kc, false)) => kc.to_string(),
+        Some((kc, true)) => format!("S({})", kc),
+        None => "KC_NO".to_string(),
+    }
+}
+
+/// The QMK keycode of a [`KeyAction`]
+fn keycode(action: &KeyAction) -> String {
+    match action {
+        KeyAction::Symbol(c) => symbol_keycode(*c),
+        KeyAction::Layer(layer, LayerModifierType::OneShot) => format!("OSL({})", layer),
+        KeyAction::Layer(layer, _) => format!("MO({})", layer),
+        // layer-tap keys only support unshifted keycodes
+        KeyAction::LayerTap(layer, c) => match Keycodes::Qmk.keycode(*c, false) {
+            Some((kc, false)) => format!("LT({}, {})", layer, kc),
+            _ => format!("MO({})", layer),
+        },
+        KeyAction::Transparent => "KC_TRNS".to_string(),
+        KeyAction::None => "KC_NO".to_string(),
+    }
+}
+
+/// Resolve a layer given by number or by the name of its designator (e.g. `[_LOWER] = LAYOUT(...)`)
+pub(crate) fn parse_layer(layer: &str, layer_names: &[String]) -> Result<u8> {
+    let layer = layer.trim();
+    if let Ok(n) = layer.parse::<u8>() {
+        return Ok(n);
+    }
+    layer_names
+        .iter()
+        .position(|name| name == layer)
+        .map(|n| n as u8)
+        .ok_or_else(|| KeymapError::Parse(format!("Unknown layer '{}'", layer)).into())
+}
+
+/// Parse a QMK keycode (as in `keymap.c` or Vial files). Unsupported keycodes do nothing.
+pub(crate) fn parse_keycode(code: &str, layer_names: &[String]) -> Result<KeyAction> {
+    let code = code.trim();
+    match code {
+        "KC_TRNS" | "KC_TRANSPARENT" | "_______" => return Ok(KeyAction::Transparent),
+        "KC_NO" | "XXXXXXX" => return Ok(KeyAction::None),
+        _ => {}
+    }
+
+    let (function, args) = match code.split_once('(') {
+        Some((function, args)) => (function.trim(), args.trim_end().trim_end_matches(')')),
+        None => {
+            return Ok(Keycodes::Qmk
+                .symbol(code, false)
+                .map(KeyAction::Symbol)
+                .unwrap_or(KeyAction::None))
+        }
+    };
+
+    let symbol = |kc: &str, shifted: bool| Keycodes::Qmk.symbol(kc.trim(), shifted);
+    let action = match function {
+        "S" | "LSFT" | "RSFT" => symbol(args, true).map(KeyAction::Symbol),
+        "MO" => Some(KeyAction::Layer(
+            parse_layer(args, layer_names)?,
+            LayerModifierType::Hold,
+        )),
+        "OSL" => Some(KeyAction::Layer(
+            parse_layer(args, layer_names)?,
+            LayerModifierType::OneShot,
+        )),
+        "LT" => match split_top_level(args).as_slice() {
+            [layer, kc] => {
+                let layer = parse_layer(layer, layer_names)?;
+                Some(match symbol(kc, false) {
+                    Some(c) => KeyAction::LayerTap(layer, c),
+                    None => KeyAction::Layer(layer, LayerModifierType::Hold),
+                })
+            }
+            _ => return Err(KeymapError::Parse(format!("Invalid keycode '{}'", code)).into()),
+        },
+        // Vial's notation of layer-tap keys, e.g. `LT1(KC_A)`
+        f if f.starts_with("LT") && f[2..].parse::<u8>().is_ok() => {
+            let layer = f[2..].parse::<u8>().unwrap();
+            Some(match symbol(args, false) {
+                Some(c) => KeyAction::LayerTap(layer, c),
+                None => KeyAction::Layer(layer, LayerModifierType::Hold),
+            })
+        }
+        // mod-tap keys generate their keycode if tapped
+        f if f.ends_with("_T") => split_top_level(args)
+            .last()
+            .and_then(|kc| symbol(kc, false))
+            .map(KeyAction::Symbol),
+        _ => None,
+    };
+
+    Ok(action.unwrap_or(KeyAction::None))
+}
+
+/// Generate a QMK `keymap.c` for a [`Keymap`]. The `title` is added as comment at the top.
+pub fn to_keymap_c(keymap: &Keymap, config: &FirmwareConfig, title: &str) -> String {
+    let codes: Vec<Vec<String>> = keymap
+        .layers
+        .iter()
+        .map(|actions| actions.iter().map(keycode).collect())
+        .collect();
+    let width = codes.iter().flatten().map(|c| c.len()).max().unwrap_or(0) + 1;
+
+    let mut res = format!(
+        "// {}\n\n#include QMK_KEYBOARD_H\n\nconst uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {{\n",
+        title
+    );
+    for (layer, layer_codes) in codes.iter().enumerate() {
+        res.push_str(&format!("    [{}] = {}(\n", layer, config.qmk_layout));
+
+        // one line for each row of the firmware config
+        let mut remaining = layer_codes.iter().enumerate();
+        for row_length in config.row_lengths.iter() {
+            let line: String = remaining
+                .by_ref()
+                .take(*row_length)
+                .map(|(i, code)| {
+                    let separator = if i + 1 < layer_codes.len() { "," } else { "" };
+                    format!("{:<w$}", format!("{}{}", code, separator), w = width + 1)
+                })
+                .collect();
+            res.push_str(&format!("        {}\n", line.trim_end()));
+        }
+        res.push_str("    ),\n");
+    }
+    res.push_str("};\n");
+
+    res
+}
+
+/// Parse a QMK `keymap.c` with one `LAYOUT` macro (of any name starting with `LAYOUT`) per layer.
+pub fn parse(keymap_c: &str, config: &FirmwareConfig) -> Result<Keymap> {
+    let text = strip_comments(keymap_c);
+
+    // collect the arguments of all LAYOUT macros and the designators of their layers
+    let mut layer_names = Vec::new();
+    let mut layer_args = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find("LAYOUT") {
+        let before = &rest[..start];
+        let is_identifier_start = !before
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let after = &rest[start..];
+        let name_len = after
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        rest = &after[name_len..];
+        if !is_identifier_start || !rest.trim_start().starts_with('(') {
+            continue;
+        }
+
+        let open = rest.find('(').unwrap();
+        let mut depth = 0;
+        let close = rest[open..]
+            .char_indices()
+            .find(|(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map(|(i, _)| open + i)
+            .ok_or_else(|| KeymapError::Parse("Unbalanced parentheses".to_string()))?;
+
+        let designator = before
+            .trim_end()
+            .strip_suffix('=')
+            .and_then(|b| b.trim_end().strip_suffix(']'))
+            .and_then(|b| b.rsplit_once('['))
+            .map(|(_, name)| name.trim().to_string())
+            .unwrap_or_default();
+        layer_names.push(designator);
+        layer_args.push(rest[open + 1..close].to_string());
+        rest = &rest[close + 1..];
+    }
+
+    if layer_args.is_empty() {
+        return Err(KeymapError::Parse("No `LAYOUT` macro found".to_string()).into());
+    }
+
+    let layers = layer_args
+        .iter()
+        .map(|args| {
+            split_top_level(args)
+                .into_iter()
+                .map(|code| parse_keycode(code, &layer_names))
+                .collect::<Result<Vec<KeyAction>>>()
+        })
+        .collect::<Result<Vec<Vec<KeyAction>>>>()?;
+
+    let keymap = Keymap { layers };
+    keymap.validate(config)?;
+
+    Ok(keymap)
+}
@@ -0,0 +1,110 @@
+//! Export and import of Vial keymaps (`.vil` JSON files).
+//!
+//! Vial stores the keycodes of each layer by rows and columns of the firmware's matrix. These are
+//! related to the firmware key indices by the `vial_matrix` of the [`FirmwareConfig`].
+
+use super::{qmk::parse_keycode, FirmwareConfig, KeyAction, Keycodes, Keymap, KeymapError};
+use crate::layout::LayerModifierType;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// The Vial keycode of a symbol (`KC_NO` if there is none)
+fn symbol_keycode(c: char) -> String {
+    match Keycodes::Qmk.keycode(c, false) {
+        Some((kc, false)) => kc.to_string(),
+        Some((kc, true)) => format!("LSFT({})", kc),
+        None => "KC_NO".to_string(),
+    }
+}
+
+/// The Vial keycode of a [`KeyAction`]
+fn keycode(action: &KeyAction) -> String {
+    match action {
+        KeyAction::Symbol(c) => symbol_keycode(*c),
+        KeyAction::Layer(layer, LayerModifierType::OneShot) => format!("OSL({})", layer),
+        KeyAction::Layer(layer, _) => format!("MO({})", layer),
+        // layer-tap keys only support unshifted keycodes
+        KeyAction::LayerTap(layer, c) => match Keycodes::Qmk.keycode(*c, false) {
+            Some((kc, false)) => format!("LT{}({})", layer, kc),
+            _ => format!("MO({})", layer),
+        },
+        KeyAction::Transparent => "KC_TRNS".to_string(),
+        KeyAction::None => "KC_NO".to_string(),
+    }
+}
+
+/// Number of rows and columns of the firmware's matrix
+fn matrix_size(config: &FirmwareConfig) -> Result<(usize, usize)> {
+    if config.vial_matrix.len() != config.positions.len() {
+        return Err(KeymapError::MissingVialMatrix.into());
+    }
+    let rows = config
+        .vial_matrix
+        .iter()
+        .map(|(r, _)| r + 1)
+        .max()
+        .unwrap_or(0);
+    let cols = config
+        .vial_matrix
+        .iter()
+        .map(|(_, c)| c + 1)
+        .max()
+        .unwrap_or(0);
+
+    Ok((rows, cols))
+}
+
+/// Generate a Vial `.vil` JSON for a [`Keymap`]. Matrix positions without a key are `-1`.
+pub fn to_json(keymap: &Keymap, config: &FirmwareConfig) -> Result<String> {
+    let (rows, cols) = matrix_size(config)?;
+
+    let layers: Vec<Vec<Vec<Value>>> = keymap
+        .layers
+        .iter()
+        .map(|actions| {
+            let mut matrix = vec![vec![json!(-1); cols]; rows];
+            actions
+                .iter()
+                .zip(config.vial_matrix.iter())
+                .for_each(|(action, (r, c))| matrix[*r][*c] = json!(keycode(action)));
+            matrix
+        })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&json!({
+        "version": 1,
+        "layout": layers,
+    }))?)
+}
+
+/// Parse the `layout` of a Vial `.vil` JSON file.
+pub fn parse(vil: &str, config: &FirmwareConfig) -> Result<Keymap> {
+    matrix_size(config)?;
+    let value: Value = serde_json::from_str(vil)?;
+    let layout = value
+        .get("layout")
+        .and_then(|l| l.as_array())
+        .ok_or_else(|| KeymapError::Parse("No `layout` found".to_string()))?;
+
+    let layers = layout
+        .iter()
+        .map(|matrix| {
+            config
+                .vial_matrix
+                .iter()
+                .map(|(r, c)| match matrix.get(r).and_then(|row| row.get(c)) {
+                    Some(Value::String(code)) => parse_keycode(code, &[]),
+                    Some(_) => Ok(KeyAction::None),
+                    None => Err(KeymapError::Parse(format!(
+                        "Matrix position ({}, {}) missing in `layout`",
+                        r, c
+                    ))
+                    .into()),
+                })
+                .collect::<Result<Vec<KeyAction>>>()
+        })
+        .collect::<Result<Vec<Vec<KeyAction>>>>()?;
+
+    Ok(Keymap { layers })
+}
@@ -0,0 +1,205 @@
+//! Export and import of ZMK keymaps (`.keymap` devicetree files).
+
+use super::{strip_comments, FirmwareConfig, KeyAction, Keycodes, Keymap, KeymapError};
+use crate::layout::LayerModifierType;
+
+use ahash::AHashMap;
+use anyhow::Result;
+
+/// The ZMK keycode of a symbol (if there is one)
+fn symbol_keycode(c: char) -> Option<String> {
+    match Keycodes::Zmk.keycode(c, true)? {
+        (kc, false) => Some(kc.to_string()),
+        (kc, true) => Some(format!("LS({})", kc)),
+    }
+}
+
+/// The ZMK binding of a [`KeyAction`]
+fn binding(action: &KeyAction) -> String {
+    match action {
+        KeyAction::Symbol(c) => match symbol_keycode(*c) {
+            Some(kc) => format!("&kp {}", kc),
+            None => "&none".to_string(),
+        },
+        KeyAction::Layer(layer, LayerModifierType::OneShot) => format!("&sl {}", layer),
+        KeyAction::Layer(layer, _) => format!("&mo {}", layer),
+        KeyAction::LayerTap(layer, c) => match symbol_keycode(*c) {
+            Some(kc) => format!("&lt {} {}", layer, kc),
+            None => format!("&mo {}", layer),
+        },
+        KeyAction::Transparent => "&trans".to_string(),
+        KeyAction::None => "&none".to_string(),
+    }
+}
+
+/// The symbol of a ZMK keycode, possibly with implicit shift modifier (e.g. `LS(A)`)
+fn parse_symbol(kc: &str) -> Option<char> {
+    match kc.split_once('(') {
+        Some((modifier, inner)) => match modifier {
+            "LS" | "RS" | "LSHFT" | "RSHFT" | "LSHIFT" | "RSHIFT" => {
+                Keycodes::Zmk.symbol(inner.trim_end_matches(')'), true)
+            }
+            _ => None,
+        },
+        None => Keycodes::Zmk.symbol(kc, false),
+    }
+}
+
+/// Resolve a layer given by number or by a `#define`d name
+fn parse_layer(layer: &str, defines: &AHashMap<String, u8>) -> Result<u8> {
+    layer
+        .parse::<u8>()
+        .ok()
+        .or_else(|| defines.get(layer).cloned())
+        .ok_or_else(|| KeymapError::Parse(format!("Unknown layer '{}'", layer)).into())
+}
+
+/// Parse a single binding (behavior and parameters). Unsupported behaviors do nothing.
+fn parse_binding(tokens: &[&str], defines: &AHashMap<String, u8>) -> Result<KeyAction> {
+    let action = match tokens {
+        ["&kp", kc] => parse_symbol(kc).map(KeyAction::Symbol),
+        ["&mo", layer] => Some(KeyAction::Layer(
+            parse_layer(layer, defines)?,
+            LayerModifierType::Hold,
+        )),
+        ["&sl", layer] => Some(KeyAction::Layer(
+            parse_layer(layer, defines)?,
+            LayerModifierType::OneShot,
+        )),
+        ["&lt", layer, kc] => {
+            let layer = parse_layer(layer, defines)?;
+            Some(match parse_symbol(kc) {
+                Some(c) => KeyAction::LayerTap(layer, c),
+                None => KeyAction::Layer(layer, LayerModifierType::Hold),
+            })
+        }
+        // mod-tap keys generate their keycode if tapped
+        ["&mt", _modifier, kc] => parse_symbol(kc).map(KeyAction::Symbol),
+        ["&trans"] => Some(KeyAction::Transparent),
+        _ => None,
+    };
+
+    Ok(action.unwrap_or(KeyAction::None))
+}
+
+/// Generate a ZMK `.keymap` devicetree for a [`Keymap`]. The `title` is added as comment at the top.
+pub fn to_devicetree(keymap: &Keymap, config: &FirmwareConfig, title: &str) -> String {
+    let bindings: Vec<Vec<String>> = keymap
+        .layers
+        .iter()
+        .map(|actions| actions.iter().map(binding).collect())
+        .collect();
+    let width = bindings
+        .iter()
+        .flatten()
+        .map(|b| b.len())
+        .max()
+        .unwrap_or(0)
+        + 1;
+
+    let mut res = format!(
+        "/*\n * {}\n */\n\n#include <behaviors.dtsi>\n#include <dt-bindings/zmk/keys.h>\n\n/ {{\n    keymap {{\n        compatible = \"zmk,keymap\";\n",
+        title
+    );
+    for (layer, layer_bindings) in bindings.iter().enumerate() {
+        res.push_str(&format!(
+            "\n        layer_{} {{\n            bindings = <\n",
+            layer
+        ));
+
+        // one line for each row of the firmware config
+        let mut remaining = layer_bindings.iter();
+        for row_length in config.row_lengths.iter() {
+            let line: String = remaining
+                .by_ref()
+                .take(*row_length)
+                .map(|b| format!("{:<w$}", b, w = width))
+                .collect();
+            res.push_str(&format!("                {}\n", line.trim_end()));
+        }
+        res.push_str("            >;\n        };\n");
+    }
+    res.push_str("    };\n};\n");
+
+    res
+}
+
+/// Parse the `bindings` of all layers of the `zmk,keymap` node of a ZMK `.keymap` file.
+pub fn parse(devicetree: &str, config: &FirmwareConfig) -> Result<Keymap> {
+    let text = strip_comments(devicetree);
+
+    // numeric `#define`s, e.g. for layer names
+    let defines: AHashMap<String, u8> = text
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().strip_prefix("#define")?.split_whitespace();
+            let name = parts.next()?;
+            let value = parts.next()?.parse::<u8>().ok()?;
+            Some((name.to_string(), value))
+        })
+        .collect();
+
+    // restrict the search for bindings to the keymap node (combos and behaviors have bindings too)
+    let compatible = text
+        .find("\"zmk,keymap\"")
+        .ok_or_else(|| KeymapError::Parse("No `zmk,keymap` node found".to_string()))?;
+    let node_start = text[..compatible]
+        .rfind('{')
+        .ok_or_else(|| KeymapError::Parse("Invalid `zmk,keymap` node".to_string()))?;
+    let mut depth = 0;
+    let node_end = text[node_start..]
+        .char_indices()
+        .find(|(_, c)| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })
+        .map(|(i, _)| node_start + i)
+        .ok_or_else(|| KeymapError::Parse("Unbalanced braces".to_string()))?;
+    let node = &text[node_start..node_end];
+
+    let mut layers = Vec::new();
+    let mut rest = node;
+    while let Some(start) = rest.find("bindings") {
+        rest = &rest[start + "bindings".len()..];
+        let after_assignment = match rest.trim_start().strip_prefix('=') {
+            Some(after) => after.trim_start(),
+            None => continue,
+        };
+        let list = after_assignment
+            .strip_prefix('<')
+            .ok_or_else(|| KeymapError::Parse("Invalid `bindings`".to_string()))?;
+        let end = list
+            .find('>')
+            .ok_or_else(|| KeymapError::Parse("Unterminated `bindings`".to_string()))?;
+
+        // each binding starts with a behavior reference ('&...') followed by its parameters
+        let mut bindings: Vec<Vec<&str>> = Vec::new();
+        for token in list[..end].split_whitespace() {
+            if token.starts_with('&') || bindings.is_empty() {
+                bindings.push(vec![token]);
+            } else {
+                bindings.last_mut().unwrap().push(token);
+            }
+        }
+        layers.push(
+            bindings
+                .iter()
+                .map(|tokens| parse_binding(tokens, &defines))
+                .collect::<Result<Vec<KeyAction>>>()?,
+        );
+        rest = &list[end + 1..];
+    }
+
+    if layers.is_empty() {
+        return Err(KeymapError::Parse("No `bindings` found".to_string()).into());
+    }
+
+    let keymap = Keymap { layers };
+    keymap.validate(config)?;
+
+    Ok(keymap)
+}
@@ -4,6 +4,7 @@
 
 use crate::key::{Hand, Key, MatrixPosition};
 use crate::keyboard::{KeyIndex, Keyboard};
+use crate::svg_plot::{self, Heatmap};
 
 use ahash::AHashMap;
 use anyhow::Result;
@@ -28,6 +29,8 @@ pub type LayerKeyIndex = u16;
 /// used.
 /// Note that if `Symbol(char)` is used, the modifier location may move with the symbol during an
 /// optimization.
+/// For tap-hold modifiers, the location is a dual-role key that generates its symbol when tapped
+/// (e.g. home-row mods).
 #[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
 #[serde(untagged)]
 pub enum ModifierLocation {
@@ -41,6 +44,7 @@ pub enum LayerModifierType {
     Hold,
     OneShot,
     LongPress,
+    TapHold,
 }
 
 impl Default for LayerModifierType {
@@ -58,8 +62,9 @@ impl LayerModifierType {
         !self.is_some()
     }
 
+    /// If the modifier is held while pressing other keys (including tap-hold modifiers)
     pub fn is_hold(&self) -> bool {
-        matches!(self, Self::Hold)
+        matches!(self, Self::Hold | Self::TapHold)
     }
 
     pub fn is_one_shot(&self) -> bool {
@@ -69,6 +74,10 @@ impl LayerModifierType {
     pub fn is_long_press(&self) -> bool {
         matches!(self, Self::LongPress)
     }
+
+    pub fn is_tap_hold(&self) -> bool {
+        matches!(self, Self::TapHold)
+    }
 }
 
 /// Enum for configuring the way how the modifiers shall be used to access a layer.
@@ -80,6 +89,8 @@ pub enum LayerModifierLocations {
     Hold(Vec<ModifierLocation>),
     OneShot(Vec<ModifierLocation>),
     LongPress,
+    /// Held like `Hold`, but located on dual-role keys that generate their symbol when tapped
+    TapHold(Vec<ModifierLocation>),
 }
 
 impl LayerModifierLocations {
@@ -88,6 +99,7 @@ impl LayerModifierLocations {
             Self::Hold(v) => v.iter(),
             Self::OneShot(v) => v.iter(),
             Self::LongPress => [].iter(),
+            Self::TapHold(v) => v.iter(),
         }
     }
     pub fn layer_modifier_type(&self) -> LayerModifierType {
@@ -95,12 +107,13 @@ impl LayerModifierLocations {
             Self::Hold(_) => LayerModifierType::Hold,
             Self::OneShot(_) => LayerModifierType::OneShot,
             Self::LongPress => LayerModifierType::LongPress,
+            Self::TapHold(_) => LayerModifierType::TapHold,
         }
     }
 }
 
 /// Enumeration describing the various modifier types (e.g. whether the modifier has to be held or tapped
-/// for activating a layer)
+/// for activating a layer). Tap-hold modifiers are held, so they resolve to `Hold`.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum LayerModifiers {
     Hold(Vec<LayerKeyIndex>),
@@ -143,6 +156,16 @@ pub struct LayerKey {
     pub is_fixed: bool,
     /// If the symbol itself is a modifier
     pub is_modifier: LayerModifierType,
+    /// For symbols generated by a combo: the keys pressed simultaneously (in terms of a [`LayerKeyIndex`]
+    /// for a layout); empty for symbols generated by a single key
+    pub combo_keys: Vec<LayerKeyIndex>,
+    /// If the layerkey represents a key pressed as part of a combo (it does not generate a symbol itself)
+    pub is_combo_part: bool,
+    /// If the symbol is generated by tapping a key that doubles as tap-hold modifier
+    pub is_dual_role: bool,
+    /// For symbols typed as compose sequence (e.g. with a dead key): the symbols typed in sequence
+    /// (in terms of a [`LayerKeyIndex`] for a layout); empty for symbols generated directly
+    pub compose_sequence: Vec<LayerKeyIndex>,
 }
 
 impl fmt::Display for LayerKey {
@@ -171,6 +194,80 @@ impl LayerKey {
             modifiers,
             is_fixed,
             is_modifier,
+            combo_keys: Vec::new(),
+            is_combo_part: false,
+            is_dual_role: false,
+            compose_sequence: Vec::new(),
+        }
+    }
+}
+
+/// A symbol (or one symbol per layer) generated by pressing several keys simultaneously.
+///
+/// Corresponds to (parts of) a YAML configuration file.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct ComboYAML {
+    /// Matrix positions of the keys to press
+    pub keys: Vec<MatrixPosition>,
+    /// Symbols of each layer (as for the keys of a base layout)
+    pub symbols: Vec<String>,
+    /// If the combo's symbols shall not be permutated for optimization
+    #[serde(default)]
+    pub fixed: bool,
+}
+
+/// A key whose output depends on the previously typed symbol. Its `symbol` is a placeholder that
+/// needs to be placed in the layout like any other symbol.
+///
+/// Corresponds to (parts of) a YAML configuration file.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum ContextKey {
+    /// Generates the previous symbol again
+    Repeat { symbol: char },
+    /// Generates the symbol(s) configured for the previous symbol ("magic" or adaptive key)
+    Magic {
+        symbol: char,
+        /// Previous symbol -> generated symbol(s), e.g. `"x": "ing"`
+        rules: AHashMap<char, String>,
+    },
+}
+
+impl ContextKey {
+    /// The placeholder symbol of the key in the layout
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Repeat { symbol } => *symbol,
+            Self::Magic { symbol, .. } => *symbol,
+        }
+    }
+
+    /// The symbol generated by the key if typed after `prev` (if it generates exactly one)
+    #[inline(always)]
+    pub fn generates(&self, prev: &char) -> Option<char> {
+        match self {
+            Self::Repeat { .. } => Some(*prev),
+            Self::Magic { rules, .. } => {
+                let mut chars = rules.get(prev)?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// The sequence of several symbols generated by the key if typed after `prev` (if any). Such
+    /// sequences can not be recognized within ngrams, so texts need to be tokenized (replacing the
+    /// sequence with the placeholder symbol) before collecting ngrams from them.
+    pub fn generates_sequence(&self, prev: &char) -> Option<&str> {
+        match self {
+            Self::Repeat { .. } => None,
+            Self::Magic { rules, .. } => rules
+                .get(prev)
+                .map(|s| s.as_str())
+                .filter(|s| s.chars().nth(1).is_some()),
         }
     }
 }
@@ -181,6 +278,12 @@ impl LayerKey {
 ///
 /// The layout is represented as a Vec of [`LayerKey`] objects with their indexes in the Vec being
 /// called [`LayerKeyIndex`].
+/// Combos (symbols generated by pressing several keys simultaneously) are handled like additional
+/// keys following the keyboard's keys. In ngrams, a combo acts like its first key.
+/// Context keys (e.g. a repeat key) generate symbols depending on the previous symbol. Whenever
+/// possible, symbols are typed with a context key instead of their own key.
+/// Symbols that are not on any key can be typed as compose sequences (e.g. a dead key followed by
+/// a base symbol). These are represented by additional [`LayerKey`]s following all others.
 /// A major task of the [`Layout`] object is to map given symbols (e.g. from a text) to corresponding
 /// [`LayerKey`] objects that describe which key(s) is (are) required to generate it (and then analyse
 /// corresponding efforts).
@@ -192,11 +295,13 @@ pub struct Layout {
     pub keyboard: Arc<Keyboard>,
     /// Vec of the [`KeyIndex`] corresponding to each [`LayerKey`] in `layerkeys`
     layerkey_to_key_index: Vec<KeyIndex>,
-    /// Vec for each [`Key`] of the [`Keyboard`] containing a Vec of all [`LayerKey`] that are
-    /// generaten with that [`Key`]
+    /// Vec for each [`Key`] of the [`Keyboard`] (followed by each combo) containing a Vec of all
+    /// [`LayerKey`] that are generaten with that [`Key`]
     key_layers: Vec<Vec<LayerKeyIndex>>,
     /// Map for retrieving the [`LayerKey`] for the symbol it generates
     key_map: Map<char, LayerKeyIndex>,
+    /// The [`LayerKey`] of each context key (in order of precedence)
+    context_keys: Vec<(LayerKeyIndex, ContextKey)>,
 }
 
 impl fmt::Display for Layout {
@@ -211,7 +316,17 @@ impl Layout {
         fixed_keys: Vec<bool>,
         keyboard: Arc<Keyboard>,
         modifiers: Vec<AHashMap<Hand, LayerModifierLocations>>,
+        combos: Vec<Vec<MatrixPosition>>,
+        context_keys: Vec<ContextKey>,
+        compose: AHashMap<char, String>,
     ) -> Result<Self> {
+        // the (virtual) keys of combos follow the keyboard's keys
+        let n_keys = keyboard.keys.len();
+        let mut keys = keyboard.keys.clone();
+        for positions in combos.iter() {
+            keys.push(Self::combo_key(&keyboard, positions)?);
+        }
+
         // generate layer keys
         let mut layerkeys = Vec::new();
         let mut layerkey_to_key_index = Vec::new();
@@ -220,7 +335,7 @@ impl Layout {
         let mut layerkey_index = 0;
         let key_layers: Vec<Vec<LayerKeyIndex>> = key_chars
             .iter()
-            .zip(keyboard.keys.iter())
+            .zip(keys.iter())
             .zip(fixed_keys.iter())
             .enumerate()
             .map(|(key_index, ((layer_chars, key), fixed))| {
@@ -239,9 +354,11 @@ impl Layout {
                         ));
                         layerkey_to_key_index.push(key_index as KeyIndex);
 
-                        pos2layerkey_index
-                            .entry(key.matrix_position)
-                            .or_insert(layerkey_index);
+                        if key_index < n_keys {
+                            pos2layerkey_index
+                                .entry(key.matrix_position)
+                                .or_insert(layerkey_index);
+                        }
 
                         // use layerkey with lowest layer for char2layerkey_index
                         let entry = char2layerkey_index.entry(*c).or_insert(layerkey_index);
@@ -328,7 +445,9 @@ impl Layout {
                     }
                 }
                 let resolved_mods = match mods {
-                    LayerModifierLocations::Hold(_) => LayerModifiers::Hold(resolved_mods_vec),
+                    LayerModifierLocations::Hold(_) | LayerModifierLocations::TapHold(_) => {
+                        LayerModifiers::Hold(resolved_mods_vec)
+                    }
                     LayerModifierLocations::OneShot(_) => {
                         LayerModifiers::OneShot(resolved_mods_vec)
                     }
@@ -339,6 +458,59 @@ impl Layout {
             mod_map.push(resolved_mods_per_hand);
         }
 
+        // symbols on the keys of tap-hold modifiers are generated by tapping a dual-role key
+        let dual_role_keys: Vec<KeyIndex> = layerkeys
+            .iter()
+            .zip(layerkey_to_key_index.iter())
+            .filter(|(lk, _)| lk.is_modifier.is_tap_hold())
+            .map(|(_, key_index)| *key_index)
+            .collect();
+        if !dual_role_keys.is_empty() {
+            layerkeys
+                .iter_mut()
+                .zip(layerkey_to_key_index.iter())
+                .filter(|(lk, key_index)| {
+                    lk.is_modifier.is_none() && dual_role_keys.contains(key_index)
+                })
+                .for_each(|(lk, _)| lk.is_dual_role = true);
+        }
+
+        // add the keys of combos as layerkeys
+        let mut pos2part_index: AHashMap<MatrixPosition, LayerKeyIndex> = AHashMap::default();
+        for (combo_idx, positions) in combos.iter().enumerate() {
+            let mut parts = Vec::with_capacity(positions.len());
+            for pos in positions.iter() {
+                let base_key_idx = *pos2layerkey_index
+                    .get(pos)
+                    .ok_or(format!("Combo position '{:?}' not found", pos))
+                    .map_err(anyhow::Error::msg)?;
+                let part_idx = *pos2part_index.entry(*pos).or_insert_with(|| {
+                    let base_layerkey = &layerkeys[base_key_idx as usize];
+                    let mut part = LayerKey::new(
+                        0,
+                        base_layerkey.key.clone(),
+                        base_layerkey.symbol,
+                        LayerModifiers::default(),
+                        base_layerkey.is_fixed,
+                        LayerModifierType::None,
+                    );
+                    part.is_combo_part = true;
+                    layerkeys.push(part);
+                    layerkey_to_key_index.push(layerkey_to_key_index[base_key_idx as usize]);
+
+                    layerkey_index += 1;
+                    layerkey_index - 1
+                });
+                parts.push(part_idx);
+            }
+
+            if let Some(combo_layers) = key_layers.get(n_keys + combo_idx) {
+                for idx in combo_layers.iter() {
+                    layerkeys[*idx as usize].combo_keys = parts.clone();
+                }
+            }
+        }
+
         // resolve each Single LayerKey's modifiers
         layerkeys.iter_mut().for_each(|k| {
             let mods = if k.layer > 0 && k.layer < (modifiers.len() + 1) as u8 {
@@ -355,7 +527,62 @@ impl Layout {
             k.modifiers = mods;
         });
 
-        let key_map = Self::gen_key_map(&layerkeys);
+        let mut key_map = Self::gen_key_map(&layerkeys);
+
+        // symbols that can not be generated directly are typed as compose sequences
+        let mut compose_sequences = compose
+            .into_iter()
+            .filter(|(c, _)| key_map.get(c).is_none())
+            .map(|(c, sequence)| {
+                let sequence = sequence
+                    .chars()
+                    .map(|s| {
+                        key_map
+                            .get(&s)
+                            .cloned()
+                            .ok_or(format!("Compose symbol '{}' of '{}' not found", s, c))
+                            .map_err(anyhow::Error::msg)
+                    })
+                    .collect::<Result<Vec<LayerKeyIndex>>>()?;
+                if sequence.len() < 2 {
+                    return Err(anyhow::Error::msg(format!(
+                        "Compose sequence of '{}' needs at least two symbols",
+                        c
+                    )));
+                }
+                Ok((c, sequence))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        compose_sequences.sort_unstable_by_key(|(c, _)| *c);
+
+        for (c, sequence) in compose_sequences {
+            // the layerkey uses the key of the sequence's last symbol, but is always expanded before evaluation
+            let last = *sequence.last().unwrap(); // can not fail due to above check
+            let mut layerkey = LayerKey::new(
+                0,
+                layerkeys[last as usize].key.clone(),
+                c,
+                LayerModifiers::default(),
+                true,
+                LayerModifierType::None,
+            );
+            layerkey.compose_sequence = sequence;
+            layerkeys.push(layerkey);
+            layerkey_to_key_index.push(layerkey_to_key_index[last as usize]);
+            key_map.insert(c, (layerkeys.len() - 1) as LayerKeyIndex);
+        }
+
+        let context_keys = context_keys
+            .into_iter()
+            .map(|context_key| {
+                let symbol = context_key.symbol();
+                key_map
+                    .get(&symbol)
+                    .map(|idx| (*idx, context_key))
+                    .ok_or(format!("Context key symbol '{}' not found", symbol))
+                    .map_err(anyhow::Error::msg)
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(Self {
             layerkeys,
@@ -363,17 +590,40 @@ impl Layout {
             keyboard,
             layerkey_to_key_index,
             key_map,
+            context_keys,
         })
     }
 
+    /// The (virtual) key of a combo. It is a copy of its first key whose cost is the sum of the costs
+    /// of all its keys.
+    fn combo_key(keyboard: &Keyboard, positions: &[MatrixPosition]) -> Result<Key> {
+        let mut combo_key: Option<Key> = None;
+        for pos in positions.iter() {
+            let key = keyboard
+                .keys
+                .iter()
+                .find(|k| k.matrix_position == *pos)
+                .ok_or(format!("Combo position '{:?}' not found", pos))
+                .map_err(anyhow::Error::msg)?;
+            match combo_key.as_mut() {
+                Some(combo_key) => combo_key.cost += key.cost,
+                None => combo_key = Some(key.clone()),
+            }
+        }
+
+        combo_key
+            .ok_or("Combo without keys".to_string())
+            .map_err(anyhow::Error::msg)
+    }
+
     fn gen_key_map(layerkeys: &[LayerKey]) -> Map<char, LayerKeyIndex> {
         let mut m = Map::default();
         layerkeys
             .iter()
             .enumerate()
             .for_each(|(layerkey_index, layerkey)| {
-                // modifiers do not generate symbols themselves -> return
-                if layerkey.is_modifier.is_some() {
+                // modifiers and parts of combos do not generate symbols themselves -> return
+                if layerkey.is_modifier.is_some() || layerkey.is_combo_part {
                     return;
                 };
 
@@ -431,6 +681,68 @@ impl Layout {
         self.key_map.get(c).cloned()
     }
 
+    /// Get the index of the context key generating symbol `c` if typed after `prev` (if any)
+    #[inline(always)]
+    pub fn get_context_key_index(&self, prev: &char, c: &char) -> Option<LayerKeyIndex> {
+        self.context_keys
+            .iter()
+            .find(|(_, context_key)| context_key.generates(prev) == Some(*c))
+            .map(|(idx, _)| *idx)
+    }
+
+    /// Get the index of a [`LayerKey`] for a given symbol typed after `prev`. This is a context key
+    /// if one generates the symbol, otherwise the symbol's own [`LayerKey`].
+    #[inline(always)]
+    pub fn get_layerkey_index_for_symbol_after(
+        &self,
+        prev: &char,
+        c: &char,
+    ) -> Option<LayerKeyIndex> {
+        if !self.context_keys.is_empty() {
+            if let Some(idx) = self.get_context_key_index(prev, c) {
+                return Some(idx);
+            }
+        }
+
+        self.get_layerkey_index_for_symbol(c)
+    }
+
+    /// All pairs of a previous symbol and a symbol in `symbols` that are typed with a context key
+    /// (together with the context key's index)
+    pub fn context_key_pairs<'a>(
+        &'a self,
+        symbols: &'a [char],
+    ) -> impl Iterator<Item = ((char, char), LayerKeyIndex)> + 'a {
+        self.context_keys
+            .iter()
+            .flat_map(move |(idx, context_key)| {
+                let pairs: Vec<(char, char)> = match context_key {
+                    ContextKey::Repeat { .. } => symbols.iter().map(|c| (*c, *c)).collect(),
+                    // rules generating several symbols are not typed within ngrams
+                    ContextKey::Magic { rules, .. } => rules
+                        .keys()
+                        .filter_map(|prev| Some((*prev, context_key.generates(prev)?)))
+                        .filter(|(_, c)| symbols.contains(c))
+                        .collect(),
+                };
+                pairs.into_iter().map(move |pair| (pair, *idx))
+            })
+            // earlier context keys take precedence
+            .filter(|((prev, c), idx)| self.get_context_key_index(prev, c) == Some(*idx))
+    }
+
+    /// If the layerkey is a context key
+    pub fn is_context_key(&self, layerkey_index: &LayerKeyIndex) -> bool {
+        self.context_keys
+            .iter()
+            .any(|(idx, _)| idx == layerkey_index)
+    }
+
+    /// If the layout has at least one context key
+    pub fn has_context_keys(&self) -> bool {
+        !self.context_keys.is_empty()
+    }
+
     /// Get the index of the "base" symbol (the one on the base layer, e.g. "A" -> "a") for a given [`LayerKeyIndex`]
     #[inline(always)]
     pub fn get_base_layerkey_index(&self, layerkey_index: &LayerKeyIndex) -> LayerKeyIndex {
@@ -438,6 +750,20 @@ impl Layout {
         self.key_layers[key_index][0]
     }
 
+    /// Get the base-layer [`LayerKey`] of each key (followed by each combo) that generates a symbol
+    pub fn base_layerkeys(&self) -> impl Iterator<Item = &LayerKey> {
+        self.key_layers
+            .iter()
+            .filter_map(|layerkeys| layerkeys.first().map(|lk| self.get_layerkey(lk)))
+    }
+
+    /// Get the [`LayerKey`]s of all layers of each key (followed by each combo)
+    pub fn key_layerkeys(&self) -> impl Iterator<Item = Vec<&LayerKey>> {
+        self.key_layers
+            .iter()
+            .map(|layerkeys| layerkeys.iter().map(|lk| self.get_layerkey(lk)).collect())
+    }
+
     /// Get a list of modifiers required to generate a given [`LayerKey`] as a Vec of [`LayerKey`]s
     #[inline(always)]
     pub fn resolve_modifiers(&self, k: &LayerKeyIndex) -> (LayerKeyIndex, LayerModifiers) {
@@ -453,6 +779,18 @@ impl Layout {
         (base, mods)
     }
 
+    /// If the layout has at least one combo
+    pub fn has_combos(&self) -> bool {
+        self.key_layers.len() > self.keyboard.keys.len()
+    }
+
+    /// If the layout types at least one symbol as compose sequence
+    pub fn has_compose_sequences(&self) -> bool {
+        self.layerkeys
+            .iter()
+            .any(|lk| !lk.compose_sequence.is_empty())
+    }
+
     /// If the layout has at least one layer configured as hold layer
     pub fn has_hold_layers(&self) -> bool {
         self.layerkeys
@@ -488,6 +826,7 @@ impl Layout {
         let key_chars: Vec<String> = self
             .key_layers
             .iter()
+            .take(self.keyboard.keys.len())
             .map(|layers| {
                 if layers.is_empty() {
                     return " ".to_string();
@@ -509,7 +848,33 @@ impl Layout {
             })
             .collect();
 
-        self.keyboard.plot(&key_chars)
+        let mut plot = self.keyboard.plot(&key_chars);
+
+        // combos are listed below the keyboard
+        let combos: Vec<String> = self
+            .key_layers
+            .iter()
+            .skip(self.keyboard.keys.len())
+            .filter_map(|layers| layers.get(layer))
+            .map(|idx| {
+                let k = self.get_layerkey(idx);
+                let keys: Vec<String> = k
+                    .combo_keys
+                    .iter()
+                    .map(|part| fmt_char(self.get_layerkey(part).symbol).to_string())
+                    .collect();
+                let mut s = fmt_char(k.symbol).to_string();
+                if !k.is_fixed {
+                    s = s.yellow().bold().to_string();
+                }
+                format!("{} {}", keys.join("+"), s)
+            })
+            .collect();
+        if !combos.is_empty() {
+            plot.push_str(&format!("\nCombos: {}", combos.join(", ")));
+        }
+
+        plot
     }
 
     /// Plot a graphical representation of the base (first) layer
@@ -517,6 +882,12 @@ impl Layout {
         self.plot_layer(0)
     }
 
+    /// Plot an SVG image of all layers based on the positions of the keys, optionally with a heatmap
+    /// of values per key (see [`crate::svg_plot`])
+    pub fn plot_svg(&self, heatmap: Option<&Heatmap>) -> String {
+        svg_plot::plot(self, heatmap)
+    }
+
     /// Plot a compact graphical representation (without borders and only non-fixed keys) of the base (first) layer
     pub fn plot_compact(&self) -> String {
         let key_chars: Vec<String> = self
@@ -529,7 +900,7 @@ impl Layout {
         self.keyboard.plot_compact(&key_chars)
     }
 
-    /// Concatenate all non-fixed keys into a string without any whitespace
+    /// Concatenate all non-fixed keys (followed by all non-fixed combos) into a string without any whitespace
     pub fn as_text(&self) -> String {
         self.key_layers
             .iter()
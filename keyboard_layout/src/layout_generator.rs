@@ -4,10 +4,23 @@
 use core::fmt;
 
 use crate::layout::Layout;
+use crate::layout_symmetry::LayoutSymmetry;
 use anyhow::Result;
 
 pub trait LayoutGenerator: Send + Sync + LayoutGeneratorClone + fmt::Debug {
     fn generate(&self, layout_keys: &str) -> Result<Layout>;
+
+    /// The layout string of the layout as configured in the `base_layout` (its non-fixed symbols)
+    fn base_layout_string(&self) -> String;
+
+    /// The mirror symmetry of the layout strings (see [`LayoutSymmetry`])
+    fn symmetry(&self) -> LayoutSymmetry;
+
+    /// Lengths of the consecutive segments of a layout string whose symbols may only be permuted
+    /// among each other. An empty Vec means that all symbols may be permuted freely.
+    fn permutation_segments(&self) -> Vec<usize> {
+        Vec::new()
+    }
 }
 
 impl Clone for Box<dyn LayoutGenerator> {
@@ -0,0 +1,226 @@
+//! This module provides functionalities for porting a layout to another keyboard.
+//!
+//! Layout strings are positional and depend on the key order of each layout config. Porting moves
+//! each base-layer symbol of a layout to the key of the other keyboard that plays the same role,
+//! i.e. that is pressed by the same hand and finger and lies in the same row and column relative to
+//! that finger's home key.
+
+use crate::key::{ClusterPosition, Finger, Hand, Key, KeyDirection, MatrixPosition};
+use crate::keyboard::Keyboard;
+use crate::layout::Layout;
+use crate::slot_layout_generator::EMPTY_SLOT;
+
+use ahash::{AHashMap, AHashSet};
+
+/// Maximum distance between the fingers pressing two keys for these to be considered as matching
+const MAX_FINGER_DISTANCE: u8 = 1;
+
+/// The role of a key: the finger pressing it and its location relative to the finger's home key
+#[derive(Clone, Copy, Debug)]
+struct KeyRole {
+    hand: Hand,
+    finger: Finger,
+    /// Rows below the home key (negative values: above)
+    row: i16,
+    /// Columns towards the other hand (negative values: outwards)
+    column: i16,
+    symmetry_index: u8,
+}
+
+impl KeyRole {
+    fn new(key: &Key, home: &MatrixPosition) -> Self {
+        let column = key.matrix_position.0 as i16 - home.0 as i16;
+        Self {
+            hand: key.hand,
+            finger: key.finger,
+            row: key.matrix_position.1 as i16 - home.1 as i16,
+            column: match key.hand {
+                Hand::Left => column,
+                Hand::Right => -column,
+            },
+            symmetry_index: key.symmetry_index,
+        }
+    }
+
+    /// How different two roles are (`None` if keys of these roles can not replace each other)
+    fn mismatch(&self, other: &KeyRole) -> Option<u32> {
+        let is_thumb = |finger: Finger| finger == Finger::Thumb;
+        let finger_distance = self.finger.distance(&other.finger);
+        if self.hand != other.hand
+            || is_thumb(self.finger) != is_thumb(other.finger)
+            || finger_distance > MAX_FINGER_DISTANCE
+        {
+            return None;
+        }
+
+        Some(
+            4 * finger_distance as u32
+                + 2 * self.row.abs_diff(other.row) as u32
+                + self.column.abs_diff(other.column) as u32,
+        )
+    }
+
+    /// Distance from the home key
+    fn reach(&self) -> u32 {
+        self.row.unsigned_abs() as u32 + self.column.unsigned_abs() as u32
+    }
+}
+
+/// The home key of each finger: the center of its cluster (if the keyboard declares clusters),
+/// otherwise its key with the lowest cost (the one closest to the finger's resting position if
+/// several have the lowest cost).
+fn home_keys(keyboard: &Keyboard) -> AHashMap<(Hand, Finger), MatrixPosition> {
+    let rank = |key: &Key| {
+        let is_center = matches!(
+            key.cluster,
+            Some(ClusterPosition(_, KeyDirection::Center | KeyDirection::Pad))
+        );
+        let resting_position = keyboard
+            .finger_resting_positions
+            .get(&key.hand, &key.finger);
+        (
+            !is_center,
+            key.cost,
+            key.position.distance(resting_position),
+        )
+    };
+
+    let mut home_keys: AHashMap<(Hand, Finger), &Key> = AHashMap::default();
+    for key in keyboard.keys.iter() {
+        let home_key = home_keys.entry((key.hand, key.finger)).or_insert(key);
+        if rank(key) < rank(home_key) {
+            *home_key = key;
+        }
+    }
+
+    home_keys
+        .into_iter()
+        .map(|(finger, key)| (finger, key.matrix_position))
+        .collect()
+}
+
+/// The result of porting a layout to another keyboard
+#[derive(Clone, Debug)]
+pub struct PortedLayout {
+    /// The base-layer symbols of all non-fixed keys of the target layout (as in its layout strings)
+    pub layout_string: String,
+    /// Symbols moved to a matching key: symbol, position in the source layout, position in the target layout
+    pub placed: Vec<(char, MatrixPosition, MatrixPosition)>,
+    /// Symbols without a matching key (with their position in the source layout). They take the
+    /// remaining keys of the target layout.
+    pub unplaced: Vec<(char, MatrixPosition)>,
+    /// Symbols of non-fixed keys of the source layout that the target layout can not generate
+    pub unsupported: Vec<char>,
+}
+
+/// Port the base layer of a layout onto the keyboard of a `target` layout.
+///
+/// Each symbol that is permuted in the `target` layout is moved to the non-fixed key of the target
+/// keyboard playing the most similar role. Pairs of keys with the smallest mismatch are matched first,
+/// keys close to their home key taking precedence. Keys of the target layout that do not receive a symbol
+/// keep their symbol if possible; otherwise, they receive one of the remaining symbols.
+pub fn port_layout(layout: &Layout, target: &Layout) -> PortedLayout {
+    let source_home_keys = home_keys(&layout.keyboard);
+    let target_home_keys = home_keys(&target.keyboard);
+
+    // the non-fixed keys of the target layout (in the order of its layout strings)
+    let slots: Vec<(Option<KeyRole>, char, MatrixPosition)> = target
+        .base_layerkeys()
+        .filter(|lk| !lk.is_fixed)
+        .map(|lk| {
+            // combos keep their symbol
+            let role = lk
+                .combo_keys
+                .is_empty()
+                .then(|| KeyRole::new(&lk.key, &target_home_keys[&(lk.key.hand, lk.key.finger)]));
+            (role, lk.symbol, lk.key.matrix_position)
+        })
+        .collect();
+    let permutable: AHashSet<char> = slots.iter().map(|(_, c, _)| *c).collect();
+
+    let mut unsupported = Vec::new();
+    let mut sources: Vec<(KeyRole, char, MatrixPosition)> = Vec::new();
+    for lk in layout.base_layerkeys() {
+        if !lk.combo_keys.is_empty() || lk.symbol == EMPTY_SLOT {
+            continue;
+        }
+        if permutable.contains(&lk.symbol) {
+            let home_key = &source_home_keys[&(lk.key.hand, lk.key.finger)];
+            sources.push((
+                KeyRole::new(&lk.key, home_key),
+                lk.symbol,
+                lk.key.matrix_position,
+            ));
+        } else if !lk.is_fixed && target.get_layerkey_for_symbol(&lk.symbol).is_none() {
+            unsupported.push(lk.symbol);
+        }
+    }
+
+    // match the pairs of keys with the smallest mismatch first
+    let mut pairs: Vec<(u32, u32, u8, u8, usize, usize)> = Vec::new();
+    for (source_idx, (source_role, _, _)) in sources.iter().enumerate() {
+        for (slot_idx, (slot_role, _, _)) in slots.iter().enumerate() {
+            if let Some(mismatch) = slot_role.and_then(|role| source_role.mismatch(&role)) {
+                pairs.push((
+                    mismatch,
+                    source_role.reach(),
+                    source_role.symmetry_index,
+                    source_role.hand as u8,
+                    source_idx,
+                    slot_idx,
+                ));
+            }
+        }
+    }
+    pairs.sort_unstable();
+
+    let mut slot_symbols: Vec<Option<char>> = vec![None; slots.len()];
+    let mut matched_sources = vec![false; sources.len()];
+    let mut used_symbols: AHashSet<char> = AHashSet::default();
+    let mut placed = Vec::new();
+    for (_, _, _, _, source_idx, slot_idx) in pairs {
+        let (_, symbol, position) = sources[source_idx];
+        if matched_sources[source_idx]
+            || slot_symbols[slot_idx].is_some()
+            || used_symbols.contains(&symbol)
+        {
+            continue;
+        }
+        matched_sources[source_idx] = true;
+        slot_symbols[slot_idx] = Some(symbol);
+        used_symbols.insert(symbol);
+        placed.push((symbol, position, slots[slot_idx].2));
+    }
+
+    let unplaced = sources
+        .iter()
+        .zip(matched_sources.iter())
+        .filter(|(_, matched)| !**matched)
+        .map(|((_, symbol, position), _)| (*symbol, *position))
+        .collect();
+
+    // the remaining keys keep their own symbol if it has not been placed elsewhere
+    for (slot_symbol, (_, symbol, _)) in slot_symbols.iter_mut().zip(slots.iter()) {
+        if slot_symbol.is_none() && !used_symbols.contains(symbol) {
+            *slot_symbol = Some(*symbol);
+            used_symbols.insert(*symbol);
+        }
+    }
+
+    // ... or receive one of the symbols that have been displaced
+    let mut remaining_symbols = slots
+        .iter()
+        .map(|(_, symbol, _)| *symbol)
+        .filter(|symbol| !used_symbols.contains(symbol));
+    let layout_string = slot_symbols
+        .into_iter()
+        .filter_map(|slot_symbol| slot_symbol.or_else(|| remaining_symbols.next()))
+        .collect();
+
+    PortedLayout {
+        layout_string,
+        placed,
+        unplaced,
+        unsupported,
+    }
+}
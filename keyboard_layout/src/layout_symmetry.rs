@@ -0,0 +1,99 @@
+//! This module provides the mirror symmetry of layout strings.
+//!
+//! Keys of different hands with identical `symmetry_index` are mirror images of each other. Swapping
+//! the symbols of all such pairs yields the mirrored layout, which is equivalent to the original one
+//! under metrics that treat both hands alike (given a symmetric keyboard and symmetric fixed keys).
+//! Of each such pair of layouts, the lexicographically smaller layout string serves as canonical
+//! representative.
+
+use crate::key::Hand;
+use crate::keyboard::Keyboard;
+
+use ahash::AHashMap;
+
+/// The mirror symmetry of the layout strings of a layout generator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayoutSymmetry {
+    /// The position of a layout string that each position is mirrored to
+    mirror: Vec<usize>,
+}
+
+impl LayoutSymmetry {
+    /// Determine the symmetry from the `(key index, group)` addressed by each position of a layout
+    /// string. Positions are only mirrored to positions of the same group (e.g. a layer). Positions
+    /// whose key has no unique mirror image within their group (or addresses no key of the keyboard)
+    /// are kept in place.
+    pub fn new(keyboard: &Keyboard, slots: &[(usize, u8)]) -> Self {
+        let mut sides: AHashMap<(u8, u8), (Vec<usize>, Vec<usize>)> = AHashMap::default();
+        for (pos, (key_idx, group)) in slots.iter().enumerate() {
+            if let Some(key) = keyboard.keys.get(*key_idx) {
+                let side = sides.entry((key.symmetry_index, *group)).or_default();
+                match key.hand {
+                    Hand::Left => side.0.push(pos),
+                    Hand::Right => side.1.push(pos),
+                }
+            }
+        }
+
+        let mut mirror: Vec<usize> = (0..slots.len()).collect();
+        for (left, right) in sides.values() {
+            if let ([l], [r]) = (left.as_slice(), right.as_slice()) {
+                mirror[*l] = *r;
+                mirror[*r] = *l;
+            }
+        }
+
+        Self { mirror }
+    }
+
+    /// Whether mirroring leaves all layout strings unchanged
+    pub fn is_trivial(&self) -> bool {
+        self.mirror.iter().enumerate().all(|(i, m)| i == *m)
+    }
+
+    /// The position that a position of a layout string is mirrored to
+    pub fn mirrored_position(&self, pos: usize) -> usize {
+        self.mirror.get(pos).cloned().unwrap_or(pos)
+    }
+
+    /// The same symmetry, but keeping the given positions (and their mirror images) in place, e.g.
+    /// for symbols that shall not be moved during an optimization.
+    pub fn keeping_positions(&self, positions: &[usize]) -> Self {
+        let mut mirror = self.mirror.clone();
+        for pos in positions {
+            if let Some(m) = mirror.get(*pos).cloned() {
+                mirror[m] = m;
+                mirror[*pos] = *pos;
+            }
+        }
+
+        Self { mirror }
+    }
+
+    /// The mirror image of a layout string. Positions whose mirror image lies beyond the end of the
+    /// string are kept.
+    pub fn mirror(&self, layout_str: &str) -> String {
+        let chars: Vec<char> = layout_str.chars().collect();
+        (0..chars.len())
+            .map(|i| match self.mirrored_position(i) {
+                m if m < chars.len() => chars[m],
+                _ => chars[i],
+            })
+            .collect()
+    }
+
+    /// The canonical representative of a layout string and its mirror image
+    pub fn canonicalize(&self, layout_str: &str) -> String {
+        let mirrored = self.mirror(layout_str);
+        if mirrored.as_str() < layout_str {
+            mirrored
+        } else {
+            layout_str.to_string()
+        }
+    }
+
+    /// Whether a layout string is the canonical representative of itself and its mirror image
+    pub fn is_canonical(&self, layout_str: &str) -> bool {
+        self.mirror(layout_str).as_str() >= layout_str
+    }
+}
@@ -7,12 +7,20 @@
 //! and other associated properties.
 
 pub mod config;
+pub mod file_layout_generator;
 pub mod grouped_layout_generator;
 pub mod key;
+pub mod key_cost_model;
 pub mod keyboard;
+pub mod keyboard_generator;
+pub mod keymap;
 pub mod layout;
 pub mod layout_generator;
+pub mod layout_port;
+pub mod layout_symmetry;
 pub mod neo_layout_generator;
+pub mod slot_layout_generator;
+pub mod svg_plot;
 
 #[cfg(test)]
 mod tests {
@@ -1,7 +1,8 @@
-use crate::key::Hand;
+use crate::key::{Hand, MatrixPosition};
 use crate::keyboard::Keyboard;
-use crate::layout::{LayerModifierLocations, Layout};
+use crate::layout::{ComboYAML, ContextKey, LayerModifierLocations, Layout};
 use crate::layout_generator::LayoutGenerator;
+use crate::layout_symmetry::LayoutSymmetry;
 
 use ahash::{AHashMap, AHashSet};
 use anyhow::Result;
@@ -21,6 +22,10 @@ pub enum LayoutError {
         "Invalid base layout: Not the same number of `keys` ({0}) as entries in `fixed_keys` ({1})"
     )]
     WrongKeyNumber(usize, usize),
+    #[error("Invalid base layout: Combo {0} needs at least two different `keys`")]
+    InvalidCombo(usize),
+    #[error("Invalid base layout: Rule of magic key '{0}' for '{1}' generates no symbol")]
+    EmptyMagicRule(char, char),
 }
 
 /// A collection of data (configuration) regarding the Neo layout (and its family)
@@ -34,6 +39,22 @@ pub struct BaseLayoutYAML {
     pub fixed_layers: Vec<u8>,
     pub modifiers: Vec<AHashMap<Hand, LayerModifierLocations>>,
     pub grouped_layers: u8,
+    /// Layers whose symbols are permuted independently of the base layer (only used by the
+    /// [`crate::slot_layout_generator::SlotLayoutGenerator`])
+    #[serde(default)]
+    pub permuted_layers: Vec<u8>,
+    /// Symbols generated by pressing several keys simultaneously. Non-fixed combos are permuted like
+    /// keys; their symbols follow those of the keys in layout strings.
+    #[serde(default)]
+    pub combos: Vec<ComboYAML>,
+    /// Keys generating symbols depending on the previous symbol (e.g. a repeat key). Their
+    /// placeholder symbols need to be part of `keys` (or of layout strings).
+    #[serde(default)]
+    pub context_keys: Vec<ContextKey>,
+    /// Sequences of symbols (e.g. a dead key followed by a base symbol) for typing symbols that are
+    /// not on any key, e.g. `é: "´e"`
+    #[serde(default)]
+    pub compose: AHashMap<char, String>,
 }
 
 impl BaseLayoutYAML {
@@ -48,8 +69,48 @@ impl BaseLayoutYAML {
             return Err(LayoutError::WrongKeyNumber(flat_keys.len(), flat_fixed_keys.len()).into());
         }
 
+        for (i, combo) in self.combos.iter().enumerate() {
+            let mut positions = combo.keys.clone();
+            positions.sort_unstable_by_key(|pos| (pos.0, pos.1));
+            positions.dedup();
+            if positions.len() < 2 || positions.len() != combo.keys.len() {
+                return Err(LayoutError::InvalidCombo(i).into());
+            }
+        }
+
+        for context_key in self.context_keys.iter() {
+            if let ContextKey::Magic { symbol, rules } = context_key {
+                if let Some((prev, _)) = rules.iter().find(|(_, output)| output.is_empty()) {
+                    return Err(LayoutError::EmptyMagicRule(*symbol, *prev).into());
+                }
+            }
+        }
+
         Ok(())
     }
+
+    /// The symbols of each key's layers, followed by those of each combo
+    pub fn key_symbols(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.keys
+            .iter()
+            .flatten()
+            .chain(self.combos.iter().map(|combo| &combo.symbols))
+    }
+
+    /// Whether each key (followed by each combo) is fixed
+    pub fn flat_fixed_keys(&self) -> Vec<bool> {
+        self.fixed_keys
+            .iter()
+            .flatten()
+            .cloned()
+            .chain(self.combos.iter().map(|combo| combo.fixed))
+            .collect()
+    }
+
+    /// The keys of each combo
+    pub fn combo_positions(&self) -> Vec<Vec<MatrixPosition>> {
+        self.combos.iter().map(|combo| combo.keys.clone()).collect()
+    }
 }
 /// Provides functionalities for generating Neo layout variants from given string representations
 /// of their base layer.
@@ -60,6 +121,9 @@ pub struct NeoLayoutGenerator {
     permutable_key_map: AHashMap<char, u8>,
     fixed_layers: Vec<u8>,
     modifiers: Vec<AHashMap<Hand, LayerModifierLocations>>,
+    combos: Vec<Vec<MatrixPosition>>,
+    context_keys: Vec<ContextKey>,
+    compose: AHashMap<char, String>,
     keyboard: Arc<Keyboard>,
 }
 
@@ -67,12 +131,11 @@ impl NeoLayoutGenerator {
     /// Generate a [`NeoLayoutGenerator`] from a [`BaseLayoutYAML`] object
     pub fn from_object(base: BaseLayoutYAML, keyboard: Arc<Keyboard>) -> Self {
         let base_layout_symbols: Vec<Vec<char>> = base
-            .keys
-            .iter()
-            .flatten()
+            .key_symbols()
             .map(|layers| layers.iter().filter_map(|l| l.chars().next()).collect())
             .collect();
-        let fixed_keys: Vec<bool> = base.fixed_keys.iter().flatten().cloned().collect();
+        let fixed_keys: Vec<bool> = base.flat_fixed_keys();
+        let combos = base.combo_positions();
 
         let mut permutable_key_map: AHashMap<char, u8> = AHashMap::default();
         base_layout_symbols
@@ -92,6 +155,9 @@ impl NeoLayoutGenerator {
             permutable_key_map,
             fixed_layers: base.fixed_layers,
             modifiers: base.modifiers,
+            combos,
+            context_keys: base.context_keys,
+            compose: base.compose,
             keyboard,
         }
     }
@@ -156,6 +222,9 @@ impl NeoLayoutGenerator {
             self.fixed_keys.clone(),
             self.keyboard.clone(),
             self.modifiers.clone(),
+            self.combos.clone(),
+            self.context_keys.clone(),
+            self.compose.clone(),
         )
     }
 
@@ -207,4 +276,25 @@ impl LayoutGenerator for NeoLayoutGenerator {
 
         self.generate_unchecked(layout_keys)
     }
+
+    fn base_layout_string(&self) -> String {
+        self.base_layout_symbols
+            .iter()
+            .zip(self.fixed_keys.iter())
+            .filter(|(_key_layers, fixed)| !**fixed)
+            .filter_map(|(key_layers, _fixed)| key_layers.first())
+            .collect()
+    }
+
+    fn symmetry(&self) -> LayoutSymmetry {
+        let slots: Vec<(usize, u8)> = self
+            .fixed_keys
+            .iter()
+            .enumerate()
+            .filter(|(_i, fixed)| !**fixed)
+            .map(|(i, _fixed)| (i, 0))
+            .collect();
+
+        LayoutSymmetry::new(&self.keyboard, &slots)
+    }
 }
@@ -0,0 +1,354 @@
+//! This module provides a layout generator that addresses individual `(key, layer)` slots.
+//! Besides the base layer, the symbols of further layers (e.g. a symbol layer) can be permuted
+//! independently of the base layer.
+
+use crate::key::{Hand, MatrixPosition};
+use crate::keyboard::Keyboard;
+use crate::layout::{ContextKey, LayerModifierLocations, Layout};
+use crate::layout_generator::LayoutGenerator;
+use crate::layout_symmetry::LayoutSymmetry;
+use crate::neo_layout_generator::BaseLayoutYAML;
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::Result;
+use std::{fs::File, iter::FromIterator, sync::Arc};
+use thiserror::Error;
+
+/// Symbol used for filling empty layers of a key (below a layer with a symbol).
+/// It does not occur in texts and therefore never contributes to any cost.
+pub const EMPTY_SLOT: char = '\u{0}';
+
+#[derive(Error, Debug)]
+pub enum LayoutError {
+    #[error("Invalid keyboard layout: Provided layout has {0} characters, but there are {1} slots ({2:?} per layer)")]
+    WrongSlotNumber(usize, usize, Vec<usize>),
+    #[error("Invalid keyboard layout: Duplicate characters in layer {0} of provided layout '{1}': '{2}'")]
+    DuplicateChars(u8, String, String),
+    #[error("Invalid keyboard layout: Missing characters in layer {0} of provided layout: '{1}'")]
+    MissingChars(u8, String),
+    #[error("Invalid keyboard layout: Unsupported characters in layer {0} of provided layout (not in that layer of `base_layout` for `fixed_keys` with value `false`): '{1}'")]
+    UnsupportedChars(u8, String),
+}
+
+/// A layer whose symbols are permuted among its slots, i.e. the non-fixed keys having a symbol in that layer.
+#[derive(Clone, Debug)]
+struct PermutedLayer {
+    layer: u8,
+    /// Indices of the keys providing the layer's slots
+    slots: Vec<usize>,
+    symbols: AHashSet<char>,
+}
+
+/// Provides functionalities for generating layouts from given string representations of their
+/// permutable `(key, layer)` slots.
+///
+/// The layout string starts with the base layer's symbols of all non-fixed keys (as for the
+/// [`crate::neo_layout_generator::NeoLayoutGenerator`]). These take the symbols of all layers that
+/// are neither in `fixed_layers` nor in `permuted_layers` with them. For each of the `permuted_layers`,
+/// the symbols of its slots follow. They are placed independently of the base layer.
+///
+/// In contrast to the other generators, empty symbols in the `base_layout` do not shift the symbols
+/// of higher layers down, such that each symbol is accessed with the modifiers of its configured layer.
+/// Non-fixed keys without a base layer symbol provide no slot of the base layer (they keep their symbols).
+#[derive(Clone, Debug)]
+pub struct SlotLayoutGenerator {
+    base_layout_symbols: Vec<Vec<Option<char>>>,
+    fixed_keys: Vec<bool>,
+    /// Indices of the keys providing the base layer's slots (non-fixed keys with a base layer symbol)
+    base_slots: Vec<usize>,
+    permutable_key_map: AHashMap<char, usize>,
+    fixed_layers: Vec<u8>,
+    permuted_layers: Vec<PermutedLayer>,
+    modifiers: Vec<AHashMap<Hand, LayerModifierLocations>>,
+    combos: Vec<Vec<MatrixPosition>>,
+    context_keys: Vec<ContextKey>,
+    compose: AHashMap<char, String>,
+    keyboard: Arc<Keyboard>,
+}
+
+impl SlotLayoutGenerator {
+    /// Generate a [`SlotLayoutGenerator`] from a [`BaseLayoutYAML`] object
+    pub fn from_object(base: BaseLayoutYAML, keyboard: Arc<Keyboard>) -> Self {
+        let base_layout_symbols: Vec<Vec<Option<char>>> = base
+            .key_symbols()
+            .map(|layers| layers.iter().map(|l| l.chars().next()).collect())
+            .collect();
+        let fixed_keys: Vec<bool> = base.flat_fixed_keys();
+        let combos = base.combo_positions();
+
+        let mut base_slots = Vec::new();
+        let mut permutable_key_map: AHashMap<char, usize> = AHashMap::default();
+        base_layout_symbols
+            .iter()
+            .zip(fixed_keys.iter())
+            .enumerate()
+            .filter(|(_i, (_key_layers, fixed))| !*fixed)
+            .for_each(|(i, (key_layers, _fixed))| match key_layers.first() {
+                Some(Some(c)) => {
+                    base_slots.push(i);
+                    permutable_key_map.entry(*c).or_insert(i);
+                }
+                _ => log::warn!(
+                    "Skipping non-fixed key {} without a symbol in the base layer",
+                    i
+                ),
+            });
+
+        let mut permuted_layer_ids = base.permuted_layers.clone();
+        permuted_layer_ids.sort_unstable();
+        permuted_layer_ids.dedup();
+        let permuted_layers = permuted_layer_ids
+            .into_iter()
+            .filter(|layer| *layer > 0)
+            .map(|layer| {
+                let (slots, symbols): (Vec<usize>, Vec<char>) = base_layout_symbols
+                    .iter()
+                    .zip(fixed_keys.iter())
+                    .enumerate()
+                    .filter(|(_i, (_key_layers, fixed))| !*fixed)
+                    .filter_map(|(i, (key_layers, _fixed))| {
+                        key_layers
+                            .get(layer as usize)
+                            .cloned()
+                            .flatten()
+                            .map(|c| (i, c))
+                    })
+                    .unzip();
+
+                PermutedLayer {
+                    layer,
+                    slots,
+                    symbols: AHashSet::from_iter(symbols),
+                }
+            })
+            .collect();
+
+        SlotLayoutGenerator {
+            base_layout_symbols,
+            fixed_keys,
+            base_slots,
+            permutable_key_map,
+            fixed_layers: base.fixed_layers,
+            permuted_layers,
+            modifiers: base.modifiers,
+            combos,
+            context_keys: base.context_keys,
+            compose: base.compose,
+            keyboard,
+        }
+    }
+
+    /// Generate a [`SlotLayoutGenerator`] from a YAML file
+    pub fn from_yaml_file(filename: &str, keyboard: Arc<Keyboard>) -> Result<Self> {
+        let f = File::open(filename)?;
+        let base: BaseLayoutYAML = serde_yaml::from_reader(f)?;
+        Ok(SlotLayoutGenerator::from_object(base, keyboard))
+    }
+
+    /// Generate a [`SlotLayoutGenerator`] from a YAML string
+    pub fn from_yaml_str(data: &str, keyboard: Arc<Keyboard>) -> Result<Self> {
+        let base: BaseLayoutYAML = serde_yaml::from_str(data)?;
+        Ok(SlotLayoutGenerator::from_object(base, keyboard))
+    }
+
+    /// The `(key index, layer)` slot addressed by each position of a layout string. Indices beyond the
+    /// keyboard's keys address combos.
+    pub fn slots(&self) -> Vec<(usize, u8)> {
+        let base_slots = self.base_slots.iter().map(|i| (*i, 0));
+        let permuted_slots = self
+            .permuted_layers
+            .iter()
+            .flat_map(|pl| pl.slots.iter().map(move |i| (*i, pl.layer)));
+
+        base_slots.chain(permuted_slots).collect()
+    }
+
+    /// Generate a [`Layout`] from given string representation of its permutable slots.
+    /// Does not check whether the given string is valid (sufficient, correct and unique charactors).
+    /// This is useful for plotting unfinished or invalid layouts.
+    pub fn generate_unchecked(&self, layout_keys: &str) -> Result<Layout> {
+        let mut key_layers: Vec<Vec<Option<char>>> = self.base_layout_symbols.clone();
+        let mut given_chars = layout_keys.chars();
+
+        // base layer: the symbols of non-fixed and non-permuted layers move with the base symbol
+        for key_idx in self.base_slots.iter().cloned() {
+            let given_char = match given_chars.next() {
+                Some(c) => c,
+                None => {
+                    log::warn!("Number of given symbols in layout string is smaller than number of non-fixed keys");
+                    break;
+                }
+            };
+
+            let home_key_idx = self
+                .permutable_key_map
+                .get(&given_char)
+                .ok_or_else(|| LayoutError::UnsupportedChars(0, given_char.to_string()))?;
+            let home_key_layers = &self.base_layout_symbols[*home_key_idx];
+            let own_key_layers = &self.base_layout_symbols[key_idx];
+            key_layers[key_idx] = (0..home_key_layers.len().max(own_key_layers.len()))
+                .map(|layer_id| {
+                    let layer = layer_id as u8;
+                    if self.fixed_layers.contains(&layer)
+                        || self.permuted_layers.iter().any(|pl| pl.layer == layer)
+                    {
+                        own_key_layers.get(layer_id).cloned().flatten()
+                    } else {
+                        home_key_layers.get(layer_id).cloned().flatten()
+                    }
+                })
+                .collect();
+        }
+
+        // permuted layers: each symbol is placed in its slot
+        for pl in self.permuted_layers.iter() {
+            for key_idx in pl.slots.iter() {
+                let given_char = match given_chars.next() {
+                    Some(c) => c,
+                    None => {
+                        log::warn!(
+                            "Number of given symbols in layout string is smaller than number of slots"
+                        );
+                        break;
+                    }
+                };
+                key_layers[*key_idx][pl.layer as usize] = Some(given_char);
+            }
+        }
+
+        let key_chars: Vec<Vec<char>> = key_layers
+            .into_iter()
+            .map(|layers| {
+                // keys without any symbol keep an empty base layer, such that they can serve as modifiers
+                let n_layers = layers
+                    .iter()
+                    .rposition(|c| c.is_some())
+                    .map(|pos| pos + 1)
+                    .unwrap_or(1);
+                layers
+                    .into_iter()
+                    .take(n_layers)
+                    .map(|c| c.unwrap_or(EMPTY_SLOT))
+                    .collect()
+            })
+            .collect();
+
+        Layout::new(
+            key_chars,
+            self.fixed_keys.clone(),
+            self.keyboard.clone(),
+            self.modifiers.clone(),
+            self.combos.clone(),
+            self.context_keys.clone(),
+            self.compose.clone(),
+        )
+    }
+
+    /// Get the list of permutable symbols of each layer (starting with the base layer)
+    pub fn permutable_keys(&self) -> Vec<(u8, Vec<char>)> {
+        let base_keys = (0, self.permutable_key_map.keys().cloned().collect());
+        let permuted_keys = self
+            .permuted_layers
+            .iter()
+            .map(|pl| (pl.layer, pl.symbols.iter().cloned().collect()));
+
+        std::iter::once(base_keys).chain(permuted_keys).collect()
+    }
+}
+
+/// Checks that the given segment of a layout string contains each of the layer's symbols exactly once.
+fn check_layer_chars(
+    layer: u8,
+    chars: &[char],
+    layer_set: &AHashSet<char>,
+    layout_keys: &str,
+) -> Result<()> {
+    let char_set: AHashSet<char> = AHashSet::from_iter(chars.iter().cloned());
+
+    // Check for duplicate chars
+    if char_set.len() != chars.len() {
+        let mut duplicates = AHashSet::default();
+        let mut seen_chars = AHashSet::default();
+        for c in chars.iter() {
+            if !seen_chars.insert(*c) {
+                duplicates.insert(*c);
+            }
+        }
+        return Err(LayoutError::DuplicateChars(
+            layer,
+            layout_keys.to_string(),
+            duplicates.iter().cloned().collect::<String>(),
+        )
+        .into());
+    }
+
+    let mut unsupported_chars: Vec<char> = char_set.difference(layer_set).cloned().collect();
+    let mut missing_chars: Vec<char> = layer_set.difference(&char_set).cloned().collect();
+
+    unsupported_chars.sort_unstable();
+    missing_chars.sort_unstable();
+
+    if !unsupported_chars.is_empty() {
+        return Err(
+            LayoutError::UnsupportedChars(layer, unsupported_chars.iter().collect()).into(),
+        );
+    }
+    if !missing_chars.is_empty() {
+        return Err(LayoutError::MissingChars(layer, missing_chars.iter().collect()).into());
+    }
+
+    Ok(())
+}
+
+impl LayoutGenerator for SlotLayoutGenerator {
+    /// Generate a [`Layout`] from a given string representation of its permutable slots
+    /// (base layer of non-fixed keys, then the slots of each permuted layer)
+    fn generate(&self, layout_keys: &str) -> Result<Layout> {
+        let chars: Vec<char> = layout_keys.chars().collect();
+
+        let segments = self.permutation_segments();
+        let n_slots: usize = segments.iter().sum();
+        if chars.len() != n_slots {
+            return Err(LayoutError::WrongSlotNumber(chars.len(), n_slots, segments).into());
+        }
+
+        let base_set: AHashSet<char> = AHashSet::from_iter(self.permutable_key_map.keys().cloned());
+        let layer_sets = std::iter::once((0, &base_set)).chain(
+            self.permuted_layers
+                .iter()
+                .map(|pl| (pl.layer, &pl.symbols)),
+        );
+
+        let mut offset = 0;
+        for ((layer, layer_set), len) in layer_sets.zip(segments.iter()) {
+            check_layer_chars(layer, &chars[offset..offset + len], layer_set, layout_keys)?;
+            offset += len;
+        }
+
+        self.generate_unchecked(layout_keys)
+    }
+
+    /// One segment for the base layer and one for each permuted layer
+    fn permutation_segments(&self) -> Vec<usize> {
+        std::iter::once(self.base_slots.len())
+            .chain(self.permuted_layers.iter().map(|pl| pl.slots.len()))
+            .collect()
+    }
+
+    fn base_layout_string(&self) -> String {
+        self.slots()
+            .into_iter()
+            .filter_map(|(key_idx, layer)| {
+                self.base_layout_symbols[key_idx]
+                    .get(layer as usize)
+                    .cloned()
+                    .flatten()
+            })
+            .collect()
+    }
+
+    /// Slots are mirrored within their layer
+    fn symmetry(&self) -> LayoutSymmetry {
+        LayoutSymmetry::new(&self.keyboard, &self.slots())
+    }
+}
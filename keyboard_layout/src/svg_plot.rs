@@ -0,0 +1,324 @@
+//! This module provides an SVG rendering of layouts that is based on the geometry of the keyboard,
+//! i.e. the `positions` of its keys. Unlike the text plots, it requires no plot templates.
+//!
+//! Each key shows the symbols of all its layers (the base layer in the center, higher layers around
+//! it) and is coloured by the finger pressing it. Optionally, values per key (e.g. the unigram load or
+//! the cost of a metric) are overlayed as heatmap.
+
+use crate::key::{Finger, MatrixPosition, Position};
+use crate::layout::{LayerKey, Layout};
+use crate::slot_layout_generator::EMPTY_SLOT;
+
+use ahash::AHashMap;
+
+/// Edge length of a key (including the gap to its neighbors) in pixels
+const KEY_SIZE: f64 = 60.0;
+/// Gap between neighboring keys
+const KEY_GAP: f64 = 4.0;
+/// Margin around the keyboard
+const MARGIN: f64 = 20.0;
+/// Height of the legend below the keyboard
+const LEGEND_HEIGHT: f64 = 30.0;
+/// Radius of the circles representing combos
+const COMBO_RADIUS: f64 = 12.0;
+
+/// Positions of the labels of higher layers relative to the key's center (as fraction of the key size)
+const LAYER_LABEL_OFFSETS: [(f64, f64); 8] = [
+    (-0.3, -0.28),
+    (0.3, -0.28),
+    (-0.3, 0.36),
+    (0.3, 0.36),
+    (0.0, -0.28),
+    (0.0, 0.36),
+    (-0.3, 0.04),
+    (0.3, 0.04),
+];
+
+/// Values per key to overlay on the keys as heatmap, e.g. the unigram load or the cost of a metric
+#[derive(Clone, Debug, Default)]
+pub struct Heatmap {
+    /// Description of the values
+    pub title: String,
+    /// Value of each key (keys without value are not coloured)
+    pub values: AHashMap<MatrixPosition, f64>,
+}
+
+impl Heatmap {
+    pub fn new(title: &str, values: AHashMap<MatrixPosition, f64>) -> Self {
+        Self {
+            title: title.to_string(),
+            values,
+        }
+    }
+}
+
+fn finger_colour(finger: Finger) -> &'static str {
+    match finger {
+        Finger::Thumb => "#d9c7f0",
+        Finger::Index => "#b3d7f2",
+        Finger::Middle => "#c9e7b0",
+        Finger::Ring => "#f7dca6",
+        Finger::Pinky => "#f4b8b8",
+    }
+}
+
+/// Colour of a heatmap value (as fraction of the maximal value), from white over yellow to red
+fn heat_colour(fraction: f64) -> String {
+    let stops = [
+        (255.0, 255.0, 255.0),
+        (254.0, 217.0, 118.0),
+        (215.0, 48.0, 31.0),
+    ];
+    let x = fraction.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let i = (x.floor() as usize).min(stops.len() - 2);
+    let t = x - i as f64;
+    let mix = |a: f64, b: f64| (a + t * (b - a)).round() as u8;
+    let (from, to) = (stops[i], stops[i + 1]);
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(from.0, to.0),
+        mix(from.1, to.1),
+        mix(from.2, to.2)
+    )
+}
+
+/// Printable representation of a symbol, escaped for XML
+fn fmt_symbol(c: char) -> String {
+    match c {
+        ' ' => "␣".to_string(),
+        '\n' => "\u{23ce}".to_string(),
+        '\t' => "\u{21e5}".to_string(),
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '"' => "&quot;".to_string(),
+        c if c.is_control() => "\u{2327}".to_string(),
+        c => c.to_string(),
+    }
+}
+
+/// Escape a text for XML
+fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '&' | '<' | '>' | '"' => fmt_symbol(c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Text elements with the symbols of a key's layers, centered at `(x, y)`
+fn layer_labels(layerkeys: &[&LayerKey], x: f64, y: f64, scale: f64) -> String {
+    let mut labels = String::new();
+    for lk in layerkeys.iter().filter(|lk| lk.symbol != EMPTY_SLOT) {
+        let class = if lk.is_fixed { "fixed" } else { "symbol" };
+        let (dx, dy, size) = match lk.layer {
+            0 => (0.0, 0.12, 0.36),
+            layer => match LAYER_LABEL_OFFSETS.get(layer as usize - 1) {
+                Some((dx, dy)) => (*dx, *dy, 0.2),
+                None => continue,
+            },
+        };
+        labels.push_str(&format!(
+            r#"<text class="{}" x="{:.1}" y="{:.1}" font-size="{:.1}">{}</text>"#,
+            class,
+            x + dx * scale,
+            y + dy * scale,
+            size * scale,
+            fmt_symbol(lk.symbol),
+        ));
+        labels.push('\n');
+    }
+    labels
+}
+
+/// Plot an SVG image of the layout (see the module description).
+pub fn plot(layout: &Layout, heatmap: Option<&Heatmap>) -> String {
+    let keys = &layout.keyboard.keys;
+    if keys.is_empty() {
+        return String::new();
+    }
+
+    // the smallest distance between two keys is taken as unit of the `positions`
+    let unit = keys
+        .iter()
+        .enumerate()
+        .flat_map(|(i, k1)| {
+            keys[i + 1..]
+                .iter()
+                .map(|k2| k1.position.distance(&k2.position))
+        })
+        .filter(|d| *d > 0.0)
+        .fold(f64::INFINITY, f64::min);
+    let unit = if unit.is_finite() { unit } else { 1.0 };
+
+    let min_x = keys
+        .iter()
+        .map(|k| k.position.0)
+        .fold(f64::INFINITY, f64::min);
+    let min_y = keys
+        .iter()
+        .map(|k| k.position.1)
+        .fold(f64::INFINITY, f64::min);
+    let max_x = keys
+        .iter()
+        .map(|k| k.position.0)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let max_y = keys
+        .iter()
+        .map(|k| k.position.1)
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    let width = ((max_x - min_x) / unit + 1.0) * KEY_SIZE + 2.0 * MARGIN;
+    let keyboard_height = ((max_y - min_y) / unit + 1.0) * KEY_SIZE + 2.0 * MARGIN;
+    let height = keyboard_height + LEGEND_HEIGHT;
+    // center of a key in the image
+    let center = |p: &Position| {
+        (
+            MARGIN + ((p.0 - min_x) / unit + 0.5) * KEY_SIZE,
+            MARGIN + ((p.1 - min_y) / unit + 0.5) * KEY_SIZE,
+        )
+    };
+
+    let max_value = heatmap
+        .map(|h| h.values.values().fold(0.0, |m: f64, v| m.max(v.abs())))
+        .unwrap_or(0.0);
+
+    let mut svg = format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="sans-serif" text-anchor="middle">
+<style>
+  .key {{ stroke: #555555; stroke-width: 1; }}
+  .symbol {{ fill: #000000; }}
+  .fixed {{ fill: #888888; }}
+  .value {{ fill: #333333; }}
+  .legend {{ fill: #333333; font-size: 12px; text-anchor: start; }}
+</style>
+<rect width="100%" height="100%" fill="#ffffff"/>
+"##,
+        width, height, width, height
+    );
+
+    let key_layerkeys: Vec<Vec<&LayerKey>> = layout.key_layerkeys().collect();
+
+    // keys
+    let size = KEY_SIZE - KEY_GAP;
+    for (key, layerkeys) in keys.iter().zip(key_layerkeys.iter()) {
+        let (x, y) = center(&key.position);
+        let value = heatmap.and_then(|h| h.values.get(&key.matrix_position));
+        let fill = match value {
+            Some(value) if max_value > 0.0 => heat_colour(value.abs() / max_value),
+            Some(_) => heat_colour(0.0),
+            None => finger_colour(key.finger).to_string(),
+        };
+        svg.push_str(&format!(
+            r#"<g><title>{:?} {:?} ({}, {})</title>"#,
+            key.hand, key.finger, key.matrix_position.0, key.matrix_position.1
+        ));
+        svg.push_str(&format!(
+            r#"<rect class="key" x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="5" fill="{}"/>"#,
+            x - size / 2.0,
+            y - size / 2.0,
+            size,
+            size,
+            fill
+        ));
+        svg.push('\n');
+        if heatmap.is_some() {
+            // the finger is indicated by a stripe at the bottom of the key
+            svg.push_str(&format!(
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="5" fill="{}"/>"#,
+                x - size / 2.0 + 4.0,
+                y + size / 2.0 - 7.0,
+                size - 8.0,
+                finger_colour(key.finger)
+            ));
+            svg.push('\n');
+        }
+        svg.push_str(&layer_labels(layerkeys, x, y, size));
+        if let Some(value) = value {
+            svg.push_str(&format!(
+                r#"<text class="value" x="{:.1}" y="{:.1}" font-size="8">{:.2}</text>"#,
+                x,
+                y + size / 2.0 - 9.0,
+                value
+            ));
+            svg.push('\n');
+        }
+        svg.push_str("</g>\n");
+    }
+
+    // combos are drawn as circles between their keys
+    for layerkeys in key_layerkeys.iter().skip(keys.len()) {
+        let parts = match layerkeys.first() {
+            Some(lk) => &lk.combo_keys,
+            None => continue,
+        };
+        let positions: Vec<(f64, f64)> = parts
+            .iter()
+            .map(|part| center(&layout.get_layerkey(part).key.position))
+            .collect();
+        if positions.is_empty() {
+            continue;
+        }
+        let x = positions.iter().map(|p| p.0).sum::<f64>() / positions.len() as f64;
+        let y = positions.iter().map(|p| p.1).sum::<f64>() / positions.len() as f64;
+        svg.push_str(&format!(
+            r##"<circle class="key" cx="{:.1}" cy="{:.1}" r="{:.1}" fill="#ffffff"/>"##,
+            x, y, COMBO_RADIUS
+        ));
+        svg.push('\n');
+        svg.push_str(&layer_labels(
+            &layerkeys[..1],
+            x,
+            y - 0.08 * 2.0 * COMBO_RADIUS,
+            2.0 * COMBO_RADIUS,
+        ));
+    }
+
+    // legend
+    let legend_y = keyboard_height + LEGEND_HEIGHT / 2.0;
+    let mut legend_x = MARGIN;
+    for finger in [
+        Finger::Thumb,
+        Finger::Index,
+        Finger::Middle,
+        Finger::Ring,
+        Finger::Pinky,
+    ] {
+        svg.push_str(&format!(
+            r#"<rect class="key" x="{:.1}" y="{:.1}" width="12" height="12" fill="{}"/>"#,
+            legend_x,
+            legend_y - 10.0,
+            finger_colour(finger)
+        ));
+        svg.push_str(&format!(
+            r#"<text class="legend" x="{:.1}" y="{:.1}">{:?}</text>"#,
+            legend_x + 16.0,
+            legend_y,
+            finger
+        ));
+        svg.push('\n');
+        legend_x += 70.0;
+    }
+    if let Some(heatmap) = heatmap {
+        for (i, fraction) in [0.0, 0.25, 0.5, 0.75, 1.0].iter().enumerate() {
+            svg.push_str(&format!(
+                r#"<rect x="{:.1}" y="{:.1}" width="12" height="12" fill="{}"/>"#,
+                legend_x + 12.0 * i as f64,
+                legend_y - 10.0,
+                heat_colour(*fraction)
+            ));
+        }
+        svg.push_str(&format!(
+            r#"<text class="legend" x="{:.1}" y="{:.1}">{} (max. {:.2})</text>"#,
+            legend_x + 66.0,
+            legend_y,
+            escape(&heatmap.title),
+            max_value
+        ));
+        svg.push('\n');
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
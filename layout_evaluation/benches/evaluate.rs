@@ -1,11 +1,14 @@
 use keyboard_layout::{
-    config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
-    neo_layout_generator::NeoLayoutGenerator,
+    config::LayoutConfig, keyboard::Keyboard, layout::Layout, layout_generator::LayoutGenerator,
+    neo_layout_generator::NeoLayoutGenerator, slot_layout_generator::SlotLayoutGenerator,
 };
 use layout_evaluation::{
     config::EvaluationParameters,
     evaluation::Evaluator,
-    ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+    ngram_mapper::{
+        dense_ngram_mapper::DenseNgramMapper, on_demand_ngram_mapper::OnDemandNgramMapper,
+        NgramMapper,
+    },
     ngrams::{Bigrams, Trigrams, Unigrams},
 };
 
@@ -15,6 +18,7 @@ use std::{path::Path, sync::Arc};
 
 const NGRAMS: &str = "../ngrams/eng/eng_wiki_1m";
 const LAYOUT_CONFIG: &str = "../config/keyboard/standard.yml";
+const SVALBOARD_LAYOUT_CONFIG: &str = "../config/keyboard/svalboard.yml";
 const EVALUATION_PARAMETERS: &str = "../config/evaluation/default.yml";
 
 #[derive(Clone, Deserialize, Debug)]
@@ -24,14 +28,8 @@ pub struct NGramConfig {
     pub trigrams: String,
 }
 
-pub fn evaluate_bench(c: &mut Criterion) {
-    let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG)
-        .unwrap_or_else(|e| panic!("Could not load config file '/keyboard/standard.yml': {}", e));
-
-    let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
-
-    let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
-
+/// The [`OnDemandNgramMapper`] and the [`DenseNgramMapper`] (in that order)
+fn ngram_mappers(split_modifiers: bool) -> (OnDemandNgramMapper, DenseNgramMapper) {
     let eval_params = EvaluationParameters::from_yaml(EVALUATION_PARAMETERS).unwrap_or_else(|_| {
         panic!("Could not read evaluation yaml file '/evaluation/default.yml'")
     });
@@ -51,7 +49,8 @@ pub fn evaluate_bench(c: &mut Criterion) {
     let mut trigrams = Trigrams::from_file(p.to_str().unwrap())
         .unwrap_or_else(|_| panic!("Could not read 3-gramme file from '{:?}'.", p));
 
-    let ngram_mapper_config = eval_params.ngram_mapper.clone();
+    let mut ngram_mapper_config = eval_params.ngram_mapper.clone();
+    ngram_mapper_config.split_modifiers.enabled = split_modifiers;
     let ngrams_config = eval_params.ngrams.clone();
 
     if ngrams_config.increase_common_ngrams.enabled {
@@ -60,11 +59,47 @@ pub fn evaluate_bench(c: &mut Criterion) {
         trigrams = trigrams.increase_common(&ngrams_config.increase_common_ngrams);
     }
 
+    let dense_ngram_provider = DenseNgramMapper::with_ngrams(
+        unigrams.clone(),
+        bigrams.clone(),
+        trigrams.clone(),
+        ngram_mapper_config.clone(),
+    );
     let ngram_provider =
         OnDemandNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config);
 
-    let evaluator =
-        Evaluator::default(Box::new(ngram_provider)).default_metrics(&eval_params.metrics);
+    (ngram_provider, dense_ngram_provider)
+}
+
+/// Evaluators using the [`OnDemandNgramMapper`] and the [`DenseNgramMapper`] (in that order)
+fn evaluators(split_modifiers: bool) -> (Evaluator, Evaluator) {
+    let eval_params = EvaluationParameters::from_yaml(EVALUATION_PARAMETERS).unwrap_or_else(|_| {
+        panic!("Could not read evaluation yaml file '/evaluation/default.yml'")
+    });
+    let (ngram_provider, dense_ngram_provider) = ngram_mappers(split_modifiers);
+
+    (
+        Evaluator::default(Box::new(ngram_provider)).default_metrics(&eval_params.metrics),
+        Evaluator::default(Box::new(dense_ngram_provider)).default_metrics(&eval_params.metrics),
+    )
+}
+
+/// Map all ngrams of a layout (without evaluating any metrics)
+fn map_ngrams(ngram_mapper: &dyn NgramMapper, layout: &Layout) -> usize {
+    ngram_mapper.map_unigrams(layout).grams.len()
+        + ngram_mapper.map_bigrams(layout).grams.len()
+        + ngram_mapper.map_trigrams(layout).grams.len()
+}
+
+pub fn evaluate_bench(c: &mut Criterion) {
+    let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG)
+        .unwrap_or_else(|e| panic!("Could not load config file '/keyboard/standard.yml': {}", e));
+
+    let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+
+    let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+
+    let (evaluator, dense_evaluator) = evaluators(false);
 
     let layout = match layout_generator.generate("jduaxphlmwqßctieobnrsgfvüäöyz,.k") {
         Ok(layout) => layout,
@@ -73,10 +108,116 @@ pub fn evaluate_bench(c: &mut Criterion) {
             panic!("{:?}", e);
         }
     };
+    let (ngram_mapper, dense_ngram_mapper) = ngram_mappers(false);
+    c.bench_function("map_ngrams", |b| {
+        b.iter(|| map_ngrams(&ngram_mapper, &layout));
+    });
+    c.bench_function("map_ngrams_dense", |b| {
+        b.iter(|| map_ngrams(&dense_ngram_mapper, &layout));
+    });
+
     c.bench_function("evaluate", |b| {
         b.iter(|| evaluator.evaluate_layout(&layout));
     });
+    c.bench_function("evaluate_dense", |b| {
+        b.iter(|| dense_evaluator.evaluate_layout(&layout));
+    });
+}
+
+pub fn evaluate_svalboard_bench(c: &mut Criterion) {
+    let layout_config = LayoutConfig::from_yaml(SVALBOARD_LAYOUT_CONFIG).unwrap_or_else(|e| {
+        panic!(
+            "Could not load config file '/keyboard/svalboard.yml': {}",
+            e
+        )
+    });
+
+    let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+
+    let layout_generator = SlotLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+
+    let layout = match layout_generator.generate(&layout_generator.base_layout_string()) {
+        Ok(layout) => layout,
+        Err(e) => {
+            log::error!("Error in generating layout: {:?}", e);
+            panic!("{:?}", e);
+        }
+    };
+
+    // the Svalboard's higher layers are hold layers, split modifiers expand their ngrams
+    for split_modifiers in [false, true] {
+        let suffix = if split_modifiers {
+            "_split_modifiers"
+        } else {
+            ""
+        };
+        let (evaluator, dense_evaluator) = evaluators(split_modifiers);
+        let (ngram_mapper, dense_ngram_mapper) = ngram_mappers(split_modifiers);
+
+        c.bench_function(&format!("map_ngrams_svalboard{}", suffix), |b| {
+            b.iter(|| map_ngrams(&ngram_mapper, &layout));
+        });
+        c.bench_function(&format!("map_ngrams_svalboard_dense{}", suffix), |b| {
+            b.iter(|| map_ngrams(&dense_ngram_mapper, &layout));
+        });
+
+        c.bench_function(&format!("evaluate_svalboard{}", suffix), |b| {
+            b.iter(|| evaluator.evaluate_layout(&layout));
+        });
+        c.bench_function(&format!("evaluate_svalboard_dense{}", suffix), |b| {
+            b.iter(|| dense_evaluator.evaluate_layout(&layout));
+        });
+    }
+}
+
+pub fn update_state_bench(c: &mut Criterion) {
+    let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG)
+        .unwrap_or_else(|e| panic!("Could not load config file '/keyboard/standard.yml': {}", e));
+
+    let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+
+    let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+
+    // layouts differing by a single swap from their predecessors (the last one from the first one),
+    // keys with symbols that force a full evaluation when moved are not swapped
+    let mut layout_chars: Vec<char> = "jduaxphlmwqßctieobnrsgfvüäöyz,.k".chars().collect();
+    let positions: Vec<usize> = (0..layout_chars.len())
+        .filter(|i| !"uvü".contains(layout_chars[*i]))
+        .collect();
+    let swaps: Vec<(usize, usize)> = (0..positions.len())
+        .map(|k| (positions[k], positions[(7 * k + 3) % positions.len()]))
+        .filter(|(i, j)| i != j)
+        .collect();
+    let mut layouts: Vec<Layout> = Vec::new();
+    for (i, j) in swaps.iter().chain(swaps.iter().rev()) {
+        layout_chars.swap(*i, *j);
+        let layout_str: String = layout_chars.iter().collect();
+        layouts.push(layout_generator.generate(&layout_str).unwrap());
+    }
+
+    let (evaluator, dense_evaluator) = evaluators(false);
+    for (evaluator, suffix) in [(evaluator, ""), (dense_evaluator, "_dense")] {
+        let mut state = evaluator.evaluation_state(layouts.last().unwrap());
+        let mut layout_iter = layouts.iter().cycle();
+        c.bench_function(&format!("update_state{}", suffix), |b| {
+            b.iter(|| evaluator.update_state(&mut state, layout_iter.next().unwrap()));
+        });
+
+        let mut layout_iter = layouts.iter().cycle();
+        c.bench_function(&format!("evaluate_swapped{}", suffix), |b| {
+            b.iter(|| {
+                evaluator
+                    .evaluate_layout(layout_iter.next().unwrap())
+                    .total_cost()
+            });
+        });
+    }
 }
 
-criterion_group!(benches, evaluate_bench);
+criterion_group!(
+    benches,
+    evaluate_bench,
+    evaluate_svalboard_bench,
+    update_state_bench
+);
 criterion_main!(benches);
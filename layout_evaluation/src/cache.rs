@@ -1,11 +1,28 @@
+use keyboard_layout::layout_symmetry::LayoutSymmetry;
+
 use ahash::AHashMap;
+use anyhow::Result;
 use colored::Colorize;
 use parking_lot::Mutex;
-use std::{fmt, sync::Arc};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Stores a newly computed cache entry (layout string and value) on disk.
+type Persist<T> = Arc<dyn Fn(&str, &T) + Send + Sync>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Cache<T: Clone> {
     cache: Arc<Mutex<AHashMap<String, T>>>,
+    persist: Option<Persist<T>>,
+    /// Layouts and their mirror images share the entry of the canonical layout string
+    symmetry: Option<Arc<LayoutSymmetry>>,
 }
 
 impl<T: Clone> Default for Cache<T> {
@@ -14,37 +31,166 @@ impl<T: Clone> Default for Cache<T> {
     }
 }
 
+impl<T: Clone + fmt::Debug> fmt::Debug for Cache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("cache", &self.cache)
+            .field("persistent", &self.persist.is_some())
+            .field("symmetry", &self.symmetry)
+            .finish()
+    }
+}
+
 impl<T: Clone> Cache<T> {
     pub fn new() -> Self {
         Self {
             cache: Arc::new(Mutex::new(AHashMap::default())),
+            persist: None,
+            symmetry: None,
+        }
+    }
+
+    /// Considers a layout and its mirror image (see [`LayoutSymmetry`]) as identical, such that
+    /// both share one entry. Entries are stored under the canonical layout string.
+    pub fn with_symmetry(mut self, symmetry: LayoutSymmetry) -> Self {
+        self.symmetry = Some(Arc::new(symmetry));
+        self
+    }
+
+    /// The layout string under which the entry of a layout is stored
+    fn key(&self, layout_str: &str) -> String {
+        match &self.symmetry {
+            Some(symmetry) => symmetry.canonicalize(layout_str),
+            None => layout_str.to_owned(),
         }
     }
 
     pub fn get_or_insert_with<F: Fn() -> T>(&self, elem: &str, f: F) -> T {
+        let key = self.key(elem);
         let cache_val;
         {
             let cache = self.cache.lock();
-            cache_val = cache.get(elem).cloned();
+            cache_val = cache.get(&key).cloned();
         }
         cache_val.unwrap_or_else(|| {
             let res = f();
+            if let Some(persist) = &self.persist {
+                persist(&key, &res);
+            }
             {
                 let mut cache = self.cache.lock();
-                cache.insert(elem.to_owned(), res.clone());
+                cache.insert(key, res.clone());
             }
             res
         })
     }
+
+    /// Returns a copy of all cached entries.
+    pub fn entries(&self) -> Vec<(String, T)> {
+        let cache = self.cache.lock();
+        cache.iter().map(|(s, c)| (s.clone(), c.clone())).collect()
+    }
+
+    /// Inserts the given entries (e.g. restored from a checkpoint), replacing existing ones.
+    /// They are not written to the file of a persistent cache.
+    pub fn extend<I: IntoIterator<Item = (String, T)>>(&self, entries: I) {
+        let entries: Vec<(String, T)> = entries
+            .into_iter()
+            .map(|(layout_str, value)| (self.key(&layout_str), value))
+            .collect();
+        let mut cache = self.cache.lock();
+        cache.extend(entries);
+    }
+}
+
+impl<T: Clone + Serialize + DeserializeOwned + 'static> Cache<T> {
+    /// Creates a cache that is backed by the file `<name>-<fingerprint>.jsonl` in `dir`.
+    ///
+    /// Entries stored by previous runs (or concurrently running processes, up to the time of
+    /// creation) are loaded. New entries are appended to the file. The `fingerprint` identifies
+    /// the inputs the cached values depend on (see [`fingerprint`]), such that changed inputs
+    /// use a fresh file.
+    pub fn persistent(dir: &str, name: &str, fingerprint: &str) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let filename: PathBuf = Path::new(dir).join(format!("{}-{}.jsonl", name, fingerprint));
+
+        let mut entries = AHashMap::default();
+        if filename.exists() {
+            let reader = BufReader::new(File::open(&filename)?);
+            for line in reader.lines() {
+                // lines may be incomplete if a process was killed while writing
+                match serde_json::from_str::<(String, T)>(&line?) {
+                    Ok((layout_str, value)) => {
+                        entries.insert(layout_str, value);
+                    }
+                    Err(e) => log::debug!("Skipping invalid cache entry: {}", e),
+                }
+            }
+        }
+        log::info!(
+            "Loaded {} cached results from '{}'",
+            entries.len(),
+            filename.display()
+        );
+
+        let file = Mutex::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&filename)?,
+        );
+        let persist: Persist<T> = Arc::new(move |layout_str: &str, value: &T| {
+            let res = serde_json::to_string(&(layout_str, value))
+                .map_err(anyhow::Error::from)
+                .and_then(|line| {
+                    // write each entry at once, such that appends of several processes do not mix
+                    Ok(file.lock().write_all(format!("{}\n", line).as_bytes())?)
+                });
+            if let Err(e) = res {
+                log::error!(
+                    "Couldn't write cache entry to '{}': {}",
+                    filename.display(),
+                    e
+                );
+            }
+        });
+
+        Ok(Self {
+            cache: Arc::new(Mutex::new(entries)),
+            persist: Some(persist),
+            symmetry: None,
+        })
+    }
+}
+
+/// Version of the cached values, to be increased whenever metrics yield different results for the
+/// same inputs (or the format of cache entries changes), such that persistent caches are invalidated.
+pub const CACHE_VERSION: u32 = 2;
+
+/// Hash of the inputs that cached values depend on (e.g. contents of configuration and ngram files).
+///
+/// The version of this crate and the [`CACHE_VERSION`] are included, such that changed metrics
+/// invalidate persistent caches.
+pub fn fingerprint<I: IntoIterator<Item = B>, B: AsRef<[u8]>>(inputs: I) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(CACHE_VERSION.to_le_bytes());
+    for input in inputs {
+        let input = input.as_ref();
+        hasher.update((input.len() as u64).to_le_bytes());
+        hasher.update(input);
+    }
+
+    hasher.finalize()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
 impl<T: Clone + fmt::Display + PartialOrd> Cache<T> {
     pub fn highlighted_fmt(&self, current_layout_str: Option<&str>, max_entries: usize) -> String {
-        let mut results: Vec<(String, T)>;
-        {
-            let cache = self.cache.lock();
-            results = cache.iter().map(|(s, c)| (s.clone(), c.clone())).collect();
-        }
+        let mut results = self.entries();
+        let current_layout_str = current_layout_str.map(|l| self.key(l));
 
         if results.is_empty() {
             String::new()
@@ -63,7 +209,7 @@ impl<T: Clone + fmt::Display + PartialOrd> Cache<T> {
                     break;
                 }
                 let result_line = format!("{} ({:.1})", l, cost);
-                if current_layout_str.is_some() && current_layout_str.unwrap() == l {
+                if current_layout_str.as_ref() == Some(&l) {
                     output_string.push_str(&format!(
                         "\n{:>width$}. {} (current)",
                         i + 1,
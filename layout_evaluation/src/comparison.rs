@@ -0,0 +1,480 @@
+//! The `comparison` module contains structs describing the differences between the evaluations of
+//! two layouts, i.e. which metrics' costs changed, which keys moved and which ngrams became more or
+//! less costly.
+
+use crate::results::{EvaluationResult, MetricType, NgramCosts};
+
+use keyboard_layout::{key::MatrixPosition, layout::Layout};
+
+use ahash::{AHashMap, AHashSet};
+use colored::Colorize;
+use serde::Serialize;
+use std::fmt;
+
+/// Format a cost difference (green if layout B is better, red if it is worse).
+fn fmt_delta(delta: f64, precision: usize) -> String {
+    let s = format!("{:>+9.*}", precision, delta);
+    if delta < 0.0 {
+        s.green().to_string()
+    } else if delta > 0.0 {
+        s.red().to_string()
+    } else {
+        s
+    }
+}
+
+/// The weighted and normalized costs of a metric for both layouts.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricDelta {
+    pub metric_type: MetricType,
+    pub name: String,
+    /// Cost for layout A (`None` if the metric is not evaluated for it)
+    pub cost_a: Option<f64>,
+    /// Cost for layout B (`None` if the metric is not evaluated for it)
+    pub cost_b: Option<f64>,
+    /// Cost for layout B minus cost for layout A
+    pub delta: f64,
+}
+
+/// Location of a symbol in a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SymbolLocation {
+    pub layer: u8,
+    pub matrix_position: MatrixPosition,
+}
+
+/// A symbol that is located at different keys (or layers) in both layouts.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyMove {
+    pub symbol: char,
+    /// Location in layout A (`None` if the symbol is not part of it)
+    pub from: Option<SymbolLocation>,
+    /// Location in layout B (`None` if the symbol is not part of it)
+    pub to: Option<SymbolLocation>,
+    /// Whether the symbol (of a higher layer) only moves along with the base layer symbol of its key
+    pub with_base_key: bool,
+}
+
+/// The plots of a layer (with moved keys) for both layouts.
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerPlots {
+    pub layer: usize,
+    pub plot_a: String,
+    pub plot_b: String,
+}
+
+/// The contribution of an ngram to the total costs of both layouts.
+#[derive(Debug, Clone, Serialize)]
+pub struct NgramDelta {
+    pub ngram: String,
+    pub cost_a: f64,
+    pub cost_b: f64,
+    /// Cost for layout B minus cost for layout A
+    pub delta: f64,
+}
+
+/// Describes the differences between the evaluations of two layouts ("A" and "B").
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutComparison {
+    pub layout_a: String,
+    pub layout_b: String,
+    pub total_cost_a: f64,
+    pub total_cost_b: f64,
+    /// Cost differences of all metrics
+    pub metrics: Vec<MetricDelta>,
+    /// Symbols that are located differently
+    pub moved_keys: Vec<KeyMove>,
+    /// Plots of all layers that contain moved keys
+    pub layer_plots: Vec<LayerPlots>,
+    /// Bigrams with the largest (absolute) changes in cost contribution
+    pub bigrams: Vec<NgramDelta>,
+    /// Trigrams with the largest (absolute) changes in cost contribution
+    pub trigrams: Vec<NgramDelta>,
+}
+
+impl LayoutComparison {
+    /// Compare the evaluations of two layouts. For bigrams and trigrams, the `n_ngrams` ones with the
+    /// largest changes in cost contribution are kept.
+    pub fn new(
+        (layout_a, result_a, ngram_costs_a): (&Layout, &EvaluationResult, &NgramCosts),
+        (layout_b, result_b, ngram_costs_b): (&Layout, &EvaluationResult, &NgramCosts),
+        n_ngrams: usize,
+    ) -> Self {
+        let moved_keys = Self::moved_keys(layout_a, layout_b);
+        let mut layers: Vec<usize> = moved_keys
+            .iter()
+            .filter(|m| !m.with_base_key)
+            .flat_map(|m| m.from.iter().chain(m.to.iter()))
+            .map(|loc| loc.layer as usize)
+            .collect();
+        layers.sort_unstable();
+        layers.dedup();
+        let layer_plots = layers
+            .into_iter()
+            .map(|layer| LayerPlots {
+                layer,
+                plot_a: layout_a.plot_layer(layer),
+                plot_b: layout_b.plot_layer(layer),
+            })
+            .collect();
+
+        Self {
+            layout_a: layout_a.as_text(),
+            layout_b: layout_b.as_text(),
+            total_cost_a: result_a.total_cost(),
+            total_cost_b: result_b.total_cost(),
+            metrics: Self::metric_deltas(result_a, result_b),
+            moved_keys,
+            layer_plots,
+            bigrams: Self::ngram_deltas(&ngram_costs_a.bigrams, &ngram_costs_b.bigrams, n_ngrams),
+            trigrams: Self::ngram_deltas(
+                &ngram_costs_a.trigrams,
+                &ngram_costs_b.trigrams,
+                n_ngrams,
+            ),
+        }
+    }
+
+    /// Match the metrics of both results by type and name.
+    fn metric_deltas(result_a: &EvaluationResult, result_b: &EvaluationResult) -> Vec<MetricDelta> {
+        let costs = |result: &EvaluationResult| -> Vec<(MetricType, String, f64)> {
+            result
+                .iter()
+                .flat_map(|results| {
+                    results.metric_costs.iter().map(|mc| {
+                        (
+                            results.metric_type.clone(),
+                            mc.core.name.clone(),
+                            mc.weighted_cost,
+                        )
+                    })
+                })
+                .collect()
+        };
+        let costs_a = costs(result_a);
+        let mut costs_b = costs(result_b);
+
+        let mut deltas: Vec<MetricDelta> = costs_a
+            .into_iter()
+            .map(|(metric_type, name, cost_a)| {
+                let idx_b = costs_b
+                    .iter()
+                    .position(|(t, n, _)| *t == metric_type && *n == name);
+                let cost_b = idx_b.map(|idx| costs_b.remove(idx).2);
+                MetricDelta {
+                    delta: cost_b.unwrap_or(0.0) - cost_a,
+                    metric_type,
+                    name,
+                    cost_a: Some(cost_a),
+                    cost_b,
+                }
+            })
+            .collect();
+        deltas.extend(
+            costs_b
+                .into_iter()
+                .map(|(metric_type, name, cost_b)| MetricDelta {
+                    metric_type,
+                    name,
+                    cost_a: None,
+                    cost_b: Some(cost_b),
+                    delta: cost_b,
+                }),
+        );
+
+        deltas
+    }
+
+    /// Collect all (non-modifier) symbols whose location differs between the layouts.
+    fn moved_keys(layout_a: &Layout, layout_b: &Layout) -> Vec<KeyMove> {
+        let location = |layout: &Layout, symbol: &char| {
+            layout
+                .get_layerkey_for_symbol(symbol)
+                .map(|lk| SymbolLocation {
+                    layer: lk.layer,
+                    matrix_position: lk.key.matrix_position,
+                })
+        };
+
+        // whether the base layer symbol of the key at `from` is located at `to` in layout B
+        let with_base_key = |from: &SymbolLocation, to: &SymbolLocation| {
+            from.layer > 0
+                && from.layer == to.layer
+                && layout_a
+                    .layerkeys
+                    .iter()
+                    .find(|lk| lk.layer == 0 && lk.key.matrix_position == from.matrix_position)
+                    .and_then(|lk| location(layout_b, &lk.symbol))
+                    .is_some_and(|base_to| {
+                        base_to.layer == 0 && base_to.matrix_position == to.matrix_position
+                    })
+        };
+
+        let mut seen = AHashSet::default();
+        layout_a
+            .layerkeys
+            .iter()
+            .chain(layout_b.layerkeys.iter())
+            .filter(|lk| lk.is_modifier.is_none() && lk.compose_sequence.is_empty())
+            .filter(|lk| seen.insert(lk.symbol))
+            .filter_map(|lk| {
+                let from = location(layout_a, &lk.symbol);
+                let to = location(layout_b, &lk.symbol);
+                (from != to).then(|| KeyMove {
+                    symbol: lk.symbol,
+                    with_base_key: match (&from, &to) {
+                        (Some(from), Some(to)) => with_base_key(from, to),
+                        _ => false,
+                    },
+                    from,
+                    to,
+                })
+            })
+            .collect()
+    }
+
+    /// The `n` ngrams with the largest absolute differences in cost contribution.
+    fn ngram_deltas(
+        costs_a: &AHashMap<String, f64>,
+        costs_b: &AHashMap<String, f64>,
+        n: usize,
+    ) -> Vec<NgramDelta> {
+        let ngrams: AHashSet<&String> = costs_a.keys().chain(costs_b.keys()).collect();
+        let mut deltas: Vec<NgramDelta> = ngrams
+            .into_iter()
+            .map(|ngram| {
+                let cost_a = costs_a.get(ngram).cloned().unwrap_or(0.0);
+                let cost_b = costs_b.get(ngram).cloned().unwrap_or(0.0);
+                NgramDelta {
+                    ngram: ngram.clone(),
+                    cost_a,
+                    cost_b,
+                    delta: cost_b - cost_a,
+                }
+            })
+            .collect();
+        deltas.sort_by(|d1, d2| {
+            d2.delta
+                .abs()
+                .partial_cmp(&d1.delta.abs())
+                .unwrap()
+                .then_with(|| d1.ngram.cmp(&d2.ngram))
+        });
+        deltas.truncate(n);
+
+        deltas
+    }
+
+    /// Highlight the characters of `plot_b` that differ from `plot_a`.
+    fn highlight_changes(plot_a: &str, plot_b: &str) -> String {
+        plot_b
+            .lines()
+            .zip(plot_a.lines().chain(std::iter::repeat("")))
+            .map(|(line_b, line_a)| {
+                let mut chars_a = line_a.chars();
+                line_b
+                    .chars()
+                    .map(|c| match chars_a.next() {
+                        Some(c_a) if c_a == c => c.to_string(),
+                        _ => c.to_string().red().bold().to_string(),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for LayoutComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", "Layout A:".bold(), self.layout_a)?;
+        writeln!(f, "{} {}", "Layout B:".bold(), self.layout_b)?;
+
+        for plots in self.layer_plots.iter() {
+            writeln!(
+                f,
+                "\nLayout A (layer {}):\n{}",
+                plots.layer + 1,
+                plots.plot_a
+            )?;
+            writeln!(
+                f,
+                "Layout B (layer {}, changes highlighted):\n{}",
+                plots.layer + 1,
+                Self::highlight_changes(&plots.plot_a, &plots.plot_b)
+            )?;
+        }
+
+        let fmt_location = |loc: &Option<SymbolLocation>| match loc {
+            Some(loc) => format!(
+                "({:>2},{:>2}) layer {}",
+                loc.matrix_position.0,
+                loc.matrix_position.1,
+                loc.layer + 1
+            ),
+            None => "-".to_string(),
+        };
+        writeln!(f, "\n{}", "Moved keys:".bold())?;
+        for m in self.moved_keys.iter().filter(|m| !m.with_base_key) {
+            writeln!(
+                f,
+                "  {:<4} {:<18} -> {}",
+                format!("{:?}", m.symbol),
+                fmt_location(&m.from),
+                fmt_location(&m.to)
+            )?;
+        }
+
+        let n_with_base_key = self.moved_keys.iter().filter(|m| m.with_base_key).count();
+        if n_with_base_key > 0 {
+            writeln!(
+                f,
+                "  ({} symbols of higher layers move along with their base layer symbol)",
+                n_with_base_key
+            )?;
+        }
+
+        writeln!(f, "\n{}", "Metric costs (B - A):".bold())?;
+        let fmt_cost = |c: Option<f64>| match c {
+            Some(c) => format!("{:>7.2}", c),
+            None => format!("{:>7}", "-"),
+        };
+        for m in self.metrics.iter() {
+            writeln!(
+                f,
+                "  {} {:<9} {} | {} -> {}",
+                fmt_delta(m.delta, 2),
+                format!("{:?}", m.metric_type),
+                format!("{:<35}", m.name).bold(),
+                fmt_cost(m.cost_a),
+                fmt_cost(m.cost_b),
+            )?;
+        }
+
+        for (title, ngrams) in [("Bigrams", &self.bigrams), ("Trigrams", &self.trigrams)] {
+            if ngrams.is_empty() {
+                continue;
+            }
+            writeln!(
+                f,
+                "\n{}",
+                format!("{} with the largest cost changes (B - A):", title).bold()
+            )?;
+            for n in ngrams.iter() {
+                writeln!(
+                    f,
+                    "  {} {:<12} | {:>7.3} -> {:>7.3}",
+                    fmt_delta(n.delta, 3),
+                    format!("{:?}", n.ngram),
+                    n.cost_a,
+                    n.cost_b,
+                )?;
+            }
+        }
+
+        writeln!(
+            f,
+            "\nCost: {:.2} -> {:.2} ({})",
+            self.total_cost_a,
+            self.total_cost_b,
+            fmt_delta(self.total_cost_b - self.total_cost_a, 2).bold()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::EvaluationParameters,
+        evaluation::Evaluator,
+        ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
+        ngrams::{Bigrams, Trigrams, Unigrams},
+    };
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
+        neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::{path::Path, sync::Arc};
+
+    const NGRAMS: &str = "../ngrams/eng/eng_wiki_1m";
+    const LAYOUT_CONFIG: &str = "../config/keyboard/standard.yml";
+    const EVAL_PARAMS: &str = "../config/evaluation/standard.yml";
+    const LAYOUT_A: &str = "xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j";
+    /// `LAYOUT_A` with "x" and "l" swapped
+    const LAYOUT_B: &str = "lvxcwkhgfqyßuiaeosnrtdüöäpzbm,.j";
+
+    #[test]
+    fn compare_swapped_layouts() {
+        let params = EvaluationParameters::from_yaml(EVAL_PARAMS).unwrap();
+        let path = Path::new(NGRAMS);
+        let unigrams = Unigrams::from_file(path.join("1-grams.txt").to_str().unwrap()).unwrap();
+        let bigrams = Bigrams::from_file(path.join("2-grams.txt").to_str().unwrap()).unwrap();
+        let trigrams = Trigrams::from_file(path.join("3-grams.txt").to_str().unwrap()).unwrap();
+        let evaluator = Evaluator::default(Box::new(OnDemandNgramMapper::with_ngrams(
+            unigrams,
+            bigrams,
+            trigrams,
+            params.ngram_mapper,
+        )))
+        .default_metrics(&params.metrics);
+
+        let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG).unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+        let layout_a = layout_generator.generate(LAYOUT_A).unwrap();
+        let layout_b = layout_generator.generate(LAYOUT_B).unwrap();
+        let result_a = evaluator.evaluate_layout(&layout_a);
+        let result_b = evaluator.evaluate_layout(&layout_b);
+
+        let comparison = LayoutComparison::new(
+            (&layout_a, &result_a, &evaluator.ngram_costs(&layout_a)),
+            (&layout_b, &result_b, &evaluator.ngram_costs(&layout_b)),
+            5,
+        );
+
+        // the base layer symbols swap their keys, the symbols of higher layers move along with them
+        let location = |layout: &Layout, symbol: char| {
+            let lk = layout.get_layerkey_for_symbol(&symbol).unwrap();
+            SymbolLocation {
+                layer: lk.layer,
+                matrix_position: lk.key.matrix_position,
+            }
+        };
+        for symbol in ['x', 'l'] {
+            let key_move = comparison
+                .moved_keys
+                .iter()
+                .find(|m| m.symbol == symbol)
+                .unwrap();
+            assert_eq!(key_move.from, Some(location(&layout_a, symbol)));
+            assert_eq!(key_move.to, Some(location(&layout_b, symbol)));
+            assert!(!key_move.with_base_key);
+        }
+        let higher_layers: Vec<&KeyMove> = comparison
+            .moved_keys
+            .iter()
+            .filter(|m| m.from.unwrap().layer > 0)
+            .collect();
+        assert!(!higher_layers.is_empty());
+        assert!(higher_layers.iter().all(|m| m.with_base_key));
+        assert_eq!(
+            comparison.moved_keys.len(),
+            2 + higher_layers.len(),
+            "{:?}",
+            comparison.moved_keys
+        );
+        assert!(comparison.layer_plots.iter().all(|p| p.layer == 0));
+
+        let delta_sum: f64 = comparison.metrics.iter().map(|m| m.delta).sum();
+        let total_delta = comparison.total_cost_b - comparison.total_cost_a;
+        assert!(
+            (delta_sum - total_delta).abs() <= 1e-9 * comparison.total_cost_a,
+            "{} != {}",
+            delta_sum,
+            total_delta
+        );
+        assert!(comparison.bigrams.len() <= 5);
+    }
+}
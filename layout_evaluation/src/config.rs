@@ -1,5 +1,6 @@
 use crate::{
-    evaluation::MetricParameters, ngram_mapper::on_demand_ngram_mapper::NgramMapperConfig,
+    evaluation::{CostAttributionParameters, MetricParameters},
+    ngram_mapper::on_demand_ngram_mapper::NgramMapperConfig,
     ngrams::NgramsConfig,
 };
 
@@ -12,6 +13,8 @@ pub struct EvaluationParameters {
     pub metrics: MetricParameters,
     pub ngrams: NgramsConfig,
     pub ngram_mapper: NgramMapperConfig,
+    #[serde(default)]
+    pub cost_attribution: CostAttributionParameters,
 }
 
 impl EvaluationParameters {
@@ -21,6 +24,23 @@ impl EvaluationParameters {
 
         Ok(k)
     }
+
+    /// Files read by the enabled metrics (besides the ngrams), e.g. measured transition times.
+    pub fn referenced_files(&self) -> Vec<&str> {
+        let metrics = &self.metrics;
+        let mut files = Vec::new();
+        if let Some(p) = metrics.typing_time.as_ref().filter(|p| p.enabled) {
+            files.extend(p.params.transitions_filename.as_deref());
+        }
+        if let Some(p) = metrics.kla_home_key_words.as_ref().filter(|p| p.enabled) {
+            files.push(p.params.words_filename.as_str());
+        }
+        if let Some(p) = metrics.kla_same_finger_words.as_ref().filter(|p| p.enabled) {
+            files.push(p.params.words_filename.as_str());
+        }
+
+        files
+    }
 }
 
 impl FromStr for EvaluationParameters {
@@ -8,16 +8,33 @@
 //! to singles, pairs, and triplets of [`LayerKey`]s that can then be analysed by the individual metrics.
 
 use crate::results::{
-    EvaluationResult, MetricResult, MetricResults, MetricType, NormalizationType,
+    CostAttribution, EvaluationResult, FingerContribution, KeyContribution, MetricResult,
+    MetricResults, MetricType, NgramContribution, NgramCosts, NormalizationType,
 };
 use crate::{
     metrics::{bigram_metrics::*, layout_metrics::*, trigram_metrics::*, unigram_metrics::*},
-    ngram_mapper::NgramMapper,
+    ngram_mapper::{IndexedNgrams, NgramMapper},
+    sval::DirectionFactorsParameters,
 };
 
-use keyboard_layout::layout::{LayerKey, Layout};
+use keyboard_layout::{
+    key::{Hand, MatrixPosition},
+    layout::{LayerKey, LayerKeyIndex, Layout},
+    svg_plot::Heatmap,
+};
 
+use ahash::{AHashMap, AHashSet};
+use ordered_float::OrderedFloat;
+use priority_queue::DoublePriorityQueue;
 use serde::Deserialize;
+use std::{hash::Hash, sync::Arc};
+
+/// Number of incremental updates of an [`EvaluationState`] after which it is recomputed from scratch
+/// (avoids accumulating floating point errors).
+const FULL_EVALUATION_INTERVAL: usize = 1000;
+
+/// Maximal fraction of the keyboard's keys that may change for an incremental update to be performed.
+const MAX_CHANGED_KEYS_FRACTION: f64 = 0.25;
 
 /// A wrapper around individuals metric's parameters (`T`) specifying
 /// additional generic attributes. This mostly facilitates configuration of
@@ -47,12 +64,14 @@ pub struct MetricParameters {
     pub row_loads: Option<WeightedParams<row_loads::Parameters>>,
     pub key_costs: Option<WeightedParams<key_costs::Parameters>>,
     pub modifier_usage: Option<WeightedParams<modifier_usage::Parameters>>,
+    pub combo_usage: Option<WeightedParams<combo_usage::Parameters>>,
 
     pub symmetric_handswitches: Option<WeightedParams<symmetric_handswitches::Parameters>>,
     pub std_finger_repeats: Option<WeightedParams<std_finger_repeats::Parameters>>,
     pub sval_finger_repeats: Option<WeightedParams<sval_finger_repeats::Parameters>>,
     pub scissoring: Option<WeightedParams<scissoring::Parameters>>,
     pub manual_bigram_penalty: Option<WeightedParams<manual_bigram_penalty::Parameters>>,
+    pub tap_hold_misfires: Option<WeightedParams<tap_hold_misfires::Parameters>>,
 
     pub std_movement_pattern: Option<WeightedParams<std_movement_pattern::Parameters>>,
     pub sval_movement_pattern: Option<WeightedParams<sval_movement_pattern::Parameters>>,
@@ -84,6 +103,313 @@ pub struct MetricParameters {
     pub oxey_alternates_sfs: Option<WeightedParams<oxey_alternates_sfs::Parameters>>,
     pub oxey_redirects: Option<WeightedParams<oxey_redirects::Parameters>>,
     pub oxey_bad_redirects: Option<WeightedParams<oxey_bad_redirects::Parameters>>,
+
+    pub typing_time: Option<WeightedParams<typing_time::Parameters>>,
+
+    /// Direction transition matrices shared by the Svalboard metrics.
+    #[serde(default)]
+    pub sval_direction_factors: DirectionFactorsParameters,
+}
+
+/// Parameters specifying which [`CostAttribution`]s the [`Evaluator`] shall provide in its results.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct CostAttributionParameters {
+    /// Number of ngrams with the largest contributions to list for each metric.
+    pub n_ngrams: usize,
+    /// Whether to break down the costs of each metric by key.
+    pub keys: bool,
+    /// Whether to break down the costs of each metric by finger.
+    pub fingers: bool,
+}
+
+impl Default for CostAttributionParameters {
+    fn default() -> Self {
+        Self {
+            n_ngrams: 5,
+            keys: false,
+            fingers: false,
+        }
+    }
+}
+
+/// The individual costs of all ngrams (indexed by their slots) for a decomposable metric together
+/// with their sum. Ngrams without a cost and free slots have a cost of zero.
+#[derive(Clone, Debug)]
+struct IndividualCosts {
+    costs: Vec<f64>,
+    sum: f64,
+}
+
+/// Mapped ngrams of one kind together with the individual costs of all ngrams for each decomposable
+/// metric. Metrics that are not decomposable keep no individual costs.
+///
+/// Each ngram occupies a slot in the vectors of weights and individual costs, such that an update only
+/// needs to look up the affected ngrams once. Slots of ngrams whose weight drops to zero are reused.
+/// The total weight and the sums of costs are kept as running totals, i.e. they are summed up (in the
+/// same order as in a full evaluation) only when the state is generated and updated by differences
+/// afterwards.
+#[derive(Clone, Debug)]
+struct NgramState<T: Eq + Hash> {
+    slots: AHashMap<T, usize>,
+    grams: Vec<T>,
+    weights: Vec<f64>,
+    free_slots: Vec<usize>,
+    weight_found: f64,
+    weight_not_found: f64,
+    total_weight: f64,
+    costs: Vec<Option<IndividualCosts>>,
+}
+
+impl<T: Copy + Eq + Hash + Ord> NgramState<T> {
+    /// Generate a state from mapped ngrams. The function `individual_cost` computes the cost of an ngram
+    /// (with given weight and total weight) for the metric with given index.
+    fn new(
+        ngrams: IndexedNgrams<T>,
+        decomposable: impl Iterator<Item = bool>,
+        individual_cost: impl Fn(usize, &T, f64, f64) -> Option<f64>,
+    ) -> Self {
+        let mut sorted_grams: Vec<(T, f64)> = ngrams.grams.into_iter().collect();
+        sorted_grams.sort_unstable_by_key(|(gram, _)| *gram);
+        let (grams, weights): (Vec<T>, Vec<f64>) = sorted_grams.into_iter().unzip();
+
+        let total_weight = weights.iter().sum();
+        let costs = decomposable
+            .enumerate()
+            .map(|(i, decomposable)| {
+                decomposable.then(|| {
+                    let costs: Vec<Option<f64>> = grams
+                        .iter()
+                        .zip(weights.iter())
+                        .map(|(gram, w)| individual_cost(i, gram, *w, total_weight))
+                        .collect();
+                    IndividualCosts {
+                        sum: costs.iter().flatten().sum(),
+                        costs: costs.into_iter().map(|c| c.unwrap_or(0.0)).collect(),
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            slots: grams
+                .iter()
+                .enumerate()
+                .map(|(slot, gram)| (*gram, slot))
+                .collect(),
+            grams,
+            weights,
+            free_slots: Vec::new(),
+            weight_found: ngrams.weight_found,
+            weight_not_found: ngrams.weight_not_found,
+            total_weight,
+            costs,
+        }
+    }
+
+    /// The mapped ngrams ordered by their [`LayerKeyIndex`]s (as in a full evaluation).
+    fn sorted_grams(&self) -> Vec<(T, f64)> {
+        let mut grams: Vec<(T, f64)> = self
+            .grams
+            .iter()
+            .zip(self.weights.iter())
+            .filter(|(_, w)| **w != 0.0)
+            .map(|(gram, w)| (*gram, *w))
+            .collect();
+        grams.sort_unstable_by_key(|(gram, _)| *gram);
+        grams
+    }
+
+    /// The slot of an ngram. New ngrams are assigned a free slot (with zero weight and costs).
+    fn slot(&mut self, gram: &T) -> usize {
+        if let Some(slot) = self.slots.get(gram) {
+            return *slot;
+        }
+
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                self.grams[slot] = *gram;
+                slot
+            }
+            None => {
+                self.grams.push(*gram);
+                self.weights.push(0.0);
+                self.costs
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|costs| costs.costs.push(0.0));
+                self.grams.len() - 1
+            }
+        };
+        self.slots.insert(*gram, slot);
+
+        slot
+    }
+
+    /// Replace the contributions of ngrams `removed` (mapped with the previous layout) with those of
+    /// `added` (mapped with the new layout) and recompute the individual costs of all affected ngrams.
+    ///
+    /// The total weight and the sums of costs only change by the differences of the affected ngrams.
+    /// The individual costs of unaffected ngrams keep the previous total weight.
+    fn update(
+        &mut self,
+        removed: IndexedNgrams<T>,
+        added: IndexedNgrams<T>,
+        individual_cost: impl Fn(usize, &T, f64, f64) -> Option<f64>,
+    ) {
+        let mut affected: Vec<usize> = Vec::with_capacity(removed.grams.len() + added.grams.len());
+
+        removed.grams.iter().for_each(|(gram, w)| {
+            let slot = self.slot(gram);
+            let prev = self.weights[slot];
+            let mut rest = prev - w;
+            // ngrams that are removed completely shall not leave floating point residues
+            if rest.abs() <= 1e-9 * prev.abs() {
+                rest = 0.0;
+            }
+            self.weights[slot] = rest;
+            self.total_weight += rest - prev;
+            affected.push(slot);
+        });
+
+        added.grams.iter().for_each(|(gram, w)| {
+            let slot = self.slot(gram);
+            self.weights[slot] += w;
+            self.total_weight += w;
+            if !removed.grams.contains_key(gram) {
+                affected.push(slot);
+            }
+        });
+
+        self.weight_found += added.weight_found - removed.weight_found;
+        self.weight_not_found += added.weight_not_found - removed.weight_not_found;
+
+        let (grams, weights, total_weight) = (&self.grams, &self.weights, self.total_weight);
+        self.costs.iter_mut().enumerate().for_each(|(i, costs)| {
+            if let Some(IndividualCosts { costs, sum }) = costs {
+                affected.iter().for_each(|slot| {
+                    let w = weights[*slot];
+                    let cost = match w == 0.0 {
+                        true => 0.0,
+                        false => individual_cost(i, &grams[*slot], w, total_weight).unwrap_or(0.0),
+                    };
+                    *sum += cost - costs[*slot];
+                    costs[*slot] = cost;
+                });
+            }
+        });
+
+        affected.iter().for_each(|slot| {
+            if self.weights[*slot] == 0.0 {
+                self.slots.remove(&self.grams[*slot]);
+                self.free_slots.push(*slot);
+            }
+        });
+    }
+}
+
+/// The state of a layout's evaluation, i.e. its mapped ngrams and the costs of all metrics.
+///
+/// It can be updated for similar layouts (e.g. after swapping a few keys) using [`Evaluator::update_state`],
+/// which only recomputes those ngrams that involve the changed keys.
+#[derive(Clone, Debug)]
+pub struct EvaluationState {
+    layout: Layout,
+    unigrams: Option<NgramState<LayerKeyIndex>>,
+    bigrams: Option<NgramState<(LayerKeyIndex, LayerKeyIndex)>>,
+    trigrams: Option<NgramState<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex)>>,
+    results: Vec<MetricResults>,
+    n_updates: usize,
+}
+
+impl EvaluationState {
+    /// The layout that the state corresponds to.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// The evaluation result of the layout. Incrementally updated metrics do not provide messages or cost attributions.
+    pub fn result(&self) -> EvaluationResult {
+        EvaluationResult::new(self.layout.as_text(), self.results.clone())
+    }
+
+    /// The total cost of the layout (identical to [`EvaluationResult::total_cost`]).
+    pub fn total_cost(&self) -> f64 {
+        self.results
+            .iter()
+            .filter(|mc| !mc.metric_costs.is_empty())
+            .map(|mc| mc.total_cost())
+            .sum()
+    }
+}
+
+/// Collect the symbols of all keys that differ between two layouts (e.g. after swapping keys).
+///
+/// Returns `None` if the layouts are not compatible for an incremental update, i.e. if they differ in their
+/// structure or modifiers or if too many keys changed.
+fn changed_symbols(old: &Layout, new: &Layout) -> Option<AHashSet<char>> {
+    if !Arc::ptr_eq(&old.keyboard, &new.keyboard) || old.layerkeys.len() != new.layerkeys.len() {
+        return None;
+    }
+
+    let mut changed_keys: AHashSet<MatrixPosition> = AHashSet::default();
+    for (idx, (lk_old, lk_new)) in old.layerkeys.iter().zip(new.layerkeys.iter()).enumerate() {
+        // compose sequences are handled below
+        if !lk_old.compose_sequence.is_empty() || !lk_new.compose_sequence.is_empty() {
+            if lk_old.symbol != lk_new.symbol {
+                return None;
+            }
+            continue;
+        }
+
+        if lk_old.key.matrix_position != lk_new.key.matrix_position || lk_old.layer != lk_new.layer
+        {
+            return None;
+        }
+
+        if lk_old != lk_new {
+            // a moved modifier affects all symbols of its layer
+            if lk_old.is_modifier.is_some() || lk_new.is_modifier.is_some() {
+                return None;
+            }
+
+            // a moved context key affects all ngrams typed with it
+            let idx = idx as LayerKeyIndex;
+            if old.is_context_key(&idx) || new.is_context_key(&idx) {
+                return None;
+            }
+            changed_keys.insert(lk_new.key.matrix_position);
+        }
+    }
+
+    if changed_keys.len() as f64 > MAX_CHANGED_KEYS_FRACTION * new.keyboard.keys.len() as f64 {
+        return None;
+    }
+
+    // all symbols of a changed key are affected, because higher-layer symbols resolve to the base-layer key
+    let mut symbols: AHashSet<char> = old
+        .layerkeys
+        .iter()
+        .chain(new.layerkeys.iter())
+        .filter(|lk| lk.compose_sequence.is_empty())
+        .filter(|lk| changed_keys.contains(&lk.key.matrix_position))
+        .map(|lk| lk.symbol)
+        .collect();
+
+    // as are symbols typed as compose sequences containing an affected symbol
+    for layout in [old, new] {
+        for lk in layout.layerkeys.iter() {
+            if lk
+                .compose_sequence
+                .iter()
+                .any(|idx| symbols.contains(&layout.get_layerkey(idx).symbol))
+            {
+                symbols.insert(lk.symbol);
+            }
+        }
+    }
+
+    Some(symbols)
 }
 
 /// The [`Evaluator`] object is responsible for evaluating multiple metrics with respect to given ngram data.
@@ -137,6 +463,20 @@ impl Evaluator {
                     }
                 }
             };
+            ($metric_type:ident, $metric_name:ident, $metric_struct:ident, "add_direction_factors") => {
+                if let Some(p) = &params.$metric_name {
+                    if p.enabled {
+                        self.$metric_type(
+                            Box::new($metric_name::$metric_struct::new(
+                                &p.params,
+                                &params.sval_direction_factors,
+                            )),
+                            p.weight,
+                            p.normalization.clone(),
+                        );
+                    }
+                }
+            };
         }
 
         // layout metrics
@@ -149,16 +489,33 @@ impl Evaluator {
         add_metric!(unigram_metric, hand_disbalance, HandDisbalance);
         add_metric!(unigram_metric, row_loads, RowLoads);
         add_metric!(unigram_metric, modifier_usage, ModifierUsage);
+        add_metric!(unigram_metric, combo_usage, ComboUsage);
         add_metric!(unigram_metric, key_costs, KeyCost);
 
         // bigram metrics
         add_metric!(bigram_metric, std_finger_repeats, StdFingerRepeats);
-        add_metric!(bigram_metric, sval_finger_repeats, SvalFingerRepeats);
-        add_metric!(bigram_metric, scissoring, Scissoring);
+        add_metric!(
+            bigram_metric,
+            sval_finger_repeats,
+            SvalFingerRepeats,
+            "add_direction_factors"
+        );
+        add_metric!(
+            bigram_metric,
+            scissoring,
+            Scissoring,
+            "add_direction_factors"
+        );
         add_metric!(bigram_metric, manual_bigram_penalty, ManualBigramPenalty);
+        add_metric!(bigram_metric, tap_hold_misfires, TapHoldMisfires);
 
         add_metric!(bigram_metric, std_movement_pattern, StdMovementPattern);
-        add_metric!(bigram_metric, sval_movement_pattern, SvalMovementPattern);
+        add_metric!(
+            bigram_metric,
+            sval_movement_pattern,
+            SvalMovementPattern,
+            "add_direction_factors"
+        );
         add_metric!(
             bigram_metric,
             no_handswitch_after_unbalancing_key,
@@ -208,6 +565,10 @@ impl Evaluator {
         add_metric!(trigram_metric, oxey_redirects, OxeyRedirects);
         add_metric!(trigram_metric, oxey_bad_redirects, OxeyBadRedirects);
 
+        // added after the trigram metrics, such that its times do not enter `irregularity` and
+        // `secondary_bigrams`
+        add_metric!(bigram_metric, typing_time, TypingTime);
+
         self
     }
 
@@ -268,6 +629,8 @@ impl Evaluator {
                     weight: *weight,
                     normalization: normalization.clone(),
                     message,
+                    attribution: None,
+                    typing_speed: None,
                 }
             })
             .collect();
@@ -280,6 +643,7 @@ impl Evaluator {
         &self,
         layout: &Layout,
         keys: &[(&LayerKey, f64)],
+        cost_attribution: Option<&CostAttributionParameters>,
     ) -> Vec<MetricResult> {
         if self.unigram_metrics.is_empty() {
             return Vec::new();
@@ -291,12 +655,25 @@ impl Evaluator {
             .iter()
             .map(|(weight, normalization, metric)| {
                 let (cost, message) = metric.total_cost(keys, Some(total_weight), layout);
+                let attribution = cost_attribution.and_then(|params| {
+                    let individual_costs = keys
+                        .iter()
+                        .filter_map(|(k1, w)| {
+                            metric
+                                .individual_cost(k1, *w, total_weight, layout)
+                                .map(|c| (vec![*k1], c))
+                        })
+                        .collect();
+                    Self::attribute_metric_cost(params, cost, individual_costs)
+                });
                 MetricResult {
                     name: metric.name().to_string(),
                     cost,
                     weight: *weight,
                     normalization: normalization.clone(),
                     message,
+                    attribution,
+                    typing_speed: None,
                 }
             })
             .collect();
@@ -309,6 +686,7 @@ impl Evaluator {
         &self,
         layout: &Layout,
         keys: &[((&LayerKey, &LayerKey), f64)],
+        cost_attribution: Option<&CostAttributionParameters>,
     ) -> Vec<MetricResult> {
         if self.bigram_metrics.is_empty() {
             return Vec::new();
@@ -320,12 +698,25 @@ impl Evaluator {
             .iter()
             .map(|(weight, normalization, metric)| {
                 let (cost, message) = metric.total_cost(keys, Some(total_weight), layout);
+                let attribution = cost_attribution.and_then(|params| {
+                    let individual_costs = keys
+                        .iter()
+                        .filter_map(|((k1, k2), w)| {
+                            metric
+                                .individual_cost(k1, k2, *w, total_weight, layout)
+                                .map(|c| (vec![*k1, *k2], c))
+                        })
+                        .collect();
+                    Self::attribute_metric_cost(params, cost, individual_costs)
+                });
                 MetricResult {
                     name: metric.name().to_string(),
                     cost,
                     weight: *weight,
                     normalization: normalization.clone(),
                     message,
+                    attribution,
+                    typing_speed: metric.typing_speed(cost, total_weight),
                 }
             })
             .collect();
@@ -338,6 +729,7 @@ impl Evaluator {
         &self,
         layout: &Layout,
         keys: &[((&LayerKey, &LayerKey, &LayerKey), f64)],
+        cost_attribution: Option<&CostAttributionParameters>,
     ) -> Vec<MetricResult> {
         if self.trigram_metrics.is_empty() {
             return Vec::new();
@@ -349,12 +741,25 @@ impl Evaluator {
             .iter()
             .map(|(weight, normalization, metric)| {
                 let (cost, message) = metric.total_cost(keys, Some(total_weight), layout);
+                let attribution = cost_attribution.and_then(|params| {
+                    let individual_costs = keys
+                        .iter()
+                        .filter_map(|((k1, k2, k3), w)| {
+                            metric
+                                .individual_cost(k1, k2, k3, *w, total_weight, layout)
+                                .map(|c| (vec![*k1, *k2, *k3], c))
+                        })
+                        .collect();
+                    Self::attribute_metric_cost(params, cost, individual_costs)
+                });
                 MetricResult {
                     name: metric.name().to_string(),
                     cost,
                     weight: *weight,
                     normalization: normalization.clone(),
                     message,
+                    attribution,
+                    typing_speed: None,
                 }
             })
             .collect();
@@ -364,6 +769,17 @@ impl Evaluator {
 
     /// Evaluate all metrics for a layout.
     pub fn evaluate_layout(&self, layout: &Layout) -> EvaluationResult {
+        self.evaluate_layout_with_cost_attribution(layout, None)
+    }
+
+    /// Evaluate all metrics for a layout and attribute the costs of all metrics that provide individual
+    /// costs to ngrams (and optionally keys and fingers). This is considerably slower than
+    /// [`Evaluator::evaluate_layout`].
+    pub fn evaluate_layout_with_cost_attribution(
+        &self,
+        layout: &Layout,
+        cost_attribution: Option<&CostAttributionParameters>,
+    ) -> EvaluationResult {
         let mut results: Vec<MetricResults> = Vec::new();
 
         // Layout metrics
@@ -379,7 +795,8 @@ impl Evaluator {
         // Unigram metrics
         if !self.unigram_metrics.is_empty() {
             let mapped_unigrams = self.ngram_mapper.map_unigrams(layout);
-            let metric_costs = self.evaluate_unigram_metrics(layout, &mapped_unigrams.grams);
+            let metric_costs =
+                self.evaluate_unigram_metrics(layout, &mapped_unigrams.grams, cost_attribution);
             let mut unigram_costs = MetricResults::new(
                 MetricType::Unigram,
                 mapped_unigrams.weight_found,
@@ -395,7 +812,8 @@ impl Evaluator {
         // Bigram metrics
         if !self.bigram_metrics.is_empty() {
             let mapped_bigrams = self.ngram_mapper.map_bigrams(layout);
-            let metric_costs = self.evaluate_bigram_metrics(layout, &mapped_bigrams.grams);
+            let metric_costs =
+                self.evaluate_bigram_metrics(layout, &mapped_bigrams.grams, cost_attribution);
             let mut bigram_costs = MetricResults::new(
                 MetricType::Bigram,
                 mapped_bigrams.weight_found,
@@ -411,7 +829,8 @@ impl Evaluator {
         // Trigram metrics
         if !self.trigram_metrics.is_empty() {
             let mapped_trigrams = self.ngram_mapper.map_trigrams(layout);
-            let metric_costs = self.evaluate_trigram_metrics(layout, &mapped_trigrams.grams);
+            let metric_costs =
+                self.evaluate_trigram_metrics(layout, &mapped_trigrams.grams, cost_attribution);
             let mut trigram_costs = MetricResults::new(
                 MetricType::Trigram,
                 mapped_trigrams.weight_found,
@@ -426,4 +845,925 @@ impl Evaluator {
 
         EvaluationResult::new(layout.as_text(), results)
     }
+
+    /// Names of all metrics of the evaluator (as in the evaluation results).
+    pub fn metric_names(&self) -> Vec<String> {
+        let layout_names = self.layout_metrics.iter().map(|(_, _, m)| m.name());
+        let unigram_names = self.unigram_metrics.iter().map(|(_, _, m)| m.name());
+        let bigram_names = self.bigram_metrics.iter().map(|(_, _, m)| m.name());
+        let trigram_names = self.trigram_metrics.iter().map(|(_, _, m)| m.name());
+
+        layout_names
+            .chain(unigram_names)
+            .chain(bigram_names)
+            .chain(trigram_names)
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Compute the share (in percent) of each key in the key presses of the unigrams (including the
+    /// presses of modifiers).
+    pub fn key_loads(&self, layout: &Layout) -> AHashMap<MatrixPosition, f64> {
+        let mapped_unigrams = self.ngram_mapper.map_unigrams(layout);
+        let total_weight: f64 = mapped_unigrams.grams.iter().map(|(_, w)| w).sum();
+
+        let mut loads: AHashMap<MatrixPosition, f64> = AHashMap::default();
+        if total_weight > 0.0 {
+            for (k, w) in mapped_unigrams.grams.iter() {
+                *loads.entry(k.key.matrix_position).or_insert(0.0) += 100.0 * w / total_weight;
+            }
+        }
+
+        loads
+    }
+
+    /// A heatmap of the layout's keys for plotting: the key loads (`values` = `"load"`, see
+    /// [`Evaluator::key_loads`]) or the contributions of the keys to the cost of the metric named
+    /// `values`. Returns `None` for metrics that do not exist or do not attribute their cost to keys.
+    pub fn key_heatmap(&self, layout: &Layout, values: &str) -> Option<Heatmap> {
+        if values == "load" {
+            return Some(Heatmap::new("Key load (%)", self.key_loads(layout)));
+        }
+
+        let params = CostAttributionParameters {
+            keys: true,
+            ..Default::default()
+        };
+        let key_costs = self
+            .evaluate_layout_with_cost_attribution(layout, Some(&params))
+            .key_costs(values)?;
+        Some(Heatmap::new(values, key_costs))
+    }
+
+    /// Compute the contributions of all bigrams and trigrams to the total cost of a layout.
+    ///
+    /// The weighted and normalized cost of each metric is attributed to the ngrams in proportion to
+    /// their individual costs. Metrics that do not provide individual costs are not included.
+    pub fn ngram_costs(&self, layout: &Layout) -> NgramCosts {
+        let mut ngram_costs = NgramCosts::default();
+
+        if !self.bigram_metrics.is_empty() {
+            let mapped_bigrams = self.ngram_mapper.map_bigrams(layout);
+            let total_weight = mapped_bigrams.grams.iter().map(|(_, w)| w).sum();
+            let mut results = MetricResults::new(
+                MetricType::Bigram,
+                mapped_bigrams.weight_found,
+                mapped_bigrams.weight_not_found,
+            );
+            let metric_costs = self.evaluate_bigram_metrics(layout, &mapped_bigrams.grams, None);
+            for ((_, _, metric), metric_cost) in self.bigram_metrics.iter().zip(metric_costs) {
+                let individual_costs = mapped_bigrams
+                    .grams
+                    .iter()
+                    .filter_map(|((k1, k2), w)| {
+                        metric
+                            .individual_cost(k1, k2, *w, total_weight, layout)
+                            .map(|c| (format!("{}{}", k1, k2), c))
+                    })
+                    .collect();
+                results.add_result(metric_cost);
+                Self::attribute_costs(&mut ngram_costs.bigrams, &results, individual_costs);
+            }
+        }
+
+        if !self.trigram_metrics.is_empty() {
+            let mapped_trigrams = self.ngram_mapper.map_trigrams(layout);
+            let total_weight = mapped_trigrams.grams.iter().map(|(_, w)| w).sum();
+            let mut results = MetricResults::new(
+                MetricType::Trigram,
+                mapped_trigrams.weight_found,
+                mapped_trigrams.weight_not_found,
+            );
+            let metric_costs = self.evaluate_trigram_metrics(layout, &mapped_trigrams.grams, None);
+            for ((_, _, metric), metric_cost) in self.trigram_metrics.iter().zip(metric_costs) {
+                let individual_costs = mapped_trigrams
+                    .grams
+                    .iter()
+                    .filter_map(|((k1, k2, k3), w)| {
+                        metric
+                            .individual_cost(k1, k2, k3, *w, total_weight, layout)
+                            .map(|c| (format!("{}{}{}", k1, k2, k3), c))
+                    })
+                    .collect();
+                results.add_result(metric_cost);
+                Self::attribute_costs(&mut ngram_costs.trigrams, &results, individual_costs);
+            }
+        }
+
+        ngram_costs
+    }
+
+    /// Attribute the weighted and normalized cost of the last metric added to `results` to ngrams in
+    /// proportion to their individual costs.
+    fn attribute_costs(
+        ngram_costs: &mut AHashMap<String, f64>,
+        results: &MetricResults,
+        individual_costs: Vec<(String, f64)>,
+    ) {
+        let individual_costs_sum: f64 = individual_costs.iter().map(|(_, c)| c).sum();
+        let weighted_cost = match results.metric_costs.last() {
+            Some(metric_cost) => metric_cost.weighted_cost,
+            None => return,
+        };
+        if individual_costs_sum == 0.0 {
+            return;
+        }
+
+        individual_costs.into_iter().for_each(|(ngram, c)| {
+            *ngram_costs.entry(ngram).or_insert(0.0) += weighted_cost * c / individual_costs_sum;
+        });
+    }
+
+    /// Attribute a metric's total cost to ngrams (given by their keys), keys, and fingers based on the
+    /// ngrams' individual costs. Returns `None` if the metric does not provide individual costs.
+    fn attribute_metric_cost(
+        params: &CostAttributionParameters,
+        total_cost: f64,
+        individual_costs: Vec<(Vec<&LayerKey>, f64)>,
+    ) -> Option<CostAttribution> {
+        if individual_costs.is_empty() {
+            return None;
+        }
+
+        // scale the individual costs such that they sum up to the total cost (relevant for metrics
+        // that are not simply the sum of their individual costs)
+        let individual_costs_sum: f64 = individual_costs.iter().map(|(_, c)| c).sum();
+        let individual_costs_abs_sum: f64 = individual_costs.iter().map(|(_, c)| c.abs()).sum();
+        let scale = if individual_costs_sum != 0.0 {
+            total_cost / individual_costs_sum
+        } else {
+            1.0
+        };
+
+        // the ngrams with the largest absolute contributions (optionally only those without fixed keys)
+        let worst_ngrams = |non_fixed: bool| -> Vec<NgramContribution> {
+            let mut worst = DoublePriorityQueue::new();
+            for (i, (keys, cost)) in individual_costs.iter().enumerate() {
+                if non_fixed && keys.iter().any(|k| k.is_fixed) {
+                    continue;
+                }
+                worst.push(i, OrderedFloat(cost.abs()));
+                if worst.len() > params.n_ngrams {
+                    worst.pop_min();
+                }
+            }
+            worst
+                .into_sorted_iter()
+                .rev()
+                .filter(|(_, cost)| cost.into_inner() > 0.0)
+                .map(|(i, _)| {
+                    let (keys, cost) = &individual_costs[i];
+                    NgramContribution {
+                        ngram: keys.iter().map(|k| k.to_string()).collect(),
+                        cost: scale * cost,
+                        fraction: cost / individual_costs_abs_sum,
+                    }
+                })
+                .collect()
+        };
+        let worst_non_fixed_ngrams = worst_ngrams(true);
+        let worst_ngrams = worst_ngrams(false);
+
+        let mut key_costs: AHashMap<MatrixPosition, KeyContribution> = AHashMap::default();
+        if params.keys || params.fingers {
+            for (keys, cost) in individual_costs.iter() {
+                let key_cost = scale * cost / keys.len() as f64;
+                for k in keys.iter() {
+                    key_costs
+                        .entry(k.key.matrix_position)
+                        .or_insert(KeyContribution {
+                            matrix_position: k.key.matrix_position,
+                            hand: k.key.hand,
+                            finger: k.key.finger,
+                            cost: 0.0,
+                        })
+                        .cost += key_cost;
+                }
+            }
+        }
+
+        let fingers = params.fingers.then(|| {
+            let mut finger_costs: Vec<FingerContribution> = Vec::new();
+            for key_cost in key_costs.values() {
+                match finger_costs
+                    .iter_mut()
+                    .find(|c| c.hand == key_cost.hand && c.finger == key_cost.finger)
+                {
+                    Some(c) => c.cost += key_cost.cost,
+                    None => finger_costs.push(FingerContribution {
+                        hand: key_cost.hand,
+                        finger: key_cost.finger,
+                        cost: key_cost.cost,
+                    }),
+                }
+            }
+            // from the left pinky to the right pinky
+            finger_costs.sort_by_key(|c| match c.hand {
+                Hand::Left => 4 - c.finger as i8,
+                Hand::Right => 5 + c.finger as i8,
+            });
+            finger_costs
+        });
+
+        let keys = params.keys.then(|| {
+            let mut key_costs: Vec<KeyContribution> = key_costs.into_values().collect();
+            key_costs.sort_by(|a, b| b.cost.abs().total_cmp(&a.cost.abs()));
+            key_costs
+        });
+
+        Some(CostAttribution {
+            worst_ngrams,
+            worst_non_fixed_ngrams,
+            keys,
+            fingers,
+        })
+    }
+
+    /// Collect metric results of one [`MetricType`].
+    fn metric_results(
+        metric_type: MetricType,
+        weight_found: f64,
+        weight_not_found: f64,
+        metric_costs: Vec<MetricResult>,
+    ) -> MetricResults {
+        let mut results = MetricResults::new(metric_type, weight_found, weight_not_found);
+        metric_costs
+            .into_iter()
+            .for_each(|mc| results.add_result(mc));
+
+        results
+    }
+
+    /// Evaluate all layout metrics for a layout, collected as [`MetricResults`].
+    fn layout_results(&self, layout: &Layout) -> MetricResults {
+        let metric_costs = self.evaluate_layout_metrics(layout);
+        Self::metric_results(MetricType::Layout, 1.0, 0.0, metric_costs)
+    }
+
+    /// The individual cost of a unigram for the unigram metric with given index.
+    fn unigram_cost(
+        &self,
+        metric_idx: usize,
+        layout: &Layout,
+        idx: &LayerKeyIndex,
+        weight: f64,
+        total_weight: f64,
+    ) -> Option<f64> {
+        let (_, _, metric) = &self.unigram_metrics[metric_idx];
+        metric.individual_cost(layout.get_layerkey(idx), weight, total_weight, layout)
+    }
+
+    /// The individual cost of a bigram for the bigram metric with given index.
+    fn bigram_cost(
+        &self,
+        metric_idx: usize,
+        layout: &Layout,
+        (idx1, idx2): &(LayerKeyIndex, LayerKeyIndex),
+        weight: f64,
+        total_weight: f64,
+    ) -> Option<f64> {
+        let (_, _, metric) = &self.bigram_metrics[metric_idx];
+        metric.individual_cost(
+            layout.get_layerkey(idx1),
+            layout.get_layerkey(idx2),
+            weight,
+            total_weight,
+            layout,
+        )
+    }
+
+    /// The individual cost of a trigram for the trigram metric with given index.
+    fn trigram_cost(
+        &self,
+        metric_idx: usize,
+        layout: &Layout,
+        (idx1, idx2, idx3): &(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex),
+        weight: f64,
+        total_weight: f64,
+    ) -> Option<f64> {
+        let (_, _, metric) = &self.trigram_metrics[metric_idx];
+        metric.individual_cost(
+            layout.get_layerkey(idx1),
+            layout.get_layerkey(idx2),
+            layout.get_layerkey(idx3),
+            weight,
+            total_weight,
+            layout,
+        )
+    }
+
+    /// Collect the unigram metrics' results from an [`NgramState`]. Metrics that are not decomposable
+    /// are evaluated fully.
+    fn unigram_results(&self, state: &NgramState<LayerKeyIndex>, layout: &Layout) -> MetricResults {
+        let grams: Vec<(&LayerKey, f64)> = match state.costs.iter().any(Option::is_none) {
+            true => state
+                .sorted_grams()
+                .iter()
+                .map(|(idx, w)| (layout.get_layerkey(idx), *w))
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let metric_costs = self
+            .unigram_metrics
+            .iter()
+            .zip(state.costs.iter())
+            .map(|((weight, normalization, metric), costs)| {
+                let (cost, message) = match costs {
+                    Some(costs) => (metric.total_cost_from_sum(costs.sum), None),
+                    None => metric.total_cost(&grams, Some(state.total_weight), layout),
+                };
+                MetricResult {
+                    name: metric.name().to_string(),
+                    cost,
+                    weight: *weight,
+                    normalization: normalization.clone(),
+                    message,
+                    attribution: None,
+                    typing_speed: None,
+                }
+            })
+            .collect();
+
+        Self::metric_results(
+            MetricType::Unigram,
+            state.weight_found,
+            state.weight_not_found,
+            metric_costs,
+        )
+    }
+
+    /// Collect the bigram metrics' results from an [`NgramState`]. Metrics that are not decomposable
+    /// are evaluated fully.
+    fn bigram_results(
+        &self,
+        state: &NgramState<(LayerKeyIndex, LayerKeyIndex)>,
+        layout: &Layout,
+    ) -> MetricResults {
+        let grams: Vec<((&LayerKey, &LayerKey), f64)> =
+            match state.costs.iter().any(Option::is_none) {
+                true => state
+                    .sorted_grams()
+                    .iter()
+                    .map(|((idx1, idx2), w)| {
+                        ((layout.get_layerkey(idx1), layout.get_layerkey(idx2)), *w)
+                    })
+                    .collect(),
+                false => Vec::new(),
+            };
+
+        let metric_costs = self
+            .bigram_metrics
+            .iter()
+            .zip(state.costs.iter())
+            .map(|((weight, normalization, metric), costs)| {
+                let (cost, message) = match costs {
+                    Some(costs) => (metric.total_cost_from_sum(costs.sum), None),
+                    None => metric.total_cost(&grams, Some(state.total_weight), layout),
+                };
+                MetricResult {
+                    name: metric.name().to_string(),
+                    cost,
+                    weight: *weight,
+                    normalization: normalization.clone(),
+                    message,
+                    attribution: None,
+                    typing_speed: metric.typing_speed(cost, state.total_weight),
+                }
+            })
+            .collect();
+
+        Self::metric_results(
+            MetricType::Bigram,
+            state.weight_found,
+            state.weight_not_found,
+            metric_costs,
+        )
+    }
+
+    /// Collect the trigram metrics' results from an [`NgramState`]. Metrics that are not decomposable
+    /// are evaluated fully.
+    fn trigram_results(
+        &self,
+        state: &NgramState<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex)>,
+        layout: &Layout,
+    ) -> MetricResults {
+        let grams: Vec<((&LayerKey, &LayerKey, &LayerKey), f64)> =
+            match state.costs.iter().any(Option::is_none) {
+                true => state
+                    .sorted_grams()
+                    .iter()
+                    .map(|((idx1, idx2, idx3), w)| {
+                        (
+                            (
+                                layout.get_layerkey(idx1),
+                                layout.get_layerkey(idx2),
+                                layout.get_layerkey(idx3),
+                            ),
+                            *w,
+                        )
+                    })
+                    .collect(),
+                false => Vec::new(),
+            };
+
+        let metric_costs = self
+            .trigram_metrics
+            .iter()
+            .zip(state.costs.iter())
+            .map(|((weight, normalization, metric), costs)| {
+                let (cost, message) = match costs {
+                    Some(costs) => (metric.total_cost_from_sum(costs.sum), None),
+                    None => metric.total_cost(&grams, Some(state.total_weight), layout),
+                };
+                MetricResult {
+                    name: metric.name().to_string(),
+                    cost,
+                    weight: *weight,
+                    normalization: normalization.clone(),
+                    message,
+                    attribution: None,
+                    typing_speed: None,
+                }
+            })
+            .collect();
+
+        Self::metric_results(
+            MetricType::Trigram,
+            state.weight_found,
+            state.weight_not_found,
+            metric_costs,
+        )
+    }
+
+    /// Evaluate all unigram metrics for a layout. If the ngram mapper supports it, the mapped unigrams
+    /// and the individual costs of all decomposable metrics are kept for incremental updates.
+    fn unigram_state(&self, layout: &Layout) -> (MetricResults, Option<NgramState<LayerKeyIndex>>) {
+        match self.ngram_mapper.map_unigram_indices(layout, None) {
+            Some(ngrams) => {
+                let state = NgramState::new(
+                    ngrams,
+                    self.unigram_metrics
+                        .iter()
+                        .map(|(_, _, metric)| metric.is_decomposable()),
+                    |i, gram, w, tw| self.unigram_cost(i, layout, gram, w, tw),
+                );
+                (self.unigram_results(&state, layout), Some(state))
+            }
+            None => {
+                let mapped_unigrams = self.ngram_mapper.map_unigrams(layout);
+                let metric_costs =
+                    self.evaluate_unigram_metrics(layout, &mapped_unigrams.grams, None);
+                let results = Self::metric_results(
+                    MetricType::Unigram,
+                    mapped_unigrams.weight_found,
+                    mapped_unigrams.weight_not_found,
+                    metric_costs,
+                );
+                (results, None)
+            }
+        }
+    }
+
+    /// Evaluate all bigram metrics for a layout. If the ngram mapper supports it, the mapped bigrams
+    /// and the individual costs of all decomposable metrics are kept for incremental updates.
+    fn bigram_state(
+        &self,
+        layout: &Layout,
+    ) -> (
+        MetricResults,
+        Option<NgramState<(LayerKeyIndex, LayerKeyIndex)>>,
+    ) {
+        match self.ngram_mapper.map_bigram_indices(layout, None) {
+            Some(ngrams) => {
+                let state = NgramState::new(
+                    ngrams,
+                    self.bigram_metrics
+                        .iter()
+                        .map(|(_, _, metric)| metric.is_decomposable()),
+                    |i, gram, w, tw| self.bigram_cost(i, layout, gram, w, tw),
+                );
+                (self.bigram_results(&state, layout), Some(state))
+            }
+            None => {
+                let mapped_bigrams = self.ngram_mapper.map_bigrams(layout);
+                let metric_costs =
+                    self.evaluate_bigram_metrics(layout, &mapped_bigrams.grams, None);
+                let results = Self::metric_results(
+                    MetricType::Bigram,
+                    mapped_bigrams.weight_found,
+                    mapped_bigrams.weight_not_found,
+                    metric_costs,
+                );
+                (results, None)
+            }
+        }
+    }
+
+    /// Evaluate all trigram metrics for a layout. If the ngram mapper supports it, the mapped trigrams
+    /// and the individual costs of all decomposable metrics are kept for incremental updates.
+    #[allow(clippy::type_complexity)]
+    fn trigram_state(
+        &self,
+        layout: &Layout,
+    ) -> (
+        MetricResults,
+        Option<NgramState<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex)>>,
+    ) {
+        match self.ngram_mapper.map_trigram_indices(layout, None) {
+            Some(ngrams) => {
+                let state = NgramState::new(
+                    ngrams,
+                    self.trigram_metrics
+                        .iter()
+                        .map(|(_, _, metric)| metric.is_decomposable()),
+                    |i, gram, w, tw| self.trigram_cost(i, layout, gram, w, tw),
+                );
+                (self.trigram_results(&state, layout), Some(state))
+            }
+            None => {
+                let mapped_trigrams = self.ngram_mapper.map_trigrams(layout);
+                let metric_costs =
+                    self.evaluate_trigram_metrics(layout, &mapped_trigrams.grams, None);
+                let results = Self::metric_results(
+                    MetricType::Trigram,
+                    mapped_trigrams.weight_found,
+                    mapped_trigrams.weight_not_found,
+                    metric_costs,
+                );
+                (results, None)
+            }
+        }
+    }
+
+    /// Incrementally update the unigram metrics for a changed layout (if the state allows for it,
+    /// otherwise evaluate them fully).
+    fn update_unigram_state(
+        &self,
+        state: &mut Option<NgramState<LayerKeyIndex>>,
+        old_layout: &Layout,
+        layout: &Layout,
+        symbols: &AHashSet<char>,
+    ) -> MetricResults {
+        let changes = state.as_mut().and_then(|s| {
+            let removed = self
+                .ngram_mapper
+                .map_unigram_indices(old_layout, Some(symbols))?;
+            let added = self
+                .ngram_mapper
+                .map_unigram_indices(layout, Some(symbols))?;
+            Some((s, removed, added))
+        });
+
+        match changes {
+            Some((s, removed, added)) => {
+                s.update(removed, added, |i, gram, w, tw| {
+                    self.unigram_cost(i, layout, gram, w, tw)
+                });
+                self.unigram_results(s, layout)
+            }
+            None => {
+                let (results, new_state) = self.unigram_state(layout);
+                *state = new_state;
+                results
+            }
+        }
+    }
+
+    /// Incrementally update the bigram metrics for a changed layout (if the state allows for it,
+    /// otherwise evaluate them fully).
+    fn update_bigram_state(
+        &self,
+        state: &mut Option<NgramState<(LayerKeyIndex, LayerKeyIndex)>>,
+        old_layout: &Layout,
+        layout: &Layout,
+        symbols: &AHashSet<char>,
+    ) -> MetricResults {
+        let changes = state.as_mut().and_then(|s| {
+            let removed = self
+                .ngram_mapper
+                .map_bigram_indices(old_layout, Some(symbols))?;
+            let added = self
+                .ngram_mapper
+                .map_bigram_indices(layout, Some(symbols))?;
+            Some((s, removed, added))
+        });
+
+        match changes {
+            Some((s, removed, added)) => {
+                s.update(removed, added, |i, gram, w, tw| {
+                    self.bigram_cost(i, layout, gram, w, tw)
+                });
+                self.bigram_results(s, layout)
+            }
+            None => {
+                let (results, new_state) = self.bigram_state(layout);
+                *state = new_state;
+                results
+            }
+        }
+    }
+
+    /// Incrementally update the trigram metrics for a changed layout (if the state allows for it,
+    /// otherwise evaluate them fully).
+    fn update_trigram_state(
+        &self,
+        state: &mut Option<NgramState<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex)>>,
+        old_layout: &Layout,
+        layout: &Layout,
+        symbols: &AHashSet<char>,
+    ) -> MetricResults {
+        let changes = state.as_mut().and_then(|s| {
+            let removed = self
+                .ngram_mapper
+                .map_trigram_indices(old_layout, Some(symbols))?;
+            let added = self
+                .ngram_mapper
+                .map_trigram_indices(layout, Some(symbols))?;
+            Some((s, removed, added))
+        });
+
+        match changes {
+            Some((s, removed, added)) => {
+                s.update(removed, added, |i, gram, w, tw| {
+                    self.trigram_cost(i, layout, gram, w, tw)
+                });
+                self.trigram_results(s, layout)
+            }
+            None => {
+                let (results, new_state) = self.trigram_state(layout);
+                *state = new_state;
+                results
+            }
+        }
+    }
+
+    /// Evaluate all metrics for a layout and keep the mapped ngrams and metric costs as an
+    /// [`EvaluationState`] that can be updated incrementally using [`Evaluator::update_state`].
+    ///
+    /// In contrast to [`Evaluator::evaluate_layout`], metrics that can be updated incrementally do not
+    /// provide messages or cost attributions.
+    pub fn evaluation_state(&self, layout: &Layout) -> EvaluationState {
+        let mut results: Vec<MetricResults> = Vec::new();
+
+        if !self.layout_metrics.is_empty() {
+            results.push(self.layout_results(layout));
+        }
+
+        let mut unigrams = None;
+        if !self.unigram_metrics.is_empty() {
+            let (unigram_results, unigram_state) = self.unigram_state(layout);
+            results.push(unigram_results);
+            unigrams = unigram_state;
+        }
+
+        let mut bigrams = None;
+        if !self.bigram_metrics.is_empty() {
+            let (bigram_results, bigram_state) = self.bigram_state(layout);
+            results.push(bigram_results);
+            bigrams = bigram_state;
+        }
+
+        let mut trigrams = None;
+        if !self.trigram_metrics.is_empty() {
+            let (trigram_results, trigram_state) = self.trigram_state(layout);
+            results.push(trigram_results);
+            trigrams = trigram_state;
+        }
+
+        EvaluationState {
+            layout: layout.clone(),
+            unigrams,
+            bigrams,
+            trigrams,
+            results,
+            n_updates: 0,
+        }
+    }
+
+    /// Update an [`EvaluationState`] for a new layout that differs only in a few keys (e.g. after
+    /// swapping two keys) and return the new total cost.
+    ///
+    /// Only ngrams that involve symbols of changed keys are mapped and evaluated again. Metrics that can not
+    /// be decomposed into individual ngram costs (e.g. finger or hand balance) are evaluated fully on the
+    /// updated ngrams. If the layouts differ too much, a full evaluation is performed instead.
+    pub fn update_state(&self, state: &mut EvaluationState, layout: &Layout) -> f64 {
+        let symbols = match state.n_updates < FULL_EVALUATION_INTERVAL {
+            true => changed_symbols(&state.layout, layout),
+            false => None,
+        };
+
+        let symbols = match symbols {
+            Some(symbols) => symbols,
+            None => {
+                *state = self.evaluation_state(layout);
+                return state.total_cost();
+            }
+        };
+
+        let mut results: Vec<MetricResults> = Vec::new();
+
+        if !self.layout_metrics.is_empty() {
+            results.push(self.layout_results(layout));
+        }
+
+        if !self.unigram_metrics.is_empty() {
+            results.push(self.update_unigram_state(
+                &mut state.unigrams,
+                &state.layout,
+                layout,
+                &symbols,
+            ));
+        }
+
+        if !self.bigram_metrics.is_empty() {
+            results.push(self.update_bigram_state(
+                &mut state.bigrams,
+                &state.layout,
+                layout,
+                &symbols,
+            ));
+        }
+
+        if !self.trigram_metrics.is_empty() {
+            results.push(self.update_trigram_state(
+                &mut state.trigrams,
+                &state.layout,
+                layout,
+                &symbols,
+            ));
+        }
+
+        state.layout = layout.clone();
+        state.results = results;
+        state.n_updates += 1;
+
+        state.total_cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::EvaluationParameters,
+        ngram_mapper::{
+            dense_ngram_mapper::DenseNgramMapper, on_demand_ngram_mapper::OnDemandNgramMapper,
+        },
+        ngrams::{Bigrams, Trigrams, Unigrams},
+    };
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout_generator::LayoutGenerator,
+        neo_layout_generator::NeoLayoutGenerator,
+    };
+    use std::path::Path;
+
+    const NGRAMS: &str = "../ngrams/eng/eng_wiki_1m";
+    const LAYOUT_CONFIG: &str = "../config/keyboard/standard.yml";
+    const EVAL_PARAMS: &str = "../config/evaluation/standard.yml";
+    const LAYOUT: &str = "xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j";
+    /// Positions of keys in the layout string that are swapped one after another (all keys with the
+    /// same layers, such that the layouts can be updated incrementally)
+    const SWAPS: [(usize, usize); 6] = [(0, 2), (13, 20), (3, 29), (14, 15), (11, 30), (13, 20)];
+
+    /// Evaluators for the standard metrics using both mappers. The ngram files contain absolute counts,
+    /// so that all accumulated weights are exact (independent of the order of summation).
+    fn evaluators() -> Vec<Evaluator> {
+        let mut params = EvaluationParameters::from_yaml(EVAL_PARAMS).unwrap();
+        // the typing speed shall be provided by incremental updates as well
+        if let Some(typing_time) = params.metrics.typing_time.as_mut() {
+            typing_time.enabled = true;
+        }
+        let path = Path::new(NGRAMS);
+        let unigrams = Unigrams::from_file(path.join("1-grams.txt").to_str().unwrap()).unwrap();
+        let bigrams = Bigrams::from_file(path.join("2-grams.txt").to_str().unwrap()).unwrap();
+        let trigrams = Trigrams::from_file(path.join("3-grams.txt").to_str().unwrap()).unwrap();
+
+        vec![
+            Evaluator::default(Box::new(OnDemandNgramMapper::with_ngrams(
+                unigrams.clone(),
+                bigrams.clone(),
+                trigrams.clone(),
+                params.ngram_mapper.clone(),
+            )))
+            .default_metrics(&params.metrics),
+            Evaluator::default(Box::new(DenseNgramMapper::with_ngrams(
+                unigrams,
+                bigrams,
+                trigrams,
+                params.ngram_mapper,
+            )))
+            .default_metrics(&params.metrics),
+        ]
+    }
+
+    /// The weights of all metric types, identified by their bits.
+    fn weight_bits(result: &EvaluationResult) -> Vec<(String, u64)> {
+        result
+            .iter()
+            .flat_map(|mc| {
+                let metric_type = format!("{:?}", mc.metric_type);
+                [
+                    (metric_type.clone(), mc.found_weight.to_bits()),
+                    (metric_type, mc.not_found_weight.to_bits()),
+                ]
+            })
+            .collect()
+    }
+
+    /// The total cost and the costs and typing speeds of all metrics.
+    fn costs(result: &EvaluationResult) -> Vec<(String, f64)> {
+        let mut costs = vec![("total".to_string(), result.total_cost())];
+        result.iter().for_each(|mc| {
+            costs.extend(
+                mc.metric_costs
+                    .iter()
+                    .map(|c| (c.core.name.clone(), c.core.cost)),
+            );
+            costs.extend(mc.metric_costs.iter().filter_map(|c| {
+                c.core
+                    .typing_speed
+                    .map(|s| (c.core.name.clone(), s.ms_per_keystroke))
+            }));
+        });
+
+        costs
+    }
+
+    /// The total cost and the weights and costs of all metrics, identified by their bits.
+    fn cost_bits(result: &EvaluationResult) -> Vec<(String, u64)> {
+        let mut bits = weight_bits(result);
+        bits.extend(
+            costs(result)
+                .into_iter()
+                .map(|(name, c)| (name, c.to_bits())),
+        );
+
+        bits
+    }
+
+    /// Swap the keys of [`SWAPS`] one after another and compare the updated state with a full
+    /// evaluation using `compare(evaluator, state, expected)`.
+    fn compare_swaps(compare: impl Fn(&Evaluator, &mut EvaluationState, &EvaluationResult)) {
+        let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG).unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+
+        for evaluator in evaluators().iter() {
+            let mut layout: Vec<char> = LAYOUT.chars().collect();
+            let mut state = evaluator.evaluation_state(&layout_generator.generate(LAYOUT).unwrap());
+
+            for (i, j) in SWAPS {
+                layout.swap(i, j);
+                let layout_str: String = layout.iter().collect();
+                let new_layout = layout_generator.generate(&layout_str).unwrap();
+
+                let total_cost = evaluator.update_state(&mut state, &new_layout);
+                let expected = evaluator.evaluate_layout(&new_layout);
+
+                assert_eq!(total_cost.to_bits(), state.total_cost().to_bits());
+                assert!(expected.iter().any(|mc| mc
+                    .metric_costs
+                    .iter()
+                    .any(|c| c.core.typing_speed.is_some())));
+                compare(evaluator, &mut state, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn updated_state_equal_to_evaluation() {
+        compare_swaps(|_, state, expected| {
+            assert!(state.n_updates > 0);
+
+            let result = state.result();
+            assert_eq!(weight_bits(&result), weight_bits(expected));
+            costs(&result).into_iter().zip(costs(expected)).for_each(
+                |((name, cost), (expected_name, expected_cost))| {
+                    assert_eq!(name, expected_name);
+                    assert!(
+                        (cost - expected_cost).abs() <= 1e-9 * expected_cost.abs(),
+                        "{}: {} != {}",
+                        name,
+                        cost,
+                        expected_cost
+                    );
+                },
+            );
+        });
+    }
+
+    #[test]
+    fn resynced_state_identical_to_evaluation() {
+        // the running totals are summed up again after `FULL_EVALUATION_INTERVAL` updates
+        compare_swaps(|evaluator, state, expected| {
+            state.n_updates = FULL_EVALUATION_INTERVAL;
+            let layout = state.layout().clone();
+            evaluator.update_state(state, &layout);
+
+            assert_eq!(state.n_updates, 0);
+            assert_eq!(cost_bits(&state.result()), cost_bits(expected));
+        });
+    }
 }
@@ -0,0 +1,388 @@
+//! The `keystroke_log` module provides structs for reading timestamped keystroke logs (e.g. CSV
+//! exported from a keylogger or typing trainer) and deriving personal ngram frequencies and measured
+//! transition times between keys from them.
+//!
+//! A log is a delimited file with (at least) a column containing a timestamp and one containing the
+//! typed key. Keys are given either as the typed symbol itself or as a key name (e.g. `space`,
+//! `enter`, `Key.backspace`). Names of keys that do not produce text (e.g. `backspace`, arrows,
+//! `ctrl`) interrupt the typed text, such that no ngrams or transitions are collected across them.
+//!
+//! In order to not leak passwords and other sensitive data from a log, it can be anonymized by
+//! dropping all words that are typed less often than a given number of times.
+
+use crate::ngram_mapper::common::NgramMap;
+use crate::ngrams::{Bigrams, Trigrams, Unigrams};
+
+use keyboard_layout::key::MatrixPosition;
+use keyboard_layout::layout::{LayerModifiers, Layout};
+
+use ahash::AHashMap;
+use anyhow::{bail, Context, Result};
+use std::{
+    fs::{self, create_dir_all, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Format of the columns of a keystroke log
+#[derive(Clone, Debug)]
+pub struct KeystrokeLogFormat {
+    /// Index of the column containing the timestamp
+    pub time_column: usize,
+    /// Index of the column containing the typed key
+    pub key_column: usize,
+    /// Delimiter of the columns
+    pub delimiter: u8,
+    /// Duration of one unit of the timestamps in milliseconds (e.g. 1000 for timestamps in seconds)
+    pub time_unit_ms: f64,
+}
+
+impl Default for KeystrokeLogFormat {
+    fn default() -> Self {
+        Self {
+            time_column: 0,
+            key_column: 1,
+            delimiter: b',',
+            time_unit_ms: 1.0,
+        }
+    }
+}
+
+/// A single typed symbol
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Keystroke {
+    /// Time of the keystroke in milliseconds
+    pub time_ms: f64,
+    /// The typed symbol
+    pub symbol: char,
+}
+
+/// A key read from a log (either a typed symbol or a key interrupting the text)
+enum LoggedKey {
+    Symbol(char),
+    Modifier,
+    Interruption,
+}
+
+/// Interpret the key field of a log entry
+fn parse_key(field: &str) -> LoggedKey {
+    let mut chars = field.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return LoggedKey::Symbol(c);
+    }
+
+    // loggers such as `pynput` prefix key names with "Key."
+    let name = field.trim().to_lowercase();
+    let name = name.strip_prefix("key.").unwrap_or(&name);
+    match name {
+        "space" | "spacebar" => LoggedKey::Symbol(' '),
+        "enter" | "return" => LoggedKey::Symbol('\n'),
+        "tab" => LoggedKey::Symbol('\t'),
+        // shifted symbols are already logged as such
+        "shift" | "shift_l" | "shift_r" | "lshift" | "rshift" | "caps_lock" | "capslock" => {
+            LoggedKey::Modifier
+        }
+        _ => LoggedKey::Interruption,
+    }
+}
+
+/// Holds the keystrokes of a log, split into segments of uninterrupted text.
+#[derive(Clone, Debug, Default)]
+pub struct KeystrokeLog {
+    pub segments: Vec<Vec<Keystroke>>,
+}
+
+impl KeystrokeLog {
+    /// Read keystrokes from a string containing a log of the given format. A first line without a
+    /// valid timestamp is treated as header.
+    pub fn from_log_str(data: &str, format: &KeystrokeLogFormat) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::Headers)
+            .delimiter(format.delimiter)
+            .from_reader(data.as_bytes());
+
+        let mut segments = Vec::new();
+        let mut segment: Vec<Keystroke> = Vec::new();
+        for (i, record) in reader.records().enumerate() {
+            let record = record.with_context(|| format!("Invalid keystroke in line {}", i + 1))?;
+            let (time, key) = match (
+                record.get(format.time_column),
+                record.get(format.key_column),
+            ) {
+                (Some(time), Some(key)) => (time.trim(), key),
+                _ => bail!("Missing column in line {}: {:?}", i + 1, record),
+            };
+            let time_ms = match time.parse::<f64>() {
+                Ok(time) => time * format.time_unit_ms,
+                // the first line may be a header
+                Err(_) if i == 0 => {
+                    log::debug!("Skipping header {:?}", record);
+                    continue;
+                }
+                Err(_) => bail!("Invalid timestamp in line {}: '{}'", i + 1, time),
+            };
+
+            match parse_key(key) {
+                LoggedKey::Symbol(symbol) => segment.push(Keystroke { time_ms, symbol }),
+                LoggedKey::Modifier => {}
+                LoggedKey::Interruption => {
+                    if !segment.is_empty() {
+                        segments.push(std::mem::take(&mut segment));
+                    }
+                }
+            }
+        }
+        if !segment.is_empty() {
+            segments.push(segment);
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Read keystrokes from a file containing a log of the given format.
+    pub fn from_file(filename: &str, format: &KeystrokeLogFormat) -> Result<Self> {
+        let data = fs::read_to_string(filename)?;
+        Self::from_log_str(&data, format)
+    }
+
+    /// Total number of keystrokes
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.len()).sum()
+    }
+
+    /// Whether the log contains no keystrokes
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|s| s.is_empty())
+    }
+
+    /// Remove all words (sequences of non-whitespace symbols) that occur less than `min_word_count`
+    /// times. The segments are split where a word has been removed.
+    pub fn anonymize(&self, min_word_count: usize) -> Self {
+        let is_word_symbol = |k: &Keystroke| !k.symbol.is_whitespace();
+        let words = |segment: &[Keystroke]| -> Vec<(usize, usize, String)> {
+            let mut res = Vec::new();
+            let mut start = None;
+            for (i, k) in segment.iter().enumerate() {
+                match (start, is_word_symbol(k)) {
+                    (None, true) => start = Some(i),
+                    (Some(s), false) => {
+                        res.push((s, i, segment[s..i].iter().map(|k| k.symbol).collect()));
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(s) = start {
+                res.push((
+                    s,
+                    segment.len(),
+                    segment[s..].iter().map(|k| k.symbol).collect(),
+                ));
+            }
+            res
+        };
+
+        let mut word_counts: AHashMap<String, usize> = AHashMap::default();
+        self.segments.iter().for_each(|segment| {
+            words(segment)
+                .into_iter()
+                .for_each(|(_, _, word)| *word_counts.entry(word).or_insert(0) += 1)
+        });
+
+        let mut segments = Vec::new();
+        let mut n_removed = 0;
+        for segment in self.segments.iter() {
+            let mut start = 0;
+            for (s, e, word) in words(segment) {
+                if word_counts[&word] < min_word_count {
+                    segments.push(segment[start..s].to_vec());
+                    start = e;
+                    n_removed += 1;
+                }
+            }
+            segments.push(segment[start..].to_vec());
+        }
+        segments.retain(|s| !s.is_empty());
+        log::info!(
+            "Removed {} occurrences of words typed less than {} times",
+            n_removed,
+            min_word_count
+        );
+
+        Self { segments }
+    }
+
+    /// Collect unigrams, bigrams, and trigrams from the typed text (not across segments).
+    pub fn to_ngrams(&self) -> (Unigrams, Bigrams, Trigrams) {
+        let mut unigrams = AHashMap::default();
+        let mut bigrams = AHashMap::default();
+        let mut trigrams = AHashMap::default();
+        for segment in self.segments.iter() {
+            let chars: Vec<char> = segment
+                .iter()
+                .map(|k| k.symbol)
+                .filter(|c| *c != '\r')
+                .collect();
+            chars
+                .iter()
+                .for_each(|c| unigrams.insert_or_add_weight(*c, 1.0));
+            chars
+                .windows(2)
+                .for_each(|w| bigrams.insert_or_add_weight((w[0], w[1]), 1.0));
+            chars
+                .windows(3)
+                .for_each(|w| trigrams.insert_or_add_weight((w[0], w[1], w[2]), 1.0));
+        }
+
+        (
+            Unigrams { grams: unigrams },
+            Bigrams { grams: bigrams },
+            Trigrams { grams: trigrams },
+        )
+    }
+}
+
+/// Measured transition time between two keys
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TransitionTime {
+    /// Mean time between pressing the first and the second key in milliseconds
+    pub mean_ms: f64,
+    /// Number of measured transitions
+    pub count: usize,
+}
+
+/// Holds a hashmap of measured transition times between (the matrix positions of) two keys.
+#[derive(Clone, Debug, Default)]
+pub struct TransitionTimes {
+    pub times: AHashMap<(MatrixPosition, MatrixPosition), TransitionTime>,
+}
+
+impl TransitionTimes {
+    /// Measure the transition times between the keys of a layout from a keystroke log. Transitions
+    /// taking longer than `max_pause_ms` are considered pauses and are ignored, as are symbols
+    /// that are not in the layout or require modifiers, combos, or compose sequences.
+    pub fn from_log(log: &KeystrokeLog, layout: &Layout, max_pause_ms: f64) -> Self {
+        let key_position = |c: &char| {
+            layout.get_layerkey_for_symbol(c).and_then(|lk| {
+                let plain = lk.combo_keys.is_empty()
+                    && lk.compose_sequence.is_empty()
+                    && matches!(&lk.modifiers, LayerModifiers::Hold(m) if m.is_empty());
+                plain.then_some(lk.key.matrix_position)
+            })
+        };
+
+        let mut sums: AHashMap<(MatrixPosition, MatrixPosition), (f64, usize)> =
+            AHashMap::default();
+        let mut n_skipped = 0;
+        for segment in log.segments.iter() {
+            for w in segment.windows(2) {
+                let dt = w[1].time_ms - w[0].time_ms;
+                if dt < 0.0 || dt > max_pause_ms {
+                    continue;
+                }
+                match (key_position(&w[0].symbol), key_position(&w[1].symbol)) {
+                    (Some(from), Some(to)) => {
+                        let entry = sums.entry((from, to)).or_default();
+                        entry.0 += dt;
+                        entry.1 += 1;
+                    }
+                    _ => n_skipped += 1,
+                }
+            }
+        }
+        if n_skipped > 0 {
+            log::info!(
+                "Skipped {} transitions involving symbols that are not directly on a key",
+                n_skipped
+            );
+        }
+
+        let times = sums
+            .into_iter()
+            .map(|(transition, (sum, count))| {
+                let mean_ms = sum / count as f64;
+                (transition, TransitionTime { mean_ms, count })
+            })
+            .collect();
+
+        Self { times }
+    }
+
+    /// Read transition times from a string containing lines `from_column,from_row,to_column,to_row,mean_ms,count`.
+    /// A header line is optional.
+    pub fn from_csv_str(data: &str) -> Result<Self> {
+        let mut times = AHashMap::default();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            let parsed = match fields.as_slice() {
+                [c1, r1, c2, r2, mean_ms, count] => (|| {
+                    Some((
+                        MatrixPosition(c1.parse().ok()?, r1.parse().ok()?),
+                        MatrixPosition(c2.parse().ok()?, r2.parse().ok()?),
+                        TransitionTime {
+                            mean_ms: mean_ms.parse().ok()?,
+                            count: count.parse().ok()?,
+                        },
+                    ))
+                })(),
+                _ => None,
+            };
+            match parsed {
+                Some((from, to, time)) => {
+                    times.insert((from, to), time);
+                }
+                // the first line may be a header
+                None if i == 0 => {}
+                None => bail!("Invalid transition time in line {}: '{}'", i + 1, line),
+            }
+        }
+
+        Ok(Self { times })
+    }
+
+    /// Read transition times from a file (as written by [`TransitionTimes::save`]).
+    pub fn from_file(filename: &str) -> Result<Self> {
+        let data = fs::read_to_string(filename)?;
+        Self::from_csv_str(&data)
+    }
+
+    /// The mean transition time between two keys (if measured at least `min_count` times)
+    pub fn get(&self, from: &MatrixPosition, to: &MatrixPosition, min_count: usize) -> Option<f64> {
+        self.times
+            .get(&(*from, *to))
+            .filter(|t| t.count >= min_count)
+            .map(|t| t.mean_ms)
+    }
+
+    /// Write the transition times into a CSV file (most frequent transitions first).
+    pub fn save<T: AsRef<Path>>(&self, filename: T) -> Result<()> {
+        if let Some(dir) = filename.as_ref().parent() {
+            create_dir_all(dir)?;
+        }
+        let mut file = BufWriter::new(File::create(filename)?);
+
+        let mut times: Vec<_> = self.times.iter().collect();
+        times.sort_by(|(k1, t1), (k2, t2)| {
+            t2.count.cmp(&t1.count).then_with(|| {
+                (k1.0 .0, k1.0 .1, k1.1 .0, k1.1 .1).cmp(&(k2.0 .0, k2.0 .1, k2.1 .0, k2.1 .1))
+            })
+        });
+
+        writeln!(file, "from_column,from_row,to_column,to_row,mean_ms,count")?;
+        for ((from, to), time) in times {
+            writeln!(
+                file,
+                "{},{},{},{},{:.1},{}",
+                from.0, from.1, to.0, to.1, time.mean_ms, time.count
+            )?;
+        }
+
+        Ok(())
+    }
+}
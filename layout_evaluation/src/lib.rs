@@ -1,9 +1,12 @@
 pub mod cache;
+pub mod comparison;
 pub mod config;
 pub mod evaluation;
+pub mod keystroke_log;
 pub mod metrics;
 pub mod ngram_mapper;
 pub mod ngrams;
+pub mod objectives;
 pub mod results;
 pub mod sval;
 
@@ -1,9 +1,9 @@
 //! The `metrics` module provides a trait for bigram metrics.
+use crate::results::TypingSpeed;
+
 use keyboard_layout::layout::{LayerKey, Layout};
 
-use ordered_float::OrderedFloat;
-use priority_queue::DoublePriorityQueue;
-use std::{env, fmt};
+use std::fmt;
 
 pub mod std_finger_repeats;
 pub mod sval_finger_repeats;
@@ -20,6 +20,8 @@ pub mod oxey_lsbs;
 pub mod oxey_sfbs;
 pub mod scissoring;
 pub mod symmetric_handswitches;
+pub mod tap_hold_misfires;
+pub mod typing_time;
 
 /// BigramMetric is a trait for metrics that iterates over weighted bigrams.
 pub trait BigramMetric: Send + Sync + BigramMetricClone + fmt::Debug {
@@ -39,6 +41,17 @@ pub trait BigramMetric: Send + Sync + BigramMetricClone + fmt::Debug {
         None
     }
 
+    /// Whether the total cost only depends on the sum of the individual costs of all bigrams. Only such
+    /// metrics can be updated incrementally for small layout changes (see [`crate::evaluation::EvaluationState`]).
+    fn is_decomposable(&self) -> bool {
+        true
+    }
+
+    /// Compute the total cost from the sum of the individual costs of all bigrams (for decomposable metrics).
+    fn total_cost_from_sum(&self, individual_costs_sum: f64) -> f64 {
+        individual_costs_sum
+    }
+
     /// Compute the total cost for the metric.
     fn total_cost(
         &self,
@@ -47,99 +60,20 @@ pub trait BigramMetric: Send + Sync + BigramMetricClone + fmt::Debug {
         total_weight: Option<f64>,
         layout: &Layout,
     ) -> (f64, Option<String>) {
-        let show_worst: bool = env::var("SHOW_WORST")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(true);
-        let n_worst: usize = env::var("N_WORST")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(5);
-
         let total_weight = total_weight.unwrap_or_else(|| bigrams.iter().map(|(_, w)| w).sum());
-        let cost_iter = bigrams
+        let individual_costs_sum: f64 = bigrams
             .iter()
-            .enumerate()
-            .filter_map(|(i, (bigram, weight))| {
-                let cost_option =
-                    self.individual_cost(bigram.0, bigram.1, *weight, total_weight, layout);
-
-                cost_option.map(|cost| (i, bigram, cost))
-            });
-        let total_cost_positive: f64 = cost_iter
-            .clone()
-            .filter(|(_, _, cost)| cost.is_sign_positive())
-            .map(|(_, _, cost)| cost)
+            .filter_map(|((k1, k2), weight)| {
+                self.individual_cost(k1, k2, *weight, total_weight, layout)
+            })
             .sum();
 
-        let (total_cost, msg) = if show_worst {
-            let (total_cost, worst, worst_nonfixed) = cost_iter.fold(
-                (0.0, DoublePriorityQueue::new(), DoublePriorityQueue::new()),
-                |(mut total_cost, mut worst, mut worst_nonfixed), (i, bigram, cost)| {
-                    total_cost += cost;
-
-                    if cost.is_sign_positive() {
-                        worst.push(i, OrderedFloat(cost));
-                        if !bigram.0.is_fixed && !bigram.1.is_fixed {
-                            worst_nonfixed.push(i, OrderedFloat(cost));
-                        }
-
-                        if worst.len() > n_worst {
-                            worst.pop_min();
-                        }
-                        if worst_nonfixed.len() > n_worst {
-                            worst_nonfixed.pop_min();
-                        }
-                    }
-
-                    (total_cost, worst, worst_nonfixed)
-                },
-            );
-
-            let gen_msgs = |q: DoublePriorityQueue<usize, OrderedFloat<f64>>| {
-                let worst_msgs: Vec<String> = q
-                    .into_sorted_iter()
-                    .rev()
-                    .filter(|(_, cost)| cost.into_inner() > 0.0)
-                    .map(|(i, cost)| {
-                        let (gram, _) = bigrams[i];
-                        format!(
-                            "{}{} ({:>5.2}%)",
-                            gram.0,
-                            gram.1,
-                            100.0 * cost.into_inner() / total_cost_positive,
-                        )
-                    })
-                    .collect();
-
-                worst_msgs
-            };
-
-            let mut msgs = Vec::new();
-
-            let worst_msgs = gen_msgs(worst);
-            if !worst_msgs.is_empty() {
-                msgs.push(format!("Worst: {}", worst_msgs.join(", ")))
-            }
-
-            let _worst_nonfixed_msgs = gen_msgs(worst_nonfixed);
-            // if !worst_nonfixed_msgs.is_empty() {
-            //     msgs.push(format!(
-            //         "Worst non-fixed: {}",
-            //         worst_nonfixed_msgs.join(", ")
-            //     ))
-            // }
-
-            let msg = Some(msgs.join(";  "));
-
-            (total_cost, msg)
-        } else {
-            let total_cost: f64 = cost_iter.map(|(_, _, c)| c).sum();
-
-            (total_cost, None)
-        };
+        (self.total_cost_from_sum(individual_costs_sum), None)
+    }
 
-        (total_cost, msg)
+    /// The typing speed corresponding to the total cost, if the metric estimates typing times.
+    fn typing_speed(&self, _total_cost: f64, _total_weight: f64) -> Option<TypingSpeed> {
+        None
     }
 }
 
@@ -76,6 +76,10 @@ impl BigramMetric for KLADistance {
         "Distance"
     }
 
+    fn is_decomposable(&self) -> bool {
+        false
+    }
+
     fn total_cost(
         &self,
         bigrams: &[((&LayerKey, &LayerKey), f64)],
@@ -37,6 +37,10 @@ impl BigramMetric for KLAFingerUsage {
         "Finger Usage"
     }
 
+    fn is_decomposable(&self) -> bool {
+        false
+    }
+
     fn total_cost(
         &self,
         bigrams: &[((&LayerKey, &LayerKey), f64)],
@@ -4,26 +4,66 @@
 //!
 //! *Note:* In contrast to ArneBab's version of the metric, thumbs are excluded.
 
-use crate::sval::SvalKeyDirection;
+use crate::sval::{
+    default_factors, DirectionFactors, DirectionFactorsParameters, DirectionMatrix,
+    TransitionDirection,
+};
 
 use super::BigramMetric;
 
+use ahash::AHashMap;
 use keyboard_layout::{
-    key::Finger,
+    key::{Finger, FingerMap},
     layout::{LayerKey, Layout},
 };
 
 use serde::Deserialize;
 
 #[derive(Clone, Deserialize, Debug)]
-pub struct Parameters {}
+pub struct Parameters {
+    /// Cost of each finger moving into the opposite direction of its neighbor with the default
+    /// effort of `sval_direction_factors` (missing fingers: 0.5, pinky 1.0)
+    #[serde(default)]
+    pub finger_factors: AHashMap<Finger, f64>,
+}
 
 #[derive(Clone, Debug)]
-pub struct Scissoring {}
+pub struct Scissoring {
+    /// Cost of each finger's transitions, i.e. its `finger_factor` scaled by the configured effort
+    /// of the transition relative to the default one
+    costs: FingerMap<DirectionMatrix>,
+}
 
 impl Scissoring {
-    pub fn new(_params: &Parameters) -> Self {
-        Self {}
+    pub fn new(params: &Parameters, direction_factors: &DirectionFactorsParameters) -> Self {
+        let direction_factors = DirectionFactors::new(direction_factors);
+
+        let mut costs = FingerMap::with_default([[0.0; 5]; 5]);
+        for finger in FingerMap::<DirectionMatrix>::keys() {
+            let default_finger_factor = if finger == Finger::Pinky { 1.0 } else { 0.5 };
+            let finger_factor = *params
+                .finger_factors
+                .get(&finger)
+                .unwrap_or(&default_finger_factor);
+            let defaults = default_factors(finger);
+
+            let mut matrix = [[0.0; 5]; 5];
+            for d1 in TransitionDirection::ALL {
+                for d2 in TransitionDirection::ALL {
+                    let default = defaults[d1 as usize][d2 as usize];
+                    // with the default effort, the cost equals the finger factor exactly
+                    let relative = if default > 0.0 {
+                        direction_factors.get(finger, d1, d2) / default
+                    } else {
+                        direction_factors.get(finger, d1, d2)
+                    };
+                    matrix[d1 as usize][d2 as usize] = finger_factor * relative;
+                }
+            }
+            costs.set(&finger, matrix);
+        }
+
+        Self { costs }
     }
 }
 
@@ -47,43 +87,27 @@ impl BigramMetric for Scissoring {
         if k1.key.finger.distance(&k2.key.finger) != 1 {
             return Some(0.0);
         }
-        let pos1 = k1.key.matrix_position;
-        let pos2 = k2.key.matrix_position;
-        let center_keys = [
-            (2, 2),
-            (5, 2),
-            (8, 2),
-            (11, 2),
-            (14, 2),
-            (17, 2),
-            (20, 2),
-            (23, 2),
-        ];
         if k1.key.finger == Finger::Thumb || k2.key.finger == Finger::Thumb {
             return Some(0.);
         }
-        let closest_center_1 = center_keys
-            .iter()
-            .min_by_key(|(x, y)| pos1.0.abs_diff(*x) + pos1.1.abs_diff(*y))
-            .unwrap();
-        let sval_key_1 = SvalKeyDirection::from_key(&k1.key, closest_center_1);
-        let closest_center_2 = center_keys
-            .iter()
-            .min_by_key(|(x, y)| pos2.0.abs_diff(*x) + pos2.1.abs_diff(*y))
-            .unwrap();
-        let sval_key_2 = SvalKeyDirection::from_key(&k2.key, closest_center_2);
-        if sval_key_1 == sval_key_2 {
+        // keys outside of clusters (e.g. on keyboards without clusters) are skipped
+        let (d1, d2) = match (
+            TransitionDirection::of_key(&k1.key),
+            TransitionDirection::of_key(&k2.key),
+        ) {
+            (Some(d1), Some(d2)) => (d1, d2),
+            _ => return Some(0.),
+        };
+        // only movements into opposite directions (north-south and inward-outward) are scissors
+        if !d1.is_opposite(d2) {
             return Some(0.);
         }
-        let includes_pinky = k1.key.finger == Finger::Pinky || k2.key.finger == Finger::Pinky;
-        match (sval_key_1, sval_key_2) {
-            (SvalKeyDirection::North, SvalKeyDirection::South)
-            | (SvalKeyDirection::South, SvalKeyDirection::North)
-            | (SvalKeyDirection::East, SvalKeyDirection::West)
-            | (SvalKeyDirection::West, SvalKeyDirection::East) => {
-                Some(weight * if includes_pinky { 1.0 } else { 0.5 })
-            }
-            _ => Some(0.),
-        }
+        // the finger suffering more from the movement determines the cost
+        let factor = f64::max(
+            self.costs.get(&k1.key.finger)[d1 as usize][d2 as usize],
+            self.costs.get(&k2.key.finger)[d2 as usize][d1 as usize],
+        );
+
+        Some(weight * factor)
     }
 }
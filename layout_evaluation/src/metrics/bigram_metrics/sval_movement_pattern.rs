@@ -1,12 +1,13 @@
 //! The bigram metric [`SvalMovementPattern`] puts cost on each bigram that is mapped to
 //! (almost) neighboring fingers on Svalboard keyboards. This metric is specifically designed
-//! for the Svalboard's unique directional activation system.
+//! for the Svalboard's unique directional activation system. Only switches starting from a lateral
+//! (inward or outward) key cost, scaled with the effort of the first finger returning to the center
+//! of its cluster (see `sval_direction_factors`).
 
-use crate::sval::SvalKeyDirection;
+use crate::sval::{DirectionFactors, DirectionFactorsParameters, TransitionDirection};
 
 use super::BigramMetric;
 
-
 use keyboard_layout::{
     key::{Finger, Hand, HandFingerMap},
     layout::{LayerKey, Layout},
@@ -30,10 +31,11 @@ pub struct Parameters {
 #[derive(Clone, Debug)]
 pub struct SvalMovementPattern {
     finger_switch_factor: HandFingerMap<HandFingerMap<f64>>,
+    direction_factors: DirectionFactors,
 }
 
 impl SvalMovementPattern {
-    pub fn new(params: &Parameters) -> Self {
+    pub fn new(params: &Parameters, direction_factors: &DirectionFactorsParameters) -> Self {
         let mut finger_switch_factor =
             HandFingerMap::with_default(HandFingerMap::with_default(0.0));
         for hand in [Hand::Left, Hand::Right] {
@@ -45,6 +47,7 @@ impl SvalMovementPattern {
 
         Self {
             finger_switch_factor,
+            direction_factors: DirectionFactors::new(direction_factors),
         }
     }
 }
@@ -72,8 +75,6 @@ impl BigramMetric for SvalMovementPattern {
             return Some(0.0);
         }
 
-        let pos1 = k1.key.matrix_position;
-
         let finger_switch_factor = *self.finger_switch_factor.get(&h1, &f1).get(&h2, &f2);
 
         let mut unbalancing_factor = k1.key.unbalancing.0.abs() + k1.key.unbalancing.1.abs();
@@ -81,27 +82,16 @@ impl BigramMetric for SvalMovementPattern {
             unbalancing_factor = 1.0;
         }
 
-        // Svalboard-specific logic: Only apply cost for East/West directional keys
-        let center_keys = [
-            (2, 2),
-            (5, 2),
-            (8, 2),
-            (11, 2),
-            (14, 2),
-            (17, 2),
-            (20, 2),
-            (23, 2),
-        ];
-        let closest_center_1 = center_keys
-            .iter()
-            .min_by_key(|(x, y)| pos1.0.abs_diff(*x) + pos1.1.abs_diff(*y))
-            .unwrap();
-        let sval_key_1 = SvalKeyDirection::from_key(&k1.key, closest_center_1);
-        if !(sval_key_1 == SvalKeyDirection::East || sval_key_1 == SvalKeyDirection::West) {
-            return Some(0.0);
-        }
+        // keys outside of clusters (e.g. on keyboards without clusters) are skipped; the direction of the
+        // second key does not matter as the first finger only needs to return to its center
+        let direction_factor = match TransitionDirection::of_key(&k1.key) {
+            Some(d1 @ (TransitionDirection::Inward | TransitionDirection::Outward)) => self
+                .direction_factors
+                .get(f1, d1, TransitionDirection::Center),
+            _ => return Some(0.0),
+        };
 
-        let cost = finger_switch_factor;
+        let cost = finger_switch_factor * direction_factor;
 
         Some(weight * cost * unbalancing_factor)
     }
@@ -0,0 +1,75 @@
+//! The bigram metric [`TapHoldMisfires`] assigns a cost to bigrams that start with tapping a
+//! dual-role key (a key that doubles as tap-hold modifier, e.g. home-row mods) and quickly continue
+//! with another key. If the dual-role key is not released in time, the firmware interprets it as
+//! held modifier (a misfire). This is particularly likely for rolls on the same hand.
+
+use super::BigramMetric;
+
+use keyboard_layout::{
+    key::Finger,
+    layout::{LayerKey, Layout},
+};
+
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Cost of continuing with a key of the same hand (a roll)
+    pub same_hand_cost: f64,
+    /// Cost of continuing with a key of the other hand (usually lower due to firmware
+    /// features like "bilateral combinations")
+    pub other_hand_cost: f64,
+    /// Exclude bigrams that continue with a thumb
+    #[serde(default)]
+    pub exclude_thumbs: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct TapHoldMisfires {
+    same_hand_cost: f64,
+    other_hand_cost: f64,
+    exclude_thumbs: bool,
+}
+
+impl TapHoldMisfires {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            same_hand_cost: params.same_hand_cost,
+            other_hand_cost: params.other_hand_cost,
+            exclude_thumbs: params.exclude_thumbs,
+        }
+    }
+}
+
+impl BigramMetric for TapHoldMisfires {
+    fn name(&self) -> &str {
+        "Tap-Hold Misfires"
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        k1: &LayerKey,
+        k2: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        // the same finger releases the first key before pressing the second
+        if !k1.is_dual_role || k1.key.finger == k2.key.finger && k1.key.hand == k2.key.hand {
+            return Some(0.0);
+        }
+
+        if self.exclude_thumbs && k2.key.finger == Finger::Thumb {
+            return Some(0.0);
+        }
+
+        let cost = if k1.key.hand == k2.key.hand {
+            self.same_hand_cost
+        } else {
+            self.other_hand_cost
+        };
+
+        Some(weight * cost)
+    }
+}
@@ -0,0 +1,185 @@
+//! The bigram metric [`TypingTime`] estimates the time (in milliseconds) for typing the second symbol
+//! of each bigram after the first one. The resulting cost (normalized with the found weight) is the
+//! mean time per keystroke, which is reported as words per minute (five keystrokes per word) as well
+//! (see [`TypingSpeed`]).
+//!
+//! The time of a transition consists of a base time depending on whether the keys are typed with
+//! the same finger, another finger of the same hand, or the other hand, plus a Fitts' law term
+//! for the movement of the finger: `fitts_slope_ms * log2(1 + distance / key_spacing)`. For a same
+//! finger transition, the finger moves from the first key, otherwise from its resting position.
+//!
+//! Optionally, measured transition times between keys (as written by the `ingest_keystrokes` binary)
+//! replace the estimates for all transitions that have been measured often enough.
+//!
+//! *Note:* Modifiers are not considered (unless they are mapped to separate keys of the bigrams).
+
+use super::BigramMetric;
+use crate::keystroke_log::TransitionTimes;
+use crate::results::TypingSpeed;
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use serde::Deserialize;
+
+/// Number of keystrokes per word for the words-per-minute estimate
+const KEYSTROKES_PER_WORD: f64 = 5.0;
+
+fn default_min_transition_count() -> usize {
+    3
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Base time (ms) of a transition to the other hand
+    pub alternate_hand_ms: f64,
+    /// Base time (ms) of a transition to another finger of the same hand
+    pub same_hand_ms: f64,
+    /// Base time (ms) of a transition to another key (or the same key) with the same finger
+    pub same_finger_ms: f64,
+    /// Additional time (ms) per bit of the Fitts' law index of difficulty of the finger's movement
+    pub fitts_slope_ms: f64,
+    /// CSV file with measured transition times between keys (as written by `ingest_keystrokes`)
+    #[serde(default)]
+    pub transitions_filename: Option<String>,
+    /// Minimal number of measurements for a measured transition time to be used
+    #[serde(default = "default_min_transition_count")]
+    pub min_transition_count: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct TypingTime {
+    alternate_hand_ms: f64,
+    same_hand_ms: f64,
+    same_finger_ms: f64,
+    fitts_slope_ms: f64,
+    transitions: Option<TransitionTimes>,
+    min_transition_count: usize,
+}
+
+impl TypingTime {
+    pub fn new(params: &Parameters) -> Self {
+        let transitions = params.transitions_filename.as_ref().map(|filename| {
+            TransitionTimes::from_file(filename).unwrap_or_else(|e| {
+                panic!("Could not read transition times from {}: {:?}", filename, e)
+            })
+        });
+
+        Self {
+            alternate_hand_ms: params.alternate_hand_ms,
+            same_hand_ms: params.same_hand_ms,
+            same_finger_ms: params.same_finger_ms,
+            fitts_slope_ms: params.fitts_slope_ms,
+            transitions,
+            min_transition_count: params.min_transition_count,
+        }
+    }
+
+    /// The measured time of a transition (if measured often enough)
+    #[inline(always)]
+    fn measured_time(&self, k1: &LayerKey, k2: &LayerKey) -> Option<f64> {
+        self.transitions.as_ref().and_then(|t| {
+            t.get(
+                &k1.key.matrix_position,
+                &k2.key.matrix_position,
+                self.min_transition_count,
+            )
+        })
+    }
+
+    /// The estimated time of a transition according to the Fitts' law model
+    #[inline(always)]
+    fn estimated_time(&self, k1: &LayerKey, k2: &LayerKey, layout: &Layout) -> f64 {
+        let keyboard = &layout.keyboard;
+        let (base_ms, start) = if k1.key.hand != k2.key.hand {
+            (
+                self.alternate_hand_ms,
+                keyboard
+                    .finger_resting_positions
+                    .get(&k2.key.hand, &k2.key.finger),
+            )
+        } else if k1.key.finger != k2.key.finger {
+            (
+                self.same_hand_ms,
+                keyboard
+                    .finger_resting_positions
+                    .get(&k2.key.hand, &k2.key.finger),
+            )
+        } else {
+            (self.same_finger_ms, &k1.key.position)
+        };
+
+        let distance = start.distance(&k2.key.position) / keyboard.key_spacing();
+
+        base_ms + self.fitts_slope_ms * (1.0 + distance).log2()
+    }
+
+    /// The time of a transition (measured if available, estimated otherwise)
+    #[inline(always)]
+    fn time(&self, k1: &LayerKey, k2: &LayerKey, layout: &Layout) -> f64 {
+        self.measured_time(k1, k2)
+            .unwrap_or_else(|| self.estimated_time(k1, k2, layout))
+    }
+}
+
+impl BigramMetric for TypingTime {
+    fn name(&self) -> &str {
+        "Typing Time"
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        k1: &LayerKey,
+        k2: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        layout: &Layout,
+    ) -> Option<f64> {
+        Some(weight * self.time(k1, k2, layout))
+    }
+
+    fn total_cost(
+        &self,
+        bigrams: &[((&LayerKey, &LayerKey), f64)],
+        total_weight: Option<f64>,
+        layout: &Layout,
+    ) -> (f64, Option<String>) {
+        let total_weight = total_weight.unwrap_or_else(|| bigrams.iter().map(|(_, w)| w).sum());
+
+        let mut cost = 0.0;
+        let mut measured_weight = 0.0;
+        bigrams.iter().for_each(|((k1, k2), weight)| {
+            let time = match self.measured_time(k1, k2) {
+                Some(time) => {
+                    measured_weight += weight;
+                    time
+                }
+                None => self.estimated_time(k1, k2, layout),
+            };
+            cost += weight * time;
+        });
+
+        if total_weight <= 0.0 || self.transitions.is_none() {
+            return (cost, None);
+        }
+
+        let message = format!(
+            "{:.1}% of bigrams measured",
+            100.0 * measured_weight / total_weight
+        );
+
+        (cost, Some(message))
+    }
+
+    fn typing_speed(&self, total_cost: f64, total_weight: f64) -> Option<TypingSpeed> {
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        let ms_per_keystroke = total_cost / total_weight;
+        Some(TypingSpeed {
+            ms_per_keystroke,
+            words_per_minute: 60_000.0 / (KEYSTROKES_PER_WORD * ms_per_keystroke),
+        })
+    }
+}
@@ -70,7 +70,10 @@ impl LayoutMetric for KLAHomeKeyWords {
             .layerkeys
             .iter()
             .filter_map(|k| {
-                if k.layer == 0 && self.home_row_positions.contains(&k.key.matrix_position) {
+                if k.layer == 0
+                    && k.compose_sequence.is_empty()
+                    && self.home_row_positions.contains(&k.key.matrix_position)
+                {
                     Some(k.symbol)
                 } else {
                     None
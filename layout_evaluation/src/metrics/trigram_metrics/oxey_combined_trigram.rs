@@ -179,6 +179,10 @@ impl TrigramMetric for OxeyCombinedTrigram {
         "Combined"
     }
 
+    fn is_decomposable(&self) -> bool {
+        false
+    }
+
     fn total_cost(
         &self,
         trigrams: &[((&LayerKey, &LayerKey, &LayerKey), f64)],
@@ -1,10 +1,9 @@
 //! The `metrics` module provides a trait for unigram metrics.
 use keyboard_layout::layout::{LayerKey, Layout};
-use ordered_float::OrderedFloat;
-use priority_queue::DoublePriorityQueue;
 
-use std::{env, fmt};
+use std::fmt;
 
+pub mod combo_usage;
 pub mod finger_balance;
 pub mod hand_disbalance;
 pub mod key_costs;
@@ -28,6 +27,17 @@ pub trait UnigramMetric: Send + Sync + UnigramMetricClone + fmt::Debug {
         None
     }
 
+    /// Whether the total cost only depends on the sum of the individual costs of all unigrams. Only such
+    /// metrics can be updated incrementally for small layout changes (see [`crate::evaluation::EvaluationState`]).
+    fn is_decomposable(&self) -> bool {
+        true
+    }
+
+    /// Compute the total cost from the sum of the individual costs of all unigrams (for decomposable metrics).
+    fn total_cost_from_sum(&self, individual_costs_sum: f64) -> f64 {
+        individual_costs_sum
+    }
+
     /// Compute the total cost for the metric.
     fn total_cost(
         &self,
@@ -36,69 +46,15 @@ pub trait UnigramMetric: Send + Sync + UnigramMetricClone + fmt::Debug {
         total_weight: Option<f64>,
         layout: &Layout,
     ) -> (f64, Option<String>) {
-        let show_worst: bool = env::var("SHOW_WORST")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(true);
-        let n_worst: usize = env::var("N_WORST")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(15);
-
         let total_weight = total_weight.unwrap_or_else(|| unigrams.iter().map(|(_, w)| w).sum());
-        let cost_iter = unigrams
+        let individual_costs_sum: f64 = unigrams
             .iter()
-            .enumerate()
-            .filter_map(|(i, (unigram, weight))| {
-                let cost_option = self.individual_cost(unigram, *weight, total_weight, layout);
-
-                cost_option.map(|cost| (i, unigram, cost))
-            });
-
-        let (total_cost, msg) = if show_worst {
-            let (total_cost, worst) = cost_iter.fold(
-                (0.0, DoublePriorityQueue::new()),
-                |(mut total_cost, mut worst), (i, _, cost)| {
-                    total_cost += cost;
-                    worst.push(i, OrderedFloat(cost.abs()));
-                    if worst.len() > n_worst {
-                        worst.pop_min();
-                    }
-
-                    (total_cost, worst)
-                },
-            );
-
-            let mut msgs = Vec::new();
-
-            let worst_msgs: Vec<String> = worst
-                .into_sorted_iter()
-                .rev()
-                .filter(|(_, cost)| cost.into_inner() > 0.0)
-                .map(|(i, cost)| {
-                    let (gram, _) = unigrams[i];
-                    format!(
-                        "{} ({:>5.2}%)",
-                        gram,
-                        100.0 * cost.into_inner() / total_cost,
-                    )
-                })
-                .collect();
-
-            if !worst_msgs.is_empty() {
-                msgs.push(format!("Worst unigrams: {}", worst_msgs.join(", ")))
-            }
-
-            let msg = Some(msgs.join(";  "));
-
-            (total_cost, msg)
-        } else {
-            let total_cost: f64 = cost_iter.map(|(_, _, c)| c).sum();
-
-            (total_cost, None)
-        };
+            .filter_map(|(unigram, weight)| {
+                self.individual_cost(unigram, *weight, total_weight, layout)
+            })
+            .sum();
 
-        (total_cost, msg)
+        (self.total_cost_from_sum(individual_costs_sum), None)
     }
 }
 
@@ -0,0 +1,47 @@
+//! The unigram metric [`ComboUsage`] penalizes each key that is pressed as part of a combo.
+
+use super::UnigramMetric;
+
+use keyboard_layout::layout::{LayerKey, Layout};
+
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Parameters {
+    /// Cost of each key that is pressed as part of a combo
+    pub key_penalty: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ComboUsage {
+    key_penalty: f64,
+}
+
+impl ComboUsage {
+    pub fn new(params: &Parameters) -> Self {
+        Self {
+            key_penalty: params.key_penalty,
+        }
+    }
+}
+
+impl UnigramMetric for ComboUsage {
+    fn name(&self) -> &str {
+        "Combo Usage"
+    }
+
+    #[inline(always)]
+    fn individual_cost(
+        &self,
+        key: &LayerKey,
+        weight: f64,
+        _total_weight: f64,
+        _layout: &Layout,
+    ) -> Option<f64> {
+        if key.is_combo_part {
+            Some(weight * self.key_penalty)
+        } else {
+            Some(0.0)
+        }
+    }
+}
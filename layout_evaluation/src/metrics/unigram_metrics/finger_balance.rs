@@ -46,6 +46,10 @@ impl UnigramMetric for FingerBalance {
         "Finger Balance"
     }
 
+    fn is_decomposable(&self) -> bool {
+        false
+    }
+
     fn total_cost(
         &self,
         unigrams: &[(&LayerKey, f64)],
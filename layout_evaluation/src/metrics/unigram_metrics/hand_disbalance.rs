@@ -27,6 +27,10 @@ impl UnigramMetric for HandDisbalance {
         "Hand Disbalance"
     }
 
+    fn is_decomposable(&self) -> bool {
+        false
+    }
+
     fn total_cost(
         &self,
         unigrams: &[(&LayerKey, f64)],
@@ -11,6 +11,9 @@ pub struct Parameters {
     pub hold_cost: f64,
     pub one_shot_cost: f64,
     pub long_press_cost: f64,
+    /// Cost of tap-hold modifiers (defaults to `hold_cost`)
+    #[serde(default)]
+    pub tap_hold_cost: Option<f64>,
 }
 
 #[derive(Clone, Debug)]
@@ -18,6 +21,7 @@ pub struct ModifierUsage {
     pub hold_cost: f64,
     pub one_shot_cost: f64,
     pub long_press_cost: f64,
+    pub tap_hold_cost: f64,
 }
 
 impl ModifierUsage {
@@ -26,6 +30,18 @@ impl ModifierUsage {
             hold_cost: params.hold_cost,
             one_shot_cost: params.one_shot_cost,
             long_press_cost: params.long_press_cost,
+            tap_hold_cost: params.tap_hold_cost.unwrap_or(params.hold_cost),
+        }
+    }
+
+    #[inline(always)]
+    fn modifier_cost(&self, modifier_type: LayerModifierType) -> f64 {
+        match modifier_type {
+            LayerModifierType::Hold => self.hold_cost,
+            LayerModifierType::OneShot => self.one_shot_cost,
+            LayerModifierType::LongPress => self.long_press_cost,
+            LayerModifierType::TapHold => self.tap_hold_cost,
+            LayerModifierType::None => 0.0,
         }
     }
 }
@@ -41,19 +57,17 @@ impl UnigramMetric for ModifierUsage {
         key: &LayerKey,
         weight: f64,
         _total_weight: f64,
-        _layout: &Layout,
+        layout: &Layout,
     ) -> Option<f64> {
         // costs if this key is a modifier
-        let key_cost = match key.is_modifier {
-            LayerModifierType::Hold => self.hold_cost,
-            LayerModifierType::OneShot => self.one_shot_cost,
-            LayerModifierType::LongPress => self.long_press_cost,
-            _ => 0.0,
-        };
+        let key_cost = self.modifier_cost(key.is_modifier);
 
         // costs if this key relies on modifiers (that were not split in ngram splitting)
         let modifier_costs = match &key.modifiers {
-            LayerModifiers::Hold(v) => self.hold_cost * v.len() as f64,
+            LayerModifiers::Hold(v) => v
+                .iter()
+                .map(|m| self.modifier_cost(layout.get_layerkey(m).is_modifier))
+                .sum(),
             LayerModifiers::OneShot(v) => self.one_shot_cost * v.len() as f64,
             LayerModifiers::LongPress => self.long_press_cost,
         };
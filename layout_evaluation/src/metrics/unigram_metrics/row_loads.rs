@@ -30,6 +30,10 @@ impl UnigramMetric for RowLoads {
         "Row Loads"
     }
 
+    fn is_decomposable(&self) -> bool {
+        false
+    }
+
     fn total_cost(
         &self,
         unigrams: &[(&LayerKey, f64)],
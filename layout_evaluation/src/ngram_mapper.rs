@@ -8,7 +8,18 @@
 //! The provided implementation [`OnDemandNgramMapper`] of the trait additionally performs some postprocessing
 //! of the ngram data. This involves increasing the weight of bigrams that appear often, adding secondary
 //! bigrams from the first and third symbol of each trigram (if they belong to the same hand), and resolving
-//! modifiers of higher-layer symbols:
+//! modifiers of higher-layer symbols. The [`DenseNgramMapper`] performs the same postprocessing, but maps
+//! precomputed tables of interned symbols instead of hashing each ngram's chars for every layout:
+//!
+//! [`DenseNgramMapper`]: dense_ngram_mapper::DenseNgramMapper
+//!
+//! Layouts with context keys (e.g. a repeat key) are tokenized greedily: each symbol following another
+//! one in an ngram is mapped to a context key if that generates it. The weight of unigrams typed with
+//! context keys is estimated from the corresponding bigrams.
+//!
+//! Symbols typed as compose sequences (e.g. a dead key followed by a base symbol) are expanded into
+//! the symbols of their sequence before modifiers are resolved, e.g. the bigram `ré` becomes the
+//! bigrams `r´` and `´e`.
 //!
 //! Input ngrams may contain symbols that may only be accessible in higher layers
 //! of a layout, e.g. uppercase characters. Generating such higher-layer symbols requires the use
@@ -36,11 +47,13 @@ pub mod common;
 pub mod trigram_mapper;
 pub mod unigram_mapper;
 
+pub mod dense_ngram_mapper;
 pub mod on_demand_ngram_mapper;
 
-use keyboard_layout::layout::{LayerKey, Layout};
+use keyboard_layout::layout::{LayerKey, LayerKeyIndex, Layout};
 
-use std::fmt;
+use ahash::{AHashMap, AHashSet};
+use std::{fmt, hash::Hash};
 
 /// Unigrams in terms of a [`Layout`]'s [`LayerKey`]s and statistics about ngrams that
 /// can not be generated by the layout.
@@ -75,11 +88,59 @@ pub struct MappedTrigrams<'s> {
     pub weight_found: f64,
 }
 
-/// Provides ngrams in terms of a [`Layout`]'s [`LayerKey`]s.
+/// Ngrams in terms of a [`Layout`]'s [`LayerKeyIndex`]s and statistics about ngrams that
+/// can not be generated by the layout. In contrast to [`MappedUnigrams`] and its siblings,
+/// these do not borrow from the layout and can be kept and updated across evaluations.
+#[derive(Clone, Debug)]
+pub struct IndexedNgrams<T: Eq + Hash> {
+    /// Ngrams in terms of [`LayerKeyIndex`]s
+    pub grams: AHashMap<T, f64>,
+    /// Total weight (frequencies) of ngrams that can not be generated by the layout
+    pub weight_not_found: f64,
+    /// Total weight (frequencies) of ngrams that can be generated by the layout
+    pub weight_found: f64,
+}
+
+pub type IndexedUnigrams = IndexedNgrams<LayerKeyIndex>;
+pub type IndexedBigrams = IndexedNgrams<(LayerKeyIndex, LayerKeyIndex)>;
+pub type IndexedTrigrams = IndexedNgrams<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex)>;
+
+/// Provides ngrams in terms of a [`Layout`]'s [`LayerKey`]s. The mapped ngrams are sorted by their
+/// [`LayerKeyIndex`]s, such that evaluations do not depend on the mapper or on hashing.
 pub trait NgramMapper: Send + Sync + NgramMapperClone + fmt::Debug {
     fn map_unigrams<'s>(&self, layout: &'s Layout) -> MappedUnigrams<'s>;
     fn map_bigrams<'s>(&self, layout: &'s Layout) -> MappedBigrams<'s>;
     fn map_trigrams<'s>(&self, layout: &'s Layout) -> MappedTrigrams<'s>;
+
+    /// Map all unigrams (or only those containing one of the given symbols) to [`LayerKeyIndex`]s.
+    /// This is required for incremental evaluations. Mappers not supporting it return `None`.
+    fn map_unigram_indices(
+        &self,
+        _layout: &Layout,
+        _symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedUnigrams> {
+        None
+    }
+
+    /// Map all bigrams (or only those containing one of the given symbols) to [`LayerKeyIndex`]s.
+    /// This is required for incremental evaluations. Mappers not supporting it return `None`.
+    fn map_bigram_indices(
+        &self,
+        _layout: &Layout,
+        _symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedBigrams> {
+        None
+    }
+
+    /// Map all trigrams (or only those containing one of the given symbols) to [`LayerKeyIndex`]s.
+    /// This is required for incremental evaluations. Mappers not supporting it return `None`.
+    fn map_trigram_indices(
+        &self,
+        _layout: &Layout,
+        _symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedTrigrams> {
+        None
+    }
 }
 
 // in order to implement clone for Box<dyn LayoutMetric>, the following trick is necessary
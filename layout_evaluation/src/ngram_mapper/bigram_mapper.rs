@@ -6,8 +6,6 @@
 
 use super::{common::*, on_demand_ngram_mapper::SplitModifiersConfig};
 
-use crate::ngrams::Bigrams;
-
 use keyboard_layout::layout::{LayerKey, LayerKeyIndex, LayerModifiers, Layout};
 
 use ahash::AHashMap;
@@ -15,21 +13,21 @@ use ahash::AHashMap;
 // Before passing the resulting LayerKey-based ngrams as a result, smaller LayerKeyIndex-based
 // ones are used because they are smaller than a reference (u16 vs usize) and yield better
 // hashing performance.
-type BigramIndices = AHashMap<(LayerKeyIndex, LayerKeyIndex), f64>;
-type BigramIndicesVec = Vec<((LayerKeyIndex, LayerKeyIndex), f64)>;
+pub type BigramIndices = OrderedNgrams<(LayerKeyIndex, LayerKeyIndex)>;
+pub type BigramIndicesVec = Vec<((LayerKeyIndex, LayerKeyIndex), f64)>;
 
-/// Turns the [`Bigrams`]'s characters into their indices, returning a [`BigramIndicesVec`].
+/// Turns the bigrams' characters (sorted by them) into their indices, returning a [`BigramIndicesVec`].
+/// The second symbol is typed with a context key (e.g. a repeat key) if possible.
 fn map_bigrams(
-    bigrams: &Bigrams,
+    bigrams: &[((char, char), f64)],
     layout: &Layout,
     exclude_line_breaks: bool,
 ) -> (BigramIndicesVec, f64) {
     let mut not_found_weight = 0.0;
-    let mut bigrams_vec: BigramIndicesVec = Vec::with_capacity(bigrams.grams.len());
+    let mut bigrams_vec: BigramIndicesVec = Vec::with_capacity(bigrams.len());
 
     bigrams_vec.extend(
         bigrams
-            .grams
             .iter()
             //.filter(|((c1, c2), _weight)| !c1.is_whitespace() && !c2.is_whitespace())
             .filter_map(|((c1, c2), weight)| {
@@ -45,7 +43,7 @@ fn map_bigrams(
                         return None;
                     }
                 };
-                let idx2 = match layout.get_layerkey_index_for_symbol(c2) {
+                let idx2 = match layout.get_layerkey_index_for_symbol_after(c1, c2) {
                     Some(idx) => idx,
                     None => {
                         not_found_weight += *weight;
@@ -72,22 +70,38 @@ impl OnDemandBigramMapper {
         Self { split_modifiers }
     }
 
-    /// For a given [`Layout`] generate [`LayerKeyIndex`]-based unigrams, optionally resolving modifiers for higer-layer symbols.
+    /// For a given [`Layout`] generate [`LayerKeyIndex`]-based bigrams, optionally resolving modifiers for higer-layer symbols.
+    /// The bigrams need to be sorted by their symbols.
     pub fn layerkey_indices(
         &self,
-        bigrams: &Bigrams,
+        bigrams: &[((char, char), f64)],
         layout: &Layout,
         exclude_line_breaks: bool,
     ) -> (BigramIndices, f64) {
-        let (mut bigram_keys_vec, not_found_weight) =
-            map_bigrams(bigrams, layout, exclude_line_breaks);
+        let (bigram_keys_vec, not_found_weight) = map_bigrams(bigrams, layout, exclude_line_breaks);
+
+        (
+            self.process_modifiers(bigram_keys_vec, layout),
+            not_found_weight,
+        )
+    }
+
+    /// Resolve compose sequences and modifiers of higher-layer symbols in [`LayerKeyIndex`]-based bigrams (if configured).
+    pub fn process_modifiers(
+        &self,
+        mut bigram_keys_vec: BigramIndicesVec,
+        layout: &Layout,
+    ) -> BigramIndices {
+        if layout.has_compose_sequences() {
+            bigram_keys_vec = Self::process_compose_sequences(bigram_keys_vec, layout);
+        }
 
         if layout.has_one_shot_layers() {
             bigram_keys_vec = self.process_one_shot_modifiers(bigram_keys_vec, layout);
         }
 
         let bigram_keys = if self.split_modifiers.enabled && layout.has_hold_layers() {
-            self.process_hold_modifiers(bigram_keys_vec, layout)
+            self.process_hold_modifiers(bigram_keys_vec, &HoldModifiers::new(layout))
         } else {
             bigram_keys_vec.into_iter().collect()
         };
@@ -99,58 +113,72 @@ impl OnDemandBigramMapper {
         //         println!("After split: {}", w);
         //     });
 
-        (bigram_keys, not_found_weight)
+        bigram_keys
     }
 
-    /// Resolves &[`LayerKey`] references for [`LayerKeyIndex`] and filters bigrams that contain
-    /// repeating identical modifiers.
-    pub fn get_filtered_layerkeys<'s>(
-        bigrams: &BigramIndices,
-        layout: &'s Layout,
-    ) -> Vec<((&'s LayerKey, &'s LayerKey), f64)> {
+    /// Resolves &[`LayerKey`] references for [`LayerKeyIndex`] and filters bigrams that contain repeating
+    /// identical modifiers (sorted by the former).
+    pub fn get_filtered_layerkeys(
+        bigrams: BigramIndices,
+        layout: &Layout,
+    ) -> Vec<((&LayerKey, &LayerKey), f64)> {
         let mut layerkeys = Vec::with_capacity(bigrams.len());
 
-        layerkeys.extend(bigrams.iter().filter_map(|((idx1, idx2), w)| {
-            let k1 = layout.get_layerkey(idx1);
-
-            // If the same modifier appears consecutively, it is usually "hold" instead of repeatedly pressed
-            // --> remove
-            match k1.is_modifier.is_hold() && idx1 == idx2 {
-                false => Some((
-                    (
-                        k1,                        // LayerKey 1
-                        layout.get_layerkey(idx2), // LayerKey 2
-                    ),
-                    *w,
-                )),
-                true => None,
-            }
-        }));
+        layerkeys.extend(
+            sorted_by_indices(bigrams.into_vec(), layout)
+                .into_iter()
+                .filter_map(|((idx1, idx2), w)| {
+                    let k1 = layout.get_layerkey(&idx1);
+
+                    // If the same modifier appears consecutively, it is usually "hold" instead of repeatedly pressed
+                    // --> remove
+                    match k1.is_modifier.is_hold() && idx1 == idx2 {
+                        false => Some((
+                            (
+                                k1,                         // LayerKey 1
+                                layout.get_layerkey(&idx2), // LayerKey 2
+                            ),
+                            w,
+                        )),
+                        true => None,
+                    }
+                }),
+        );
 
         layerkeys
     }
 
+    /// Removes bigrams that contain repeating identical modifiers (analogous to [`Self::get_filtered_layerkeys`]).
+    pub fn filter_layerkey_indices(
+        bigrams: &mut AHashMap<(LayerKeyIndex, LayerKeyIndex), f64>,
+        layout: &Layout,
+    ) {
+        bigrams.retain(|(idx1, idx2), _| {
+            !(layout.get_layerkey(idx1).is_modifier.is_hold() && idx1 == idx2)
+        });
+    }
+
     /// Map all bigrams to base-layer bigrams, potentially generating multiple bigrams
     /// with modifiers for those with higer-layer keys.
     ///
     /// Each bigram of higher-layer symbols will transform into a series of bigrams with permutations of
     /// the involved base-keys and modifers. However, the base-key will always be after its modifier.
-    fn process_hold_modifiers(&self, bigrams: BigramIndicesVec, layout: &Layout) -> BigramIndices {
-        let mut bigram_w_map = AHashMap::with_capacity(bigrams.len() / 3);
+    fn process_hold_modifiers(
+        &self,
+        bigrams: BigramIndicesVec,
+        hold_modifiers: &HoldModifiers,
+    ) -> BigramIndices {
+        let mut bigram_w_map = OrderedNgrams::with_capacity(bigrams.len() / 3);
 
         bigrams.into_iter().for_each(|((k1, k2), w)| {
-            let (base1, mods1) = layout.resolve_modifiers(&k1);
-            let (base2, mods2) = layout.resolve_modifiers(&k2);
-
-            let (key1, mods1) = match mods1 {
-                LayerModifiers::Hold(mods) => (base1, mods),
-                _ => (k1, Vec::new()),
-            };
+            let (key1, mods1) = hold_modifiers.get(&k1);
+            let (key2, mods2) = hold_modifiers.get(&k2);
 
-            let (key2, mods2) = match mods2 {
-                LayerModifiers::Hold(mods) => (base2, mods),
-                _ => (k2, Vec::new()),
-            };
+            // most bigrams do not involve any modifiers
+            if mods1.is_empty() && mods2.is_empty() {
+                bigram_w_map.insert_or_add_weight((key1, key2), w);
+                return;
+            }
 
             bigram_w_map.insert_or_add_weight((key1, key2), w);
             // log::trace!("{:>3}{:<3} -> {:>3}{:<3}", layout.get_layerkey(&k1).symbol, layout.get_layerkey(&k2).symbol, layout.get_layerkey(&base1).symbol, layout.get_layerkey(&base2).symbol);
@@ -174,13 +202,13 @@ impl OnDemandBigramMapper {
             });
 
             // same key mods
-            TakeTwoLayerKey::new(key1, &mods1, w, self.split_modifiers.same_key_mod_factor)
+            TakeTwoLayerKey::new(key1, mods1, w, self.split_modifiers.same_key_mod_factor)
                 .for_each(|(e, w)| {
                     bigram_w_map.insert_or_add_weight(e, w);
                     // log::trace!("{:>3}{:<3} -> {:>3}{:<3}", layout.get_layerkey(&k1).symbol, layout.get_layerkey(&k2).symbol, layout.get_layerkey(&e.0).symbol, layout.get_layerkey(&e.1).symbol);
                 });
 
-            TakeTwoLayerKey::new(key2, &mods2, w, self.split_modifiers.same_key_mod_factor)
+            TakeTwoLayerKey::new(key2, mods2, w, self.split_modifiers.same_key_mod_factor)
                 .for_each(|(e, w)| {
                     bigram_w_map.insert_or_add_weight(e, w);
                     // log::trace!("{:>3}{:<3} -> {:>3}{:<3}", layout.get_layerkey(&k1).symbol, layout.get_layerkey(&k2).symbol, layout.get_layerkey(&e.0).symbol, layout.get_layerkey(&e.1).symbol);
@@ -224,4 +252,28 @@ impl OnDemandBigramMapper {
 
         processed_bigrams
     }
+
+    /// Map each bigram containing symbols typed as compose sequence (e.g. with a dead key) to the
+    /// bigrams of the resulting sequence of symbols.
+    fn process_compose_sequences(bigrams: BigramIndicesVec, layout: &Layout) -> BigramIndicesVec {
+        let mut idx_w_map = OrderedNgrams::with_capacity(bigrams.len());
+
+        bigrams.into_iter().for_each(|((k1, k2), w)| {
+            let mut keys = Vec::new();
+            for k in [k1, k2] {
+                let sequence = &layout.get_layerkey(&k).compose_sequence;
+                if sequence.is_empty() {
+                    keys.push(k);
+                } else {
+                    keys.extend(sequence);
+                }
+            }
+
+            keys.iter().zip(keys.iter().skip(1)).for_each(|(lk1, lk2)| {
+                idx_w_map.insert_or_add_weight((*lk1, *lk2), w);
+            });
+        });
+
+        idx_w_map.into_vec()
+    }
 }
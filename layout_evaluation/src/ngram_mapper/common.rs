@@ -1,5 +1,5 @@
 /// The `common` module provides utility functions for resolving modifiers in ngrams.
-use keyboard_layout::layout::LayerKeyIndex;
+use keyboard_layout::layout::{LayerKeyIndex, LayerModifiers, Layout};
 
 use ahash::AHashMap;
 use std::{cmp::Eq, hash::Hash, slice};
@@ -438,3 +438,179 @@ impl<Ngram: Eq + Hash> NgramMap<Ngram> for AHashMap<Ngram, f64> {
         *self.entry(k).or_insert(0.0) += w;
     }
 }
+
+/// Ngrams with accumulated weights in the order of their first insertion.
+///
+/// The mappers process the char-based ngrams sorted by their symbols, so that the weights of ngrams
+/// generated several times are summed up in an order that depends neither on the mapper nor on hashing.
+#[derive(Clone, Debug)]
+pub struct OrderedNgrams<Ngram: Eq + Hash> {
+    positions: AHashMap<Ngram, usize>,
+    grams: Vec<(Ngram, f64)>,
+}
+
+impl<Ngram: Copy + Eq + Hash> OrderedNgrams<Ngram> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            positions: AHashMap::with_capacity(capacity),
+            grams: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.grams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grams.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, (Ngram, f64)> {
+        self.grams.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<(Ngram, f64)> {
+        self.grams
+    }
+}
+
+impl<Ngram: Copy + Eq + Hash> NgramMap<Ngram> for OrderedNgrams<Ngram> {
+    #[inline(always)]
+    fn insert_or_add_weight(&mut self, k: Ngram, w: f64) {
+        match self.positions.get(&k) {
+            Some(i) => self.grams[*i].1 += w,
+            None => {
+                self.positions.insert(k, self.grams.len());
+                self.grams.push((k, w));
+            }
+        }
+    }
+}
+
+impl<Ngram: Copy + Eq + Hash> FromIterator<(Ngram, f64)> for OrderedNgrams<Ngram> {
+    fn from_iter<I: IntoIterator<Item = (Ngram, f64)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut ngrams = Self::with_capacity(iter.size_hint().0);
+        iter.for_each(|(k, w)| ngrams.insert_or_add_weight(k, w));
+
+        ngrams
+    }
+}
+
+impl<Ngram: Copy + Eq + Hash> IntoIterator for OrderedNgrams<Ngram> {
+    type Item = (Ngram, f64);
+    type IntoIter = std::vec::IntoIter<(Ngram, f64)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.grams.into_iter()
+    }
+}
+
+/// The base-layer key and hold modifiers of each of a layout's [`LayerKey`]s (keys of other layers
+/// resolve to themselves without modifiers). Resolving them once per layout avoids resolving (and
+/// allocating) them for each ngram when expanding higher-layer symbols.
+///
+/// [`LayerKey`]: keyboard_layout::layout::LayerKey
+#[derive(Clone, Debug)]
+pub struct HoldModifiers(Vec<(LayerKeyIndex, Vec<LayerKeyIndex>)>);
+
+impl HoldModifiers {
+    pub fn new(layout: &Layout) -> Self {
+        Self(
+            (0..layout.layerkeys.len() as LayerKeyIndex)
+                .map(|k| match layout.resolve_modifiers(&k) {
+                    (base, LayerModifiers::Hold(mods)) => (base, mods),
+                    _ => (k, Vec::new()),
+                })
+                .collect(),
+        )
+    }
+
+    /// The key to press and the modifiers to hold for generating the given [`LayerKeyIndex`].
+    #[inline(always)]
+    pub fn get(&self, k: &LayerKeyIndex) -> (LayerKeyIndex, &[LayerKeyIndex]) {
+        let (base, mods) = &self.0[*k as usize];
+        (*base, mods)
+    }
+}
+
+/// Ngrams of [`LayerKeyIndex`]s that can be sorted digit by digit (see [`sorted_by_indices`]).
+pub trait KeyIndices: Copy {
+    /// Number of [`LayerKeyIndex`]s in the ngram.
+    const LEN: usize;
+
+    /// The [`LayerKeyIndex`] at position `i` of the ngram.
+    fn index(&self, i: usize) -> LayerKeyIndex;
+}
+
+impl KeyIndices for LayerKeyIndex {
+    const LEN: usize = 1;
+
+    #[inline(always)]
+    fn index(&self, _i: usize) -> LayerKeyIndex {
+        *self
+    }
+}
+
+impl KeyIndices for (LayerKeyIndex, LayerKeyIndex) {
+    const LEN: usize = 2;
+
+    #[inline(always)]
+    fn index(&self, i: usize) -> LayerKeyIndex {
+        match i {
+            0 => self.0,
+            _ => self.1,
+        }
+    }
+}
+
+impl KeyIndices for (LayerKeyIndex, LayerKeyIndex, LayerKeyIndex) {
+    const LEN: usize = 3;
+
+    #[inline(always)]
+    fn index(&self, i: usize) -> LayerKeyIndex {
+        match i {
+            0 => self.0,
+            1 => self.1,
+            _ => self.2,
+        }
+    }
+}
+
+/// Sort ngrams by their [`LayerKeyIndex`]s. All mappers provide their ngrams in this order, so that
+/// summing up costs over them yields identical results, independent of hashing (and identical to the
+/// incrementally updated [`EvaluationState`]).
+///
+/// As the indices are bounded by the number of the layout's keys, a radix sort (one counting pass per
+/// index of the ngram) takes linear time instead of comparing ngrams for each layout.
+///
+/// [`EvaluationState`]: crate::evaluation::EvaluationState
+pub fn sorted_by_indices<Ngram: KeyIndices>(
+    ngrams: Vec<(Ngram, f64)>,
+    layout: &Layout,
+) -> Vec<(Ngram, f64)> {
+    let n_keys = layout.layerkeys.len();
+    let mut counts = vec![0usize; n_keys + 1];
+    let mut ngrams = ngrams;
+    let mut sorted = ngrams.clone();
+
+    // least significant index first, each pass keeps the order of the previous ones (stable)
+    for i in (0..Ngram::LEN).rev() {
+        counts.iter_mut().for_each(|c| *c = 0);
+        ngrams
+            .iter()
+            .for_each(|(ngram, _)| counts[ngram.index(i) as usize + 1] += 1);
+        for k in 1..counts.len() {
+            counts[k] += counts[k - 1];
+        }
+
+        ngrams.iter().for_each(|gram| {
+            let position = &mut counts[gram.0.index(i) as usize];
+            sorted[*position] = *gram;
+            *position += 1;
+        });
+        std::mem::swap(&mut ngrams, &mut sorted);
+    }
+
+    ngrams
+}
@@ -0,0 +1,708 @@
+//! This module provides an implementation of the [`NgramMapper`] trait that works on precomputed
+//! tables of interned symbols.
+//!
+//! All symbols of the char-based ngrams are interned into dense integer ids once and the ngrams are stored
+//! as flat arrays of these ids (sorted). For each layout, a vector mapping each symbol id to the
+//! [`LayerKeyIndex`] generating it is computed (only one hashmap lookup per symbol) and the ngrams are
+//! mapped through it. In contrast to the [`OnDemandNgramMapper`], this avoids hashing chars for every
+//! ngram and every evaluated layout.
+//!
+//! The resolution of modifiers (resolved once per layout for each key) is shared with the
+//! [`OnDemandNgramMapper`] and yields identical results in the same order. With split modifiers, most
+//! of the time is spent accumulating the expanded ngrams in this shared code, so the mapper is only
+//! slightly faster there (see `benches/evaluate.rs`).
+//!
+//! [`OnDemandNgramMapper`]: super::on_demand_ngram_mapper::OnDemandNgramMapper
+
+use super::bigram_mapper::OnDemandBigramMapper;
+use super::common::sorted_by_indices;
+use super::on_demand_ngram_mapper::NgramMapperConfig;
+use super::trigram_mapper::OnDemandTrigramMapper;
+use super::unigram_mapper::OnDemandUnigramMapper;
+use super::{
+    IndexedBigrams, IndexedTrigrams, IndexedUnigrams, MappedBigrams, MappedTrigrams,
+    MappedUnigrams, NgramMapper,
+};
+
+use crate::ngrams::{Bigrams, Trigrams, Unigrams};
+
+use keyboard_layout::layout::{LayerKeyIndex, Layout};
+
+use ahash::{AHashMap, AHashSet};
+
+/// Dense integer id of an interned symbol.
+type SymbolId = u32;
+
+/// Ngrams of length `N` in terms of [`SymbolId`]s, sorted by their ids.
+#[derive(Clone, Debug)]
+struct DenseNgrams<const N: usize> {
+    /// Ngrams in terms of [`SymbolId`]s
+    grams: Vec<([SymbolId; N], f64)>,
+    /// For each [`SymbolId`] the positions (in `grams`) of all ngrams containing the symbol
+    positions: Vec<Vec<u32>>,
+    /// Total weight of all ngrams (including those excluded from `grams`)
+    total_weight: f64,
+}
+
+impl<const N: usize> DenseNgrams<N> {
+    fn new<'a>(
+        grams: impl Iterator<Item = ([char; N], &'a f64)>,
+        symbol_ids: &AHashMap<char, SymbolId>,
+        exclude: impl Fn(&[char; N]) -> bool,
+    ) -> Self {
+        let mut total_weight = 0.0;
+        let mut dense_grams: Vec<([SymbolId; N], f64)> = grams
+            .filter_map(|(gram, w)| {
+                total_weight += *w;
+                if exclude(&gram) {
+                    return None;
+                }
+
+                Some((gram.map(|c| symbol_ids[&c]), *w))
+            })
+            .collect();
+        dense_grams.sort_unstable_by_key(|(gram, _)| *gram);
+
+        let mut positions = vec![Vec::new(); symbol_ids.len()];
+        dense_grams.iter().enumerate().for_each(|(i, (gram, _))| {
+            gram.iter().enumerate().for_each(|(j, id)| {
+                // do not list ngrams with repeating symbols multiple times
+                if !gram[..j].contains(id) {
+                    positions[*id as usize].push(i as u32);
+                }
+            });
+        });
+
+        Self {
+            grams: dense_grams,
+            positions,
+            total_weight,
+        }
+    }
+
+    /// Map the ngrams (or only those at the given positions) to [`LayerKeyIndex`]s using the given
+    /// symbol-to-key vector. Symbols following another one are typed with a context key if given in
+    /// `context_keys`. Returns the mapped ngrams as well as the total weight of (selected) ngrams
+    /// that could not be mapped and the total weight of all selected ngrams.
+    fn map<T>(
+        &self,
+        keys: &[Option<LayerKeyIndex>],
+        context_keys: &AHashMap<(SymbolId, SymbolId), LayerKeyIndex>,
+        selection: Option<&[u32]>,
+        to_gram: impl Fn([LayerKeyIndex; N]) -> T,
+    ) -> (Vec<(T, f64)>, f64, f64) {
+        let mut not_found_weight = 0.0;
+        let mut selected_weight = 0.0;
+        let mut mapped = Vec::with_capacity(selection.map_or(self.grams.len(), |s| s.len()));
+
+        let mut map_gram = |(gram, w): &([SymbolId; N], f64)| {
+            selected_weight += *w;
+            let mut key_indices = [0; N];
+            for (i, (idx, id)) in key_indices.iter_mut().zip(gram).enumerate() {
+                if i > 0 && !context_keys.is_empty() {
+                    if let Some(context_key) = context_keys.get(&(gram[i - 1], *id)) {
+                        *idx = *context_key;
+                        continue;
+                    }
+                }
+
+                match keys[*id as usize] {
+                    Some(key_idx) => *idx = key_idx,
+                    None => {
+                        not_found_weight += *w;
+                        return;
+                    }
+                }
+            }
+
+            mapped.push((to_gram(key_indices), *w));
+        };
+
+        match selection {
+            Some(selection) => selection
+                .iter()
+                .for_each(|i| map_gram(&self.grams[*i as usize])),
+            None => self.grams.iter().for_each(map_gram),
+        }
+
+        (mapped, not_found_weight, selected_weight)
+    }
+
+    /// Collect the positions of all ngrams containing at least one of the given symbols.
+    fn positions_containing(&self, symbols: &[SymbolId]) -> Vec<u32> {
+        let mut positions: Vec<u32> = symbols
+            .iter()
+            .flat_map(|id| self.positions[*id as usize].iter().cloned())
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        positions
+    }
+}
+
+/// Implements the [`NgramMapper`] trait for generating ngrams in terms of [`LayerKey`]s for a given [`Layout`]
+/// using precomputed tables of interned symbols.
+///
+/// [`LayerKey`]: keyboard_layout::layout::LayerKey
+#[derive(Clone, Debug)]
+pub struct DenseNgramMapper {
+    symbols: Vec<char>,
+    symbol_ids: AHashMap<char, SymbolId>,
+    unigrams: DenseNgrams<1>,
+    bigrams: DenseNgrams<2>,
+    trigrams: DenseNgrams<3>,
+    unigram_mapper: OnDemandUnigramMapper,
+    bigram_mapper: OnDemandBigramMapper,
+    trigram_mapper: OnDemandTrigramMapper,
+    config: NgramMapperConfig,
+}
+
+impl DenseNgramMapper {
+    /// Generate a [`DenseNgramMapper`] with given char-based ngrams.
+    pub fn with_ngrams(
+        unigrams: Unigrams,
+        bigrams: Bigrams,
+        trigrams: Trigrams,
+        config: NgramMapperConfig,
+    ) -> Self {
+        let mut symbols: Vec<char> = unigrams
+            .grams
+            .keys()
+            .cloned()
+            .chain(bigrams.grams.keys().flat_map(|(c1, c2)| [*c1, *c2]))
+            .chain(
+                trigrams
+                    .grams
+                    .keys()
+                    .flat_map(|(c1, c2, c3)| [*c1, *c2, *c3]),
+            )
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let symbol_ids: AHashMap<char, SymbolId> = symbols
+            .iter()
+            .enumerate()
+            .map(|(id, c)| (*c, id as SymbolId))
+            .collect();
+
+        let exclude_line_breaks = config.exclude_line_breaks;
+        let unigrams = DenseNgrams::new(
+            unigrams.grams.iter().map(|(c, w)| ([*c], w)),
+            &symbol_ids,
+            |_| false,
+        );
+        // Exclude ngrams that contain a line break, followed by a non-line-break character
+        let bigrams = DenseNgrams::new(
+            bigrams.grams.iter().map(|((c1, c2), w)| ([*c1, *c2], w)),
+            &symbol_ids,
+            |[c1, c2]| exclude_line_breaks && *c1 == '\n' && *c2 != '\n',
+        );
+        let trigrams = DenseNgrams::new(
+            trigrams
+                .grams
+                .iter()
+                .map(|((c1, c2, c3), w)| ([*c1, *c2, *c3], w)),
+            &symbol_ids,
+            |[c1, c2, c3]| {
+                exclude_line_breaks
+                    && ((*c1 == '\n' && *c2 != '\n') || (*c2 == '\n' && *c3 != '\n'))
+            },
+        );
+
+        Self {
+            symbols,
+            symbol_ids,
+            unigrams,
+            bigrams,
+            trigrams,
+            unigram_mapper: OnDemandUnigramMapper::new(config.split_modifiers.clone()),
+            bigram_mapper: OnDemandBigramMapper::new(config.split_modifiers.clone()),
+            trigram_mapper: OnDemandTrigramMapper::new(config.split_modifiers.clone()),
+            config,
+        }
+    }
+
+    /// For each interned symbol, the [`LayerKeyIndex`] generating it in the given layout (if any).
+    fn symbol_keys(&self, layout: &Layout) -> Vec<Option<LayerKeyIndex>> {
+        self.symbols
+            .iter()
+            .map(|c| layout.get_layerkey_index_for_symbol(c))
+            .collect()
+    }
+
+    /// For each pair of interned symbols typed with a context key in the given layout, the
+    /// [`LayerKeyIndex`] of that key.
+    fn context_keys(&self, layout: &Layout) -> AHashMap<(SymbolId, SymbolId), LayerKeyIndex> {
+        if !layout.has_context_keys() {
+            return AHashMap::default();
+        }
+
+        layout
+            .context_key_pairs(&self.symbols)
+            .filter_map(|((prev, c), idx)| {
+                Some(((*self.symbol_ids.get(&prev)?, self.symbol_ids[&c]), idx))
+            })
+            .collect()
+    }
+
+    /// The weight of a bigram relative to the unigrams' weight (zero for excluded bigrams)
+    fn relative_bigram_weight(&self, c1: char, c2: char) -> f64 {
+        let (id1, id2) = match (self.symbol_ids.get(&c1), self.symbol_ids.get(&c2)) {
+            (Some(id1), Some(id2)) => (*id1, *id2),
+            _ => return 0.0,
+        };
+
+        match self
+            .bigrams
+            .grams
+            .binary_search_by_key(&[id1, id2], |(gram, _)| *gram)
+        {
+            Ok(i) => {
+                self.bigrams.grams[i].1 * (self.unigrams.total_weight / self.bigrams.total_weight)
+            }
+            Err(_) => 0.0,
+        }
+    }
+
+    /// Whether the layout requires resolving modifiers (or compose sequences). Otherwise, each mapped
+    /// ngram is unique and the mapped ngrams can be used directly (without accumulating them in a hashmap).
+    fn requires_modifier_processing(&self, layout: &Layout) -> bool {
+        layout.has_compose_sequences()
+            || layout.has_one_shot_layers()
+            || (self.config.split_modifiers.enabled && layout.has_hold_layers())
+    }
+
+    /// Interned ids of the given symbols (symbols not contained in any ngram are skipped).
+    fn symbol_ids(&self, symbols: &AHashSet<char>) -> Vec<SymbolId> {
+        symbols
+            .iter()
+            .filter_map(|c| self.symbol_ids.get(c).cloned())
+            .collect()
+    }
+}
+
+impl NgramMapper for DenseNgramMapper {
+    fn map_unigrams<'s>(&self, layout: &'s Layout) -> MappedUnigrams<'s> {
+        let keys = self.symbol_keys(layout);
+        let (mut key_indices, weight_not_found, _) =
+            self.unigrams
+                .map(&keys, &AHashMap::default(), None, |[k1]| k1);
+        let weight_found = self.unigrams.total_weight - weight_not_found;
+
+        if layout.has_context_keys() {
+            key_indices =
+                OnDemandUnigramMapper::process_context_keys(key_indices, layout, |c1, c2| {
+                    self.relative_bigram_weight(c1, c2)
+                });
+        }
+
+        // the keys of combos are resolved in unigrams
+        let grams = if self.requires_modifier_processing(layout)
+            || layout.has_combos()
+            || layout.has_context_keys()
+        {
+            let key_indices = self.unigram_mapper.process_modifiers(key_indices, layout);
+            OnDemandUnigramMapper::get_layerkeys(key_indices, layout)
+        } else {
+            sorted_by_indices(key_indices, layout)
+                .into_iter()
+                .map(|(k1, w)| (layout.get_layerkey(&k1), w))
+                .collect()
+        };
+
+        MappedUnigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        }
+    }
+
+    fn map_bigrams<'s>(&self, layout: &'s Layout) -> MappedBigrams<'s> {
+        let keys = self.symbol_keys(layout);
+        let context_keys = self.context_keys(layout);
+        let (key_indices, weight_not_found, _) =
+            self.bigrams
+                .map(&keys, &context_keys, None, |[k1, k2]| (k1, k2));
+        let weight_found = self.bigrams.total_weight - weight_not_found;
+
+        let grams = if self.requires_modifier_processing(layout) {
+            let key_indices = self.bigram_mapper.process_modifiers(key_indices, layout);
+            OnDemandBigramMapper::get_filtered_layerkeys(key_indices, layout)
+        } else {
+            sorted_by_indices(key_indices, layout)
+                .into_iter()
+                .filter_map(|((idx1, idx2), w)| {
+                    let k1 = layout.get_layerkey(&idx1);
+
+                    // If the same modifier appears consecutively, it is usually "hold" instead of repeatedly pressed
+                    // --> remove
+                    match k1.is_modifier.is_hold() && idx1 == idx2 {
+                        false => Some(((k1, layout.get_layerkey(&idx2)), w)),
+                        true => None,
+                    }
+                })
+                .collect()
+        };
+
+        MappedBigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        }
+    }
+
+    fn map_trigrams<'s>(&self, layout: &'s Layout) -> MappedTrigrams<'s> {
+        let keys = self.symbol_keys(layout);
+        let context_keys = self.context_keys(layout);
+        let (key_indices, weight_not_found, _) =
+            self.trigrams
+                .map(&keys, &context_keys, None, |[k1, k2, k3]| (k1, k2, k3));
+        let weight_found = self.trigrams.total_weight - weight_not_found;
+
+        let grams = if self.requires_modifier_processing(layout) {
+            let key_indices = self.trigram_mapper.process_modifiers(key_indices, layout);
+            OnDemandTrigramMapper::get_filtered_layerkeys(key_indices, layout)
+        } else {
+            sorted_by_indices(key_indices, layout)
+                .into_iter()
+                .filter_map(|((idx1, idx2, idx3), w)| {
+                    let k2 = layout.get_layerkey(&idx2);
+
+                    // If the same modifier appears consecutively, it is usually "hold" instead of repeatedly pressed
+                    // --> remove
+                    match k2.is_modifier.is_hold() && (idx1 == idx2 || idx2 == idx3) {
+                        false => Some((
+                            (layout.get_layerkey(&idx1), k2, layout.get_layerkey(&idx3)),
+                            w,
+                        )),
+                        true => None,
+                    }
+                })
+                .collect()
+        };
+
+        MappedTrigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        }
+    }
+
+    fn map_unigram_indices(
+        &self,
+        layout: &Layout,
+        symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedUnigrams> {
+        let keys = self.symbol_keys(layout);
+        let positions = symbols.map(|s| self.unigrams.positions_containing(&self.symbol_ids(s)));
+        let (mut key_indices, weight_not_found, selected_weight) =
+            self.unigrams
+                .map(&keys, &AHashMap::default(), positions.as_deref(), |[k1]| k1);
+        let weight_found = match symbols {
+            Some(_) => selected_weight - weight_not_found,
+            None => self.unigrams.total_weight - weight_not_found,
+        };
+
+        if layout.has_context_keys() {
+            key_indices =
+                OnDemandUnigramMapper::process_context_keys(key_indices, layout, |c1, c2| {
+                    self.relative_bigram_weight(c1, c2)
+                });
+        }
+
+        Some(IndexedUnigrams {
+            grams: self
+                .unigram_mapper
+                .process_modifiers(key_indices, layout)
+                .into_iter()
+                .collect(),
+            weight_not_found,
+            weight_found,
+        })
+    }
+
+    fn map_bigram_indices(
+        &self,
+        layout: &Layout,
+        symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedBigrams> {
+        let keys = self.symbol_keys(layout);
+        let positions = symbols.map(|s| self.bigrams.positions_containing(&self.symbol_ids(s)));
+        let context_keys = self.context_keys(layout);
+        let (key_indices, weight_not_found, selected_weight) =
+            self.bigrams
+                .map(&keys, &context_keys, positions.as_deref(), |[k1, k2]| {
+                    (k1, k2)
+                });
+        let weight_found = match symbols {
+            Some(_) => selected_weight - weight_not_found,
+            None => self.bigrams.total_weight - weight_not_found,
+        };
+
+        let mut grams = self
+            .bigram_mapper
+            .process_modifiers(key_indices, layout)
+            .into_iter()
+            .collect();
+        OnDemandBigramMapper::filter_layerkey_indices(&mut grams, layout);
+
+        Some(IndexedBigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        })
+    }
+
+    fn map_trigram_indices(
+        &self,
+        layout: &Layout,
+        symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedTrigrams> {
+        let keys = self.symbol_keys(layout);
+        let positions = symbols.map(|s| self.trigrams.positions_containing(&self.symbol_ids(s)));
+        let context_keys = self.context_keys(layout);
+        let (key_indices, weight_not_found, selected_weight) =
+            self.trigrams
+                .map(&keys, &context_keys, positions.as_deref(), |[k1, k2, k3]| {
+                    (k1, k2, k3)
+                });
+        let weight_found = match symbols {
+            Some(_) => selected_weight - weight_not_found,
+            None => self.trigrams.total_weight - weight_not_found,
+        };
+
+        let mut grams = self
+            .trigram_mapper
+            .process_modifiers(key_indices, layout)
+            .into_iter()
+            .collect();
+        OnDemandTrigramMapper::filter_layerkey_indices(&mut grams, layout);
+
+        Some(IndexedTrigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::EvaluationParameters,
+        evaluation::Evaluator,
+        ngram_mapper::on_demand_ngram_mapper::{OnDemandNgramMapper, SplitModifiersConfig},
+        results::EvaluationResult,
+    };
+
+    use keyboard_layout::{
+        config::LayoutConfig, keyboard::Keyboard, layout::LayerKey,
+        layout_generator::LayoutGenerator, neo_layout_generator::NeoLayoutGenerator,
+        slot_layout_generator::SlotLayoutGenerator,
+    };
+    use std::{path::Path, sync::Arc};
+
+    const NGRAMS: &str = "../ngrams/eng/eng_wiki_1m";
+    const LAYOUT_CONFIG: &str = "../config/keyboard/standard.yml";
+    const SVALBOARD_LAYOUT_CONFIG: &str = "../config/keyboard/svalboard.yml";
+    const EVAL_PARAMS: &str = "../config/evaluation/standard.yml";
+    const LAYOUTS: [&str; 2] = [
+        "jduaxphlmwqßctieobnrsgfvüäöyz,.k",
+        "xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j",
+    ];
+
+    fn layouts() -> Vec<Layout> {
+        let layout_config = LayoutConfig::from_yaml(LAYOUT_CONFIG).unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator = NeoLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+
+        let mut layouts: Vec<Layout> = LAYOUTS
+            .iter()
+            .map(|layout_str| layout_generator.generate(layout_str).unwrap())
+            .collect();
+
+        // hold layers of several modifiers
+        let layout_config = LayoutConfig::from_yaml(SVALBOARD_LAYOUT_CONFIG).unwrap();
+        let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+        let layout_generator =
+            SlotLayoutGenerator::from_object(layout_config.base_layout, keyboard);
+        layouts.push(
+            layout_generator
+                .generate(&layout_generator.base_layout_string())
+                .unwrap(),
+        );
+
+        layouts
+    }
+
+    /// Both mappers reading the same ngrams. The ngram files contain absolute counts, so that
+    /// all accumulated weights are exact (independent of the order of summation).
+    fn mappers(split_modifiers: bool) -> (OnDemandNgramMapper, DenseNgramMapper) {
+        let path = Path::new(NGRAMS);
+        let unigrams = Unigrams::from_file(path.join("1-grams.txt").to_str().unwrap()).unwrap();
+        let bigrams = Bigrams::from_file(path.join("2-grams.txt").to_str().unwrap()).unwrap();
+        let trigrams = Trigrams::from_file(path.join("3-grams.txt").to_str().unwrap()).unwrap();
+
+        let config = NgramMapperConfig {
+            split_modifiers: SplitModifiersConfig {
+                enabled: split_modifiers,
+                same_key_mod_factor: 0.03125,
+            },
+            exclude_line_breaks: true,
+        };
+
+        (
+            OnDemandNgramMapper::with_ngrams(
+                unigrams.clone(),
+                bigrams.clone(),
+                trigrams.clone(),
+                config.clone(),
+            ),
+            DenseNgramMapper::with_ngrams(unigrams, bigrams, trigrams, config),
+        )
+    }
+
+    /// Identify mapped ngrams by the addresses of their [`LayerKey`]s and their weights by their bits.
+    fn canonical<T: Ord>(
+        grams: impl Iterator<Item = (T, f64)>,
+        weight_not_found: f64,
+        weight_found: f64,
+    ) -> (Vec<(T, u64)>, u64, u64) {
+        let mut grams: Vec<(T, u64)> = grams.map(|(gram, w)| (gram, w.to_bits())).collect();
+        grams.sort_unstable();
+
+        (grams, weight_not_found.to_bits(), weight_found.to_bits())
+    }
+
+    fn address(k: &LayerKey) -> usize {
+        k as *const LayerKey as usize
+    }
+
+    fn assert_identical_mapping(split_modifiers: bool) {
+        let (on_demand, dense) = mappers(split_modifiers);
+
+        for layout in layouts().iter() {
+            let (expected, actual) = (on_demand.map_unigrams(layout), dense.map_unigrams(layout));
+            assert_eq!(
+                canonical(
+                    expected.grams.into_iter().map(|(k, w)| (address(k), w)),
+                    expected.weight_not_found,
+                    expected.weight_found
+                ),
+                canonical(
+                    actual.grams.into_iter().map(|(k, w)| (address(k), w)),
+                    actual.weight_not_found,
+                    actual.weight_found
+                ),
+            );
+
+            let (expected, actual) = (on_demand.map_bigrams(layout), dense.map_bigrams(layout));
+            assert_eq!(
+                canonical(
+                    expected
+                        .grams
+                        .into_iter()
+                        .map(|((k1, k2), w)| ((address(k1), address(k2)), w)),
+                    expected.weight_not_found,
+                    expected.weight_found
+                ),
+                canonical(
+                    actual
+                        .grams
+                        .into_iter()
+                        .map(|((k1, k2), w)| ((address(k1), address(k2)), w)),
+                    actual.weight_not_found,
+                    actual.weight_found
+                ),
+            );
+
+            let (expected, actual) = (on_demand.map_trigrams(layout), dense.map_trigrams(layout));
+            assert_eq!(
+                canonical(
+                    expected
+                        .grams
+                        .into_iter()
+                        .map(|((k1, k2, k3), w)| ((address(k1), address(k2), address(k3)), w)),
+                    expected.weight_not_found,
+                    expected.weight_found
+                ),
+                canonical(
+                    actual
+                        .grams
+                        .into_iter()
+                        .map(|((k1, k2, k3), w)| ((address(k1), address(k2), address(k3)), w)),
+                    actual.weight_not_found,
+                    actual.weight_found
+                ),
+            );
+
+            let (expected, actual) = (
+                on_demand.map_trigram_indices(layout, None).unwrap(),
+                dense.map_trigram_indices(layout, None).unwrap(),
+            );
+            assert_eq!(
+                canonical(
+                    expected.grams.into_iter(),
+                    expected.weight_not_found,
+                    expected.weight_found
+                ),
+                canonical(
+                    actual.grams.into_iter(),
+                    actual.weight_not_found,
+                    actual.weight_found
+                ),
+            );
+        }
+    }
+
+    /// The costs of all metrics, identified by their bits.
+    fn metric_cost_bits(result: &EvaluationResult) -> Vec<(String, u64)> {
+        result
+            .iter()
+            .flat_map(|mc| mc.metric_costs.iter())
+            .map(|c| (c.core.name.clone(), c.core.cost.to_bits()))
+            .collect()
+    }
+
+    fn assert_identical_evaluation(split_modifiers: bool) {
+        let params = EvaluationParameters::from_yaml(EVAL_PARAMS).unwrap();
+        let (on_demand, dense) = mappers(split_modifiers);
+        let on_demand = Evaluator::default(Box::new(on_demand)).default_metrics(&params.metrics);
+        let dense = Evaluator::default(Box::new(dense)).default_metrics(&params.metrics);
+
+        for layout in layouts().iter() {
+            let (expected, actual) = (
+                on_demand.evaluate_layout(layout),
+                dense.evaluate_layout(layout),
+            );
+            assert_eq!(
+                expected.total_cost().to_bits(),
+                actual.total_cost().to_bits()
+            );
+            assert_eq!(metric_cost_bits(&expected), metric_cost_bits(&actual));
+        }
+    }
+
+    #[test]
+    fn identical_to_on_demand_mapper() {
+        assert_identical_mapping(false);
+    }
+
+    #[test]
+    fn identical_to_on_demand_mapper_with_split_modifiers() {
+        assert_identical_mapping(true);
+    }
+
+    #[test]
+    fn identical_evaluation_to_on_demand_mapper() {
+        assert_identical_evaluation(false);
+    }
+
+    #[test]
+    fn identical_evaluation_to_on_demand_mapper_with_split_modifiers() {
+        assert_identical_evaluation(true);
+    }
+}
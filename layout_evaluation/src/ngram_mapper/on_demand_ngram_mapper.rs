@@ -3,12 +3,16 @@
 use super::bigram_mapper::OnDemandBigramMapper;
 use super::trigram_mapper::OnDemandTrigramMapper;
 use super::unigram_mapper::OnDemandUnigramMapper;
-use super::{MappedBigrams, MappedTrigrams, MappedUnigrams, NgramMapper};
+use super::{
+    IndexedBigrams, IndexedTrigrams, IndexedUnigrams, MappedBigrams, MappedTrigrams,
+    MappedUnigrams, NgramMapper,
+};
 
 use crate::ngrams::{Bigrams, Trigrams, Unigrams};
 
 use keyboard_layout::layout::Layout;
 
+use ahash::{AHashMap, AHashSet};
 use serde::Deserialize;
 
 /// Configuration parameters for the modifier splitting process.
@@ -29,6 +33,50 @@ pub struct NgramMapperConfig {
     pub exclude_line_breaks: bool,
 }
 
+/// The ngrams sorted by their symbols (the order in which they are mapped) and for each symbol the
+/// ngrams containing it. This allows for quickly selecting those ngrams that are affected by small
+/// changes of a layout.
+#[derive(Clone, Debug)]
+struct SymbolIndex<T> {
+    grams: Vec<(T, f64)>,
+    positions: AHashMap<char, Vec<u32>>,
+}
+
+impl<T: Copy + Ord> SymbolIndex<T> {
+    fn new(grams: &AHashMap<T, f64>, symbols: impl Fn(&T) -> Vec<char>) -> Self {
+        let mut grams: Vec<(T, f64)> = grams.iter().map(|(gram, w)| (*gram, *w)).collect();
+        grams.sort_unstable_by_key(|(gram, _)| *gram);
+
+        let mut positions: AHashMap<char, Vec<u32>> = AHashMap::default();
+        grams.iter().enumerate().for_each(|(i, (gram, _))| {
+            let mut gram_symbols = symbols(gram);
+            gram_symbols.dedup();
+            gram_symbols
+                .into_iter()
+                .for_each(|c| positions.entry(c).or_default().push(i as u32));
+        });
+
+        Self { grams, positions }
+    }
+
+    /// Collect all ngrams containing at least one of the given symbols (sorted by their symbols).
+    fn containing(&self, symbols: &AHashSet<char>) -> Vec<(T, f64)> {
+        let mut positions: Vec<u32> = symbols
+            .iter()
+            .filter_map(|c| self.positions.get(c))
+            .flatten()
+            .cloned()
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        positions
+            .into_iter()
+            .map(|i| self.grams[i as usize])
+            .collect()
+    }
+}
+
 /// Implements the [`NgramMapper`] trait for generating ngrams in terms of [`LayerKey`]s for a given [`Layout`].
 #[derive(Clone, Debug)]
 pub struct OnDemandNgramMapper {
@@ -38,6 +86,11 @@ pub struct OnDemandNgramMapper {
     unigram_mapper: OnDemandUnigramMapper,
     bigram_mapper: OnDemandBigramMapper,
     trigram_mapper: OnDemandTrigramMapper,
+    unigram_index: SymbolIndex<char>,
+    bigram_index: SymbolIndex<(char, char)>,
+    trigram_index: SymbolIndex<(char, char, char)>,
+    /// Ratio of the total weights of unigrams and bigrams
+    bigram_scale: f64,
     config: NgramMapperConfig,
 }
 
@@ -49,26 +102,53 @@ impl OnDemandNgramMapper {
         trigrams: Trigrams,
         config: NgramMapperConfig,
     ) -> Self {
+        let unigram_index = SymbolIndex::new(&unigrams.grams, |c| vec![*c]);
+        let bigram_index = SymbolIndex::new(&bigrams.grams, |(c1, c2)| vec![*c1, *c2]);
+        let trigram_index = SymbolIndex::new(&trigrams.grams, |(c1, c2, c3)| vec![*c1, *c2, *c3]);
+        let bigram_scale = match bigrams.total_weight() {
+            total if total > 0.0 => unigrams.total_weight() / total,
+            _ => 0.0,
+        };
+
         Self {
             unigrams,
             bigrams,
             trigrams,
+            unigram_index,
+            bigram_index,
+            trigram_index,
+            bigram_scale,
             unigram_mapper: OnDemandUnigramMapper::new(config.split_modifiers.clone()),
             bigram_mapper: OnDemandBigramMapper::new(config.split_modifiers.clone()),
             trigram_mapper: OnDemandTrigramMapper::new(config.split_modifiers.clone()),
             config,
         }
     }
+
+    /// The weight of a bigram relative to the unigrams' weight (zero for excluded bigrams)
+    fn relative_bigram_weight(&self, c1: char, c2: char) -> f64 {
+        if self.config.exclude_line_breaks && c1 == '\n' && c2 != '\n' {
+            return 0.0;
+        }
+
+        self.bigrams
+            .grams
+            .get(&(c1, c2))
+            .map_or(0.0, |w| w * self.bigram_scale)
+    }
 }
 
 impl NgramMapper for OnDemandNgramMapper {
     fn map_unigrams<'s>(&self, layout: &'s Layout) -> MappedUnigrams<'s> {
         // map char-based unigrams to LayerKeyIndex
         let (key_indices, weight_not_found) =
-            self.unigram_mapper.layerkey_indices(&self.unigrams, layout);
+            self.unigram_mapper
+                .layerkey_indices(&self.unigram_index.grams, layout, |c1, c2| {
+                    self.relative_bigram_weight(c1, c2)
+                });
         let weight_found = self.unigrams.total_weight() - weight_not_found;
         // map LayerKeyIndex to &LayerKey
-        let grams = OnDemandUnigramMapper::get_layerkeys(&key_indices, layout);
+        let grams = OnDemandUnigramMapper::get_layerkeys(key_indices, layout);
 
         MappedUnigrams {
             grams,
@@ -80,13 +160,13 @@ impl NgramMapper for OnDemandNgramMapper {
     fn map_bigrams<'s>(&self, layout: &'s Layout) -> MappedBigrams<'s> {
         // map char-based bigrams to LayerKeyIndex
         let (key_indices, weight_not_found) = self.bigram_mapper.layerkey_indices(
-            &self.bigrams,
+            &self.bigram_index.grams,
             layout,
             self.config.exclude_line_breaks,
         );
         let weight_found = self.bigrams.total_weight() - weight_not_found;
         // map LayerKeyIndex to &LayerKey
-        let grams = OnDemandBigramMapper::get_filtered_layerkeys(&key_indices, layout);
+        let grams = OnDemandBigramMapper::get_filtered_layerkeys(key_indices, layout);
 
         MappedBigrams {
             grams,
@@ -98,13 +178,13 @@ impl NgramMapper for OnDemandNgramMapper {
     fn map_trigrams<'s>(&self, layout: &'s Layout) -> MappedTrigrams<'s> {
         // map char-based trigrams to LayerKeyIndex
         let (key_indices, weight_not_found) = self.trigram_mapper.layerkey_indices(
-            &self.trigrams,
+            &self.trigram_index.grams,
             layout,
             self.config.exclude_line_breaks,
         );
         let weight_found = self.trigrams.total_weight() - weight_not_found;
         // map LayerKeyIndex to &LayerKey
-        let grams = OnDemandTrigramMapper::get_filtered_layerkeys(&key_indices, layout);
+        let grams = OnDemandTrigramMapper::get_filtered_layerkeys(key_indices, layout);
 
         MappedTrigrams {
             grams,
@@ -112,4 +192,88 @@ impl NgramMapper for OnDemandNgramMapper {
             weight_found,
         }
     }
+
+    fn map_unigram_indices(
+        &self,
+        layout: &Layout,
+        symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedUnigrams> {
+        let subset;
+        let (unigrams, total_weight) = match symbols {
+            Some(symbols) => {
+                subset = self.unigram_index.containing(symbols);
+                (&subset, subset.iter().map(|(_, w)| w).sum())
+            }
+            None => (&self.unigram_index.grams, self.unigrams.total_weight()),
+        };
+
+        let (grams, weight_not_found) =
+            self.unigram_mapper
+                .layerkey_indices(unigrams, layout, |c1, c2| {
+                    self.relative_bigram_weight(c1, c2)
+                });
+        let weight_found = total_weight - weight_not_found;
+
+        Some(IndexedUnigrams {
+            grams: grams.into_iter().collect(),
+            weight_not_found,
+            weight_found,
+        })
+    }
+
+    fn map_bigram_indices(
+        &self,
+        layout: &Layout,
+        symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedBigrams> {
+        let subset;
+        let (bigrams, total_weight) = match symbols {
+            Some(symbols) => {
+                subset = self.bigram_index.containing(symbols);
+                (&subset, subset.iter().map(|(_, w)| w).sum())
+            }
+            None => (&self.bigram_index.grams, self.bigrams.total_weight()),
+        };
+
+        let (grams, weight_not_found) =
+            self.bigram_mapper
+                .layerkey_indices(bigrams, layout, self.config.exclude_line_breaks);
+        let mut grams = grams.into_iter().collect();
+        OnDemandBigramMapper::filter_layerkey_indices(&mut grams, layout);
+        let weight_found = total_weight - weight_not_found;
+
+        Some(IndexedBigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        })
+    }
+
+    fn map_trigram_indices(
+        &self,
+        layout: &Layout,
+        symbols: Option<&AHashSet<char>>,
+    ) -> Option<IndexedTrigrams> {
+        let subset;
+        let (trigrams, total_weight) = match symbols {
+            Some(symbols) => {
+                subset = self.trigram_index.containing(symbols);
+                (&subset, subset.iter().map(|(_, w)| w).sum())
+            }
+            None => (&self.trigram_index.grams, self.trigrams.total_weight()),
+        };
+
+        let (grams, weight_not_found) =
+            self.trigram_mapper
+                .layerkey_indices(trigrams, layout, self.config.exclude_line_breaks);
+        let mut grams = grams.into_iter().collect();
+        OnDemandTrigramMapper::filter_layerkey_indices(&mut grams, layout);
+        let weight_found = total_weight - weight_not_found;
+
+        Some(IndexedTrigrams {
+            grams,
+            weight_not_found,
+            weight_found,
+        })
+    }
 }
@@ -3,29 +3,27 @@
 
 use super::{common::*, on_demand_ngram_mapper::SplitModifiersConfig};
 
-use crate::ngrams::Trigrams;
-
 use ahash::AHashMap;
 use keyboard_layout::layout::{LayerKey, LayerKeyIndex, LayerModifiers, Layout};
 
 // Before passing the resulting LayerKey-based ngrams as a result, smaller LayerKeyIndex-based
 // ones are used because they are smaller than a reference (u16 vs usize) and yield better
 // hashing performance.
-pub type TrigramIndices = AHashMap<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex), f64>;
-type TrigramIndicesVec = Vec<((LayerKeyIndex, LayerKeyIndex, LayerKeyIndex), f64)>;
+pub type TrigramIndices = OrderedNgrams<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex)>;
+pub type TrigramIndicesVec = Vec<((LayerKeyIndex, LayerKeyIndex, LayerKeyIndex), f64)>;
 
-/// Turns the [`Trigrams`]'s characters into their indices, returning a [`TrigramIndicesVec`].
+/// Turns the trigrams' characters (sorted by them) into their indices, returning a [`TrigramIndicesVec`].
+/// The second and third symbols are typed with a context key (e.g. a repeat key) if possible.
 fn map_trigrams(
-    trigrams: &Trigrams,
+    trigrams: &[((char, char, char), f64)],
     layout: &Layout,
     exclude_line_breaks: bool,
 ) -> (TrigramIndicesVec, f64) {
     let mut not_found_weight = 0.0;
-    let mut trigrams_vec = Vec::with_capacity(trigrams.grams.len());
+    let mut trigrams_vec = Vec::with_capacity(trigrams.len());
 
     trigrams_vec.extend(
         trigrams
-            .grams
             .iter()
             //.filter(|((c1, c2, c3), _weight)| {
             //    !c1.is_whitespace() && !c2.is_whitespace() && !c3.is_whitespace()
@@ -45,14 +43,14 @@ fn map_trigrams(
                         return None;
                     }
                 };
-                let idx2 = match layout.get_layerkey_index_for_symbol(c2) {
+                let idx2 = match layout.get_layerkey_index_for_symbol_after(c1, c2) {
                     Some(idx) => idx,
                     None => {
                         not_found_weight += *weight;
                         return None;
                     }
                 };
-                let idx3 = match layout.get_layerkey_index_for_symbol(c3) {
+                let idx3 = match layout.get_layerkey_index_for_symbol_after(c2, c3) {
                     Some(idx) => idx,
                     None => {
                         not_found_weight += *weight;
@@ -79,58 +77,87 @@ impl OnDemandTrigramMapper {
         Self { split_modifiers }
     }
 
-    /// For a given [`Layout`] generate [`LayerKeyIndex`]-based unigrams, optionally resolving modifiers for higer-layer symbols.
+    /// For a given [`Layout`] generate [`LayerKeyIndex`]-based trigrams, optionally resolving modifiers for higer-layer symbols.
+    /// The trigrams need to be sorted by their symbols.
     pub fn layerkey_indices(
         &self,
-        trigrams: &Trigrams,
+        trigrams: &[((char, char, char), f64)],
         layout: &Layout,
         exclude_line_breaks: bool,
     ) -> (TrigramIndices, f64) {
-        let (mut trigram_keys_vec, not_found_weight) =
+        let (trigram_keys_vec, not_found_weight) =
             map_trigrams(trigrams, layout, exclude_line_breaks);
 
+        (
+            self.process_modifiers(trigram_keys_vec, layout),
+            not_found_weight,
+        )
+    }
+
+    /// Resolve compose sequences and modifiers of higher-layer symbols in [`LayerKeyIndex`]-based trigrams (if configured).
+    pub fn process_modifiers(
+        &self,
+        mut trigram_keys_vec: TrigramIndicesVec,
+        layout: &Layout,
+    ) -> TrigramIndices {
+        if layout.has_compose_sequences() {
+            trigram_keys_vec = Self::process_compose_sequences(trigram_keys_vec, layout);
+        }
+
         if layout.has_one_shot_layers() {
             trigram_keys_vec = self.process_one_shot_modifiers(trigram_keys_vec, layout);
         }
 
-        let trigram_keys = if self.split_modifiers.enabled && layout.has_hold_layers() {
-            self.process_hold_modifiers(trigram_keys_vec, layout)
+        if self.split_modifiers.enabled && layout.has_hold_layers() {
+            self.process_hold_modifiers(trigram_keys_vec, &HoldModifiers::new(layout))
         } else {
             trigram_keys_vec.into_iter().collect()
-        };
-
-        (trigram_keys, not_found_weight)
+        }
     }
 
-    /// Resolve &[`LayerKey`] references for [`LayerKeyIndex`] and filters trigrams that contain
-    /// repeating identical modifiers.
-    pub fn get_filtered_layerkeys<'s>(
-        trigrams: &TrigramIndices,
-        layout: &'s Layout,
-    ) -> Vec<((&'s LayerKey, &'s LayerKey, &'s LayerKey), f64)> {
+    /// Resolve &[`LayerKey`] references for [`LayerKeyIndex`] and filters trigrams that contain repeating
+    /// identical modifiers (sorted by the former).
+    pub fn get_filtered_layerkeys(
+        trigrams: TrigramIndices,
+        layout: &Layout,
+    ) -> Vec<((&LayerKey, &LayerKey, &LayerKey), f64)> {
         let mut layerkeys = Vec::with_capacity(trigrams.len());
 
-        layerkeys.extend(trigrams.iter().filter_map(|((idx1, idx2, idx3), w)| {
-            let k2 = layout.get_layerkey(idx2);
-
-            // If the same modifier appears consecutively, it is usually "hold" instead of repeatedly pressed
-            // --> remove
-            match k2.is_modifier.is_hold() && (idx1 == idx2 || idx2 == idx3) {
-                false => Some((
-                    (
-                        layout.get_layerkey(idx1), // LayerKey 1
-                        k2,                        // LayerKey 2
-                        layout.get_layerkey(idx3), // LayerKey 3
-                    ),
-                    *w,
-                )),
-                true => None,
-            }
-        }));
+        layerkeys.extend(
+            sorted_by_indices(trigrams.into_vec(), layout)
+                .into_iter()
+                .filter_map(|((idx1, idx2, idx3), w)| {
+                    let k2 = layout.get_layerkey(&idx2);
+
+                    // If the same modifier appears consecutively, it is usually "hold" instead of repeatedly pressed
+                    // --> remove
+                    match k2.is_modifier.is_hold() && (idx1 == idx2 || idx2 == idx3) {
+                        false => Some((
+                            (
+                                layout.get_layerkey(&idx1), // LayerKey 1
+                                k2,                         // LayerKey 2
+                                layout.get_layerkey(&idx3), // LayerKey 3
+                            ),
+                            w,
+                        )),
+                        true => None,
+                    }
+                }),
+        );
 
         layerkeys
     }
 
+    /// Removes trigrams that contain repeating identical modifiers (analogous to [`Self::get_filtered_layerkeys`]).
+    pub fn filter_layerkey_indices(
+        trigrams: &mut AHashMap<(LayerKeyIndex, LayerKeyIndex, LayerKeyIndex), f64>,
+        layout: &Layout,
+    ) {
+        trigrams.retain(|(idx1, idx2, idx3), _| {
+            !(layout.get_layerkey(idx2).is_modifier.is_hold() && (idx1 == idx2 || idx2 == idx3))
+        });
+    }
+
     /// Map all trigrams to base-layer trigrams, potentially generating multiple trigrams
     /// with modifiers for those with higer-layer keys.
     ///
@@ -143,39 +170,30 @@ impl OnDemandTrigramMapper {
     fn process_hold_modifiers(
         &self,
         trigrams: TrigramIndicesVec,
-        layout: &Layout,
+        hold_modifiers: &HoldModifiers,
     ) -> TrigramIndices {
-        let mut trigram_w_map = AHashMap::with_capacity(trigrams.len() / 3);
+        let mut trigram_w_map = OrderedNgrams::with_capacity(trigrams.len() / 3);
         trigrams.into_iter().for_each(|((k1, k2, k3), w)| {
-            let (base1, mods1) = layout.resolve_modifiers(&k1);
-            let (base2, mods2) = layout.resolve_modifiers(&k2);
-            let (base3, mods3) = layout.resolve_modifiers(&k3);
-
-            let (key1, mods1) = match mods1 {
-                LayerModifiers::Hold(mods) => (base1, mods),
-                _ => (k1, Vec::new()),
-            };
-
-            let (key2, mods2) = match mods2 {
-                LayerModifiers::Hold(mods) => (base2, mods),
-                _ => (k2, Vec::new()),
-            };
-
-            let (key3, mods3) = match mods3 {
-                LayerModifiers::Hold(mods) => (base3, mods),
-                _ => (k3, Vec::new()),
-            };
+            let (key1, mods1) = hold_modifiers.get(&k1);
+            let (key2, mods2) = hold_modifiers.get(&k2);
+            let (key3, mods3) = hold_modifiers.get(&k3);
+
+            // most trigrams do not involve any modifiers
+            if mods1.is_empty() && mods2.is_empty() && mods3.is_empty() {
+                trigram_w_map.insert_or_add_weight((key1, key2, key3), w);
+                return;
+            }
 
-            let k1_take_one = TakeOneLayerKey::new(key1, &mods1, w);
-            let k2_take_one = TakeOneLayerKey::new(key2, &mods2, w);
-            let k3_take_one = TakeOneLayerKey::new(key3, &mods3, w);
+            let k1_take_one = TakeOneLayerKey::new(key1, mods1, w);
+            let k2_take_one = TakeOneLayerKey::new(key2, mods2, w);
+            let k3_take_one = TakeOneLayerKey::new(key3, mods3, w);
 
             let k1_take_two =
-                TakeTwoLayerKey::new(key1, &mods1, w, self.split_modifiers.same_key_mod_factor);
+                TakeTwoLayerKey::new(key1, mods1, w, self.split_modifiers.same_key_mod_factor);
             let k2_take_two =
-                TakeTwoLayerKey::new(key2, &mods2, w, self.split_modifiers.same_key_mod_factor);
+                TakeTwoLayerKey::new(key2, mods2, w, self.split_modifiers.same_key_mod_factor);
             let k3_take_two =
-                TakeTwoLayerKey::new(key3, &mods3, w, self.split_modifiers.same_key_mod_factor);
+                TakeTwoLayerKey::new(key3, mods3, w, self.split_modifiers.same_key_mod_factor);
 
             k1_take_one.clone().for_each(|(e1, _)| {
                 k2_take_one.clone().for_each(|(e2, _)| {
@@ -239,7 +257,7 @@ impl OnDemandTrigramMapper {
                 });
             });
 
-            TakeThreeLayerKey::new(key1, &mods1, w, self.split_modifiers.same_key_mod_factor)
+            TakeThreeLayerKey::new(key1, mods1, w, self.split_modifiers.same_key_mod_factor)
                 .for_each(|(e, w)| {
                     // log::trace!(
                     //     "three of first:              {}{}{}",
@@ -250,7 +268,7 @@ impl OnDemandTrigramMapper {
                     trigram_w_map.insert_or_add_weight(e, w);
                 });
 
-            TakeThreeLayerKey::new(key2, &mods2, w, self.split_modifiers.same_key_mod_factor)
+            TakeThreeLayerKey::new(key2, mods2, w, self.split_modifiers.same_key_mod_factor)
                 .for_each(|(e, w)| {
                     // log::trace!(
                     //     "three of second:             {}{}{}",
@@ -261,7 +279,7 @@ impl OnDemandTrigramMapper {
                     trigram_w_map.insert_or_add_weight(e, w);
                 });
 
-            TakeThreeLayerKey::new(key3, &mods3, w, self.split_modifiers.same_key_mod_factor)
+            TakeThreeLayerKey::new(key3, mods3, w, self.split_modifiers.same_key_mod_factor)
                 .for_each(|(e, w)| {
                     // log::trace!(
                     //     "three of third:              {}{}{}",
@@ -321,4 +339,34 @@ impl OnDemandTrigramMapper {
 
         processed_trigrams
     }
+
+    /// Map each trigram containing symbols typed as compose sequence (e.g. with a dead key) to the
+    /// trigrams of the resulting sequence of symbols.
+    fn process_compose_sequences(
+        trigrams: TrigramIndicesVec,
+        layout: &Layout,
+    ) -> TrigramIndicesVec {
+        let mut idx_w_map = OrderedNgrams::with_capacity(trigrams.len());
+
+        trigrams.into_iter().for_each(|((k1, k2, k3), w)| {
+            let mut keys = Vec::new();
+            for k in [k1, k2, k3] {
+                let sequence = &layout.get_layerkey(&k).compose_sequence;
+                if sequence.is_empty() {
+                    keys.push(k);
+                } else {
+                    keys.extend(sequence);
+                }
+            }
+
+            keys.iter()
+                .zip(keys.iter().skip(1))
+                .zip(keys.iter().skip(2))
+                .for_each(|((lk1, lk2), lk3)| {
+                    idx_w_map.insert_or_add_weight((*lk1, *lk2, *lk3), w);
+                });
+        });
+
+        idx_w_map.into_vec()
+    }
 }
@@ -3,25 +3,21 @@
 
 use super::{common::*, on_demand_ngram_mapper::SplitModifiersConfig};
 
-use crate::ngrams::Unigrams;
-
-use ahash::AHashMap;
 use keyboard_layout::layout::{LayerKey, LayerKeyIndex, LayerModifiers, Layout};
 
 // Before passing the resulting LayerKey-based ngrams as a result, smaller LayerKeyIndex-based
 // ones are used because they are smaller than a reference (u16 vs usize) and yield better
 // hashing performance.
-type UnigramIndices = AHashMap<LayerKeyIndex, f64>;
-type UnigramIndicesVec = Vec<(LayerKeyIndex, f64)>;
+pub type UnigramIndices = OrderedNgrams<LayerKeyIndex>;
+pub type UnigramIndicesVec = Vec<(LayerKeyIndex, f64)>;
 
-/// Turns the [`Unigrams`]'s characters into their indices, returning a [`UnigramIndicesVec`].
-fn map_unigrams(unigrams: &Unigrams, layout: &Layout) -> (UnigramIndicesVec, f64) {
+/// Turns the unigrams' characters (sorted by them) into their indices, returning a [`UnigramIndicesVec`].
+fn map_unigrams(unigrams: &[(char, f64)], layout: &Layout) -> (UnigramIndicesVec, f64) {
     let mut not_found_weight = 0.0;
-    let mut unigrams_vec = Vec::with_capacity(unigrams.grams.len());
+    let mut unigrams_vec = Vec::with_capacity(unigrams.len());
 
     unigrams_vec.extend(
         unigrams
-            .grams
             .iter()
             //.filter(|(c, _weight)| !c.is_whitespace())
             .filter_map(|(c, weight)| {
@@ -53,30 +49,111 @@ impl OnDemandUnigramMapper {
     }
 
     /// For a given [`Layout`] generate [`LayerKeyIndex`]-based unigrams, optionally resolving modifiers for higer-layer symbols.
-    pub fn layerkey_indices(&self, unigrams: &Unigrams, layout: &Layout) -> (UnigramIndices, f64) {
+    /// The unigrams need to be sorted by their symbols.
+    ///
+    /// `bigram_weight` provides the weight of a bigram relative to the unigrams' weight (see
+    /// [`Self::process_context_keys`]).
+    pub fn layerkey_indices(
+        &self,
+        unigrams: &[(char, f64)],
+        layout: &Layout,
+        bigram_weight: impl Fn(char, char) -> f64,
+    ) -> (UnigramIndices, f64) {
         let (mut unigram_keys_vec, not_found_weight) = map_unigrams(unigrams, layout);
+        if layout.has_context_keys() {
+            unigram_keys_vec = Self::process_context_keys(unigram_keys_vec, layout, bigram_weight);
+        }
+
+        (
+            self.process_modifiers(unigram_keys_vec, layout),
+            not_found_weight,
+        )
+    }
+
+    /// Resolve compose sequences, modifiers of higher-layer symbols in [`LayerKeyIndex`]-based unigrams
+    /// (if configured) and the keys of combos.
+    pub fn process_modifiers(
+        &self,
+        mut unigram_keys_vec: UnigramIndicesVec,
+        layout: &Layout,
+    ) -> UnigramIndices {
+        if layout.has_compose_sequences() {
+            unigram_keys_vec = Self::process_compose_sequences(unigram_keys_vec, layout);
+        }
 
         if layout.has_one_shot_layers() {
             unigram_keys_vec = self.process_one_shot_modifiers(unigram_keys_vec, layout);
         }
 
         let unigram_keys = if self.split_modifiers.enabled && layout.has_hold_layers() {
-            Self::process_hold_modifiers(unigram_keys_vec, layout)
+            Self::process_hold_modifiers(unigram_keys_vec, &HoldModifiers::new(layout))
         } else {
             unigram_keys_vec.into_iter().collect()
         };
 
-        (unigram_keys, not_found_weight)
+        if layout.has_combos() {
+            self.process_combos(unigram_keys, layout)
+        } else {
+            unigram_keys
+        }
     }
 
-    /// Resolve &[`LayerKey`] references for [`LayerKeyIndex`]
-    pub fn get_layerkeys<'s>(
-        unigrams: &UnigramIndices,
-        layout: &'s Layout,
-    ) -> Vec<(&'s LayerKey, f64)> {
-        unigrams
-            .iter()
-            .map(|(k1, w)| (layout.get_layerkey(k1), *w))
+    /// Move the weight of symbols typed with a context key (e.g. a repeat key) to that key.
+    ///
+    /// Unigrams lack the previous symbol, so the weight typed with a context key is estimated from
+    /// the weight of the bigram consisting of the previous symbol and the symbol (`bigram_weight`,
+    /// relative to the unigrams' weight).
+    pub fn process_context_keys(
+        unigrams: UnigramIndicesVec,
+        layout: &Layout,
+        bigram_weight: impl Fn(char, char) -> f64,
+    ) -> UnigramIndicesVec {
+        let mut idx_w_map = OrderedNgrams::with_capacity(unigrams.len());
+        unigrams.into_iter().for_each(|(k, w)| {
+            let c = layout.get_layerkey(&k).symbol;
+            let mut remaining_w = w;
+            layout
+                .context_key_pairs(&[c])
+                .for_each(|((prev, c), context_key)| {
+                    let context_w = bigram_weight(prev, c).min(remaining_w);
+                    if context_w > 0.0 {
+                        idx_w_map.insert_or_add_weight(context_key, context_w);
+                        remaining_w -= context_w;
+                    }
+                });
+
+            idx_w_map.insert_or_add_weight(k, remaining_w);
+        });
+
+        idx_w_map.into_vec()
+    }
+
+    /// Map each unigram of a symbol typed as compose sequence (e.g. with a dead key) to a unigram
+    /// for each symbol of the sequence.
+    fn process_compose_sequences(
+        unigrams: UnigramIndicesVec,
+        layout: &Layout,
+    ) -> UnigramIndicesVec {
+        let mut idx_w_map = OrderedNgrams::with_capacity(unigrams.len());
+        unigrams.into_iter().for_each(|(k, w)| {
+            let sequence = &layout.get_layerkey(&k).compose_sequence;
+            if sequence.is_empty() {
+                idx_w_map.insert_or_add_weight(k, w);
+            } else {
+                sequence
+                    .iter()
+                    .for_each(|idx| idx_w_map.insert_or_add_weight(*idx, w));
+            }
+        });
+
+        idx_w_map.into_vec()
+    }
+
+    /// Resolve &[`LayerKey`] references for [`LayerKeyIndex`] (sorted by the latter)
+    pub fn get_layerkeys(unigrams: UnigramIndices, layout: &Layout) -> Vec<(&LayerKey, f64)> {
+        sorted_by_indices(unigrams.into_vec(), layout)
+            .into_iter()
+            .map(|(k1, w)| (layout.get_layerkey(&k1), w))
             .collect()
     }
 
@@ -85,18 +162,16 @@ impl OnDemandUnigramMapper {
     ///
     /// Each unigram of a higher-layer symbol will transform into a unigram with the base-layer key and one
     /// for each modifier involved in accessing the higher layer.
-    fn process_hold_modifiers(unigrams: UnigramIndicesVec, layout: &Layout) -> UnigramIndices {
-        let mut idx_w_map = AHashMap::with_capacity(unigrams.len() / 3);
+    fn process_hold_modifiers(
+        unigrams: UnigramIndicesVec,
+        hold_modifiers: &HoldModifiers,
+    ) -> UnigramIndices {
+        let mut idx_w_map = OrderedNgrams::with_capacity(unigrams.len() / 3);
         unigrams.into_iter().for_each(|(k, w)| {
-            let (base, mods) = layout.resolve_modifiers(&k);
-
-            let (key, mods) = match mods {
-                LayerModifiers::Hold(mods) => (base, mods),
-                _ => (k, Vec::new()),
-            };
+            let (key, mods) = hold_modifiers.get(&k);
 
             // Make sure we don't have any duplicate unigrams by adding them up.
-            TakeOneLayerKey::new(key, &mods, w)
+            TakeOneLayerKey::new(key, mods, w)
                 .for_each(|(idx, w)| idx_w_map.insert_or_add_weight(idx, w));
 
             // if base.symbol == ' ' {
@@ -117,6 +192,32 @@ impl OnDemandUnigramMapper {
         idx_w_map
     }
 
+    /// Map each unigram of a combo symbol to a unigram for each of the combo's keys (each of them
+    /// is pressed). Modifiers of higher-layer combo symbols are resolved as well if hold modifiers
+    /// are split (as in [`Self::process_hold_modifiers`]).
+    fn process_combos(&self, unigrams: UnigramIndices, layout: &Layout) -> UnigramIndices {
+        let split_hold_modifiers = self.split_modifiers.enabled && layout.has_hold_layers();
+        let mut idx_w_map = OrderedNgrams::with_capacity(unigrams.len());
+        unigrams.into_iter().for_each(|(k, w)| {
+            let lk = layout.get_layerkey(&k);
+            if lk.combo_keys.is_empty() {
+                idx_w_map.insert_or_add_weight(k, w);
+                return;
+            }
+
+            let mods: &[LayerKeyIndex] = match &lk.modifiers {
+                LayerModifiers::Hold(mods) if split_hold_modifiers && lk.layer > 0 => mods,
+                _ => &[],
+            };
+            lk.combo_keys
+                .iter()
+                .chain(mods.iter())
+                .for_each(|idx| idx_w_map.insert_or_add_weight(*idx, w));
+        });
+
+        idx_w_map
+    }
+
     fn process_one_shot_modifiers(
         &self,
         unigrams: UnigramIndicesVec,
@@ -6,6 +6,7 @@ use crate::ngram_mapper::common::NgramMap;
 
 use ahash::AHashMap;
 use anyhow::Result;
+use keyboard_layout::layout::ContextKey;
 use serde::Deserialize;
 use std::{
     fs::{self, create_dir_all, File},
@@ -65,6 +66,43 @@ pub fn increase_common_ngrams<T>(
     });
 }
 
+/// Replace sequences of several symbols generated by a context key (e.g. a magic key typing "ing"
+/// after "x") with the key's placeholder symbol, such that ngrams collected from the text contain
+/// the key. Sequences are replaced greedily from the start of the text (earlier context keys take
+/// precedence). Context keys generating a single symbol are recognized within the ngrams by the
+/// ngram mappers, so their symbols are kept.
+pub fn tokenize_context_keys(text: &str, context_keys: &[ContextKey]) -> String {
+    let mut tokenized = String::with_capacity(text.len());
+    let mut prev: Option<char> = None;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let sequence = prev.and_then(|prev| {
+            context_keys.iter().find_map(|context_key| {
+                context_key
+                    .generates_sequence(&prev)
+                    .filter(|sequence| rest.starts_with(sequence))
+                    .map(|sequence| (context_key.symbol(), sequence))
+            })
+        });
+
+        match sequence {
+            Some((symbol, sequence)) => {
+                tokenized.push(symbol);
+                // following context keys refer to the last generated symbol
+                prev = sequence.chars().last();
+                rest = &rest[sequence.len()..];
+            }
+            None => {
+                tokenized.push(c);
+                prev = Some(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    tokenized
+}
+
 /// Holds a hashmap of unigrams (single chars) with corresponding frequency (here often called "weight").
 #[derive(Clone, Debug)]
 pub struct Unigrams {
@@ -454,3 +492,29 @@ impl Trigrams {
         Self { grams }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_sequences_of_magic_keys() {
+        let context_keys = vec![
+            ContextKey::Repeat { symbol: '⟲' },
+            ContextKey::Magic {
+                symbol: '★',
+                rules: [('x', "ing".to_string()), ('o', "w".to_string())]
+                    .into_iter()
+                    .collect(),
+            },
+        ];
+
+        // single-symbol outputs are left to the ngram mappers
+        assert_eq!(
+            tokenize_context_keys("fixing looking xin", &context_keys),
+            "fix★ looking xin"
+        );
+        // the last generated symbol is the previous one for the next key
+        assert_eq!(tokenize_context_keys("xingg", &context_keys), "x★g");
+    }
+}
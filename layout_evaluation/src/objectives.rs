@@ -0,0 +1,89 @@
+//! The `objectives` module splits the evaluation of a layout into several objectives for
+//! multi-objective optimization. Instead of aggregating all metrics into one total cost, each
+//! objective aggregates only a selection of metrics.
+
+use crate::results::{EvaluationResult, MetricType};
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs::File;
+
+/// An objective aggregates the (weighted and normalized) costs of a selection of metrics.
+#[derive(Clone, Deserialize, Debug)]
+pub struct Objective {
+    /// Name of the objective.
+    pub name: String,
+    /// Names of metrics (as shown in evaluation results, e.g. "Finger Balance") that contribute to the objective.
+    #[serde(default)]
+    pub metrics: Vec<String>,
+    /// Types of metrics whose metrics all contribute to the objective.
+    #[serde(default)]
+    pub metric_types: Vec<MetricType>,
+}
+
+impl Objective {
+    /// Whether a metric of given type and name contributes to the objective.
+    pub fn contains(&self, metric_type: &MetricType, metric_name: &str) -> bool {
+        self.metric_types.contains(metric_type) || self.metrics.iter().any(|m| m == metric_name)
+    }
+
+    /// The cost of the objective for an evaluation result, i.e. the sum of the weighted and normalized
+    /// costs of all contributing metrics.
+    pub fn cost(&self, evaluation_result: &EvaluationResult) -> f64 {
+        evaluation_result
+            .iter()
+            .flat_map(|mr| {
+                mr.metric_costs
+                    .iter()
+                    .filter(|mc| self.contains(&mr.metric_type, &mc.core.name))
+            })
+            .map(|mc| mc.weighted_cost)
+            .sum()
+    }
+}
+
+/// A list of objectives, usually read from a configuration file.
+#[derive(Clone, Deserialize, Debug)]
+pub struct Objectives {
+    pub objectives: Vec<Objective>,
+}
+
+impl Objectives {
+    pub fn from_yaml(filename: &str) -> Result<Self> {
+        let f = File::open(filename)?;
+        let o: Objectives = serde_yaml::from_reader(f)?;
+
+        Ok(o)
+    }
+
+    /// The costs of all objectives for an evaluation result.
+    pub fn costs(&self, evaluation_result: &EvaluationResult) -> Vec<f64> {
+        self.objectives
+            .iter()
+            .map(|o| o.cost(evaluation_result))
+            .collect()
+    }
+
+    /// The names of all objectives.
+    pub fn names(&self) -> Vec<String> {
+        self.objectives.iter().map(|o| o.name.clone()).collect()
+    }
+
+    /// Names of the metrics in an evaluation result that do not contribute to any objective.
+    pub fn unused_metrics(&self, evaluation_result: &EvaluationResult) -> Vec<String> {
+        evaluation_result
+            .iter()
+            .flat_map(|mr| {
+                mr.metric_costs
+                    .iter()
+                    .filter(|mc| {
+                        !self
+                            .objectives
+                            .iter()
+                            .any(|o| o.contains(&mr.metric_type, &mc.core.name))
+                    })
+                    .map(|mc| mc.core.name.clone())
+            })
+            .collect()
+    }
+}
@@ -1,5 +1,8 @@
 //! The `results` module contains structs representing the results of metric evaluations.
 
+use keyboard_layout::key::{Finger, Hand, MatrixPosition};
+
+use ahash::AHashMap;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use std::{fmt, slice};
@@ -39,6 +42,106 @@ pub struct MetricResult {
     pub weight: f64,
     /// The normalization type to apply.
     pub normalization: NormalizationType,
+    /// Attribution of the cost to individual ngrams, keys, and fingers (if requested and supported by the metric).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<CostAttribution>,
+    /// Estimated typing speed (if provided by the metric).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typing_speed: Option<TypingSpeed>,
+}
+
+/// Typing speed corresponding to a mean time per keystroke.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct TypingSpeed {
+    /// Mean time (ms) per keystroke.
+    pub ms_per_keystroke: f64,
+    /// Words per minute (five keystrokes per word).
+    pub words_per_minute: f64,
+}
+
+impl fmt::Display for TypingSpeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} ms per keystroke, {:.1} words per minute",
+            self.ms_per_keystroke, self.words_per_minute
+        )
+    }
+}
+
+/// The contribution of a single ngram to a metric's cost.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NgramContribution {
+    /// Symbols of the ngram (modifiers are enclosed in brackets).
+    pub ngram: String,
+    /// Contribution to the metric's total cost (not normalized).
+    pub cost: f64,
+    /// Fraction of the sum of absolute contributions of all ngrams.
+    pub fraction: f64,
+}
+
+/// The contribution of a single key to a metric's cost (summed over all its layers).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyContribution {
+    pub matrix_position: MatrixPosition,
+    pub hand: Hand,
+    pub finger: Finger,
+    /// Contribution to the metric's total cost (not normalized).
+    pub cost: f64,
+}
+
+/// The contribution of a single finger to a metric's cost.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FingerContribution {
+    pub hand: Hand,
+    pub finger: Finger,
+    /// Contribution to the metric's total cost (not normalized).
+    pub cost: f64,
+}
+
+/// Breakdown of a metric's cost into the contributions of ngrams, keys, and fingers.
+///
+/// The cost of an ngram is split evenly among its keys. For metrics whose total cost is not the sum of the
+/// individual costs, the contributions are scaled proportionally such that they sum up to the total cost.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CostAttribution {
+    /// The ngrams with the largest absolute contributions, in descending order.
+    pub worst_ngrams: Vec<NgramContribution>,
+    /// The ngrams without fixed keys with the largest absolute contributions, in descending order.
+    #[serde(default)]
+    pub worst_non_fixed_ngrams: Vec<NgramContribution>,
+    /// Contributions of all keys (in descending order), if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<KeyContribution>>,
+    /// Contributions of all fingers (left to right), if requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingers: Option<Vec<FingerContribution>>,
+}
+
+impl fmt::Display for CostAttribution {
+    /// Lists the worst ngrams and, if they differ from those, the worst ngrams without fixed keys.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format_ngrams = |ngrams: &[NgramContribution]| -> Vec<String> {
+            ngrams
+                .iter()
+                .map(|c| format!("{} ({:>5.2}%)", c.ngram, 100.0 * c.fraction))
+                .collect()
+        };
+
+        let mut msgs = Vec::new();
+
+        let worst = format_ngrams(&self.worst_ngrams);
+        if !worst.is_empty() {
+            msgs.push(format!("Worst: {}", worst.join(", ")));
+        }
+
+        let worst_non_fixed = format_ngrams(&self.worst_non_fixed_ngrams);
+        if !worst_non_fixed.is_empty() && worst_non_fixed != worst {
+            msgs.push(format!("Worst non-fixed: {}", worst_non_fixed.join(", ")));
+        }
+
+        write!(f, "{}", msgs.join(";  "))
+    }
 }
 
 /// Describes the normalized results of an individual metric evaluation
@@ -77,13 +180,22 @@ impl fmt::Display for MetricResults {
             )?;
         }
         for metric_cost in self.metric_costs.iter() {
+            let details: Vec<String> = metric_cost
+                .core
+                .typing_speed
+                .iter()
+                .map(|s| s.to_string())
+                .chain(metric_cost.core.message.iter().cloned())
+                .chain(metric_cost.core.attribution.iter().map(|a| a.to_string()))
+                .filter(|d| !d.is_empty())
+                .collect();
             writeln!(
                 f,
                 "  {} {} | {}",
                 // metric_cost.unweighted_cost,
                 format!("{:>7.2}", metric_cost.weighted_cost).green(),
                 format!("{:<35}", metric_cost.core.name).bold(),
-                metric_cost.core.message.as_ref().unwrap_or(&"".to_string()),
+                details.join(";  "),
             )?;
         }
         Ok(())
@@ -214,4 +326,33 @@ impl EvaluationResult {
     pub fn iter(&self) -> slice::Iter<'_, MetricResults> {
         self.individual_results.iter()
     }
+
+    /// The contribution of each key to the weighted and normalized cost of the metric with the given
+    /// name, if the metric's cost was attributed to keys.
+    pub fn key_costs(&self, metric_name: &str) -> Option<AHashMap<MatrixPosition, f64>> {
+        let metric_cost = self
+            .individual_results
+            .iter()
+            .flat_map(|results| results.metric_costs.iter())
+            .find(|mc| mc.core.name == metric_name)?;
+        let keys = metric_cost.core.attribution.as_ref()?.keys.as_ref()?;
+
+        let scale = if metric_cost.core.cost != 0.0 {
+            metric_cost.weighted_cost / metric_cost.core.cost
+        } else {
+            0.0
+        };
+        Some(
+            keys.iter()
+                .map(|k| (k.matrix_position, scale * k.cost))
+                .collect(),
+        )
+    }
+}
+
+/// Contributions of individual ngrams (given by their symbols) to the total cost of a layout.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct NgramCosts {
+    pub bigrams: AHashMap<String, f64>,
+    pub trigrams: AHashMap<String, f64>,
 }
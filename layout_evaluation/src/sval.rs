@@ -1,31 +1,167 @@
-#[derive(Clone, Debug, PartialEq, Copy)]
-pub enum SvalKeyDirection {
+//! Helpers for metrics that evaluate movements within the key clusters of Svalboard-like keyboards.
+//!
+//! The clusters are declared in the keyboard's configuration (see [`ClusterPosition`]). On keyboards
+//! without clusters, all helpers return `None` such that the metrics can skip the corresponding keys.
+//!
+//! The effort of transitions between the directions of a cluster is configured as one 5×5 matrix
+//! per finger ([`DirectionFactors`]), shared by all Svalboard metrics. Lateral directions are given relative to the hand (inward or
+//! outward), such that the same matrices apply to both hands.
+
+use keyboard_layout::key::{ClusterPosition, Finger, FingerMap, Hand, Key, KeyDirection};
+
+use ahash::AHashMap;
+use std::str::FromStr;
+
+/// The direction of a key within its cluster (if it belongs to one).
+#[inline(always)]
+pub fn direction(key: &Key) -> Option<KeyDirection> {
+    key.cluster.map(|ClusterPosition(_, direction)| direction)
+}
+
+/// The directions of two keys within their cluster (if both belong to the same one).
+#[inline(always)]
+pub fn directions_in_same_cluster(k1: &Key, k2: &Key) -> Option<(KeyDirection, KeyDirection)> {
+    match (k1.cluster, k2.cluster) {
+        (Some(ClusterPosition(c1, d1)), Some(ClusterPosition(c2, d2))) if c1 == c2 => {
+            Some((d1, d2))
+        }
+        _ => None,
+    }
+}
+
+/// The lateral direction pointing towards the other hand.
+#[inline(always)]
+pub fn inward_direction(hand: Hand) -> KeyDirection {
+    match hand {
+        Hand::Left => KeyDirection::East,
+        Hand::Right => KeyDirection::West,
+    }
+}
+
+/// Direction of a key within a finger's cluster relative to the hand. Its index corresponds to the
+/// rows and columns of a [`DirectionMatrix`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[repr(u8)]
+pub enum TransitionDirection {
+    Center,
     North,
     South,
-    East,
-    West,
-    Center,
+    Inward,
+    Outward,
 }
-impl SvalKeyDirection {
-    pub fn from_key(key: &keyboard_layout::key::Key, closest_center: &(u8, u8)) -> Self {
-        let (x, y) = (key.matrix_position.0, key.matrix_position.1);
-        if x == closest_center.0 && y == closest_center.1 {
-            return SvalKeyDirection::Center;
-        }
-        if x == closest_center.0 {
-            if y < closest_center.1 {
-                SvalKeyDirection::North
-            } else {
-                SvalKeyDirection::South
-            }
-        } else if y == closest_center.1 {
-            if x < closest_center.0 {
-                SvalKeyDirection::West
-            } else {
-                SvalKeyDirection::East
+
+impl TransitionDirection {
+    /// All directions in the order of the rows and columns of a [`DirectionMatrix`].
+    pub const ALL: [TransitionDirection; 5] = [
+        TransitionDirection::Center,
+        TransitionDirection::North,
+        TransitionDirection::South,
+        TransitionDirection::Inward,
+        TransitionDirection::Outward,
+    ];
+
+    /// The direction of a key within its cluster, relative to the hand pressing it. Keys outside of
+    /// clusters and keys of thumb clusters have none.
+    #[inline(always)]
+    pub fn of_key(key: &Key) -> Option<Self> {
+        let direction = direction(key)?;
+        let inward = inward_direction(key.hand);
+        match direction {
+            KeyDirection::Center => Some(TransitionDirection::Center),
+            KeyDirection::North => Some(TransitionDirection::North),
+            KeyDirection::South => Some(TransitionDirection::South),
+            KeyDirection::East | KeyDirection::West if direction == inward => {
+                Some(TransitionDirection::Inward)
             }
-        } else {
-            panic!("Key is not on the closest center");
+            KeyDirection::East | KeyDirection::West => Some(TransitionDirection::Outward),
+            _ => None,
         }
     }
+
+    /// Whether the two directions point to opposite sides of the cluster (north-south or
+    /// inward-outward).
+    #[inline(always)]
+    pub fn is_opposite(self, other: Self) -> bool {
+        use TransitionDirection::*;
+        matches!(
+            (self, other),
+            (North, South) | (South, North) | (Inward, Outward) | (Outward, Inward)
+        )
+    }
+}
+
+impl FromStr for TransitionDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "center" | "c" => Ok(TransitionDirection::Center),
+            "north" | "n" => Ok(TransitionDirection::North),
+            "south" | "s" => Ok(TransitionDirection::South),
+            "inward" | "in" | "i" => Ok(TransitionDirection::Inward),
+            "outward" | "out" | "o" => Ok(TransitionDirection::Outward),
+            _ => Err(format!("Unknown direction '{}'", s)),
+        }
+    }
+}
+
+/// Cost factors for transitions between the directions of a cluster. Rows correspond to the
+/// direction of the first key, columns to the direction of the second key (both in the order of
+/// [`TransitionDirection::ALL`]).
+pub type DirectionMatrix = [[f64; 5]; 5];
+
+// The scale:
+// 0 = as if there's no movement, would as easily type this as alternating fingers
+// 1 = as annoying as a curling SFB on a regular keyboard
+//
+// Rows: first key (center, north, south, inward, outward), columns: second key
+const DEFAULT_FACTORS: DirectionMatrix = [
+    // center-south is virtually free, center-inward almost as well
+    [0.7, 0.3, 0.0, 0.4, 3.0],
+    // north-south is a wall-to-wall movement
+    [1.0, 1.0, 1.75, 1.0, 1.0],
+    [1.0, 1.75, 0.5, 1.0, 1.0],
+    // inward-outward is a wall-to-wall movement
+    [1.0, 1.0, 1.0, 1.0, 1.75],
+    [1.0, 1.0, 1.0, 1.75, 1.0],
+];
+
+/// Default effort of transitions between the directions of a finger's cluster.
+pub fn default_factors(finger: Finger) -> DirectionMatrix {
+    let mut factors = DEFAULT_FACTORS;
+    if finger == Finger::Index {
+        // south to inward is a comfortable roll for the index finger
+        factors[TransitionDirection::South as usize][TransitionDirection::Inward as usize] = 0.2;
+    }
+    factors
+}
+
+/// Per-finger [`DirectionMatrix`]s as configured in the evaluation parameters
+/// (`sval_direction_factors`). Fingers that are not specified use [`default_factors`].
+pub type DirectionFactorsParameters = AHashMap<Finger, DirectionMatrix>;
+
+/// A [`DirectionMatrix`] for each finger, giving the effort of the finger moving from one direction
+/// of its cluster to another. It is shared by all metrics evaluating such movements.
+#[derive(Clone, Debug)]
+pub struct DirectionFactors(FingerMap<DirectionMatrix>);
+
+impl DirectionFactors {
+    /// Take the matrices given in `matrices` and the [`default_factors`] for all other fingers.
+    pub fn new(matrices: &DirectionFactorsParameters) -> Self {
+        let mut factors = FingerMap::with_default([[0.0; 5]; 5]);
+        for finger in FingerMap::<DirectionMatrix>::keys() {
+            factors.set(
+                &finger,
+                *matrices.get(&finger).unwrap_or(&default_factors(finger)),
+            );
+        }
+
+        Self(factors)
+    }
+
+    /// The cost factor of a finger for a transition from one direction to another.
+    #[inline(always)]
+    pub fn get(&self, finger: Finger, from: TransitionDirection, to: TransitionDirection) -> f64 {
+        self.0.get(&finger)[from as usize][to as usize]
+    }
 }
@@ -1,4 +1,6 @@
-use rand::{seq::SliceRandom, thread_rng};
+use keyboard_layout::layout_symmetry::LayoutSymmetry;
+
+use rand::{seq::SliceRandom, Rng};
 
 #[derive(Clone, Debug)]
 pub struct LayoutPermutator {
@@ -6,32 +8,113 @@ pub struct LayoutPermutator {
     perm_indices: Vec<usize>,
     fixed_keys: Vec<char>,
     fixed_indices: Vec<usize>,
+    /// Entries of a permutation that may be exchanged among each other (one Vec per segment)
+    segments: Vec<Vec<usize>>,
+    /// Entries of a permutation whose segment allows for at least one swap
+    swappable: Vec<usize>,
+    /// Index of the segment of each entry of a permutation
+    segment_of: Vec<usize>,
+    /// If given, only permutations yielding canonical layout strings are generated
+    symmetry: Option<LayoutSymmetry>,
 }
 
 impl LayoutPermutator {
     pub fn new(layout: &str, fixed: &str) -> Self {
+        Self::with_segments(layout, fixed, &[])
+    }
+
+    /// Like [`LayoutPermutator::new`], but the layout string consists of consecutive segments of the
+    /// given lengths (e.g. see [`keyboard_layout::layout_generator::LayoutGenerator::permutation_segments`]).
+    /// Symbols are only ever permuted within their segment. Empty `segment_lengths` allow any permutation.
+    pub fn with_segments(layout: &str, fixed: &str, segment_lengths: &[usize]) -> Self {
         let mut perm_keys = Vec::new();
         let mut perm_indices = Vec::new();
         let mut fixed_keys = Vec::new();
         let mut fixed_indices = Vec::new();
+        let mut segments: Vec<Vec<usize>> = vec![Vec::new(); segment_lengths.len().max(1)];
+        let mut segment_of = Vec::new();
 
+        let mut segment = 0;
+        let mut segment_end = segment_lengths.first().cloned().unwrap_or(usize::MAX);
         for (i, c) in layout.chars().enumerate() {
+            while i >= segment_end && segment + 1 < segments.len() {
+                segment += 1;
+                segment_end += segment_lengths[segment];
+            }
+
             if fixed.contains(c) {
                 fixed_keys.push(c);
                 fixed_indices.push(i);
             } else {
+                segments[segment].push(perm_keys.len());
+                segment_of.push(segment);
                 perm_keys.push(c);
                 perm_indices.push(i);
             }
         }
+        let swappable = segments
+            .iter()
+            .filter(|entries| entries.len() > 1)
+            .flatten()
+            .cloned()
+            .collect();
+
         Self {
             perm_keys,
             perm_indices,
             fixed_keys,
             fixed_indices,
+            segments,
+            swappable,
+            segment_of,
+            symmetry: None,
         }
     }
 
+    /// Only generate permutations whose layout strings are canonical wrt. the given symmetry, i.e.
+    /// one of each layout and its mirror image. Fixed symbols (and their mirror images) stay in place.
+    pub fn with_symmetry(mut self, symmetry: &LayoutSymmetry) -> Self {
+        let symmetry = symmetry.keeping_positions(&self.fixed_indices);
+        self.symmetry = (!symmetry.is_trivial()).then_some(symmetry);
+        self
+    }
+
+    /// The permutation yielding the canonical layout string of the given permutation and its mirror
+    /// image (the given one if no symmetry is used).
+    pub fn canonicalize(&self, permutation: Vec<usize>) -> Vec<usize> {
+        let symmetry = match &self.symmetry {
+            Some(symmetry) => symmetry,
+            None => return permutation,
+        };
+
+        let mirrored: Vec<usize> = permutation
+            .iter()
+            .map(|i| symmetry.mirrored_position(*i))
+            .collect();
+        if self.generate_string(&mirrored) < self.generate_string(&permutation) {
+            mirrored
+        } else {
+            permutation
+        }
+    }
+
+    /// Whether the permutable symbols are divided into more than one segment
+    pub fn is_segmented(&self) -> bool {
+        self.segments.len() > 1
+    }
+
+    /// Choose two different entries of a permutation that belong to the same segment
+    fn choose_swap<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(usize, usize)> {
+        let first = *self.swappable.choose(rng)?;
+        let segment = &self.segments[self.segment_of[first]];
+        let mut second = first;
+        while second == first {
+            second = *segment.choose(rng).unwrap();
+        }
+
+        Some((first, second))
+    }
+
     pub fn generate_string(&self, permutation: &[usize]) -> String {
         let mut res: Vec<char> = vec!['-'; self.fixed_keys.len() + self.perm_keys.len()];
 
@@ -48,19 +131,44 @@ impl LayoutPermutator {
         res.iter().collect()
     }
 
-    pub fn generate_random(&self) -> Vec<usize> {
+    pub fn generate_random<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<usize> {
         let mut indices: Vec<usize> = self.perm_indices.to_vec();
-        indices.shuffle(&mut thread_rng());
+        if !self.is_segmented() {
+            indices.shuffle(rng);
+            return self.canonicalize(indices);
+        }
+
+        for segment in self.segments.iter() {
+            let mut segment_indices: Vec<usize> = segment.iter().map(|e| indices[*e]).collect();
+            segment_indices.shuffle(rng);
+            segment
+                .iter()
+                .zip(segment_indices)
+                .for_each(|(e, i)| indices[*e] = i);
+        }
 
-        indices
+        self.canonicalize(indices)
     }
 
     /// Takes in a Layout, switches [nr_switches] keys in that layout, then returns it.
     /// Layout, in this case, is a [Vec<usize>].
-    pub fn perform_n_swaps(&self, permutation: &[usize], nr_switches: usize) -> Vec<usize> {
+    pub fn perform_n_swaps<R: Rng + ?Sized>(
+        &self,
+        permutation: &[usize],
+        nr_switches: usize,
+        rng: &mut R,
+    ) -> Vec<usize> {
         let mut indices: Vec<usize> = permutation.to_vec();
+        if self.is_segmented() {
+            for _ in 0..nr_switches {
+                if let Some((sw0, sw1)) = self.choose_swap(rng) {
+                    indices.swap(sw0, sw1);
+                }
+            }
+            return self.canonicalize(indices);
+        }
+
         let vec: Vec<usize> = (0..permutation.len()).collect();
-        let rng = &mut thread_rng();
 
         // Perform nr_switches switches
         for _ in 0..nr_switches {
@@ -70,14 +178,25 @@ impl LayoutPermutator {
             indices.swap(*sw0, *sw1);
         }
 
-        indices
+        self.canonicalize(indices)
     }
 
-    pub fn switch_n_keys(&self, permutation: &[usize], n_keys: usize) -> Vec<usize> {
+    pub fn switch_n_keys<R: Rng + ?Sized>(
+        &self,
+        permutation: &[usize],
+        n_keys: usize,
+        rng: &mut R,
+    ) -> Vec<usize> {
         let mut indices: Vec<usize> = permutation.to_vec();
-        let rng = &mut thread_rng();
 
-        let vec: Vec<usize> = (0..permutation.len()).collect();
+        // with segments, only switch keys of a single (randomly chosen) segment
+        let vec: Vec<usize> = match self.is_segmented() {
+            true => match self.swappable.choose(rng) {
+                Some(e) => self.segments[self.segment_of[*e]].clone(),
+                None => return self.canonicalize(indices),
+            },
+            false => (0..permutation.len()).collect(),
+        };
         let sw_from: Vec<&usize> = vec.choose_multiple(rng, n_keys).collect();
         let mut sw_to = sw_from.to_vec();
         sw_to.shuffle(rng);
@@ -87,7 +206,7 @@ impl LayoutPermutator {
             indices[*to] = permutation[*from];
         }
 
-        indices
+        self.canonicalize(indices)
     }
 
     pub fn get_permutable_indices(&self) -> Vec<usize> {
@@ -95,6 +214,16 @@ impl LayoutPermutator {
     }
 }
 
+/// Periodic checkpointing of an optimization run, whose state is described by checkpoints of type `C`.
+pub struct Checkpointing<C> {
+    /// Store a checkpoint every this many iterations (or generations).
+    pub interval: u64,
+    /// Called with each new checkpoint, e.g. for writing it to disk.
+    pub store: Box<dyn Fn(&C) + Send + Sync>,
+    /// Resume the run from this checkpoint instead of starting anew.
+    pub resume_from: Option<C>,
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
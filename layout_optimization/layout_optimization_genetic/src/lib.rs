@@ -1,4 +1,5 @@
 pub mod optimization;
+pub mod pareto;
 
 #[cfg(test)]
 mod tests {
@@ -1,19 +1,25 @@
 use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
-use layout_evaluation::{cache::Cache, evaluation::Evaluator};
+use layout_evaluation::{
+    cache::Cache,
+    evaluation::{EvaluationState, Evaluator},
+};
 
-use layout_optimization_common::LayoutPermutator;
+use layout_optimization_common::{Checkpointing, LayoutPermutator};
 
 use anyhow::Result;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, sync::Arc};
+use std::{
+    fs::File,
+    sync::{Arc, Mutex},
+};
 
 use genevo::{
     genetic::{Children, FitnessFunction, Parents},
-    operator::{prelude::*, CrossoverOp, GeneticOperator},
+    operator::{prelude::*, CrossoverOp, GeneticOperator, MutationOp},
     population::Population,
     prelude::*,
-    random::SliceRandom,
+    random::{get_rng, random_seed, Prng, Seed, SeedableRng},
     simulation::simulator::Simulator,
     types::fmt::Display,
 };
@@ -26,6 +32,11 @@ pub struct Parameters {
     pub selection_ratio: f64,
     pub mutation_rate: f64,
     pub reinsertion_ratio: f64,
+    /// Only search one layout of each pair of mirror images (see
+    /// [`keyboard_layout::layout_symmetry::LayoutSymmetry`]). This assumes that all metrics treat
+    /// both hands alike.
+    #[serde(default)]
+    pub only_canonical_layouts: bool,
 }
 
 impl Default for Parameters {
@@ -37,6 +48,7 @@ impl Default for Parameters {
             selection_ratio: 0.7,
             mutation_rate: 0.1,
             reinsertion_ratio: 0.7,
+            only_canonical_layouts: false,
         }
     }
 }
@@ -49,7 +61,7 @@ impl Parameters {
 }
 
 // The genotype
-type Genotype = Vec<usize>;
+pub(crate) type Genotype = Vec<usize>;
 
 /// The fitness function for [`Genotype`]s.
 #[derive(Clone, Debug)]
@@ -58,6 +70,48 @@ pub struct FitnessCalc {
     permutator: LayoutPermutator,
     layout_generator: Box<dyn LayoutGenerator>,
     result_cache: Option<Cache<usize>>,
+    /// Evaluation states of previously evaluated layouts (one per concurrent evaluation). As layouts
+    /// of a population are mostly similar, they can often be updated incrementally.
+    evaluation_states: Arc<Mutex<Vec<EvaluationState>>>,
+}
+
+impl FitnessCalc {
+    fn new(
+        evaluator: &Evaluator,
+        permutator: &LayoutPermutator,
+        layout_generator: Box<dyn LayoutGenerator>,
+        cache_results: bool,
+    ) -> Self {
+        let result_cache = if cache_results {
+            Some(Cache::new())
+        } else {
+            None
+        };
+
+        Self {
+            evaluator: Arc::new(evaluator.clone()),
+            permutator: permutator.clone(),
+            layout_generator,
+            result_cache,
+            evaluation_states: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Evaluate a layout, incrementally updating one of the stored evaluation states if possible.
+    fn evaluate(&self, layout: &Layout) -> usize {
+        let stored_state = self.evaluation_states.lock().unwrap().pop();
+        let state = match stored_state {
+            Some(mut state) => {
+                self.evaluator.update_state(&mut state, layout);
+                state
+            }
+            None => self.evaluator.evaluation_state(layout),
+        };
+        let score = (1e8 / state.total_cost()) as usize;
+        self.evaluation_states.lock().unwrap().push(state);
+
+        score
+    }
 }
 
 impl FitnessFunction<Genotype, usize> for FitnessCalc {
@@ -67,10 +121,10 @@ impl FitnessFunction<Genotype, usize> for FitnessCalc {
 
         // Get & return the evaluation-result
         match &self.result_cache {
-            Some(result_cache) => result_cache.get_or_insert_with(&layout_str, || {
-                self.evaluator.evaluate_layout(&l).optimization_score()
-            }),
-            None => self.evaluator.evaluate_layout(&l).optimization_score(),
+            Some(result_cache) => {
+                result_cache.get_or_insert_with(&layout_str, || self.evaluate(&l))
+            }
+            None => self.evaluate(&l),
         }
     }
 
@@ -87,14 +141,14 @@ impl FitnessFunction<Genotype, usize> for FitnessCalc {
     }
 }
 
-struct LayoutBuilder {
-    indices: Vec<usize>,
+pub(crate) struct LayoutBuilder {
+    permutator: LayoutPermutator,
 }
 
 impl LayoutBuilder {
-    fn with_permutable_layout(layout_prototype: &LayoutPermutator) -> Self {
+    pub(crate) fn with_permutable_layout(layout_prototype: &LayoutPermutator) -> Self {
         Self {
-            indices: layout_prototype.get_permutable_indices(),
+            permutator: layout_prototype.clone(),
         }
     }
 }
@@ -104,18 +158,16 @@ impl GenomeBuilder<Vec<usize>> for LayoutBuilder {
     where
         R: Rng + Sized,
     {
-        let mut s: Vec<usize> = self.indices.clone();
-        s.shuffle(rng);
-        s
+        self.permutator.generate_random(rng)
     }
 }
 
-struct FromGivenLayoutBuilder {
+pub(crate) struct FromGivenLayoutBuilder {
     indices: Vec<usize>,
 }
 
 impl FromGivenLayoutBuilder {
-    fn with_permutable_layout(layout_prototype: &LayoutPermutator) -> Self {
+    pub(crate) fn with_permutable_layout(layout_prototype: &LayoutPermutator) -> Self {
         Self {
             indices: layout_prototype.get_permutable_indices(),
         }
@@ -206,6 +258,45 @@ impl CrossoverOp<Vec<usize>> for MyCrossover {
     }
 }
 
+/// Swaps genes like the [`SwapOrderMutator`], but keeps them within the segments of a segmented
+/// [`LayoutPermutator`] (e.g. within a layer).
+#[derive(Clone, Debug)]
+pub struct SegmentSwapMutator {
+    mutation_rate: f64,
+    permutator: LayoutPermutator,
+}
+
+impl SegmentSwapMutator {
+    pub fn new(mutation_rate: f64, permutator: &LayoutPermutator) -> Self {
+        SegmentSwapMutator {
+            mutation_rate,
+            permutator: permutator.clone(),
+        }
+    }
+}
+
+impl GeneticOperator for SegmentSwapMutator {
+    fn name() -> String {
+        "Segment-Swap-Mutation".to_string()
+    }
+}
+
+impl MutationOp<Vec<usize>> for SegmentSwapMutator {
+    fn mutate<R>(&self, genome: Vec<usize>, rng: &mut R) -> Vec<usize>
+    where
+        R: Rng + Sized,
+    {
+        if !self.permutator.is_segmented() {
+            let genome = SwapOrderMutator::new(self.mutation_rate).mutate(genome, rng);
+            return self.permutator.canonicalize(genome);
+        }
+
+        let num_mutations =
+            ((genome.len() as f64 * self.mutation_rate) + rng.gen::<f64>()).floor() as usize;
+        self.permutator.perform_n_swaps(&genome, num_mutations, rng)
+    }
+}
+
 pub type MySimulator = Simulator<
     GeneticAlgorithm<
         Vec<usize>,
@@ -215,48 +306,44 @@ pub type MySimulator = Simulator<
         // PartiallyMappedCrossover,
         // MyCrossover,
         NoOpCrossover,
-        SwapOrderMutator,
+        SegmentSwapMutator,
         UniformReinserter, // we do not use an elitist reinserter due to performance reasons (non-parallelized evaluation)
     >,
     GenerationLimit,
 >;
 
-pub fn init_optimization(
-    params: &Parameters,
-    evaluator: &Evaluator,
-    layout_str: &str,
-    layout_generator: &Box<dyn LayoutGenerator>,
-    fixed_characters: &str,
-    start_with_layout: bool,
-    cache_results: bool,
-) -> (MySimulator, LayoutPermutator) {
-    let pm = LayoutPermutator::new(layout_str, fixed_characters);
-    let initial_population: Population<Genotype> = if start_with_layout {
-        build_population()
-            .with_genome_builder(FromGivenLayoutBuilder::with_permutable_layout(&pm))
-            .of_size(params.population_size)
-            .uniform_at_random()
-    } else {
-        build_population()
-            .with_genome_builder(LayoutBuilder::with_permutable_layout(&pm))
-            .of_size(params.population_size)
-            .uniform_at_random()
-    };
+/// State of a genetic optimization run, from which the run can be resumed.
+///
+/// As the offspring bred within a generation is not accessible, the population at the start of the
+/// generation is stored and bred anew when continuing. Taking a checkpoint does not affect the run.
+/// The random decisions of a resumed run are derived from the seed and the checkpoint's generation,
+/// i.e. they differ from those of the uninterrupted run.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct GeneticCheckpoint {
+    seed: u64,
+    generation: u64,
+    population: Vec<Genotype>,
+    all_time_best: Option<(usize, Genotype)>,
+}
 
-    let result_cache = if cache_results {
-        Some(Cache::new())
-    } else {
-        None
-    };
+impl GeneticCheckpoint {
+    /// The generation at which the checkpoint was taken.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
 
-    let sim = simulate(
+fn build_simulator(
+    params: &Parameters,
+    fitness_calc: FitnessCalc,
+    pm: &LayoutPermutator,
+    initial_population: Population<Genotype>,
+    generation_limit: u64,
+    seed: Seed,
+) -> MySimulator {
+    simulate(
         genetic_algorithm()
-            .with_evaluation(FitnessCalc {
-                evaluator: Arc::new(evaluator.clone()),
-                permutator: pm.clone(),
-                layout_generator: layout_generator.clone(),
-                result_cache,
-            })
+            .with_evaluation(fitness_calc)
             .with_selection(MaximizeSelector::new(
                 params.selection_ratio,
                 params.num_individuals_per_parents,
@@ -264,17 +351,124 @@ pub fn init_optimization(
             // .with_crossover(PartiallyMappedCrossover::new())
             // .with_crossover(MyCrossover::new())
             .with_crossover(NoOpCrossover::new())
-            .with_mutation(SwapOrderMutator::new(params.mutation_rate))
+            .with_mutation(SegmentSwapMutator::new(params.mutation_rate, pm))
             .with_reinsertion(UniformReinserter::new(params.reinsertion_ratio))
             .with_initial_population(initial_population)
             .build(),
     )
-    .until(GenerationLimit::new(params.generation_limit))
-    .build();
+    .until(GenerationLimit::new(generation_limit))
+    .build_with_seed(seed)
+}
+
+/// Builds a simulator for a new run, deriving all random decisions from `rng`.
+fn start_simulator(
+    params: &Parameters,
+    fitness_calc: FitnessCalc,
+    pm: &LayoutPermutator,
+    start_with_layout: bool,
+    rng: &mut Prng,
+) -> MySimulator {
+    let initial_population: Population<Genotype> = if start_with_layout {
+        build_population()
+            .with_genome_builder(FromGivenLayoutBuilder::with_permutable_layout(pm))
+            .of_size(params.population_size)
+            .using_seed(rng.gen())
+    } else {
+        build_population()
+            .with_genome_builder(LayoutBuilder::with_permutable_layout(pm))
+            .of_size(params.population_size)
+            .using_seed(rng.gen())
+    };
+
+    build_simulator(
+        params,
+        fitness_calc,
+        pm,
+        initial_population,
+        params.generation_limit,
+        rng.gen(),
+    )
+}
+
+/// Builds a simulator that continues a run from a checkpoint. Its first generation corresponds to
+/// the checkpoint's generation.
+fn resume_simulator(
+    params: &Parameters,
+    fitness_calc: FitnessCalc,
+    pm: &LayoutPermutator,
+    checkpoint: &GeneticCheckpoint,
+) -> MySimulator {
+    let mut rng = Prng::seed_from_u64(
+        checkpoint.seed ^ checkpoint.generation.wrapping_mul(0x9E37_79B9_7F4A_7C15),
+    );
+
+    build_simulator(
+        params,
+        fitness_calc,
+        pm,
+        Population::with_individuals(checkpoint.population.clone()),
+        params
+            .generation_limit
+            .saturating_sub(checkpoint.generation - 1),
+        rng.gen(),
+    )
+}
+
+/// The permutator for the given layout (restricted to canonical layouts if configured).
+pub(crate) fn init_permutator(
+    params: &Parameters,
+    layout_str: &str,
+    layout_generator: &dyn LayoutGenerator,
+    fixed_characters: &str,
+) -> LayoutPermutator {
+    let pm = LayoutPermutator::with_segments(
+        layout_str,
+        fixed_characters,
+        &layout_generator.permutation_segments(),
+    );
+    match params.only_canonical_layouts {
+        true => pm.with_symmetry(&layout_generator.symmetry()),
+        false => pm,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_optimization(
+    params: &Parameters,
+    evaluator: &Evaluator,
+    layout_str: &str,
+    layout_generator: &Box<dyn LayoutGenerator>,
+    fixed_characters: &str,
+    start_with_layout: bool,
+    cache_results: bool,
+    seed: Option<u64>,
+) -> (MySimulator, LayoutPermutator) {
+    // derive the seeds for the initial population and the simulation
+    let mut rng = match seed {
+        Some(seed) => Prng::seed_from_u64(seed),
+        None => get_rng(random_seed()),
+    };
+
+    let pm = init_permutator(
+        params,
+        layout_str,
+        layout_generator.as_ref(),
+        fixed_characters,
+    );
+    let fitness_calc = FitnessCalc::new(evaluator, &pm, layout_generator.clone(), cache_results);
+    let sim = start_simulator(params, fitness_calc, &pm, start_with_layout, &mut rng);
 
     (sim, pm)
 }
 
+/// Performs one run of the genetic algorithm, then returns the best layout found.
+///
+/// All random decisions are derived from `seed`, such that runs with the same seed (and configuration)
+/// yield the same result. Without a seed, a random one is used.
+///
+/// With `checkpointing`, the state of the run is stored periodically. If it contains a checkpoint to
+/// resume from, the run continues from there (and `seed` is ignored).
+#[allow(clippy::too_many_arguments)]
 pub fn optimize(
     params: &Parameters,
     evaluator: &Evaluator,
@@ -283,24 +477,63 @@ pub fn optimize(
     fixed_characters: &str,
     start_with_layout: bool,
     cache_results: bool,
+    seed: Option<u64>,
+    checkpointing: Option<Checkpointing<GeneticCheckpoint>>,
 ) -> (String, Layout) {
-    let (mut sim, pm) = init_optimization(
+    let pm = init_permutator(
         params,
-        evaluator,
         layout_str,
-        layout_generator,
+        layout_generator.as_ref(),
         fixed_characters,
-        start_with_layout,
-        cache_results,
     );
+    let fitness_calc = FitnessCalc::new(evaluator, &pm, layout_generator.clone(), cache_results);
 
-    log::info!("Starting optimization with: {:?}", params);
+    let resume_from = checkpointing.as_ref().and_then(|c| c.resume_from.as_ref());
+    let seed = match resume_from {
+        Some(checkpoint) => checkpoint.seed,
+        None => seed.unwrap_or_else(|| get_rng(random_seed()).gen()),
+    };
+
+    // Generations performed before the simulator of this run was started (when resuming)
+    let mut generation_offset = 0;
+    let mut last_generation = 0;
     let mut all_time_best: Option<(usize, Genotype)> = None;
+    let mut sim = match resume_from {
+        Some(checkpoint) => {
+            log::info!(
+                "Resuming optimization at generation {} with: {:?}",
+                checkpoint.generation,
+                params
+            );
+            generation_offset = checkpoint.generation - 1;
+            last_generation = checkpoint.generation;
+            all_time_best = checkpoint.all_time_best.clone();
+            resume_simulator(params, fitness_calc.clone(), &pm, checkpoint)
+        }
+        None => {
+            log::info!("Starting optimization with: {:?}", params);
+            let mut rng = Prng::seed_from_u64(seed);
+            start_simulator(
+                params,
+                fitness_calc.clone(),
+                &pm,
+                start_with_layout,
+                &mut rng,
+            )
+        }
+    };
 
     loop {
         let result = sim.step();
         match result {
             Ok(SimResult::Intermediate(step)) => {
+                let generation = generation_offset + step.iteration;
+                if generation <= last_generation {
+                    // the checkpoint's generation has already been processed before resuming
+                    continue;
+                }
+                last_generation = generation;
+
                 let evaluated_population = step.result.evaluated_population;
                 let best_solution = step.result.best_solution;
                 if let Some(king) = &all_time_best {
@@ -313,7 +546,7 @@ pub fn optimize(
                             "{}: {} (score: {})\n{}",
                             format!(
                                 "New best in generation {} (pop: {}):",
-                                step.iteration,
+                                generation,
                                 evaluated_population.individuals().len()
                             )
                             .yellow()
@@ -337,7 +570,7 @@ pub fn optimize(
                 log::info!(
                     "{}, average_fitness: {}, \
                      best fitness: {}, all time best: {}, duration: {}, processing_time: {}, generation's best: {}",
-                    format!("Generation {}:", step.iteration).yellow().bold(),
+                    format!("Generation {}:", generation).yellow().bold(),
                     evaluated_population.average_fitness(),
                     best_solution.solution.fitness,
                     all_time_best.as_ref().unwrap().0,
@@ -345,6 +578,18 @@ pub fn optimize(
                     step.processing_time.fmt(),
                     pm.generate_string(&best_solution.solution.genome)
                 );
+
+                if let Some(checkpointing) = &checkpointing {
+                    if generation % checkpointing.interval == 0 {
+                        let checkpoint = GeneticCheckpoint {
+                            seed,
+                            generation,
+                            population: evaluated_population.individuals().to_vec(),
+                            all_time_best: all_time_best.clone(),
+                        };
+                        (checkpointing.store)(&checkpoint);
+                    }
+                }
             }
             Ok(SimResult::Final(step, processing_time, duration, _stop_reason)) => {
                 let layout_str = pm.generate_string(&all_time_best.as_ref().unwrap().1);
@@ -352,7 +597,7 @@ pub fn optimize(
                 println!(
                     "{} after generation {}, duration {}, processing time {}\n\n{}\n\n{}\n{}",
                     "Final result".green().bold(),
-                    step.iteration,
+                    generation_offset + step.iteration,
                     duration.fmt(),
                     processing_time.fmt(),
                     layout_str,
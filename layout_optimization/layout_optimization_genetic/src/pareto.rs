@@ -0,0 +1,348 @@
+//! Multi-objective optimization with the NSGA-II algorithm (Deb et al., 2002).
+//!
+//! Instead of a single total cost, each layout is rated by the costs of several [`Objectives`].
+//! The population is ranked by Pareto dominance and crowding distance, such that the optimization
+//! yields a front of non-dominated layouts representing different trade-offs between the objectives.
+
+use crate::optimization::{
+    init_permutator, FromGivenLayoutBuilder, Genotype, LayoutBuilder, MyCrossover, Parameters,
+    SegmentSwapMutator,
+};
+
+use keyboard_layout::{
+    layout::Layout, layout_generator::LayoutGenerator, layout_symmetry::LayoutSymmetry,
+};
+use layout_evaluation::{
+    cache::Cache,
+    evaluation::{EvaluationState, Evaluator},
+    objectives::Objectives,
+};
+use layout_optimization_common::LayoutPermutator;
+
+use ahash::AHashSet;
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use genevo::{
+    operator::{CrossoverOp, MutationOp},
+    population::Population,
+    prelude::*,
+    random::{get_rng, random_seed, Prng, SeedableRng},
+};
+
+/// A non-dominated layout with the costs of all objectives.
+#[derive(Clone, Serialize, Debug)]
+pub struct ParetoSolution {
+    pub layout: String,
+    pub costs: Vec<f64>,
+}
+
+/// Computes the objective costs of [`Genotype`]s.
+struct ObjectivesCalc {
+    evaluator: Arc<Evaluator>,
+    objectives: Objectives,
+    permutator: LayoutPermutator,
+    layout_generator: Box<dyn LayoutGenerator>,
+    result_cache: Option<Cache<Vec<f64>>>,
+    /// Evaluation states of previously evaluated layouts (one per concurrent evaluation).
+    evaluation_states: Mutex<Vec<EvaluationState>>,
+}
+
+impl ObjectivesCalc {
+    fn evaluate(&self, layout: &Layout) -> Vec<f64> {
+        let stored_state = self.evaluation_states.lock().unwrap().pop();
+        let state = match stored_state {
+            Some(mut state) => {
+                self.evaluator.update_state(&mut state, layout);
+                state
+            }
+            None => self.evaluator.evaluation_state(layout),
+        };
+        let costs = self.objectives.costs(&state.result());
+        self.evaluation_states.lock().unwrap().push(state);
+
+        costs
+    }
+
+    fn costs_of(&self, genome: &Genotype) -> Vec<f64> {
+        let layout_str = self.permutator.generate_string(genome);
+        let l = self.layout_generator.generate(&layout_str).unwrap();
+
+        match &self.result_cache {
+            Some(result_cache) => {
+                result_cache.get_or_insert_with(&layout_str, || self.evaluate(&l))
+            }
+            None => self.evaluate(&l),
+        }
+    }
+}
+
+/// An evaluated member of the population.
+struct Individual {
+    genome: Genotype,
+    costs: Vec<f64>,
+    /// Index of the individual's front (0 is the non-dominated front)
+    rank: usize,
+    crowding_distance: f64,
+}
+
+impl Individual {
+    /// The crowded-comparison operator: prefer lower ranks, then less crowded regions.
+    fn is_better_than(&self, other: &Individual) -> bool {
+        self.rank < other.rank
+            || (self.rank == other.rank && self.crowding_distance > other.crowding_distance)
+    }
+}
+
+/// Whether costs `a` dominate costs `b`, i.e. are nowhere worse and better in at least one objective.
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y)
+}
+
+/// Sorts the indices of the given costs into fronts. No member of a front is dominated by a member of
+/// the same or a later front.
+fn non_dominated_fronts(costs: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    let n = costs.len();
+    let mut dominated_by: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut n_dominating = vec![0usize; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            if dominates(&costs[i], &costs[j]) {
+                dominated_by[i].push(j);
+                n_dominating[j] += 1;
+            } else if dominates(&costs[j], &costs[i]) {
+                dominated_by[j].push(i);
+                n_dominating[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..n).filter(|i| n_dominating[*i] == 0).collect();
+    while !front.is_empty() {
+        let mut next_front = Vec::new();
+        for i in front.iter() {
+            for j in dominated_by[*i].iter() {
+                n_dominating[*j] -= 1;
+                if n_dominating[*j] == 0 {
+                    next_front.push(*j);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next_front;
+    }
+
+    fronts
+}
+
+/// Computes the crowding distance of each member of a front, i.e. how far its neighbors in the
+/// front are apart (summed over all objectives). The extreme members get an infinite distance.
+fn crowding_distances(front: &[usize], costs: &[Vec<f64>]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    if front.is_empty() {
+        return distances;
+    }
+
+    let n_objectives = costs[front[0]].len();
+    let mut order: Vec<usize> = (0..front.len()).collect();
+    let values_per_objective: Vec<Vec<f64>> = (0..n_objectives)
+        .map(|m| front.iter().map(|i| costs[*i][m]).collect())
+        .collect();
+    for values in values_per_objective.iter() {
+        order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+        let first = order[0];
+        let last = order[order.len() - 1];
+        distances[first] = f64::INFINITY;
+        distances[last] = f64::INFINITY;
+
+        let range = values[last] - values[first];
+        if range > 0.0 {
+            for k in 1..order.len().saturating_sub(1) {
+                distances[order[k]] += (values[order[k + 1]] - values[order[k - 1]]) / range;
+            }
+        }
+    }
+
+    distances
+}
+
+/// Selects the best `size` candidates by rank and crowding distance (the NSGA-II environmental selection).
+fn select_survivors(candidates: Vec<(Genotype, Vec<f64>)>, size: usize) -> Vec<Individual> {
+    let costs: Vec<Vec<f64>> = candidates.iter().map(|(_, c)| c.clone()).collect();
+    let mut candidates: Vec<Option<(Genotype, Vec<f64>)>> =
+        candidates.into_iter().map(Some).collect();
+
+    let mut survivors = Vec::with_capacity(size);
+    for (rank, front) in non_dominated_fronts(&costs).into_iter().enumerate() {
+        let distances = crowding_distances(&front, &costs);
+        let mut front: Vec<(usize, f64)> = front.into_iter().zip(distances).collect();
+        if survivors.len() + front.len() > size {
+            // only keep the least crowded members of the last front
+            front.sort_by(|(_, d1), (_, d2)| d2.total_cmp(d1));
+            front.truncate(size - survivors.len());
+        }
+
+        for (i, crowding_distance) in front {
+            let (genome, costs) = candidates[i].take().unwrap();
+            survivors.push(Individual {
+                genome,
+                costs,
+                rank,
+                crowding_distance,
+            });
+        }
+
+        if survivors.len() >= size {
+            break;
+        }
+    }
+
+    survivors
+}
+
+/// Binary tournament selection using the crowded-comparison operator.
+fn tournament<'a>(population: &'a [Individual], rng: &mut Prng) -> &'a Individual {
+    let a = &population[rng.gen_range(0..population.len())];
+    let b = &population[rng.gen_range(0..population.len())];
+    if b.is_better_than(a) {
+        b
+    } else {
+        a
+    }
+}
+
+/// Performs one run of the NSGA-II algorithm, then returns the non-dominated layouts (ordered by the
+/// cost of the first objective).
+///
+/// Of the [`Parameters`], only `population_size`, `generation_limit`, `mutation_rate` and
+/// `only_canonical_layouts` are used. If a `symmetry` is given, a layout and its mirror image are
+/// considered identical, i.e. only one of them is kept in the population and the front. As in [`crate::optimization::optimize`], all random decisions are derived from `seed`.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize(
+    params: &Parameters,
+    evaluator: &Evaluator,
+    objectives: &Objectives,
+    layout_str: &str,
+    layout_generator: &dyn LayoutGenerator,
+    fixed_characters: &str,
+    start_with_layout: bool,
+    cache_results: bool,
+    symmetry: Option<&LayoutSymmetry>,
+    seed: Option<u64>,
+) -> Vec<ParetoSolution> {
+    let mut rng = match seed {
+        Some(seed) => Prng::seed_from_u64(seed),
+        None => get_rng(random_seed()),
+    };
+
+    let pm = init_permutator(params, layout_str, layout_generator, fixed_characters);
+    let canonical_string = |genome: &Genotype| {
+        let layout_str = pm.generate_string(genome);
+        match symmetry {
+            Some(symmetry) => symmetry.canonicalize(&layout_str),
+            None => layout_str,
+        }
+    };
+    let initial_population: Population<Genotype> = if start_with_layout {
+        build_population()
+            .with_genome_builder(FromGivenLayoutBuilder::with_permutable_layout(&pm))
+            .of_size(params.population_size)
+            .using_seed(rng.gen())
+    } else {
+        build_population()
+            .with_genome_builder(LayoutBuilder::with_permutable_layout(&pm))
+            .of_size(params.population_size)
+            .using_seed(rng.gen())
+    };
+
+    let calc = ObjectivesCalc {
+        evaluator: Arc::new(evaluator.clone()),
+        objectives: objectives.clone(),
+        permutator: pm.clone(),
+        layout_generator: layout_generator.clone_box(),
+        result_cache: cache_results.then(|| match symmetry {
+            Some(symmetry) => Cache::new().with_symmetry(symmetry.clone()),
+            None => Cache::new(),
+        }),
+        evaluation_states: Mutex::new(Vec::new()),
+    };
+    let crossover = MyCrossover::new();
+    let mutator = SegmentSwapMutator::new(params.mutation_rate, &pm);
+    let names = objectives.names();
+
+    log::info!(
+        "Starting multi-objective optimization of {} with: {:?}",
+        names.join(", "),
+        params
+    );
+
+    let candidates: Vec<(Genotype, Vec<f64>)> = initial_population
+        .individuals()
+        .par_iter()
+        .map(|genome| (genome.clone(), calc.costs_of(genome)))
+        .collect();
+    let mut population = select_survivors(candidates, params.population_size);
+
+    for generation in 1..=params.generation_limit {
+        let mut offspring: Vec<Genotype> = Vec::with_capacity(params.population_size);
+        while offspring.len() < params.population_size {
+            let parents = vec![
+                tournament(&population, &mut rng).genome.clone(),
+                tournament(&population, &mut rng).genome.clone(),
+            ];
+            for child in crossover.crossover(parents, &mut rng) {
+                offspring.push(mutator.mutate(child, &mut rng));
+            }
+        }
+        offspring.truncate(params.population_size);
+
+        let offspring_costs: Vec<Vec<f64>> = offspring
+            .par_iter()
+            .map(|genome| calc.costs_of(genome))
+            .collect();
+
+        // duplicates (and mirror images) would crowd out other non-dominated layouts
+        let mut seen: AHashSet<String> = AHashSet::default();
+        let candidates: Vec<(Genotype, Vec<f64>)> = population
+            .into_iter()
+            .map(|individual| (individual.genome, individual.costs))
+            .chain(offspring.into_iter().zip(offspring_costs))
+            .filter(|(genome, _)| seen.insert(canonical_string(genome)))
+            .collect();
+        population = select_survivors(candidates, params.population_size);
+
+        let front_size = population.iter().filter(|i| i.rank == 0).count();
+        let best_costs: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(m, name)| {
+                let best = population
+                    .iter()
+                    .map(|i| i.costs[m])
+                    .fold(f64::INFINITY, f64::min);
+                format!("{}: {:.2}", name, best)
+            })
+            .collect();
+        log::info!(
+            "{} non-dominated layouts: {}, best costs: {}",
+            format!("Generation {}:", generation).yellow().bold(),
+            front_size,
+            best_costs.join(", "),
+        );
+    }
+
+    let mut solutions: Vec<ParetoSolution> = population
+        .into_iter()
+        .filter(|i| i.rank == 0)
+        .map(|i| ParetoSolution {
+            layout: canonical_string(&i.genome),
+            costs: i.costs,
+        })
+        .collect();
+    solutions.sort_by(|s1, s2| s1.costs[0].total_cmp(&s2.costs[0]));
+
+    solutions
+}
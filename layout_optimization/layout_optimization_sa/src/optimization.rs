@@ -1,7 +1,10 @@
 use keyboard_layout::{layout::Layout, layout_generator::LayoutGenerator};
-use layout_evaluation::{cache::Cache, evaluation::Evaluator};
+use layout_evaluation::{
+    cache::Cache,
+    evaluation::{EvaluationState, Evaluator},
+};
 
-use layout_optimization_common::LayoutPermutator;
+use layout_optimization_common::{Checkpointing, LayoutPermutator};
 
 use anyhow::Result;
 use colored::Colorize;
@@ -9,11 +12,15 @@ use rand::thread_rng;
 use rand::Rng;
 use rand_xoshiro::rand_core::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
-use serde::Deserialize;
-use std::{fs::File, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    sync::{Arc, Mutex},
+};
 
 use argmin::{
     core::{
+        checkpointing::{Checkpoint, CheckpointingFrequency},
         observers::{Observe, ObserverMode},
         CostFunction, Error, Executor, IterState, State, KV,
     },
@@ -35,6 +42,12 @@ pub struct Parameters {
     // Parameters for the [Executor].
     /// Set maximum number of iterations (defaults to `std::u64::MAX`)
     pub max_iters: u64,
+
+    /// Only search one layout of each pair of mirror images (see
+    /// [`keyboard_layout::layout_symmetry::LayoutSymmetry`]). This assumes that all metrics treat
+    /// both hands alike.
+    #[serde(default)]
+    pub only_canonical_layouts: bool,
 }
 
 impl Default for Parameters {
@@ -46,6 +59,7 @@ impl Default for Parameters {
             stall_accepted: 5000,
             // Parameters for the [Executor].
             max_iters: 100_000,
+            only_canonical_layouts: false,
         }
     }
 }
@@ -75,6 +89,11 @@ pub struct AnnealingStruct {
     layout_generator: Box<dyn LayoutGenerator>,
     key_switches: usize,
     result_cache: Option<Cache<f64>>,
+    /// Evaluation state of the most recently evaluated layout. As subsequent layouts only differ
+    /// in a few swapped keys, it can be updated incrementally.
+    evaluation_state: Mutex<Option<EvaluationState>>,
+    /// Random number generator used for modifying layouts. Shared with the [`CheckpointHandler`].
+    rng: Arc<Mutex<Xoshiro256PlusPlus>>,
 }
 
 impl CostFunction for AnnealingStruct {
@@ -85,7 +104,16 @@ impl CostFunction for AnnealingStruct {
     fn cost(&self, param: &Self::Param) -> Result<Self::Output, Error> {
         let evaluate_layout_str = |layout_str: &str| -> f64 {
             let l = self.layout_generator.generate(layout_str).unwrap();
-            self.evaluator.evaluate_layout(&l).total_cost()
+            let mut evaluation_state = self.evaluation_state.lock().unwrap();
+            match evaluation_state.as_mut() {
+                Some(state) => self.evaluator.update_state(state, &l),
+                None => {
+                    let state = self.evaluator.evaluation_state(&l);
+                    let cost = state.total_cost();
+                    *evaluation_state = Some(state);
+                    cost
+                }
+            }
         };
 
         let layout_string = self.permutator.generate_string(param);
@@ -106,11 +134,61 @@ impl Anneal for AnnealingStruct {
 
     /// Anneal a parameter vector, slightly changing it.
     fn anneal(&self, param: &Self::Param, _temp: f64) -> Result<Self::Output, Error> {
-        Ok(self.permutator.perform_n_swaps(param, self.key_switches))
+        let mut rng = self.rng.lock().unwrap();
+        Ok(self
+            .permutator
+            .perform_n_swaps(param, self.key_switches, &mut *rng))
     }
 }
 
 pub type SaIterState = IterState<Vec<usize>, (), (), (), (), f64>;
+pub type SaSolver = SimulatedAnnealing<f64, Xoshiro256PlusPlus>;
+
+/// State of a Simulated Annealing run (current layout, temperature, iteration, random number generators),
+/// from which the run can be resumed exactly where it stopped.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaCheckpoint {
+    solver: SaSolver,
+    state: SaIterState,
+    rng: Xoshiro256PlusPlus,
+}
+
+impl SaCheckpoint {
+    /// The number of iterations performed before the checkpoint was taken.
+    pub fn iteration(&self) -> u64 {
+        self.state.iter
+    }
+}
+
+/// Connects [`Checkpointing`] to `Argmin`'s checkpointing mechanism.
+struct CheckpointHandler {
+    checkpointing: Checkpointing<SaCheckpoint>,
+    rng: Arc<Mutex<Xoshiro256PlusPlus>>,
+}
+
+impl Checkpoint<SaSolver, SaIterState> for CheckpointHandler {
+    fn save(&self, solver: &SaSolver, state: &SaIterState) -> Result<(), Error> {
+        let checkpoint = SaCheckpoint {
+            solver: solver.clone(),
+            state: state.clone(),
+            rng: self.rng.lock().unwrap().clone(),
+        };
+        (self.checkpointing.store)(&checkpoint);
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Option<(SaSolver, SaIterState)>, Error> {
+        Ok(self
+            .checkpointing
+            .resume_from
+            .as_ref()
+            .map(|c| (c.solver.clone(), c.state.clone())))
+    }
+
+    fn frequency(&self) -> CheckpointingFrequency {
+        CheckpointingFrequency::Every(self.checkpointing.interval)
+    }
+}
 
 /// An observer that outputs important information in a more human-readable format than `Argmin`'s original implementation.
 struct BestObserver {
@@ -233,6 +311,7 @@ fn get_cost_sd(
     permutator: &LayoutPermutator,
     layout_generator: &Box<dyn LayoutGenerator>,
     key_pair_switches: usize,
+    rng: &mut Xoshiro256PlusPlus,
 ) -> f64 {
     const USED_NEIGHBORS: u16 = 100;
 
@@ -247,7 +326,7 @@ fn get_cost_sd(
             .unwrap();
         let evaluation_result = evaluator.evaluate_layout(&layout);
         costs.push(evaluation_result.total_cost());
-        current_indices = permutator.perform_n_swaps(&current_indices, key_pair_switches);
+        current_indices = permutator.perform_n_swaps(&current_indices, key_pair_switches, rng);
     }
     let average: f64 = mean(&costs);
 
@@ -261,6 +340,12 @@ fn get_cost_sd(
 }
 
 /// Performs one run of Simulated Annealing, then returns the best layout found.
+///
+/// All random decisions are derived from `seed`, such that runs with the same seed (and configuration)
+/// yield the same result. Without a seed, a random one is used.
+///
+/// With `checkpointing`, the state of the run is stored periodically. If it contains a checkpoint to
+/// resume from, the run continues from there (and `seed` is ignored).
 #[allow(clippy::too_many_arguments)]
 pub fn optimize(
     process_name: &str,
@@ -273,12 +358,26 @@ pub fn optimize(
     log_everything: bool,
     result_cache: Option<Cache<f64>>,
     custom_observer: Option<CustomObserver>,
+    seed: Option<u64>,
+    checkpointing: Option<Checkpointing<SaCheckpoint>>,
 ) -> (String, Layout) {
-    let pm = LayoutPermutator::new(layout_str, fixed_characters);
+    let mut rng = match seed {
+        Some(seed) => Xoshiro256PlusPlus::seed_from_u64(seed),
+        None => Xoshiro256PlusPlus::from_seed(thread_rng().gen()),
+    };
+
+    let mut pm = LayoutPermutator::with_segments(
+        layout_str,
+        fixed_characters,
+        &layout_generator.permutation_segments(),
+    );
+    if params.only_canonical_layouts {
+        pm = pm.with_symmetry(&layout_generator.symmetry());
+    }
     // Get initial Layout.
     let initial_indices = match start_with_layout {
         true => pm.get_permutable_indices(),
-        false => pm.generate_random(),
+        false => pm.generate_random(&mut rng),
     };
 
     /* // Test 10_000 Layouts to get a good default initial temperature.
@@ -292,49 +391,78 @@ pub fn optimize(
     }
     println!("Average init_temp: {}", mean(&init_temp_vec)); */
 
-    let init_temp = match params.init_temp {
-        Some(t) => t,
-        None => {
+    let resume_from = checkpointing.as_ref().and_then(|c| c.resume_from.as_ref());
+    // Create new SA solver with some parameters (see docs for details)
+    // This essentially just prepares the SA solver. It is not run yet, nor does it know anything about the problem it is about to solve.
+    // When resuming, the solver (including its temperature) is restored from the checkpoint instead.
+    let (solver, anneal_rng) = match resume_from {
+        Some(checkpoint) => {
             log::info!(
-                "{} Calculating initial temperature",
+                "{} Resuming optimization at iteration {} with: {:?}",
                 format!("{}:", process_name).yellow().bold(),
+                checkpoint.iteration(),
+                params,
             );
-            let init_temp = get_cost_sd(
-                &initial_indices,
-                Arc::new(evaluator.clone()),
-                &pm,
-                layout_generator,
-                params.key_switches,
-            );
+            (checkpoint.solver.clone(), checkpoint.rng.clone())
+        }
+        None => {
+            let init_temp = match params.init_temp {
+                Some(t) => t,
+                None => {
+                    log::info!(
+                        "{} Calculating initial temperature",
+                        format!("{}:", process_name).yellow().bold(),
+                    );
+                    let init_temp = get_cost_sd(
+                        &initial_indices,
+                        Arc::new(evaluator.clone()),
+                        &pm,
+                        layout_generator,
+                        params.key_switches,
+                        &mut rng,
+                    );
+                    log::info!(
+                        "{} Initial temperature = {}°",
+                        format!("{}:", process_name).yellow().bold(),
+                        init_temp,
+                    );
+                    init_temp
+                }
+            };
             log::info!(
-                "{} Initial temperature = {}°",
+                "{} Starting optimization with: initial_temperature: {:.2}°, {:?}",
                 format!("{}:", process_name).yellow().bold(),
                 init_temp,
+                params,
             );
-            init_temp
+
+            let anneal_rng = Xoshiro256PlusPlus::from_seed(rng.gen());
+            let solver_rng = Xoshiro256PlusPlus::from_seed(rng.gen());
+            let solver = SimulatedAnnealing::new_with_rng(init_temp, solver_rng)
+                .unwrap()
+                // Optional: Define temperature function (defaults to `SATempFunc::TemperatureFast`)
+                .with_temp_func(SATempFunc::TemperatureFast)
+                /////////////////////////
+                // Stopping criteria   //
+                /////////////////////////
+                // Optional: stop if there was no accepted solution after [params.stall_accepted] iterations
+                .with_stall_accepted(params.stall_accepted);
+
+            (solver, anneal_rng)
         }
     };
+    let anneal_rng = Arc::new(Mutex::new(anneal_rng));
+
     let problem = AnnealingStruct {
         evaluator: Arc::new(evaluator.clone()),
         permutator: pm.clone(),
         layout_generator: layout_generator.clone(),
         key_switches: params.key_switches,
         result_cache,
+        evaluation_state: Mutex::new(None),
+        rng: anneal_rng.clone(),
     };
 
-    // Create new SA solver with some parameters (see docs for details)
-    // This essentially just prepares the SA solver. It is not run yet, nor does it know anything about the problem it is about to solve.
-    let rng = Xoshiro256PlusPlus::from_seed(thread_rng().gen());
-    let solver = SimulatedAnnealing::new_with_rng(init_temp, rng)
-        .unwrap()
-        // Optional: Define temperature function (defaults to `SATempFunc::TemperatureFast`)
-        .with_temp_func(SATempFunc::TemperatureFast)
-        /////////////////////////
-        // Stopping criteria   //
-        /////////////////////////
-        // Optional: stop if there was no accepted solution after [params.stall_accepted] iterations
-        .with_stall_accepted(params.stall_accepted);
-
     // Create and run the executor, which will apply the solver to the problem, given a starting point (`init_param`)
     let mut executor = Executor::new(problem, solver)
         .configure(|state| {
@@ -373,12 +501,13 @@ pub fn optimize(
         }
     }
 
-    log::info!(
-        "{} Starting optimization with: initial_temperature: {:.2}°, {:?}",
-        format!("{}:", process_name).yellow().bold(),
-        init_temp,
-        params,
-    );
+    if let Some(checkpointing) = checkpointing {
+        executor = executor.checkpointing(CheckpointHandler {
+            checkpointing,
+            rng: anneal_rng,
+        });
+    }
+
     let res = executor.run().unwrap();
 
     let best_layout_param = res.state().get_best_param().unwrap();
@@ -0,0 +1,206 @@
+use ahash::AHashMap;
+use clap::Parser;
+
+use keyboard_layout::key::Finger;
+use layout_evaluation::sval::{self, DirectionMatrix, TransitionDirection};
+
+use std::{fs, str::FromStr};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Svalboard direction factor calibration")]
+/// Derive the `sval_direction_factors` matrices of the evaluation config from measured
+/// same-finger transition times
+///
+/// Transitions that have not been measured keep their default factor, rescaled to the scale of
+/// the measured ones (the ratio of the sums of measured and default factors of the same finger).
+struct Options {
+    /// CSV file with lines `finger,from,to,ms` (e.g. `index,center,south,142`); a header line is optional.
+    /// Repeated measurements of a transition are averaged.
+    measurements: String,
+
+    /// Transition time (ms) corresponding to a factor of zero (default: fastest transition)
+    #[clap(long)]
+    baseline_ms: Option<f64>,
+
+    /// Transition time (ms) corresponding to a factor of one (default: mean of all transitions)
+    #[clap(long)]
+    reference_ms: Option<f64>,
+
+    /// Write the matrices to this file instead of stdout
+    #[clap(short, long)]
+    out: Option<String>,
+}
+
+/// Factors derived from measurements as (row, column, factor)
+type MeasuredFactors = Vec<(usize, usize, f64)>;
+
+const FINGERS: [Finger; 4] = [Finger::Index, Finger::Middle, Finger::Ring, Finger::Pinky];
+
+fn parse_finger(s: &str) -> Option<Finger> {
+    match s.trim().to_lowercase().as_str() {
+        "thumb" => Some(Finger::Thumb),
+        "index" => Some(Finger::Index),
+        "middle" => Some(Finger::Middle),
+        "ring" => Some(Finger::Ring),
+        "pinky" => Some(Finger::Pinky),
+        _ => None,
+    }
+}
+
+/// Read the measured times and average them per transition
+fn read_measurements(
+    filename: &str,
+) -> AHashMap<(Finger, TransitionDirection, TransitionDirection), f64> {
+    let content = fs::read_to_string(filename)
+        .unwrap_or_else(|_| panic!("Could not read measurements from '{}'", filename));
+
+    let mut sums: AHashMap<(Finger, TransitionDirection, TransitionDirection), (f64, usize)> =
+        AHashMap::default();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let parsed = match fields.as_slice() {
+            [finger, from, to, ms] => parse_finger(finger).and_then(|finger| {
+                Some((
+                    finger,
+                    TransitionDirection::from_str(from).ok()?,
+                    TransitionDirection::from_str(to).ok()?,
+                    f64::from_str(ms).ok()?,
+                ))
+            }),
+            _ => None,
+        };
+        match parsed {
+            Some((Finger::Thumb, ..)) => {
+                log::warn!("Line {}: thumbs have no direction factors; skipping", i + 1)
+            }
+            Some((finger, from, to, ms)) => {
+                let entry = sums.entry((finger, from, to)).or_default();
+                entry.0 += ms;
+                entry.1 += 1;
+            }
+            // the first line may be a header
+            None if i == 0 => log::debug!("Skipping header '{}'", line),
+            None => panic!("Invalid measurement in line {}: '{}'", i + 1, line),
+        }
+    }
+
+    sums.into_iter()
+        .map(|(transition, (sum, n))| (transition, sum / n as f64))
+        .collect()
+}
+
+/// Scale of measured factors relative to the defaults of the same transitions, given as pairs of
+/// (measured, default) factors (`None` if nothing comparable has been measured)
+fn measured_scale(factors: impl Iterator<Item = (f64, f64)>) -> Option<f64> {
+    let (measured_sum, default_sum) =
+        factors.fold((0.0, 0.0), |(m_sum, d_sum), (m, d)| (m_sum + m, d_sum + d));
+    if default_sum > 0.0 {
+        Some(measured_sum / default_sum)
+    } else {
+        None
+    }
+}
+
+fn format_matrices(matrices: &[(Finger, DirectionMatrix)]) -> String {
+    let mut res = "  sval_direction_factors:\n".to_string();
+    for (finger, matrix) in matrices {
+        res.push_str(&format!("    {:?}:\n", finger));
+        for (from, row) in TransitionDirection::ALL.iter().zip(matrix.iter()) {
+            let row: Vec<String> = row.iter().map(|f| format!("{:.2}", f)).collect();
+            let from = format!("{:?}", from).to_lowercase();
+            res.push_str(&format!("      - [{}]  # from {}\n", row.join(", "), from));
+        }
+    }
+
+    res
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    let options = Options::parse();
+    env_logger::init();
+
+    let times = read_measurements(&options.measurements);
+    if times.is_empty() {
+        panic!("No measurements found in '{}'", options.measurements);
+    }
+
+    let baseline = options
+        .baseline_ms
+        .unwrap_or_else(|| times.values().cloned().fold(f64::INFINITY, f64::min));
+    let reference = options
+        .reference_ms
+        .unwrap_or_else(|| times.values().sum::<f64>() / times.len() as f64);
+    if reference <= baseline {
+        panic!(
+            "Reference time ({} ms) needs to be larger than baseline time ({} ms)",
+            reference, baseline
+        );
+    }
+    log::info!(
+        "Mapping {:.1} ms to a factor of 0 and {:.1} ms to a factor of 1",
+        baseline,
+        reference
+    );
+
+    let factor = |ms: f64| f64::max(0.0, (ms - baseline) / (reference - baseline));
+    let measured: Vec<(Finger, MeasuredFactors)> = FINGERS
+        .iter()
+        .map(|finger| {
+            let mut measured = Vec::new();
+            for from in TransitionDirection::ALL {
+                for to in TransitionDirection::ALL {
+                    if let Some(ms) = times.get(&(*finger, from, to)) {
+                        measured.push((from as usize, to as usize, factor(*ms)));
+                    }
+                }
+            }
+            (*finger, measured)
+        })
+        .collect();
+
+    // fingers without (comparable) measurements use the scale of all measurements
+    let overall_scale = measured_scale(measured.iter().flat_map(|(finger, m)| {
+        let default = sval::default_factors(*finger);
+        m.iter().map(move |(from, to, f)| (*f, default[*from][*to]))
+    }))
+    .unwrap_or(1.0);
+
+    let matrices: Vec<(Finger, DirectionMatrix)> = measured
+        .iter()
+        .map(|(finger, measured)| {
+            let default = sval::default_factors(*finger);
+            let scale =
+                measured_scale(measured.iter().map(|(from, to, f)| (*f, default[*from][*to])))
+                    .unwrap_or(overall_scale);
+            let n_unmeasured = 25 - measured.len();
+            if n_unmeasured > 0 {
+                log::warn!(
+                    "{:?}: {} transitions have not been measured; using their defaults scaled by {:.2}",
+                    finger,
+                    n_unmeasured,
+                    scale
+                );
+            }
+
+            let mut matrix = default.map(|row| row.map(|f| f * scale));
+            for (from, to, f) in measured {
+                matrix[*from][*to] = *f;
+            }
+            (*finger, matrix)
+        })
+        .collect();
+
+    let res = format_matrices(&matrices);
+    match &options.out {
+        Some(filename) => {
+            fs::write(filename, res).expect("Could not write matrices");
+            log::info!("Wrote matrices to {}", filename);
+        }
+        None => print!("{}", res),
+    }
+}
@@ -0,0 +1,96 @@
+use clap::Parser;
+
+use svalboar::common;
+
+use ahash::AHashSet;
+use std::fs;
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout deduplication")]
+/// Remove duplicate layouts from a file with one layout string per line (e.g. as written by
+/// `--append-solutions-to`), keeping the first occurrence of each layout
+struct Options {
+    /// File with one layout string per line
+    filename: String,
+
+    /// Write the remaining layouts to this file instead of replacing the given one
+    #[clap(short, long)]
+    out: Option<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Also consider a layout and its mirror image (symbols of keys with identical symmetry index
+    /// swapped between hands) as duplicates
+    #[clap(long)]
+    mirror_symmetric: bool,
+
+    /// Filename of layout configuration file defining the mirrored keys
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Interpred layout strings using the "grouped" logic
+    #[clap(long)]
+    grouped_layout_generator: bool,
+
+    /// Interpred layout strings as (key, layer) slots
+    #[clap(long, conflicts_with = "grouped_layout_generator")]
+    slot_layout_generator: bool,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    let symmetry = options.mirror_symmetric.then(|| {
+        common::init_layout_generator(
+            &options.layout_config,
+            options.grouped_layout_generator,
+            options.slot_layout_generator,
+        )
+        .symmetry()
+    });
+
+    let content = fs::read_to_string(&options.filename)
+        .unwrap_or_else(|e| panic!("Could not read layouts from {}: {}", options.filename, e));
+
+    let mut seen: AHashSet<String> = AHashSet::default();
+    let mut n_layouts = 0;
+    let mut unique_lines = Vec::new();
+    for line in content.lines() {
+        let layout_str: String = line
+            .chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect();
+        if layout_str.is_empty() {
+            continue;
+        }
+        n_layouts += 1;
+
+        let key = match &symmetry {
+            Some(symmetry) => symmetry.canonicalize(&layout_str),
+            None => layout_str,
+        };
+        if seen.insert(key) {
+            unique_lines.push(line);
+        }
+    }
+
+    let out = options.out.as_ref().unwrap_or(&options.filename);
+    if n_layouts == unique_lines.len() && out == &options.filename {
+        println!("There are no duplicate layouts.");
+        return;
+    }
+
+    let res: String = unique_lines.iter().map(|l| format!("{}\n", l)).collect();
+    fs::write(out, res).unwrap_or_else(|e| panic!("Could not write layouts to {}: {}", out, e));
+
+    println!(
+        "Wrote {} of {} layouts to {}",
+        unique_lines.len(),
+        n_layouts,
+        out
+    );
+}
@@ -0,0 +1,74 @@
+use clap::Parser;
+
+use keyboard_layout::{
+    config::LayoutConfig, key_cost_model::KeyCostModel, keyboard::Keyboard,
+    keyboard_generator::format_list,
+};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Key cost derivation")]
+/// Derive the key costs and unbalancing positions of a keyboard from the positions of its keys and
+/// the resting positions of the fingers, and print them as lists of a keyboard config
+struct Options {
+    /// Filename of layout configuration file
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Filename of a YAML key cost model (default: the `key_cost_model` of the layout config, if any,
+    /// or the default model)
+    #[clap(short, long)]
+    model: Option<String>,
+
+    /// Print the change of each key cost relative to the layout config
+    #[clap(long)]
+    compare: bool,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    let layout_config = LayoutConfig::from_yaml(&options.layout_config)
+        .unwrap_or_else(|e| panic!("Could not read layout config: {}", e));
+    let row_lengths = layout_config.keyboard.row_lengths();
+    let model = match &options.model {
+        Some(filename) => KeyCostModel::from_yaml_file(filename)
+            .unwrap_or_else(|e| panic!("Could not read key cost model {}: {}", filename, e)),
+        None => layout_config
+            .keyboard
+            .key_cost_model()
+            .cloned()
+            .unwrap_or_default(),
+    };
+
+    let original = Keyboard::from_yaml_object(layout_config.keyboard);
+    let mut keyboard = original.clone();
+    model.apply(&mut keyboard);
+
+    if options.compare {
+        for (old, new) in original.keys.iter().zip(keyboard.keys.iter()) {
+            println!(
+                "# [{}, {}] {:?} {:?}: {} -> {}",
+                old.matrix_position.0,
+                old.matrix_position.1,
+                old.hand,
+                old.finger,
+                old.cost,
+                new.cost
+            );
+        }
+    }
+
+    let costs: Vec<String> = keyboard.keys.iter().map(|k| k.cost.to_string()).collect();
+    let unbalancing: Vec<String> = keyboard
+        .keys
+        .iter()
+        .map(|k| format!("[{}, {}]", k.unbalancing.0, k.unbalancing.1))
+        .collect();
+    print!("{}", format_list("key_costs", &costs, &row_lengths));
+    print!(
+        "{}",
+        format_list("unbalancing_positions", &unbalancing, &row_lengths)
+    );
+}
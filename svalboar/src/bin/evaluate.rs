@@ -1,13 +1,17 @@
 use keyboard_layout::layout::Layout;
 use svalboar::common;
-use layout_evaluation::{cache::Cache, results::EvaluationResult};
+use layout_evaluation::{
+    cache::Cache, comparison::LayoutComparison, config::EvaluationParameters,
+    results::EvaluationResult,
+};
 
 use clap::Parser;
 use rayon::prelude::*;
 use serde::Serialize;
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{BufRead, BufReader},
+    path::Path,
 };
 
 #[derive(Serialize)]
@@ -40,6 +44,11 @@ struct Options {
     #[clap(long)]
     from_file: Option<String>,
 
+    /// Layout file listing the symbols of all layers of each key (can be given several times).
+    /// Evaluated after the layout strings
+    #[clap(long)]
+    layout_file: Vec<String>,
+
     /// General parameters
     #[clap(flatten)]
     general_parameters: common::Options,
@@ -55,6 +64,34 @@ struct Options {
     /// Sort results by total costs
     #[clap(long)]
     sort: bool,
+
+    /// Compare two layouts metric by metric (the second one relative to the first one)
+    #[clap(long, conflicts_with_all = ["only_total_costs", "sort"])]
+    compare: bool,
+
+    /// Number of bigrams and trigrams with the largest cost changes to show when comparing
+    #[clap(long, default_value = "10")]
+    compare_ngrams: usize,
+
+    /// Write an SVG image of the layout to this file (numbered for several layouts, e.g. `out_2.svg`)
+    #[clap(long)]
+    svg: Option<String>,
+
+    /// Overlay the SVG image with a heatmap of the key loads ("load") or of the keys' contributions
+    /// to the cost of the metric with this name (e.g. "Key Costs")
+    #[clap(long, requires = "svg")]
+    svg_heatmap: Option<String>,
+}
+
+/// Insert a number before the extension of a filename, e.g. `layout.svg` -> `layout_2.svg`
+fn numbered_filename(filename: &str, n: usize) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let numbered = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, n),
+    };
+    path.with_file_name(numbered).to_string_lossy().to_string()
 }
 
 fn main() {
@@ -66,6 +103,19 @@ fn main() {
     }
 
     let (layout_generator, evaluator) = common::init(&options.general_parameters);
+    let eval_params = EvaluationParameters::from_yaml(&options.general_parameters.eval_parameters)
+        .expect("Could not read evaluation yaml file");
+
+    if let Some(values) = &options.svg_heatmap {
+        let metric_names = evaluator.metric_names();
+        if values != "load" && !metric_names.contains(values) {
+            panic!(
+                "Unknown heatmap '{}'; valid values are \"load\" and the metric names \"{}\"",
+                values,
+                metric_names.join("\", \"")
+            );
+        }
+    }
 
     // collect layout strings to a vec
     let mut layout_strings = options.layout_str.to_vec();
@@ -81,7 +131,12 @@ fn main() {
         }
     }
 
-    let result_cache: Cache<EvaluationResult> = Cache::new();
+    let result_cache: Cache<EvaluationResult> = common::init_result_cache(
+        &options.general_parameters,
+        "evaluation",
+        layout_generator.as_ref(),
+        None,
+    );
 
     // evaluate layouts
     let mut results: Vec<(String, Layout, EvaluationResult)> = layout_strings
@@ -98,12 +153,82 @@ fn main() {
                     panic!("{:?}", e);
                 }
             };
-            let evaluation_result =
-                result_cache.get_or_insert_with(&layout_str, || evaluator.evaluate_layout(&layout));
+            let evaluation_result = result_cache.get_or_insert_with(&layout_str, || {
+                evaluator.evaluate_layout_with_cost_attribution(
+                    &layout,
+                    Some(&eval_params.cost_attribution),
+                )
+            });
             (layout_str, layout, evaluation_result)
         })
         .collect();
 
+    // evaluate layout files (their layout strings refer to another `base_layout`, hence no caching)
+    let file_results: Vec<(String, Layout, EvaluationResult)> = options
+        .layout_file
+        .par_iter()
+        .map(|filename| {
+            let layout = common::init_file_layout_generator(
+                &options.general_parameters.layout_config,
+                filename,
+            )
+            .layout()
+            .unwrap_or_else(|e| panic!("Error in generating layout of {}: {:?}", filename, e));
+            let evaluation_result = evaluator.evaluate_layout_with_cost_attribution(
+                &layout,
+                Some(&eval_params.cost_attribution),
+            );
+            (filename.to_string(), layout, evaluation_result)
+        })
+        .collect();
+    results.extend(file_results);
+
+    if let Some(filename) = &options.svg {
+        let n_results = results.len();
+        for (i, (_, layout, _)) in results.iter().enumerate() {
+            let heatmap = options.svg_heatmap.as_ref().map(|values| {
+                evaluator.key_heatmap(layout, values).unwrap_or_else(|| {
+                    log::error!("No per-key values for heatmap '{}'", values);
+                    panic!("No per-key values for heatmap '{}'", values);
+                })
+            });
+            let filename = match n_results {
+                1 => filename.to_string(),
+                _ => numbered_filename(filename, i + 1),
+            };
+            fs::write(&filename, layout.plot_svg(heatmap.as_ref()))
+                .unwrap_or_else(|e| panic!("Could not write SVG to {}: {}", filename, e));
+        }
+    }
+
+    if options.compare {
+        if results.len() != 2 {
+            panic!(
+                "Comparing requires exactly two layouts, got {}",
+                results.len()
+            );
+        }
+        if options.json {
+            // the layer plots shall not contain terminal colors
+            colored::control::set_override(false);
+        }
+
+        let (_, layout_a, result_a) = &results[0];
+        let (_, layout_b, result_b) = &results[1];
+        let comparison = LayoutComparison::new(
+            (layout_a, result_a, &evaluator.ngram_costs(layout_a)),
+            (layout_b, result_b, &evaluator.ngram_costs(layout_b)),
+            options.compare_ngrams,
+        );
+
+        if options.json {
+            println!("{}", serde_json::to_string(&comparison).unwrap());
+        } else {
+            println!("{}", comparison);
+        }
+        return;
+    }
+
     // sort if required
     if options.sort {
         results.sort_by(|(_, _, c1), (_, _, c2)| {
@@ -0,0 +1,121 @@
+use clap::Parser;
+
+use svalboar::common;
+
+use keyboard_layout::keymap::{FirmwareConfig, Keymap, KeymapFormat};
+
+use std::fs;
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout export to firmware keymaps")]
+struct Options {
+    /// Layout keys from left to right, top to bottom
+    layout_str: String,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Filename of layout configuration file to use
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Interpred given layout string using the "grouped" logic
+    #[clap(long)]
+    pub grouped_layout_generator: bool,
+
+    /// Interpred given layout string as (key, layer) slots, permuting the `permuted_layers` of the
+    /// layout config independently of the base layer
+    #[clap(long, conflicts_with = "grouped_layout_generator")]
+    pub slot_layout_generator: bool,
+
+    /// Filename of firmware configuration file relating firmware keys to the keyboard's keys
+    #[clap(short, long)]
+    firmware_config: String,
+
+    /// Keymap format (qmk, zmk, vial)
+    #[clap(long, default_value = "qmk")]
+    format: KeymapFormat,
+
+    /// Write the keymap to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<String>,
+
+    /// Export the keymap even if permuted symbols of the layout have no keycodes (they are
+    /// exported as no key and can not be imported again)
+    #[clap(long)]
+    allow_untranslatable: bool,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    let layout_str: String = options
+        .layout_str
+        .chars()
+        .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+        .collect();
+    let layout_generator = common::init_layout_generator(
+        &options.layout_config,
+        options.grouped_layout_generator,
+        options.slot_layout_generator,
+    );
+
+    let layout = match layout_generator.generate(&layout_str) {
+        Ok(layout) => layout,
+        Err(e) => {
+            log::error!("{:?}", e);
+            panic!("{:?}", e);
+        }
+    };
+
+    let firmware_config =
+        FirmwareConfig::from_yaml(&options.firmware_config).unwrap_or_else(|_| {
+            panic!(
+                "Could not read firmware config {}",
+                &options.firmware_config
+            )
+        });
+
+    if layout.has_combos() {
+        log::warn!("Combos are not exported; configure them in the firmware manually");
+    }
+
+    let keymap = Keymap::from_layout(&layout, &firmware_config);
+    let untranslatable = keymap.untranslatable_symbols();
+    let untranslatable_permuted: String = untranslatable
+        .iter()
+        .filter(|c| layout_str.contains(**c))
+        .collect();
+    if !untranslatable_permuted.is_empty() && !options.allow_untranslatable {
+        log::error!(
+            "No keycodes for symbols '{}' of the layout string; use --allow-untranslatable to export them as no key",
+            untranslatable_permuted
+        );
+        panic!("No keycodes for symbols '{}'", untranslatable_permuted);
+    }
+    if !untranslatable.is_empty() {
+        log::warn!(
+            "No keycodes for symbols '{}'; they are exported as no key",
+            untranslatable.iter().collect::<String>()
+        );
+    }
+
+    let exported = keymap
+        .export(
+            options.format,
+            &firmware_config,
+            &format!("Layout '{}' ({})", layout_str, options.layout_config),
+        )
+        .expect("Could not export keymap");
+
+    match &options.output {
+        Some(filename) => {
+            fs::write(filename, exported).expect("Could not write keymap file");
+            log::info!("Wrote keymap to {}", filename);
+        }
+        None => print!("{}", exported),
+    }
+}
@@ -0,0 +1,60 @@
+use clap::Parser;
+use std::fs;
+
+use keyboard_layout::keyboard_generator::{generate_from_kle, KeyboardDescription, KleOptions};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard config generator")]
+/// Generate a keyboard config (the `keyboard` section of a layout config) including plot templates
+/// from a compact description of the keyboard or from a KLE (keyboard-layout-editor) JSON file
+struct Options {
+    /// Filename of a YAML keyboard description (rows, columns, column stagger, thumb keys, split)
+    #[clap(required_unless_present = "kle", conflicts_with = "kle")]
+    description: Option<String>,
+
+    /// Filename of a KLE JSON file ("Download JSON" in the keyboard-layout-editor)
+    #[clap(long)]
+    kle: Option<String>,
+
+    /// Row of the KLE file containing the home keys (default: the middle row)
+    #[clap(long, requires = "kle")]
+    home_row: Option<usize>,
+
+    /// Horizontal coordinate (in key units) separating the keys of the hands in the KLE file
+    /// (default: the center of the keyboard)
+    #[clap(long, requires = "kle")]
+    split_x: Option<f64>,
+
+    /// Write the keyboard config to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<String>,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    let yaml = match (&options.description, &options.kle) {
+        (_, Some(filename)) => {
+            let data = fs::read_to_string(filename)
+                .unwrap_or_else(|e| panic!("Could not read KLE file {}: {}", filename, e));
+            let kle_options = KleOptions {
+                home_row: options.home_row,
+                split_x: options.split_x,
+            };
+            generate_from_kle(&data, &kle_options)
+        }
+        (Some(filename), None) => KeyboardDescription::from_yaml_file(filename)
+            .unwrap_or_else(|e| panic!("Could not read keyboard description {}: {}", filename, e))
+            .generate(),
+        (None, None) => unreachable!(),
+    }
+    .unwrap_or_else(|e| panic!("Could not generate keyboard config: {}", e));
+
+    match &options.output {
+        Some(filename) => fs::write(filename, yaml)
+            .unwrap_or_else(|e| panic!("Could not write keyboard config to {}: {}", filename, e)),
+        None => print!("{}", yaml),
+    }
+}
@@ -0,0 +1,122 @@
+use clap::Parser;
+
+use keyboard_layout::{
+    config::LayoutConfig,
+    keyboard::Keyboard,
+    keymap::{FirmwareConfig, Keymap, KeymapFormat},
+    slot_layout_generator::SlotLayoutGenerator,
+};
+
+use ahash::AHashSet;
+use std::{fs, sync::Arc};
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout import from firmware keymaps")]
+struct Options {
+    /// Filename of the keymap (QMK `keymap.c`, ZMK `.keymap` or Vial `.vil`)
+    keymap: String,
+
+    /// Filename of layout configuration file to use
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Filename of firmware configuration file relating firmware keys to the keyboard's keys
+    #[clap(short, long)]
+    firmware_config: String,
+
+    /// Keymap format (qmk, zmk, vial)
+    #[clap(long, default_value = "qmk")]
+    format: KeymapFormat,
+
+    /// Generate a layout string for the slot layout generator (including the `permuted_layers`)
+    #[clap(long)]
+    slot_layout_generator: bool,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    let layout_config = LayoutConfig::from_yaml(&options.layout_config)
+        .unwrap_or_else(|_| panic!("Could not load config file {}", &options.layout_config));
+    let firmware_config =
+        FirmwareConfig::from_yaml(&options.firmware_config).unwrap_or_else(|_| {
+            panic!(
+                "Could not read firmware config {}",
+                &options.firmware_config
+            )
+        });
+    let data = fs::read_to_string(&options.keymap)
+        .unwrap_or_else(|_| panic!("Could not read keymap {}", &options.keymap));
+
+    let keymap =
+        Keymap::import(options.format, &data, &firmware_config).expect("Could not parse keymap");
+
+    // symbols of the layout config take precedence over equivalent ones (e.g. "␣" over " ")
+    let known_symbols: AHashSet<char> = layout_config
+        .base_layout
+        .keys
+        .iter()
+        .flatten()
+        .flatten()
+        .flat_map(|s| s.chars())
+        .collect();
+
+    // per key and layer, the first symbol of the layout config (used for keys without keycodes)
+    let config_symbols: Vec<Vec<Option<char>>> = layout_config
+        .base_layout
+        .keys
+        .iter()
+        .flatten()
+        .map(|layers| layers.iter().map(|s| s.chars().next()).collect())
+        .collect();
+
+    let base_layout = layout_config.base_layout;
+    let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+    let slots: Vec<(usize, u8)> = if options.slot_layout_generator {
+        SlotLayoutGenerator::from_object(base_layout, keyboard.clone()).slots()
+    } else {
+        base_layout
+            .fixed_keys
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter(|(_i, fixed)| !**fixed)
+            .map(|(i, _fixed)| (i, 0))
+            .collect()
+    };
+
+    let fallback_symbols: Vec<Option<char>> = slots
+        .iter()
+        .map(|(key_idx, layer)| {
+            config_symbols
+                .get(*key_idx)
+                .and_then(|layers| layers.get(*layer as usize).cloned().flatten())
+        })
+        .collect();
+
+    let (layout_str, missing) = match keymap.layout_string(
+        &firmware_config,
+        &keyboard,
+        &slots,
+        &known_symbols,
+        &fallback_symbols,
+    ) {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("{:?}", e);
+            panic!("{:?}", e);
+        }
+    };
+
+    if !missing.is_empty() {
+        log::warn!(
+            "No symbols in the keymap for the keys (matrix position, layer) {:?}; \
+             keeping the symbols of the layout config",
+            missing
+        );
+    }
+
+    println!("{}", layout_str);
+}
@@ -0,0 +1,132 @@
+use clap::Parser;
+use std::path::Path;
+
+use layout_evaluation::keystroke_log::{KeystrokeLog, KeystrokeLogFormat, TransitionTimes};
+use svalboar::common;
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keystroke log ingestion")]
+/// Generate personal ngram-frequency files and a table of measured transition times between keys
+/// from a log of timestamped keystrokes (e.g. CSV exported from a keylogger or typing trainer)
+struct Options {
+    /// Read keystrokes from this file (lines with a timestamp and a key; a header line is optional)
+    filename: String,
+
+    /// Write ngram frequencies to this directory (1-grams.txt, 2-grams.txt, 3-grams.txt)
+    #[clap(long)]
+    out: Option<String>,
+
+    /// Write the measured transition times between the keys of the layout to this CSV file
+    #[clap(long, requires = "layout")]
+    transitions: Option<String>,
+
+    /// Layout (keys from left to right, top to bottom) that the keystrokes were typed with
+    #[clap(long)]
+    layout: Option<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Filename of layout configuration file
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Interpred given layout string using the "grouped" logic
+    #[clap(long)]
+    pub grouped_layout_generator: bool,
+
+    /// Interpred given layout string as (key, layer) slots, permuting the `permuted_layers` of the
+    /// layout config independently of the base layer
+    #[clap(long, conflicts_with = "grouped_layout_generator")]
+    pub slot_layout_generator: bool,
+
+    /// Index of the column containing the timestamps
+    #[clap(long, default_value_t = 0)]
+    time_column: usize,
+
+    /// Index of the column containing the keys
+    #[clap(long, default_value_t = 1)]
+    key_column: usize,
+
+    /// Delimiter of the columns
+    #[clap(long, default_value_t = ',')]
+    delimiter: char,
+
+    /// Duration of one unit of the timestamps in milliseconds (e.g. 1000 for timestamps in seconds)
+    #[clap(long, default_value_t = 1.0)]
+    time_unit_ms: f64,
+
+    /// Transitions taking longer than this (in milliseconds) are considered pauses and not measured
+    #[clap(long, default_value_t = 2000.0)]
+    max_pause_ms: f64,
+
+    /// Drop all words typed less often than this (to not leak passwords and other sensitive data)
+    #[clap(long, default_value_t = 0)]
+    min_word_count: usize,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    let options = Options::parse();
+    env_logger::init();
+
+    if options.out.is_none() && options.transitions.is_none() {
+        panic!("Nothing to do: specify --out and/or --transitions");
+    }
+    if !options.delimiter.is_ascii() {
+        panic!("The delimiter needs to be an ASCII character");
+    }
+
+    let format = KeystrokeLogFormat {
+        time_column: options.time_column,
+        key_column: options.key_column,
+        delimiter: options.delimiter as u8,
+        time_unit_ms: options.time_unit_ms,
+    };
+    let mut log = KeystrokeLog::from_file(&options.filename, &format)
+        .unwrap_or_else(|e| panic!("Could not read keystroke log {}: {:?}", options.filename, e));
+    if options.min_word_count > 1 {
+        log = log.anonymize(options.min_word_count);
+    }
+    log::info!(
+        "Read {} keystrokes in {} segments of uninterrupted text",
+        log.len(),
+        log.segments.len()
+    );
+    if log.is_empty() {
+        panic!("No keystrokes found in {}", options.filename);
+    }
+
+    if let Some(out) = &options.out {
+        let d = Path::new(out);
+        let (unigrams, bigrams, trigrams) = log.to_ngrams();
+        unigrams.save_frequencies(d.join("1-grams.txt")).unwrap();
+        bigrams.save_frequencies(d.join("2-grams.txt")).unwrap();
+        trigrams.save_frequencies(d.join("3-grams.txt")).unwrap();
+    }
+
+    if let (Some(filename), Some(layout_str)) = (&options.transitions, &options.layout) {
+        let layout_generator = common::init_layout_generator(
+            &options.layout_config,
+            options.grouped_layout_generator,
+            options.slot_layout_generator,
+        );
+        let layout_str: String = layout_str
+            .chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect();
+        let layout = layout_generator
+            .generate(&layout_str)
+            .unwrap_or_else(|e| panic!("Error in generating layout: {:?}", e));
+
+        let transitions = TransitionTimes::from_log(&log, &layout, options.max_pause_ms);
+        log::info!(
+            "Measured {} transitions between keys",
+            transitions.times.len()
+        );
+        transitions.save(filename).unwrap_or_else(|e| {
+            panic!("Could not write transition times to {}: {:?}", filename, e)
+        });
+    }
+}
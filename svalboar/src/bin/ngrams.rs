@@ -1,7 +1,8 @@
 use clap::Parser;
 use std::{fs, path::Path};
 
-use layout_evaluation::ngrams::{Bigrams, Trigrams, Unigrams};
+use keyboard_layout::config::LayoutConfig;
+use layout_evaluation::ngrams::{tokenize_context_keys, Bigrams, Trigrams, Unigrams};
 
 #[derive(Parser, Debug)]
 #[clap(name = "Ngram frequency generator")]
@@ -12,6 +13,11 @@ struct Options {
 
     /// Name for resulting ngram frequencies (a directory at that path will be generated)
     out: String,
+
+    /// Replace symbol sequences generated by the context keys of this layout config (e.g. a magic
+    /// key typing "ing") with their placeholder symbols
+    #[clap(short, long)]
+    layout_config: Option<String>,
 }
 
 fn main() {
@@ -19,9 +25,15 @@ fn main() {
     let options = Options::parse();
     env_logger::init();
 
-    let text = fs::read_to_string(&options.filename)
+    let mut text = fs::read_to_string(&options.filename)
         .unwrap_or_else(|_| panic!("Could not read corpus file from {}.", options.filename));
 
+    if let Some(layout_config) = &options.layout_config {
+        let layout_config = LayoutConfig::from_yaml(layout_config)
+            .unwrap_or_else(|e| panic!("Could not load config file {}: {}", layout_config, e));
+        text = tokenize_context_keys(&text, &layout_config.base_layout.context_keys);
+    }
+
     let d = Path::new(&options.out);
 
     let unigrams = Unigrams::from_text(&text).expect("Could not generate unigrams from text.");
@@ -1,9 +1,12 @@
 use svalboar::common;
 use layout_evaluation::cache::Cache;
-use layout_optimization_genetic::optimization;
+use layout_optimization_common::Checkpointing;
+use layout_optimization_genetic::optimization::{self, GeneticCheckpoint};
 
 use clap::Parser;
-use std::{env, process};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::process;
 
 #[derive(Parser, Debug)]
 #[clap(name = "Keyboard layout optimization - Genetic Algorithm")]
@@ -28,6 +31,11 @@ struct Options {
     #[clap(short, long)]
     start_layout: Option<String>,
 
+    /// Start optimization from the layout of this layout file (listing the symbols of all layers of
+    /// each key). Its higher layers replace those of the layout config
+    #[clap(long, conflicts_with = "start_layout")]
+    start_layout_file: Option<String>,
+
     /// Do not remove whitespace from layout strings
     #[clap(long)]
     do_not_remove_whitespace: bool,
@@ -36,6 +44,10 @@ struct Options {
     #[clap(long)]
     no_cache_results: bool,
 
+    /// Only search one layout of each pair of mirror images (overrides the optimization parameters)
+    #[clap(long)]
+    only_canonical_layouts: bool,
+
     /// Maximum number of generations
     #[clap(long)]
     generation_limit: Option<u64>,
@@ -48,32 +60,46 @@ struct Options {
     #[clap(long)]
     run_forever: bool,
 
+    /// Seed for the random number generators. The n-th optimization run uses seed + n.
+    /// If not given, a random seed is used.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Periodically write the state of the optimization to this file
+    #[clap(long)]
+    checkpoint: Option<String>,
+
+    /// Number of generations between two checkpoints
+    #[clap(long, default_value = "50", value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_interval: u64,
+
+    /// Resume an interrupted run from this checkpoint file (use the same options as for the
+    /// interrupted run). Further checkpoints are written to it unless --checkpoint is given.
+    #[clap(long)]
+    resume: Option<String>,
+
     /// Publishing options
     #[clap(flatten)]
     publishing_options: common::PublishingOptions,
 }
 
+/// Contents of a checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    /// Seed of the first optimization run
+    seed: u64,
+    /// Number of finished optimization runs
+    finished: u64,
+    /// Latest checkpoint of the current optimization run (if one has been taken yet)
+    state: Option<GeneticCheckpoint>,
+    /// Results of the finished optimization runs
+    results: Vec<(String, f64)>,
+}
+
 fn main() {
     dotenv::dotenv().ok();
     env_logger::init();
 
-    // Disable storing worst ngrams for speed boost
-    if env::var("SHOW_WORST").is_err() {
-        env::set_var("SHOW_WORST", "false");
-    };
-
-    let final_results: Cache<f64> = Cache::new();
-
-    // Handle Ctrl+C
-    let cloned_final_results = final_results.clone();
-    ctrlc::set_handler(move || {
-        // Display a summary of the optimization.
-        println!("\n\n{}\n", cloned_final_results);
-        // Stop execution
-        process::exit(0);
-    })
-    .expect("Error setting Ctrl-C handler");
-
     let options = Options::parse();
 
     let fix_from: String = options
@@ -82,13 +108,34 @@ fn main() {
         .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
         .collect();
 
-    let start_layout = options.start_layout.as_ref().map(|s| {
+    let mut start_layout = options.start_layout.as_ref().map(|s| {
         s.chars()
             .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
             .collect::<String>()
     });
 
-    let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+    let (mut layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+    if let Some(layout_file) = &options.start_layout_file {
+        let generator = common::init_file_layout_generator(
+            &options.evaluation_parameters.layout_config,
+            layout_file,
+        );
+        start_layout = Some(generator.layout_string().to_string());
+        layout_generator = Box::new(generator);
+    }
+
+    let final_results: Cache<f64> =
+        common::init_results(&options.evaluation_parameters, layout_generator.as_ref());
+
+    // Handle Ctrl+C
+    let cloned_final_results = final_results.clone();
+    ctrlc::set_handler(move || {
+        // Display a summary of the optimization.
+        println!("\n\n{}\n", cloned_final_results);
+        // Stop execution
+        process::exit(0);
+    })
+    .expect("Error setting Ctrl-C handler");
 
     let mut optimization_params = optimization::Parameters::from_yaml(
         &options.optimization_parameters,
@@ -103,10 +150,52 @@ fn main() {
     if let Some(generation_limit) = options.generation_limit {
         optimization_params.generation_limit = generation_limit
     }
+    if options.only_canonical_layouts {
+        optimization_params.only_canonical_layouts = true;
+    }
 
     let fix_from = start_layout.as_ref().unwrap_or(&fix_from).to_string();
 
-    loop {
+    let resumed: Option<CheckpointFile> = options.resume.as_ref().map(|filename| {
+        common::read_checkpoint(filename).unwrap_or_else(|e| {
+            panic!("Could not read checkpoint from {}: {}", filename, e);
+        })
+    });
+    let checkpoint_filename = options.checkpoint.as_ref().or(options.resume.as_ref());
+
+    let (first_seed, mut finished, mut resume_from) = match resumed {
+        Some(resumed) => {
+            final_results.extend(resumed.results);
+            (resumed.seed, resumed.finished, resumed.state)
+        }
+        None => (
+            options.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+            0,
+            None,
+        ),
+    };
+
+    while options.run_forever || finished == 0 {
+        let seed = first_seed.wrapping_add(finished);
+        let checkpointing = checkpoint_filename.map(|filename| {
+            let filename = filename.to_string();
+            let final_results = final_results.clone();
+            Checkpointing {
+                interval: options.checkpoint_interval,
+                store: Box::new(move |checkpoint: &GeneticCheckpoint| {
+                    let contents = CheckpointFile {
+                        seed: first_seed,
+                        finished,
+                        state: Some(checkpoint.clone()),
+                        results: final_results.entries(),
+                    };
+                    common::write_checkpoint(&contents, &filename);
+                }),
+                resume_from: resume_from.take(),
+            }
+        });
+
+        log::info!("Starting optimization with seed {}", seed);
         let (layout_str, layout) = optimization::optimize(
             &optimization_params,
             &evaluator,
@@ -115,11 +204,24 @@ fn main() {
             &options.fix.clone().unwrap_or_default(),
             start_layout.is_some(),
             !options.no_cache_results,
+            Some(seed),
+            checkpointing,
         );
+        finished += 1;
         let evaluation_result = evaluator.evaluate_layout(&layout);
         let cost = evaluation_result.total_cost();
         let _ = final_results.get_or_insert_with(&layout_str, || cost);
 
+        if let Some(filename) = checkpoint_filename {
+            let contents = CheckpointFile {
+                seed: first_seed,
+                finished,
+                state: None,
+                results: final_results.entries(),
+            };
+            common::write_checkpoint(&contents, filename);
+        }
+
         println!(
             "{}\n\n{}\n",
             evaluation_result,
@@ -141,9 +243,5 @@ fn main() {
                 &o.publish_layout_config,
             );
         }
-
-        if !options.run_forever {
-            break;
-        }
     }
 }
@@ -0,0 +1,197 @@
+use svalboar::common;
+use layout_evaluation::objectives::Objectives;
+use layout_optimization_genetic::{optimization, pareto};
+
+use clap::Parser;
+use colored::Colorize;
+use rand::Rng;
+use serde::Serialize;
+use std::fs::File;
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout optimization - Multi-objective (NSGA-II)")]
+struct Options {
+    /// Evaluation parameters
+    #[clap(flatten)]
+    evaluation_parameters: common::Options,
+
+    /// Do not optimize those keys (wrt. --start-layout or --fix-from)
+    #[clap(short, long)]
+    fix: Option<String>,
+
+    /// Fix the keys from this layout (will be overwritten by --start-layout)
+    #[clap(long, default_value = "xvlcwkhgfqyßuiaeosnrtdüöäpzbm,.j")]
+    fix_from: String,
+
+    /// Filename of optimization configuration file
+    #[clap(short, long, default_value = "config/optimization/pareto.yml")]
+    optimization_parameters: String,
+
+    /// Filename of the objectives configuration file
+    #[clap(long, default_value = "config/optimization/objectives.yml")]
+    objectives: String,
+
+    /// Start optimization from this layout (keys from left to right, top to bottom)
+    #[clap(short, long)]
+    start_layout: Option<String>,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Do not cache intermediate results
+    #[clap(long)]
+    no_cache_results: bool,
+
+    /// Only search one layout of each pair of mirror images (overrides the optimization parameters)
+    #[clap(long)]
+    only_canonical_layouts: bool,
+
+    /// Maximum number of generations
+    #[clap(long)]
+    generation_limit: Option<u64>,
+
+    /// Seed for the random number generators. If not given, a random seed is used.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Write the non-dominated layouts with their objective costs to this (YAML) file
+    #[clap(long)]
+    output: Option<String>,
+}
+
+/// Contents of the output file.
+#[derive(Serialize)]
+struct ParetoFront {
+    objectives: Vec<String>,
+    solutions: Vec<pareto::ParetoSolution>,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let options = Options::parse();
+
+    let fix_from: String = options
+        .fix_from
+        .chars()
+        .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+        .collect();
+
+    let start_layout = options.start_layout.as_ref().map(|s| {
+        s.chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect::<String>()
+    });
+
+    let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+
+    let mut optimization_params = optimization::Parameters::from_yaml(
+        &options.optimization_parameters,
+    )
+    .unwrap_or_else(|_| {
+        panic!(
+            "Could not read optimization parameters from {}.",
+            &options.optimization_parameters
+        )
+    });
+
+    if let Some(generation_limit) = options.generation_limit {
+        optimization_params.generation_limit = generation_limit
+    }
+    if options.only_canonical_layouts {
+        optimization_params.only_canonical_layouts = true;
+    }
+
+    let objectives = Objectives::from_yaml(&options.objectives).unwrap_or_else(|e| {
+        panic!(
+            "Could not read objectives from {}: {}",
+            &options.objectives, e
+        )
+    });
+    if objectives.objectives.is_empty() {
+        panic!("No objectives given in {}.", &options.objectives);
+    }
+
+    let fix_from = start_layout.as_ref().unwrap_or(&fix_from).to_string();
+
+    // Metrics that are not part of any objective are ignored by the optimization.
+    let layout = layout_generator.generate(&fix_from).unwrap();
+    let unused_metrics = objectives.unused_metrics(&evaluator.evaluate_layout(&layout));
+    if !unused_metrics.is_empty() {
+        log::warn!(
+            "Metrics not contained in any objective: {}",
+            unused_metrics.join(", ")
+        );
+    }
+
+    let symmetry =
+        common::layout_symmetry(&options.evaluation_parameters, layout_generator.as_ref());
+
+    let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    log::info!("Starting optimization with seed {}", seed);
+    let solutions = pareto::optimize(
+        &optimization_params,
+        &evaluator,
+        &objectives,
+        &fix_from,
+        layout_generator.as_ref(),
+        &options.fix.clone().unwrap_or_default(),
+        start_layout.is_some(),
+        !options.no_cache_results,
+        symmetry.as_ref(),
+        Some(seed),
+    );
+
+    // Plot the non-dominated layouts with their objective costs.
+    let names = objectives.names();
+    let layout_width = solutions
+        .iter()
+        .map(|s| s.layout.chars().count())
+        .max()
+        .unwrap_or(0);
+    println!(
+        "\n{} ({} layouts)\n",
+        "Non-dominated layouts".green().bold(),
+        solutions.len()
+    );
+    println!(
+        "{:<width$} {}",
+        "Layout",
+        names
+            .iter()
+            .map(|n| format!("{:>16}", n))
+            .collect::<Vec<String>>()
+            .join(" ")
+            .bold(),
+        width = layout_width,
+    );
+    for solution in solutions.iter() {
+        println!(
+            "{:<width$} {}",
+            solution.layout,
+            solution
+                .costs
+                .iter()
+                .map(|c| format!("{:>16.2}", c))
+                .collect::<Vec<String>>()
+                .join(" "),
+            width = layout_width,
+        );
+    }
+
+    if let Some(filename) = &options.output {
+        let front = ParetoFront {
+            objectives: names,
+            solutions,
+        };
+        match File::create(filename)
+            .map_err(anyhow::Error::from)
+            .and_then(|f| Ok(serde_yaml::to_writer(f, &front)?))
+        {
+            Ok(()) => log::info!("Wrote non-dominated layouts to '{}'", filename),
+            Err(e) => log::error!("Couldn't write to file: {}", e),
+        }
+    }
+}
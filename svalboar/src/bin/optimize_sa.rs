@@ -1,11 +1,18 @@
 use svalboar::common;
 use layout_evaluation::cache::Cache;
-use layout_optimization_sa::optimization;
+use layout_optimization_common::Checkpointing;
+use layout_optimization_sa::optimization::{self, SaCheckpoint};
 
 use clap::Parser;
 use colored::Colorize;
+use rand::Rng;
 use rayon::iter::{ParallelBridge, ParallelIterator};
-use std::{env, process};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    process,
+    sync::{Arc, Mutex},
+};
 
 #[derive(Parser, Debug)]
 #[clap(name = "Keyboard layout optimization - Simulated Annealing")]
@@ -30,6 +37,11 @@ struct Options {
     #[clap(short, long)]
     start_layouts: Vec<String>,
 
+    /// Start optimization from the layout of this layout file (listing the symbols of all layers of
+    /// each key). Its higher layers replace those of the layout config, also for --start-layouts
+    #[clap(long)]
+    start_layout_file: Option<String>,
+
     /// Do not remove whitespace from layout strings
     #[clap(long)]
     do_not_remove_whitespace: bool,
@@ -38,6 +50,10 @@ struct Options {
     #[clap(long)]
     no_cache_results: bool,
 
+    /// Only search one layout of each pair of mirror images (overrides the optimization parameters)
+    #[clap(long)]
+    only_canonical_layouts: bool,
+
     /// Set the initial temperature (Will be overwritten by --greedy)
     #[clap(long)]
     init_temp: Option<f64>,
@@ -58,31 +74,59 @@ struct Options {
     #[clap(long)]
     run_forever: bool,
 
+    /// Seed for the random number generators. The n-th optimization run uses seed + n.
+    /// If not given, a random seed is used.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Periodically write the state of all optimization processes to this file
+    #[clap(long)]
+    checkpoint: Option<String>,
+
+    /// Number of iterations between two checkpoints of an optimization process
+    #[clap(long, default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_interval: u64,
+
+    /// Resume an interrupted run from this checkpoint file (use the same options as for the
+    /// interrupted run). Further checkpoints are written to it unless --checkpoint is given.
+    #[clap(long)]
+    resume: Option<String>,
+
     /// Publishing options
     #[clap(flatten)]
     publishing_options: common::PublishingOptions,
 }
 
-/// An iterator for layouts to feed into the optimizer.
+/// An iterator for layouts to feed into the optimizer, together with the seed to use for each run.
 /// If `run_forever` is true, it iterates over the given layouts indefinitely.
 struct LayoutIterator {
     layouts: Vec<String>,
     run_forever: bool,
     i: usize,
+    seed: u64,
 }
 
 impl LayoutIterator {
-    fn new<T: AsRef<str>>(layouts: &[T], run_forever: bool) -> Self {
+    fn new<T: AsRef<str>>(layouts: &[T], run_forever: bool, seed: u64) -> Self {
         Self {
             layouts: layouts.iter().map(|s| s.as_ref().to_string()).collect(),
             run_forever,
             i: 0,
+            seed,
         }
     }
+
+    /// The seed for the next run (increases with each run).
+    fn next_seed(&mut self) -> u64 {
+        let seed = self.seed;
+        self.seed = self.seed.wrapping_add(1);
+
+        seed
+    }
 }
 
 impl Iterator for LayoutIterator {
-    type Item = String;
+    type Item = (String, u64);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.i < self.layouts.len() {
@@ -90,14 +134,14 @@ impl Iterator for LayoutIterator {
             let res = self.layouts[self.i].clone();
             self.i += 1;
 
-            Some(res)
+            Some((res, self.next_seed()))
         } else {
             // All elements of this.layouts have been given
             if self.run_forever {
                 // Loop around and start anew
                 self.i = 0;
 
-                Some(self.layouts[self.i].clone())
+                Some((self.layouts[self.i].clone(), self.next_seed()))
             } else {
                 // Finish iteration
                 None
@@ -106,26 +150,49 @@ impl Iterator for LayoutIterator {
     }
 }
 
-fn main() {
-    dotenv::dotenv().ok();
-    env_logger::init();
+/// Contents of a checkpoint file.
+#[derive(Serialize, Deserialize, Default)]
+struct CheckpointFile {
+    /// Seed of the first optimization process
+    seed: u64,
+    /// Number of optimization processes that have been started
+    started: usize,
+    /// Unfinished optimization processes by their index
+    unfinished: BTreeMap<usize, ProcessCheckpoint>,
+    /// Results of the finished optimization processes
+    results: Vec<(String, f64)>,
+    /// Entries of the cache of evaluated layouts (if results are cached)
+    #[serde(default)]
+    cache: Vec<(String, f64)>,
+}
 
-    // Disable storing worst ngrams for speed boost
-    if env::var("SHOW_WORST").is_err() {
-        env::set_var("SHOW_WORST", "false");
-    };
+/// State of an unfinished optimization process.
+#[derive(Clone, Serialize, Deserialize)]
+struct ProcessCheckpoint {
+    layout: String,
+    seed: u64,
+    /// Latest checkpoint of the process (if one has been taken yet)
+    state: Option<SaCheckpoint>,
+}
 
-    let final_results: Cache<f64> = Cache::new();
+/// Keeps the checkpoint file up to date with the state of all optimization processes.
+struct Checkpoints {
+    filename: String,
+    contents: Mutex<CheckpointFile>,
+}
 
-    // Handle Ctrl+C
-    let cloned_final_results = final_results.clone();
-    ctrlc::set_handler(move || {
-        // Display a summary of the optimization.
-        println!("\n\n{}\n", cloned_final_results);
-        // Stop execution
-        process::exit(0);
-    })
-    .expect("Error setting Ctrl-C handler");
+impl Checkpoints {
+    /// Updates the contents and writes them to the checkpoint file.
+    fn update<F: FnOnce(&mut CheckpointFile)>(&self, f: F) {
+        let mut contents = self.contents.lock().unwrap();
+        f(&mut contents);
+        common::write_checkpoint(&*contents, &self.filename);
+    }
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
 
     let options = Options::parse();
 
@@ -135,7 +202,7 @@ fn main() {
         .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
         .collect();
 
-    let start_layouts: Vec<String> = options
+    let mut start_layouts: Vec<String> = options
         .start_layouts
         .iter()
         .map(|s| {
@@ -145,7 +212,28 @@ fn main() {
         })
         .collect();
 
-    let (layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+    let (mut layout_generator, evaluator) = common::init(&options.evaluation_parameters);
+    if let Some(layout_file) = &options.start_layout_file {
+        let generator = common::init_file_layout_generator(
+            &options.evaluation_parameters.layout_config,
+            layout_file,
+        );
+        start_layouts.push(generator.layout_string().to_string());
+        layout_generator = Box::new(generator);
+    }
+
+    let final_results: Cache<f64> =
+        common::init_results(&options.evaluation_parameters, layout_generator.as_ref());
+
+    // Handle Ctrl+C
+    let cloned_final_results = final_results.clone();
+    ctrlc::set_handler(move || {
+        // Display a summary of the optimization.
+        println!("\n\n{}\n", cloned_final_results);
+        // Stop execution
+        process::exit(0);
+    })
+    .expect("Error setting Ctrl-C handler");
 
     let mut optimization_params = optimization::Parameters::from_yaml(
         &options.optimization_parameters,
@@ -162,34 +250,124 @@ fn main() {
         optimization_params.init_temp = options.init_temp;
     }
     optimization_params.correct_init_temp();
+    if options.only_canonical_layouts {
+        optimization_params.only_canonical_layouts = true;
+    }
 
     let mut layouts: Vec<String> = start_layouts.to_vec();
     if layouts.is_empty() {
         layouts = vec![fix_from];
     }
-    let layout_iterator = LayoutIterator::new(&layouts, options.run_forever);
+    let resumed: Option<CheckpointFile> = options.resume.as_ref().map(|filename| {
+        common::read_checkpoint(filename).unwrap_or_else(|e| {
+            panic!("Could not read checkpoint from {}: {}", filename, e);
+        })
+    });
+    let seed = match &resumed {
+        Some(resumed) => resumed.seed,
+        None => options.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+    };
+    log::info!("Using seed {}", seed);
+    let layout_iterator = LayoutIterator::new(&layouts, options.run_forever, seed);
     let start_from_layout = !start_layouts.is_empty();
 
     let cache: Option<Cache<f64>> = match !options.no_cache_results {
-        true => Some(Cache::new()),
+        true => Some(common::init_result_cache(
+            &options.evaluation_parameters,
+            "total_cost",
+            layout_generator.as_ref(),
+            options.start_layout_file.as_deref(),
+        )),
         false => None,
     };
 
-    layout_iterator
-        .enumerate()
+    // Unfinished processes of a resumed run are continued first, then new processes are started
+    // where the resumed run stopped.
+    let (n_started, resumed_processes) = match resumed {
+        Some(resumed) => {
+            final_results.extend(resumed.results);
+            if let Some(cache) = &cache {
+                cache.extend(resumed.cache);
+            }
+            (resumed.started, resumed.unfinished)
+        }
+        None => (0, BTreeMap::new()),
+    };
+    let cache_entries = |cache: &Option<Cache<f64>>| -> Vec<(String, f64)> {
+        cache.as_ref().map(Cache::entries).unwrap_or_default()
+    };
+    let checkpoints = options
+        .checkpoint
+        .as_ref()
+        .or(options.resume.as_ref())
+        .map(|filename| {
+            Arc::new(Checkpoints {
+                filename: filename.to_string(),
+                contents: Mutex::new(CheckpointFile {
+                    seed,
+                    started: n_started,
+                    unfinished: resumed_processes.clone(),
+                    results: final_results.entries(),
+                    cache: cache_entries(&cache),
+                }),
+            })
+        });
+    let processes = resumed_processes
+        .into_iter()
+        .map(|(i, p)| (i, (p.layout, p.seed), p.state))
+        .chain(
+            layout_iterator
+                .enumerate()
+                .skip(n_started)
+                .map(|(i, layout_and_seed)| (i, layout_and_seed, None)),
+        );
+
+    processes
         .par_bridge()
-        .for_each(|(i, fix_from)| {
+        .for_each(|(i, (fix_from, seed), resume_from)| {
             let process_id = format!("Process {:>3}", i);
+
+            let checkpointing = checkpoints.as_ref().map(|checkpoints| {
+                checkpoints.update(|contents| {
+                    contents.started = contents.started.max(i + 1);
+                    contents.unfinished.insert(
+                        i,
+                        ProcessCheckpoint {
+                            layout: fix_from.clone(),
+                            seed,
+                            state: resume_from.clone(),
+                        },
+                    );
+                });
+
+                let checkpoints = checkpoints.clone();
+                let cache = cache.clone();
+                Checkpointing {
+                    interval: options.checkpoint_interval,
+                    store: Box::new(move |checkpoint: &SaCheckpoint| {
+                        checkpoints.update(|contents| {
+                            if let Some(process) = contents.unfinished.get_mut(&i) {
+                                process.state = Some(checkpoint.clone());
+                            }
+                            contents.cache = cache_entries(&cache);
+                        });
+                    }),
+                    resume_from,
+                }
+            });
+
             if start_from_layout {
                 log::info!(
-                    "{} Starting optimization from {}",
+                    "{} Starting optimization from {} (seed {})",
                     format!("{}:", process_id).yellow().bold(),
-                    fix_from
+                    fix_from,
+                    seed,
                 );
             } else {
                 log::info!(
-                    "{} Starting optimization",
+                    "{} Starting optimization (seed {})",
                     format!("{}:", process_id).yellow().bold(),
+                    seed,
                 );
             }
 
@@ -205,11 +383,21 @@ fn main() {
                 options.log_everything,
                 cache.clone(),
                 None,
+                Some(seed),
+                checkpointing,
             );
             let evaluation_result = evaluator.evaluate_layout(&layout);
             let cost = evaluation_result.total_cost();
             let _ = final_results.get_or_insert_with(&layout_str, || cost);
 
+            if let Some(checkpoints) = &checkpoints {
+                checkpoints.update(|contents| {
+                    contents.unfinished.remove(&i);
+                    contents.results = final_results.entries();
+                    contents.cache = cache_entries(&cache);
+                });
+            }
+
             // Plot some information regarding the layout.
             println!(
                 "{} {}\n\n{}\n\n{}\n{}\n{}\n\n{}\n",
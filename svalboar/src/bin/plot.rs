@@ -1,12 +1,20 @@
 use clap::Parser;
 
+use keyboard_layout::layout_generator::LayoutGenerator;
 use svalboar::common;
 
+use std::fs;
+
 #[derive(Parser, Debug)]
 #[clap(name = "Keyboard layout plotting")]
 struct Options {
     /// Layout keys from left to right, top to bottom
-    layout_str: String,
+    #[clap(required_unless_present = "layout_file")]
+    layout_str: Option<String>,
+
+    /// Plot the layout of this layout file (listing the symbols of all layers of each key) instead
+    #[clap(long, conflicts_with = "layout_str")]
+    layout_file: Option<String>,
 
     /// Do not remove whitespace from layout strings
     #[clap(long)]
@@ -19,6 +27,15 @@ struct Options {
     /// Interpred given layout string using the "grouped" logic
     #[clap(long)]
     pub grouped_layout_generator: bool,
+
+    /// Interpred given layout string as (key, layer) slots, permuting the `permuted_layers` of the
+    /// layout config independently of the base layer
+    #[clap(long, conflicts_with = "grouped_layout_generator")]
+    pub slot_layout_generator: bool,
+
+    /// Write an SVG image of the layout (showing all layers, based on the keys' positions) to this file
+    #[clap(long)]
+    svg: Option<String>,
 }
 
 fn main() {
@@ -26,13 +43,28 @@ fn main() {
     env_logger::init();
     let options = Options::parse();
 
-    let layout_str: String = options
-        .layout_str
-        .chars()
-        .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
-        .collect();
-    let layout_generator =
-        common::init_layout_generator(&options.layout_config, options.grouped_layout_generator);
+    let (layout_str, layout_generator): (String, Box<dyn LayoutGenerator>) = match &options
+        .layout_file
+    {
+        Some(layout_file) => {
+            let generator = common::init_file_layout_generator(&options.layout_config, layout_file);
+            (generator.layout_string().to_string(), Box::new(generator))
+        }
+        None => (
+            options
+                .layout_str
+                .as_deref()
+                .unwrap_or_default()
+                .chars()
+                .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+                .collect(),
+            common::init_layout_generator(
+                &options.layout_config,
+                options.grouped_layout_generator,
+                options.slot_layout_generator,
+            ),
+        ),
+    };
 
     let layout = match layout_generator.generate(&layout_str) {
         Ok(layout) => layout,
@@ -52,4 +84,9 @@ fn main() {
     }
     println!("Layout compact: \n{}", layout.plot_compact());
     println!("Layout as text: \n{}", layout);
+
+    if let Some(filename) = &options.svg {
+        fs::write(filename, layout.plot_svg(None))
+            .unwrap_or_else(|e| panic!("Could not write SVG to {}: {}", filename, e));
+    }
 }
@@ -0,0 +1,133 @@
+use clap::Parser;
+
+use keyboard_layout::layout_port::port_layout;
+use svalboar::common;
+
+#[derive(Parser, Debug)]
+#[clap(name = "Keyboard layout porting")]
+/// Port a layout to another keyboard by moving its symbols to the keys playing the same role
+/// (same hand and finger, same row and column relative to the finger's home key)
+struct Options {
+    /// Layout keys from left to right, top to bottom
+    layout_str: String,
+
+    /// Do not remove whitespace from layout strings
+    #[clap(long)]
+    do_not_remove_whitespace: bool,
+
+    /// Filename of layout configuration file of the given layout
+    #[clap(short, long, default_value = "config/keyboard/standard.yml")]
+    layout_config: String,
+
+    /// Interpred given layout string using the "grouped" logic
+    #[clap(long)]
+    pub grouped_layout_generator: bool,
+
+    /// Interpred given layout string as (key, layer) slots, permuting the `permuted_layers` of the
+    /// layout config independently of the base layer
+    #[clap(long, conflicts_with = "grouped_layout_generator")]
+    pub slot_layout_generator: bool,
+
+    /// Filename of layout configuration file to port the layout to
+    #[clap(short, long)]
+    target_layout_config: String,
+
+    /// Interpred layout strings of the target layout config using the "grouped" logic
+    #[clap(long)]
+    target_grouped_layout_generator: bool,
+
+    /// Interpred layout strings of the target layout config as (key, layer) slots
+    #[clap(long, conflicts_with = "target_grouped_layout_generator")]
+    target_slot_layout_generator: bool,
+
+    /// Layout of the target layout config whose keys are reused for symbols that can not be ported
+    /// (default: its `base_layout`)
+    #[clap(long)]
+    target_layout: Option<String>,
+
+    /// Print only the layout string of the ported layout
+    #[clap(long)]
+    only_layout: bool,
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let options = Options::parse();
+
+    let remove_whitespace = |s: &str| -> String {
+        s.chars()
+            .filter(|c| options.do_not_remove_whitespace || !c.is_whitespace())
+            .collect()
+    };
+
+    let layout_generator = common::init_layout_generator(
+        &options.layout_config,
+        options.grouped_layout_generator,
+        options.slot_layout_generator,
+    );
+    let target_generator = common::init_layout_generator(
+        &options.target_layout_config,
+        options.target_grouped_layout_generator,
+        options.target_slot_layout_generator,
+    );
+
+    let layout_str = remove_whitespace(&options.layout_str);
+    let layout = layout_generator
+        .generate(&layout_str)
+        .unwrap_or_else(|e| panic!("Error in generating layout: {:?}", e));
+
+    let target_str = match &options.target_layout {
+        Some(target_layout) => remove_whitespace(target_layout),
+        None => target_generator.base_layout_string(),
+    };
+    let target = target_generator
+        .generate(&target_str)
+        .unwrap_or_else(|e| panic!("Error in generating target layout: {:?}", e));
+
+    let ported = port_layout(&layout, &target);
+
+    // symbols beyond the base layer (e.g. permuted layers of slot layout strings) are kept
+    let n_base = ported.layout_string.chars().count();
+    let ported_str: String = ported
+        .layout_string
+        .chars()
+        .chain(target_str.chars().skip(n_base))
+        .collect();
+
+    if options.only_layout {
+        println!("{}", ported_str);
+        return;
+    }
+
+    let ported_layout = target_generator
+        .generate(&ported_str)
+        .unwrap_or_else(|e| panic!("Error in generating ported layout: {:?}", e));
+    println!(
+        "Layout '{}' ported to '{}':\n{}",
+        layout_str,
+        options.target_layout_config,
+        ported_layout.plot()
+    );
+    println!("Layout string: \n{}", ported_str);
+
+    if !ported.unplaced.is_empty() {
+        let unplaced: Vec<String> = ported
+            .unplaced
+            .iter()
+            .map(|(symbol, pos)| format!("{} ({:?})", symbol.escape_debug(), pos))
+            .collect();
+        println!(
+            "\nNo matching key for (placed on remaining keys): {}",
+            unplaced.join(", ")
+        );
+    }
+    if !ported.unsupported.is_empty() {
+        let unsupported: String = ported
+            .unsupported
+            .iter()
+            .map(|c| c.escape_debug().to_string())
+            .collect();
+        println!("\nNot available in the target layout: {}", unsupported);
+    }
+}
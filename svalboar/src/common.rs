@@ -1,20 +1,25 @@
 use keyboard_layout::{
-    config::LayoutConfig, grouped_layout_generator::GroupedLayoutGenerator, keyboard::Keyboard,
-    layout_generator::LayoutGenerator, neo_layout_generator::NeoLayoutGenerator,
+    config::LayoutConfig, file_layout_generator::FileLayoutGenerator,
+    grouped_layout_generator::GroupedLayoutGenerator, keyboard::Keyboard, layout::ContextKey,
+    layout_generator::LayoutGenerator, layout_symmetry::LayoutSymmetry,
+    neo_layout_generator::NeoLayoutGenerator, slot_layout_generator::SlotLayoutGenerator,
 };
 use layout_evaluation::{
+    cache::{fingerprint, Cache},
     config::EvaluationParameters,
     evaluation::Evaluator,
-    ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
-    ngrams::{Bigrams, Trigrams, Unigrams},
+    ngram_mapper::dense_ngram_mapper::DenseNgramMapper,
+    ngrams::{tokenize_context_keys, Bigrams, Trigrams, Unigrams},
 };
 
 use ahash::AHashMap;
+use anyhow::Result;
 use clap::Parser;
 use itertools::Itertools;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
-    fs::{self, OpenOptions},
-    io::prelude::*,
+    fs::{self, File, OpenOptions},
+    io::{prelude::*, BufWriter},
     path::Path,
     sync::Arc,
 };
@@ -61,6 +66,22 @@ pub struct Options {
     /// Interpred given layout string using the "grouped" logic
     #[clap(long)]
     pub grouped_layout_generator: bool,
+
+    /// Interpred given layout string as (key, layer) slots, permuting the `permuted_layers` of the
+    /// layout config independently of the base layer
+    #[clap(long, conflicts_with = "grouped_layout_generator")]
+    pub slot_layout_generator: bool,
+
+    /// Directory of a persistent result cache shared by all runs. Results are reused as long as
+    /// the layout config, evaluation parameters and ngrams (and the options above) are unchanged
+    #[clap(long)]
+    pub persistent_cache: Option<String>,
+
+    /// Consider a layout and its mirror image (symbols of keys with identical symmetry index swapped
+    /// between hands) as the same layout when caching and collecting results. Only meaningful if the
+    /// keyboard and all metrics treat both hands alike
+    #[clap(long)]
+    pub mirror_symmetric: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -86,7 +107,11 @@ pub struct PublishingOptions {
 
 pub fn init(options: &Options) -> (Box<dyn LayoutGenerator>, Evaluator) {
     (
-        init_layout_generator(&options.layout_config, options.grouped_layout_generator),
+        init_layout_generator(
+            &options.layout_config,
+            options.grouped_layout_generator,
+            options.slot_layout_generator,
+        ),
         init_evaluator(options),
     )
 }
@@ -94,6 +119,7 @@ pub fn init(options: &Options) -> (Box<dyn LayoutGenerator>, Evaluator) {
 pub fn init_layout_generator(
     layout_config: &str,
     grouped_layout_generator: bool,
+    slot_layout_generator: bool,
 ) -> Box<dyn LayoutGenerator> {
     let layout_config = LayoutConfig::from_yaml(layout_config)
         .unwrap_or_else(|e| panic!("Could not load config file {}: {}", layout_config, e));
@@ -121,6 +147,11 @@ pub fn init_layout_generator(
             layout_config.base_layout,
             keyboard,
         ))
+    } else if slot_layout_generator {
+        Box::new(SlotLayoutGenerator::from_object(
+            layout_config.base_layout,
+            keyboard,
+        ))
     } else {
         Box::new(NeoLayoutGenerator::from_object(
             layout_config.base_layout,
@@ -129,6 +160,17 @@ pub fn init_layout_generator(
     }
 }
 
+/// Initializes a generator for the layout of a layout file, whose remaining settings are taken from
+/// the layout config.
+pub fn init_file_layout_generator(layout_config: &str, layout_file: &str) -> FileLayoutGenerator {
+    let layout_config = LayoutConfig::from_yaml(layout_config)
+        .unwrap_or_else(|e| panic!("Could not load config file {}: {}", layout_config, e));
+    let keyboard = Arc::new(Keyboard::from_yaml_object(layout_config.keyboard));
+
+    FileLayoutGenerator::from_yaml_file(layout_file, layout_config.base_layout, keyboard)
+        .unwrap_or_else(|e| panic!("Could not load layout file {}: {}", layout_file, e))
+}
+
 pub fn init_evaluator(options: &Options) -> Evaluator {
     let eval_params =
         EvaluationParameters::from_yaml(&options.eval_parameters).unwrap_or_else(|e| {
@@ -155,8 +197,21 @@ pub fn init_evaluator(options: &Options) -> Evaluator {
         ngrams_config.increase_common_ngrams.enabled = false;
     }
 
+    let context_keys = LayoutConfig::from_yaml(&options.layout_config)
+        .unwrap_or_else(|e| {
+            panic!(
+                "Could not load config file {}: {}",
+                options.layout_config, e
+            )
+        })
+        .base_layout
+        .context_keys;
+
     let (mut unigrams, mut bigrams, mut trigrams) = match text {
         Some(txt) => {
+            // symbol sequences typed with a single context key can not be recognized within ngrams
+            let txt = tokenize_context_keys(&txt, &context_keys);
+
             let unigrams =
                 Unigrams::from_text(&txt).expect("Could not generate unigrams from text.");
             let bigrams = Bigrams::from_text(&txt).expect("Could not generate bigrams from text.");
@@ -179,6 +234,19 @@ pub fn init_evaluator(options: &Options) -> Evaluator {
             let trigrams = Trigrams::from_file(p.to_str().unwrap())
                 .unwrap_or_else(|_| panic!("Could not read 3-gramme file from '{:?}'.", &p));
 
+            for context_key in context_keys.iter() {
+                if let ContextKey::Magic { symbol, rules } = context_key {
+                    let has_sequences = rules.values().any(|s| s.chars().count() > 1);
+                    if has_sequences && !unigrams.grams.contains_key(symbol) {
+                        log::warn!(
+                            "Magic key '{}' generates sequences of symbols that are not found in the ngrams. \
+                            Generate them with `ngrams --layout-config` to evaluate these rules.",
+                            symbol
+                        );
+                    }
+                }
+            }
+
             (unigrams, bigrams, trigrams)
         }
     };
@@ -204,11 +272,120 @@ pub fn init_evaluator(options: &Options) -> Evaluator {
     }
 
     let ngram_provider =
-        OnDemandNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config);
+        DenseNgramMapper::with_ngrams(unigrams, bigrams, trigrams, ngram_mapper_config);
 
     Evaluator::default(Box::new(ngram_provider)).default_metrics(&eval_params.metrics)
 }
 
+/// Hash of all inputs that determine the evaluation of a layout string (including the layout file
+/// replacing the keys of the layout config, if any).
+pub fn input_fingerprint(options: &Options, layout_file: Option<&str>) -> String {
+    let read = |filename: &str| {
+        fs::read(filename).unwrap_or_else(|e| panic!("Could not read {}: {}", filename, e))
+    };
+
+    // the resolved layout config covers changes of the configs it extends
+    let layout_config = LayoutConfig::resolved_yaml(&options.layout_config).unwrap_or_else(|e| {
+        panic!(
+            "Could not load config file {}: {}",
+            options.layout_config, e
+        )
+    });
+    let mut inputs: Vec<Vec<u8>> = vec![layout_config.into_bytes(), read(&options.eval_parameters)];
+    // files referenced by the evaluation parameters (e.g. measured transition times)
+    let eval_params =
+        EvaluationParameters::from_yaml(&options.eval_parameters).unwrap_or_else(|e| {
+            panic!(
+                "Could not read evaluation yaml file {}: {:?}",
+                options.eval_parameters, e
+            )
+        });
+    inputs.extend(eval_params.referenced_files().into_iter().map(read));
+    if let Some(layout_file) = layout_file {
+        inputs.push(read(layout_file));
+    }
+    match (&options.text, &options.corpus) {
+        (Some(text), _) => inputs.push(text.as_bytes().to_vec()),
+        (None, Some(corpus_file)) => inputs.push(read(corpus_file)),
+        (None, None) => {
+            for ngram_file in ["1-grams.txt", "2-grams.txt", "3-grams.txt"] {
+                let p = Path::new(&options.ngrams).join(ngram_file);
+                inputs.push(read(p.to_str().unwrap()));
+            }
+        }
+    }
+    inputs.push(
+        format!(
+            "{:?} {:?} {} {} {} {} {}",
+            options.tops,
+            options.exclude_chars,
+            options.no_split_modifiers,
+            options.no_increase_common_ngrams,
+            options.grouped_layout_generator,
+            options.slot_layout_generator,
+            // cached values of canonical layouts may stem from their mirror images
+            options.mirror_symmetric,
+        )
+        .into_bytes(),
+    );
+
+    fingerprint(inputs)
+}
+
+/// The symmetry of the generator's layout strings if the `mirror_symmetric` option is given.
+pub fn layout_symmetry(
+    options: &Options,
+    layout_generator: &dyn LayoutGenerator,
+) -> Option<LayoutSymmetry> {
+    options.mirror_symmetric.then(|| {
+        let symmetry = layout_generator.symmetry();
+        if symmetry.is_trivial() {
+            log::warn!("The layout config has no mirrored keys; layouts are not canonicalized");
+        }
+        symmetry
+    })
+}
+
+/// Initializes a cache of results, which considers mirror images of layouts as identical if the
+/// `mirror_symmetric` option is given.
+pub fn init_results<T: Clone>(
+    options: &Options,
+    layout_generator: &dyn LayoutGenerator,
+) -> Cache<T> {
+    with_layout_symmetry(Cache::new(), options, layout_generator)
+}
+
+/// Initializes a result cache, which is persistent if the `persistent_cache` option is given.
+/// The `name` distinguishes caches of different kinds of results.
+pub fn init_result_cache<T>(
+    options: &Options,
+    name: &str,
+    layout_generator: &dyn LayoutGenerator,
+    layout_file: Option<&str>,
+) -> Cache<T>
+where
+    T: Clone + Serialize + DeserializeOwned + 'static,
+{
+    let cache = match &options.persistent_cache {
+        Some(dir) => Cache::persistent(dir, name, &input_fingerprint(options, layout_file))
+            .unwrap_or_else(|e| panic!("Could not open persistent cache in {}: {}", dir, e)),
+        None => Cache::new(),
+    };
+
+    with_layout_symmetry(cache, options, layout_generator)
+}
+
+fn with_layout_symmetry<T: Clone>(
+    cache: Cache<T>,
+    options: &Options,
+    layout_generator: &dyn LayoutGenerator,
+) -> Cache<T> {
+    match layout_symmetry(options, layout_generator) {
+        Some(symmetry) => cache.with_symmetry(symmetry),
+        None => cache,
+    }
+}
+
 /// Appends a layout-string to a file.
 pub fn append_to_file(layout_str: &str, filename: &str) {
     let mut file = OpenOptions::new()
@@ -223,6 +400,32 @@ pub fn append_to_file(layout_str: &str, filename: &str) {
     }
 }
 
+/// Writes a checkpoint to a file. The file is replaced atomically, such that an interruption while
+/// writing does not corrupt the previous checkpoint.
+pub fn write_checkpoint<T: Serialize>(checkpoint: &T, filename: &str) {
+    let tmp_filename = format!("{}.tmp", filename);
+    let res = File::create(&tmp_filename)
+        .map_err(anyhow::Error::from)
+        .and_then(|f| {
+            let mut writer = BufWriter::new(f);
+            serde_yaml::to_writer(&mut writer, checkpoint)?;
+            writer.flush()?;
+            Ok(())
+        })
+        .and_then(|_| Ok(fs::rename(&tmp_filename, filename)?));
+    if let Err(e) = res {
+        log::error!("Couldn't write checkpoint to '{}': {}", filename, e);
+    } else {
+        log::debug!("Wrote checkpoint to '{}'", filename);
+    }
+}
+
+/// Reads a checkpoint written by [`write_checkpoint`].
+pub fn read_checkpoint<T: DeserializeOwned>(filename: &str) -> Result<T> {
+    let f = File::open(filename)?;
+    Ok(serde_yaml::from_reader(f)?)
+}
+
 /// Publishes the layout to a webservice.
 pub fn publish_to_webservice(
     layout_str: &str,
@@ -15,7 +15,7 @@ use keyboard_layout::{
 use layout_evaluation::{
     cache::Cache,
     config::EvaluationParameters,
-    evaluation::Evaluator,
+    evaluation::{CostAttributionParameters, Evaluator},
     ngram_mapper::on_demand_ngram_mapper::OnDemandNgramMapper,
     ngrams::{Bigrams, Trigrams, Unigrams},
     results::EvaluationResult,
@@ -46,6 +46,7 @@ struct LayoutEvaluation {
     details: EvaluationResult,
     printed: Option<String>,
     plot: Option<String>,
+    svg: Option<String>,
     layout: Option<String>,
 }
 
@@ -56,6 +57,7 @@ impl From<EvaluationResult> for LayoutEvaluation {
             details: res,
             printed: None,
             plot: None,
+            svg: None,
             layout: None,
         }
     }
@@ -89,6 +91,15 @@ impl LayoutPlotter {
             .map_err(|e| format!("Could not plot the layout: {:?}", e))?;
         Ok(layout.plot_layer(layer))
     }
+
+    pub fn plot_svg(&self, layout_str: &str) -> Result<String, JsValue> {
+        let layout_str: String = layout_str.chars().filter(|c| !c.is_whitespace()).collect();
+        let layout = self
+            .layout_generator
+            .generate_unchecked(&layout_str)
+            .map_err(|e| format!("Could not plot the layout: {:?}", e))?;
+        Ok(layout.plot_svg(None))
+    }
 }
 
 #[wasm_bindgen]
@@ -156,6 +167,7 @@ impl NgramProvider {
 pub struct LayoutEvaluator {
     layout_generator: NeoLayoutGenerator,
     evaluator: Evaluator,
+    cost_attribution: CostAttributionParameters,
 }
 
 #[wasm_bindgen]
@@ -183,6 +195,7 @@ impl LayoutEvaluator {
         Ok(LayoutEvaluator {
             layout_generator,
             evaluator,
+            cost_attribution: eval_params.cost_attribution,
         })
     }
 
@@ -192,14 +205,18 @@ impl LayoutEvaluator {
             .layout_generator
             .generate(&layout_str)
             .map_err(|e| format!("Could not generate layout: {:?}", e))?;
-        let res = self.evaluator.evaluate_layout(&layout);
+        let res = self
+            .evaluator
+            .evaluate_layout_with_cost_attribution(&layout, Some(&self.cost_attribution));
         let printed = Some(format!("{}", res));
         let plot = Some(layout.plot());
+        let svg = Some(layout.plot_svg(None));
         let layout_str = Some(layout_str);
 
         let mut res: LayoutEvaluation = res.into();
         res.printed = printed;
         res.plot = plot;
+        res.svg = svg;
         res.layout = layout_str;
         Ok(JsValue::from_serde(&res).unwrap())
     }
@@ -213,6 +230,25 @@ impl LayoutEvaluator {
         Ok(layout.plot_layer(layer))
     }
 
+    /// Plot an SVG image of the layout with a heatmap of the key loads (`heatmap` = "load") or of
+    /// the keys' contributions to the cost of the metric with the given name (none if empty)
+    pub fn plot_svg(&self, layout_str: &str, heatmap: &str) -> Result<String, JsValue> {
+        let layout_str: String = layout_str.chars().filter(|c| !c.is_whitespace()).collect();
+        let layout = self
+            .layout_generator
+            .generate(&layout_str)
+            .map_err(|e| format!("Could not plot the layout: {:?}", e))?;
+        let heatmap = match heatmap {
+            "" => None,
+            values => Some(
+                self.evaluator
+                    .key_heatmap(&layout, values)
+                    .ok_or(format!("No per-key values for heatmap '{}'", values))?,
+            ),
+        };
+        Ok(layout.plot_svg(heatmap.as_ref()))
+    }
+
     pub fn permutable_keys(&self) -> JsValue {
         let permutable_keys = self.layout_generator.permutable_keys();
         JsValue::from_serde(&permutable_keys).unwrap()
@@ -257,6 +293,7 @@ impl LayoutOptimizer {
             fixed_characters,
             start_with_layout,
             true,
+            None,
         );
 
         Ok(LayoutOptimizer {
@@ -407,6 +444,8 @@ pub fn sa_optimize(
         /* log_everything: */ false,
         Some(Cache::new()),
         Some(SaCustomObserver(Box::new(observer))),
+        None,
+        None,
     );
     let minus_one = JsValue::from(-1);
     let _ = update_callback.call1(&this, &minus_one);
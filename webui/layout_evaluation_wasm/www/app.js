@@ -84,7 +84,7 @@ Vue.component('evaluator-app', {
         <keyboard-selector @selected="selectLayoutConfigType"></keyboard-selector>
         <b-form-invalid-feedback>{{invalidInputFeedback}}</b-form-invalid-feedback>
       </b-form>
-      <layout-plot :layout-string="inputLayout" :wasm="wasm" :layout-config="layoutConfig" :permutableKeys="permutableKeys"></layout-plot>
+      <layout-plot :layout-string="inputLayout" :wasm="wasm" :layout-config="layoutConfig" :permutableKeys="permutableKeys" svg></layout-plot>
 
       <b-button :disabled="loading > 0 || saOptimizationOngoing" @click="evaluateInput" variant="primary">
         <div v-if="loading > 0"><b-spinner small></b-spinner> Loading</div>
@@ -743,7 +743,8 @@ Vue.component('config-file', {
 
 Vue.component('layout-plot', {
   template: `
-    <pre style="overflow-y: hidden"><code v-html="plotString"></code></pre>
+    <div v-if="svg" style="overflow-x: auto" v-html="plotString"></div>
+    <pre v-else style="overflow-y: hidden"><code v-html="plotString"></code></pre>
 `,
   props: {
     layoutString: { type: String, default: "" },
@@ -751,6 +752,7 @@ Vue.component('layout-plot', {
     wasm: { type: Object, default: null },
     layoutConfig: { type: Object, default: null },
     permutableKeys: { type: Array, default: null },
+    svg: { type: Boolean, default: false },
   },
   data() {
     return {
@@ -795,7 +797,7 @@ Vue.component('layout-plot', {
       }
       let layout = this.layoutString + Array(nMissing + 1).join(this.defaultSymbol)
       try {
-        this.plotString = this.layoutPlotter.plot(layout, 0)
+        this.plotString = this.svg ? this.layoutPlotter.plot_svg(layout) : this.layoutPlotter.plot(layout, 0)
       } catch (err) {
         // this.$bvToast.toast(`Could not plot layout: ${err}`, {variant: "danger"})
         return
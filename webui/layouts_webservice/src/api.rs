@@ -2,7 +2,10 @@ use super::Options;
 
 use keyboard_layout::layout_generator::LayoutGenerator;
 use keyboard_layout::neo_layout_generator::NeoLayoutGenerator;
-use layout_evaluation::{evaluation::Evaluator, results::EvaluationResult};
+use layout_evaluation::{
+    evaluation::{CostAttributionParameters, Evaluator},
+    results::EvaluationResult,
+};
 
 use ahash::AHashMap;
 use rocket::{
@@ -80,6 +83,7 @@ async fn post(
     layout: Json<PostLayout>,
     layout_generators: &State<AHashMap<String, NeoLayoutGenerator>>,
     evaluator: &State<Evaluator>,
+    cost_attribution: &State<CostAttributionParameters>,
     config: &State<Options>,
 ) -> Result<Created<Json<LayoutEvaluation>>> {
     // check if highlight wants to be set without permission
@@ -120,7 +124,8 @@ async fn post(
     let result = match result {
         None => {
             println!("Evaluating new layout: {}", layout_str);
-            let evaluation_result = evaluator.evaluate_layout(&l);
+            let evaluation_result = evaluator
+                .evaluate_layout_with_cost_attribution(&l, Some(cost_attribution.inner()));
 
             let result = LayoutEvaluationDB {
                 id: None,
@@ -219,6 +224,7 @@ async fn reeval(
     secret: &str,
     layout_generators: &State<AHashMap<String, NeoLayoutGenerator>>,
     evaluator: &State<Evaluator>,
+    cost_attribution: &State<CostAttributionParameters>,
     config: &State<Options>,
 ) -> Result<()> {
     let is_admin = config.secret == *secret;
@@ -240,7 +246,8 @@ async fn reeval(
             .get(&result.layout_config)
             .ok_or(Status::BadRequest)?;
         let layout = layout_generator.generate(&result.layout).unwrap();
-        let evaluation_result = evaluator.evaluate_layout(&layout);
+        let evaluation_result = evaluator
+            .evaluate_layout_with_cost_attribution(&layout, Some(cost_attribution.inner()));
         let total_cost = evaluation_result.total_cost();
         let details_json = Some(serde_json::to_string(&evaluation_result).unwrap());
         let printed = format!("{}", evaluation_result);
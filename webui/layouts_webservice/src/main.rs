@@ -117,6 +117,7 @@ fn rocket() -> _ {
 
     rocket
         .manage(evaluator)
+        .manage(eval_params.cost_attribution.clone())
         .manage(layout_generators)
         .attach(AdHoc::config::<Options>())
         .attach(api::stage())